@@ -0,0 +1,71 @@
+//! Latency of a capacity query on a large uncompressed TIFF, which decodes
+//! the whole carrier. `capacity query` goes through the library and so uses a
+//! memory map only when built with `--features mmap`; the other two cases
+//! decode the same file from a copy read into memory and, with the feature,
+//! from a mapping, so one run compares both:
+//!
+//!     cargo bench --bench carrier_loading --features mmap
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use deepscene::processor::DataProcessor;
+use image::{ImageFormat, RgbImage};
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use tempfile::TempDir;
+
+const WIDTH: u32 = 6000;
+const HEIGHT: u32 = 4000;
+
+fn write_carrier(dir: &Path) -> std::path::PathBuf {
+    let path = dir.join("large.tiff");
+    RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        image::Rgb([(x * 7 + y) as u8, (x + y * 5) as u8, (x ^ y) as u8])
+    })
+    .save(&path)
+    .unwrap();
+    path
+}
+
+fn dimensions(bytes: &[u8]) -> (u32, u32) {
+    let image = image::load_from_memory_with_format(bytes, ImageFormat::Tiff).unwrap();
+    (image.width(), image.height())
+}
+
+fn carrier_loading(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let carrier = write_carrier(dir.path());
+    let file_size = fs::metadata(&carrier).unwrap().len();
+
+    let mut group = c.benchmark_group("tiff capacity");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(file_size));
+
+    group.bench_function("capacity query", |b| {
+        b.iter(|| {
+            let table =
+                DataProcessor::capacity_table(black_box(std::slice::from_ref(&carrier))).unwrap();
+            assert_eq!(table.unreadable(), 0);
+            table
+        })
+    });
+    group.bench_function("read into memory", |b| {
+        b.iter(|| dimensions(&fs::read(black_box(&carrier)).unwrap()))
+    });
+    #[cfg(feature = "mmap")]
+    group.bench_function("memory-mapped", |b| {
+        b.iter(|| {
+            let file = fs::File::open(black_box(&carrier)).unwrap();
+            // SAFETY: the benchmark's own temporary file, not modified while
+            // mapped.
+            let map = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+            dimensions(&map)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, carrier_loading);
+criterion_main!(benches);
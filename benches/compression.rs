@@ -0,0 +1,62 @@
+//! Compression of a large, compressible payload on one thread and on all
+//! cores. Run with `cargo bench --features parallel-compression` to compare
+//! chunked parallel deflate with a single stream; without the feature both
+//! cases deflate one stream on one thread.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use deepscene::core::{CompressionEngine, parallel};
+use std::hint::black_box;
+
+/// Bytes compressed per iteration, well above the 4 MiB parallel threshold.
+const PAYLOAD_LEN: usize = 32 * 1024 * 1024;
+
+/// Log-like text: repetitive enough to compress well, varied enough that
+/// deflate still has to search for matches.
+fn payload() -> Vec<u8> {
+    let mut data = Vec::with_capacity(PAYLOAD_LEN + 128);
+    let mut line = 0u64;
+    while data.len() < PAYLOAD_LEN {
+        data.extend_from_slice(
+            format!(
+                "2026-10-16T12:{:02}:{:02}Z INFO request id={} status={} bytes={}\n",
+                line / 60 % 60,
+                line % 60,
+                line.wrapping_mul(2_654_435_761) % 1_000_000,
+                [200, 200, 200, 304, 404][line as usize % 5],
+                line * 37 % 65_536
+            )
+            .as_bytes(),
+        );
+        line += 1;
+    }
+    data.truncate(PAYLOAD_LEN);
+    data
+}
+
+fn compression(c: &mut Criterion) {
+    let data = payload();
+    let mut group = c.benchmark_group("compress");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Bytes(PAYLOAD_LEN as u64));
+
+    group.bench_function("single-threaded", |b| {
+        b.iter(|| {
+            parallel::run_with_threads(Some(1), || CompressionEngine::compress(black_box(&data)))
+                .unwrap()
+                .unwrap()
+        })
+    });
+    group.bench_function("all cores", |b| {
+        b.iter(|| {
+            parallel::run_with_threads(None, || CompressionEngine::compress(black_box(&data)))
+                .unwrap()
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, compression);
+criterion_main!(benches);
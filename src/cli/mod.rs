@@ -1,4 +1,10 @@
-use clap::{Parser, Subcommand};
+use crate::core::Region;
+use crate::core::steganography::FORMAT_VERSION;
+use crate::io::timestamp;
+use crate::processor::metadata::PayloadMetadata;
+use crate::processor::output_template::DEFAULT_OUTPUT_TEMPLATE;
+use crate::processor::{DEFAULT_MIN_PASSWORD_LENGTH, DEFAULT_OUTPUT_SUFFIX};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -8,36 +14,468 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    #[arg(
+        long = "bytes",
+        global = true,
+        help = "Print exact byte counts instead of KiB/MiB/GiB"
+    )]
+    pub bytes: bool,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        global = true,
+        help = "Suppress progress messages; only warnings, results and errors are printed"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        global = true,
+        conflicts_with = "quiet",
+        help = "Print extra detail, such as the files encode will create or replace before it starts"
+    )]
+    pub verbose: bool,
+
+    #[arg(
+        long = "progress-json",
+        global = true,
+        conflicts_with = "verbose",
+        help = "Write embedding and extraction progress to stderr as JSON lines instead of printing step messages"
+    )]
+    pub progress_json: bool,
+
+    #[arg(
+        long = "strict",
+        global = true,
+        help = "Treat warnings (lossy carrier, weak password, near-full carrier, ...) as errors"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long = "offline",
+        global = true,
+        help = "Never access the network. Default builds make no network calls; this also blocks URL inputs in builds with the net feature"
+    )]
+    pub offline: bool,
+
+    #[arg(
+        long = "follow-symlinks",
+        global = true,
+        help = "Allow writing to an output path that is a symbolic link, replacing the file it points to. Refused by default"
+    )]
+    pub follow_symlinks: bool,
+
+    #[arg(
+        long = "audit-log",
+        global = true,
+        value_name = "PATH",
+        help = "Append a JSON line per operation (time, command, paths, sizes, flags, outcome) to PATH. Passwords and file contents are never logged"
+    )]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(
+        long = "threads",
+        global = true,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Limit embedding/extraction to N worker threads (defaults to all cores)"
+    )]
+    pub threads: Option<usize>,
 }
 
+// Parsed once per run, so the size of the `Encode` variant does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Embed a file into an image using steganography")]
+    #[command(group(ArgGroup::new("password_source").args(["password", "password_file", "password_stdin", "recipient_password", "generate_mnemonic"])))]
     Encode {
-        #[arg(help = "Path to the carrier image")]
-        input: PathBuf,
+        #[arg(
+            help = "Path to the carrier image",
+            required_unless_present_any = ["batch", "archive"]
+        )]
+        input: Option<PathBuf>,
 
-        #[arg(help = "Path to the file to be embedded")]
-        file: PathBuf,
+        #[arg(
+            help = "Path to the file to be embedded",
+            required_unless_present_any = ["batch", "archive", "from_clipboard"]
+        )]
+        file: Option<PathBuf>,
+
+        #[arg(
+            long = "from-clipboard",
+            conflicts_with_all = ["file", "batch", "archive"],
+            help = "Embed the clipboard text as clipboard.txt instead of a file (requires the clipboard feature)"
+        )]
+        from_clipboard: bool,
 
         #[arg(
             short = 'o',
             long = "output",
-            help = "Output path for the generated image (defaults to input_steg.png)"
+            help = "Output path for the generated image (defaults to <input>-edited.png), or the output directory with --batch"
         )]
         output: Option<PathBuf>,
 
+        #[arg(
+            long = "output-suffix",
+            value_name = "SUFFIX",
+            default_value = DEFAULT_OUTPUT_SUFFIX,
+            conflicts_with_all = ["output", "batch"],
+            help = "Appended to the carrier's name to form the default output name"
+        )]
+        output_suffix: String,
+
+        #[arg(
+            long = "in-place",
+            conflicts_with_all = ["output", "output_suffix", "batch", "archive", "overwrite_policy"],
+            help = "Replace the carrier with the output, keeping its name and format (lossless carriers only)"
+        )]
+        in_place: bool,
+
         #[arg(
             short = 'p',
             long = "password",
             help = "Optional encryption password for securing the embedded data"
         )]
         password: Option<String>,
+
+        #[arg(
+            long = "password-file",
+            value_name = "PATH",
+            help = "Read the password from PATH (see --raw-password)"
+        )]
+        password_file: Option<PathBuf>,
+
+        #[arg(
+            long = "password-stdin",
+            help = "Read the password from standard input (see --raw-password)"
+        )]
+        password_stdin: bool,
+
+        #[arg(
+            long = "generate-mnemonic",
+            conflicts_with_all = ["estimate", "estimate_fit"],
+            help = "Encrypt with a new 12-word BIP39 mnemonic as the password, printed once after encoding"
+        )]
+        generate_mnemonic: bool,
+
+        #[arg(
+            long = "raw-password",
+            help = "Keep a leading BOM and trailing newline in --password-file/--password-stdin input"
+        )]
+        raw_password: bool,
+
+        #[arg(
+            long = "recipient-password",
+            value_name = "PASSWORD",
+            action = clap::ArgAction::Append,
+            conflicts_with_all = ["salt_file", "batch"],
+            help = "Encrypt for several recipients: repeat once per password; any one of them decrypts"
+        )]
+        recipient_password: Vec<String>,
+
+        #[arg(
+            long = "kdf",
+            value_enum,
+            default_value_t = Kdf::Argon2,
+            requires = "password_source",
+            help = "Key derivation function used to turn the password into a key"
+        )]
+        kdf: Kdf,
+
+        #[arg(
+            long = "min-password-length",
+            value_name = "CHARS",
+            default_value_t = DEFAULT_MIN_PASSWORD_LENGTH,
+            help = "Reject passwords shorter than CHARS characters (0 disables the check)"
+        )]
+        min_password_length: usize,
+
+        #[arg(
+            long = "encrypt-metadata",
+            requires = "password_source",
+            help = "Encrypt the file name and metadata too, so the password is needed even to list them"
+        )]
+        encrypt_metadata: bool,
+
+        #[arg(
+            long = "bit-plane",
+            default_value_t = 0,
+            value_parser = clap::value_parser!(u8).range(0..=7),
+            help = "Bit plane (0-7) carrying the payload; higher planes are more robust but more visible"
+        )]
+        bit_plane: u8,
+
+        #[arg(
+            long = "batch",
+            value_name = "DIR",
+            conflicts_with_all = ["input", "file"],
+            requires_all = ["carrier_dir", "output"],
+            help = "Embed every file in DIR (recursively) into its own carrier image"
+        )]
+        batch: Option<PathBuf>,
+
+        #[arg(
+            long = "carrier-dir",
+            value_name = "DIR",
+            requires = "batch",
+            help = "Directory of carrier images used with --batch"
+        )]
+        carrier_dir: Option<PathBuf>,
+
+        #[arg(
+            long = "archive",
+            value_name = "ZIP",
+            conflicts_with_all = ["input", "file", "batch"],
+            help = "Read the carrier and file from a .zip whose manifest.txt names them (carrier=..., payload=...)"
+        )]
+        archive: Option<PathBuf>,
+
+        #[arg(
+            long = "pairing",
+            value_enum,
+            default_value_t = Pairing::RoundRobin,
+            help = "How --batch assigns carriers to files"
+        )]
+        pairing: Pairing,
+
+        #[arg(
+            long = "resume",
+            requires = "batch",
+            help = "Skip files a previous, interrupted --batch run already embedded"
+        )]
+        resume: bool,
+
+        #[arg(
+            long = "output-template",
+            value_name = "TEMPLATE",
+            default_value = DEFAULT_OUTPUT_TEMPLATE,
+            requires = "batch",
+            help = "Output file names for --batch, using {stem} and {ext} of the carrier, {index} and the payload's {name}"
+        )]
+        output_template: String,
+
+        #[arg(
+            long = "seed-file",
+            value_name = "PATH",
+            hide = true,
+            conflicts_with = "batch",
+            help = "INSECURE: seed salts, nonces, LSB matching and padding from PATH for reproducible output (testing only)"
+        )]
+        seed_file: Option<PathBuf>,
+
+        #[arg(
+            long = "salt-file",
+            value_name = "PATH",
+            requires = "password_source",
+            conflicts_with = "seed_file",
+            help = "Use the 16 raw bytes in PATH as the key derivation salt instead of a random one (the nonce stays random)"
+        )]
+        salt_file: Option<PathBuf>,
+
+        #[arg(
+            long = "lsb-matching",
+            help = "Randomly increment or decrement channel values instead of overwriting bits (harder to detect)"
+        )]
+        lsb_matching: bool,
+
+        #[arg(
+            long = "carrier-noise",
+            visible_alias = "fill-remaining",
+            help = "Randomize the unused low bits after the payload so embedded and untouched regions look alike"
+        )]
+        carrier_noise: bool,
+
+        #[arg(
+            long = "stride",
+            value_name = "N",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            conflicts_with_all = ["compat", "batch"],
+            help = "Write one payload group (8 pixels) every N groups, spreading a small payload across the image"
+        )]
+        stride: u16,
+
+        #[arg(
+            long = "channel-copies",
+            conflicts_with_all = ["compat", "batch", "stride"],
+            help = "Store a full copy of the payload in each of R, G and B so damage to one channel is survivable (divides capacity by 3)"
+        )]
+        channel_copies: bool,
+
+        #[arg(
+            long = "preserve-region",
+            value_name = "X,Y,W,H",
+            value_parser = parse_region,
+            conflicts_with_all = ["compat", "batch", "stride", "channel_copies"],
+            help = "Leave the W x H pixel rectangle at (X, Y) untouched, e.g. a preview area; decode skips it automatically"
+        )]
+        preserve_region: Option<Region>,
+
+        #[arg(
+            long = "tiff-strip",
+            value_name = "N",
+            conflicts_with_all = ["compat", "batch", "stride", "channel_copies", "preserve_region", "dimensions"],
+            help = "Confine the payload to strip N (from 0) of a TIFF carrier and keep its strip layout; edits to other strips leave it intact"
+        )]
+        tiff_strip: Option<u32>,
+
+        #[arg(
+            long = "perceptual",
+            conflicts_with_all = ["compat", "batch", "bit_plane", "stride", "channel_copies", "preserve_region", "tiff_strip"],
+            help = "Write two bits in red and blue and one in green, where the eye notices least (5 bits per pixel instead of 3)"
+        )]
+        perceptual: bool,
+
+        #[arg(
+            long = "match-size",
+            conflicts_with = "dimensions",
+            help = "Tune PNG encoding and padding so the output is about as large as the carrier file"
+        )]
+        match_size: bool,
+
+        #[arg(
+            long = "confirm-convert",
+            conflicts_with = "dimensions",
+            help = "Convert a lossy carrier to PNG even when the PNG could exceed 64 MiB"
+        )]
+        confirm_convert: bool,
+
+        #[arg(
+            long = "max-output-size",
+            value_name = "BYTES",
+            help = "Fail and discard the output if the encoded image is larger than BYTES"
+        )]
+        max_output_size: Option<u64>,
+
+        #[arg(
+            long = "size-bucket",
+            value_name = "BYTES",
+            conflicts_with_all = ["match_size", "dimensions"],
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Pad the PNG output up to the next multiple of BYTES, so file sizes do not reveal the payload"
+        )]
+        size_bucket: Option<u64>,
+
+        #[arg(
+            long = "canonical-output",
+            conflicts_with_all = ["match_size", "batch", "dimensions"],
+            help = "Write the PNG with fixed encoder settings, so identical inputs give byte-identical output"
+        )]
+        canonical_output: bool,
+
+        #[arg(
+            long = "preserve-exif",
+            conflicts_with = "dimensions",
+            help = "Copy the carrier's EXIF and ICC metadata onto the output (PNG or TIFF output only)"
+        )]
+        preserve_exif: bool,
+
+        #[arg(
+            long = "preserve-dpi",
+            conflicts_with = "dimensions",
+            help = "Copy the carrier's pixel density (DPI) onto the output so it prints at the same size (PNG, TIFF or BMP output)"
+        )]
+        preserve_dpi: bool,
+
+        #[arg(
+            long = "no-name",
+            help = "Store only the file extension, not its name; decode writes extracted.<ext>"
+        )]
+        no_name: bool,
+
+        #[arg(
+            long = "compat",
+            value_name = "SPEC",
+            conflicts_with_all = ["password_source", "meta", "batch", "bit_plane"],
+            help = "Embed only the raw file bytes using a published interop format instead of the DeepScene header"
+        )]
+        compat: Option<Compat>,
+
+        #[arg(
+            long = "overwrite-policy",
+            value_name = "POLICY",
+            value_enum,
+            default_value_t = Overwrite::Overwrite,
+            conflicts_with = "batch",
+            help = "What to do when the converted carrier PNG or the output image already exists"
+        )]
+        overwrite_policy: Overwrite,
+
+        #[arg(
+            long = "estimate",
+            conflicts_with = "batch",
+            help = "Print the projected compressed and embedded size, then exit without encoding"
+        )]
+        estimate: bool,
+
+        #[arg(
+            long = "estimate-fit",
+            conflicts_with_all = ["batch", "estimate"],
+            help = "Compress samples of the file to judge quickly whether it fits, then exit without encoding"
+        )]
+        estimate_fit: bool,
+
+        #[arg(
+            long = "meta",
+            value_name = "KEY=VALUE",
+            value_parser = parse_meta_entry,
+            help = "Attach a metadata entry (repeatable), stored unencrypted"
+        )]
+        meta: Vec<(String, String)>,
+
+        #[arg(
+            long = "expires",
+            value_name = "DATE",
+            value_parser = parse_expiry,
+            conflicts_with = "compat",
+            help = "Advisory expiry (YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]Z, UTC); decode warns once it has passed"
+        )]
+        expires: Option<u64>,
+
+        #[arg(
+            long = "hint",
+            value_name = "TEXT",
+            requires = "password_source",
+            help = "Password hint stored unencrypted and shown when decode is given no or the wrong password"
+        )]
+        hint: Option<String>,
+
+        #[arg(
+            long = "sign-key",
+            value_name = "PATH",
+            conflicts_with_all = ["compat", "batch"],
+            help = "Sign the file with the Ed25519 private key in PATH (PKCS#8 PEM or 32 raw bytes); decode reports the signer"
+        )]
+        sign_key: Option<PathBuf>,
+
+        #[arg(
+            long = "format-version",
+            value_name = "N",
+            value_parser = clap::value_parser!(u8).range(1..=FORMAT_VERSION as i64),
+            conflicts_with_all = ["compat", "batch"],
+            help = "Write an older format version for readers that predate the current one; options it cannot store are rejected"
+        )]
+        format_version: Option<u8>,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
     },
 
     #[command(about = "Extract an embedded file from a steganographic image")]
+    #[command(group(ArgGroup::new("password_source").args(["password", "password_file", "password_stdin", "password_list"])))]
     Decode {
-        #[arg(help = "Path to the steganographic image")]
+        #[arg(
+            help = "Path to the steganographic image, '-' for standard input, or an http(s) URL (requires the net feature)"
+        )]
         input: PathBuf,
 
         #[arg(
@@ -50,8 +488,483 @@ pub enum Commands {
         #[arg(
             short = 'p',
             long = "password",
-            help = "Decryption password if the embedded data was encrypted"
+            action = clap::ArgAction::Append,
+            help = "Decryption password if the embedded data was encrypted (repeatable with --all)"
+        )]
+        password: Vec<String>,
+
+        #[arg(
+            long = "password-file",
+            value_name = "PATH",
+            help = "Read the password from PATH (see --raw-password)"
+        )]
+        password_file: Option<PathBuf>,
+
+        #[arg(
+            long = "password-stdin",
+            help = "Read the password from standard input (see --raw-password)"
+        )]
+        password_stdin: bool,
+
+        #[arg(
+            long = "password-list",
+            value_name = "PATH",
+            conflicts_with_all = ["raw_password", "all"],
+            help = "Try each line of PATH as the password until one decrypts the payload, at most two a second"
+        )]
+        password_list: Option<PathBuf>,
+
+        #[arg(
+            long = "raw-password",
+            help = "Keep a leading BOM and trailing newline in --password-file/--password-stdin input"
+        )]
+        raw_password: bool,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+
+        #[arg(
+            long = "compat",
+            value_name = "SPEC",
+            requires = "output",
+            conflicts_with = "password_source",
+            help = "Extract raw file bytes written with a published interop format"
+        )]
+        compat: Option<Compat>,
+
+        #[arg(
+            long = "enforce-expiry",
+            help = "Refuse to extract a payload whose --expires date has passed"
+        )]
+        enforce_expiry: bool,
+
+        #[arg(
+            long = "consume",
+            help = "Overwrite and delete the image after a successful extraction, for one-time delivery"
+        )]
+        consume: bool,
+
+        #[arg(
+            long = "recover",
+            conflicts_with_all = ["compat", "consume"],
+            help = "If the stored name or metadata is corrupted, extract the file data anyway as recovered.<ext>"
+        )]
+        recover: bool,
+
+        #[arg(
+            long = "check",
+            conflicts_with_all = ["output", "consume"],
+            help = "Run the full extraction and integrity checks but write no file, reporting OK or FAIL"
+        )]
+        check: bool,
+
+        #[arg(
+            long = "all",
+            conflicts_with_all = ["output", "compat", "consume", "recover", "check"],
+            help = "Extract every payload in the image, such as one per TIFF strip, trying each password on each"
+        )]
+        all: bool,
+
+        #[arg(
+            long = "to-clipboard",
+            conflicts_with_all = ["output", "compat", "consume", "check", "all"],
+            help = "Place the extracted text on the clipboard instead of writing a file (requires the clipboard feature)"
+        )]
+        to_clipboard: bool,
+
+        #[arg(
+            long = "verify-after-write",
+            conflicts_with_all = ["check", "to_clipboard", "all"],
+            help = "Read the written file back and check it against the stored fingerprint, removing it on a mismatch"
+        )]
+        verify_after_write: bool,
+
+        #[arg(
+            long = "verify-key",
+            value_name = "PATH",
+            conflicts_with_all = ["compat", "all"],
+            help = "Require a signature by the Ed25519 public key in PATH (PEM or 32 raw bytes), failing if it is missing or by another key"
+        )]
+        verify_key: Option<PathBuf>,
+    },
+
+    #[command(about = "List the files embedded in an image without extracting them")]
+    List {
+        #[arg(
+            help = "Path to the steganographic image, '-' for standard input, or an http(s) URL (requires the net feature)"
+        )]
+        input: PathBuf,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+    },
+
+    #[command(
+        about = "Check the embedded payload of every image in a directory without writing anything"
+    )]
+    #[command(group(ArgGroup::new("password_source").args(["password", "password_file", "password_stdin"])))]
+    VerifyAll {
+        #[arg(help = "Directory of steganographic images")]
+        input_dir: PathBuf,
+
+        #[arg(
+            short = 'p',
+            long = "password",
+            help = "Password to authenticate encrypted payloads; without one only their checksums are checked"
         )]
         password: Option<String>,
+
+        #[arg(
+            long = "password-file",
+            value_name = "PATH",
+            help = "Read the password from PATH (see --raw-password)"
+        )]
+        password_file: Option<PathBuf>,
+
+        #[arg(
+            long = "password-stdin",
+            help = "Read the password from standard input (see --raw-password)"
+        )]
+        password_stdin: bool,
+
+        #[arg(
+            long = "raw-password",
+            help = "Keep a leading BOM and trailing newline in --password-file/--password-stdin input"
+        )]
+        raw_password: bool,
+
+        #[arg(
+            short = 'r',
+            long = "recursive",
+            help = "Include images in subdirectories"
+        )]
+        recursive: bool,
     },
+
+    #[command(
+        about = "Print the fingerprint of the embedded file, or check it against a file without extracting"
+    )]
+    #[command(group(ArgGroup::new("password_source").args(["password", "password_file", "password_stdin"])))]
+    Fingerprint {
+        #[arg(
+            help = "Path to the steganographic image, '-' for standard input, or an http(s) URL (requires the net feature)"
+        )]
+        input: PathBuf,
+
+        #[arg(
+            long = "compare",
+            value_name = "FILE",
+            help = "Compare the stored fingerprint with FILE's and exit with status 1 if they differ"
+        )]
+        compare: Option<PathBuf>,
+
+        #[arg(
+            short = 'p',
+            long = "password",
+            help = "Password, needed when the embedded file or its metadata is encrypted"
+        )]
+        password: Option<String>,
+
+        #[arg(
+            long = "password-file",
+            value_name = "PATH",
+            help = "Read the password from PATH (see --raw-password)"
+        )]
+        password_file: Option<PathBuf>,
+
+        #[arg(
+            long = "password-stdin",
+            help = "Read the password from standard input (see --raw-password)"
+        )]
+        password_stdin: bool,
+
+        #[arg(
+            long = "raw-password",
+            help = "Keep a leading BOM and trailing newline in --password-file/--password-stdin input"
+        )]
+        raw_password: bool,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+    },
+
+    #[command(
+        about = "Move the embedded data of one image into a new carrier without decrypting it"
+    )]
+    Transfer {
+        #[arg(
+            help = "Path to the steganographic image, '-' for standard input, or an http(s) URL (requires the net feature)"
+        )]
+        input: PathBuf,
+
+        #[arg(help = "Path to the new carrier image")]
+        carrier: PathBuf,
+
+        #[arg(short = 'o', long = "output", help = "Output path for the new image")]
+        output: PathBuf,
+
+        #[arg(
+            long = "lsb-matching",
+            help = "Randomly increment or decrement channel values instead of overwriting bits (harder to detect)"
+        )]
+        lsb_matching: bool,
+
+        #[arg(
+            long = "carrier-noise",
+            visible_alias = "fill-remaining",
+            help = "Randomize the unused low bits after the payload so embedded and untouched regions look alike"
+        )]
+        carrier_noise: bool,
+    },
+
+    #[command(about = "Write a heatmap of where an image hides embedded data best")]
+    CapacityMap {
+        #[arg(help = "Path to the carrier image")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long = "output",
+            help = "Output path for the heatmap (defaults to input_capacity.png)"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+    },
+
+    #[command(
+        name = "diffmap",
+        about = "Write a map of where an image's low bits are set, as embedding would have changed them"
+    )]
+    DiffMap {
+        #[arg(help = "Path to the image to analyse")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long = "output",
+            help = "Output path for the map (defaults to input_diff.png)"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            long = "clean",
+            value_name = "PATH",
+            help = "Also write the image with the bit plane cleared to PATH"
+        )]
+        clean: Option<PathBuf>,
+
+        #[arg(
+            long = "bit-plane",
+            default_value_t = 0,
+            value_parser = clap::value_parser!(u8).range(0..=7),
+            help = "Bit plane (0-7) to map"
+        )]
+        bit_plane: u8,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+    },
+
+    #[command(
+        visible_alias = "strip-metadata",
+        about = "Write a copy of an image with its low bits cleared, destroying any embedded data"
+    )]
+    Strip {
+        #[arg(help = "Path to the image to clean")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long = "output",
+            help = "Output path for the clean image (defaults to input_clean.png)"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            long = "planes",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u8).range(1..=8),
+            help = "Number of low bit planes (1-8) to clear in R, G and B"
+        )]
+        planes: u8,
+
+        #[arg(
+            long = "randomize",
+            help = "Fill the cleared bits with random bits instead of zeros"
+        )]
+        randomize: bool,
+    },
+
+    #[command(
+        about = "List the dimensions, format and capacity of several images to choose a carrier"
+    )]
+    CapacityTable {
+        #[arg(
+            required = true,
+            help = "Carrier images, or directories whose images are all listed"
+        )]
+        inputs: Vec<PathBuf>,
+
+        #[arg(
+            long = "json",
+            help = "Print a JSON array with exact byte counts instead of a table"
+        )]
+        json: bool,
+    },
+
+    #[command(
+        about = "List the supported ciphers, key derivation functions and compression backends"
+    )]
+    ListAlgorithms {
+        #[arg(long = "json", help = "Print a JSON array instead of a table")]
+        json: bool,
+    },
+
+    #[command(
+        about = "Exit with status 0 if an image can hold a payload of the given size, 1 otherwise"
+    )]
+    Check {
+        #[arg(help = "Path to the carrier image")]
+        input: PathBuf,
+
+        #[arg(
+            long = "needs",
+            value_name = "BYTES",
+            help = "Payload size in bytes the image must be able to hold"
+        )]
+        needs: usize,
+
+        #[arg(
+            long = "dimensions",
+            value_name = "WxH",
+            value_parser = parse_dimensions,
+            help = "Treat the image as raw RGBA bytes with these dimensions"
+        )]
+        dimensions: Option<(u32, u32)>,
+    },
+}
+
+impl Commands {
+    /// Subcommand name as typed on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Encode { .. } => "encode",
+            Commands::Decode { .. } => "decode",
+            Commands::List { .. } => "list",
+            Commands::VerifyAll { .. } => "verify-all",
+            Commands::Fingerprint { .. } => "fingerprint",
+            Commands::Transfer { .. } => "transfer",
+            Commands::CapacityMap { .. } => "capacity-map",
+            Commands::DiffMap { .. } => "diffmap",
+            Commands::Strip { .. } => "strip",
+            Commands::CapacityTable { .. } => "capacity-table",
+            Commands::ListAlgorithms { .. } => "list-algorithms",
+            Commands::Check { .. } => "check",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Pairing {
+    #[value(help = "Cycle through carriers in name order")]
+    RoundRobin,
+    #[value(help = "Use the smallest carrier large enough for each file")]
+    Fit,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Kdf {
+    #[value(help = "Argon2id, 19 MiB and 2 passes (default)")]
+    Argon2,
+    #[value(help = "scrypt, N=2^15, r=8, p=1 (32 MiB)")]
+    Scrypt,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Overwrite {
+    #[value(help = "Replace existing files")]
+    Overwrite,
+    #[value(help = "Fail before doing any work if a file would be replaced")]
+    Never,
+    #[value(help = "Write to the first free <name>-<N>.<ext> instead")]
+    Rename,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Compat {
+    #[value(help = "32-bit big-endian length then data, MSB first, one bit per R/G/B value")]
+    PlainLsb,
+}
+
+fn parse_meta_entry(entry: &str) -> Result<(String, String), String> {
+    PayloadMetadata::parse_entry(entry).map_err(|e| e.to_string())
+}
+
+fn parse_expiry(value: &str) -> Result<u64, String> {
+    timestamp::parse_timestamp(value).map_err(|e| e.to_string())
+}
+
+fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("Invalid dimensions '{}'. Expected WxH", value))?;
+
+    let width = width
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid width '{}'", width))?;
+    let height = height
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid height '{}'", height))?;
+
+    Ok((width, height))
+}
+
+fn parse_region(value: &str) -> Result<Region, String> {
+    let fields: Vec<&str> = value.split(',').collect();
+    let [x, y, width, height] = fields.as_slice() else {
+        return Err(format!("Invalid region '{}'. Expected X,Y,W,H", value));
+    };
+
+    let parse = |field: &str, name: &str| {
+        field
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid region {} '{}'", name, field))
+    };
+
+    Ok(Region {
+        x: parse(x, "x")?,
+        y: parse(y, "y")?,
+        width: parse(width, "width")?,
+        height: parse(height, "height")?,
+    })
 }
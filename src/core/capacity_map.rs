@@ -0,0 +1,74 @@
+use crate::core::error::{DeepSceneError, Result};
+use crate::core::parallel;
+use image::{GrayImage, RgbaImage};
+
+/// Normalised scores below this mark a pixel as flat: changes there are the
+/// easiest to spot, both visually and statistically.
+pub const FLAT_THRESHOLD: u8 = 16;
+
+/// Local embedding suitability of a carrier, measured as the Sobel gradient
+/// magnitude of its luminance. Busy, high-contrast regions mask LSB changes
+/// far better than smooth ones such as sky or studio backgrounds.
+pub struct CapacityMap;
+
+impl CapacityMap {
+    /// Gradient magnitude for every pixel in row-major order. Borders reuse
+    /// the nearest edge pixel.
+    pub fn gradient_magnitude(image: &RgbaImage) -> Vec<f32> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+
+        let luma: Vec<f32> = image
+            .pixels()
+            .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+            .collect();
+
+        let at = |x: usize, y: usize, dx: isize, dy: isize| {
+            let x = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+            luma[y * width + x]
+        };
+
+        let rows = parallel::map_range(height, |y| {
+            (0..width)
+                .map(|x| {
+                    let gx = at(x, y, 1, -1) + 2.0 * at(x, y, 1, 0) + at(x, y, 1, 1)
+                        - at(x, y, -1, -1)
+                        - 2.0 * at(x, y, -1, 0)
+                        - at(x, y, -1, 1);
+                    let gy = at(x, y, -1, 1) + 2.0 * at(x, y, 0, 1) + at(x, y, 1, 1)
+                        - at(x, y, -1, -1)
+                        - 2.0 * at(x, y, 0, -1)
+                        - at(x, y, 1, -1);
+                    (gx * gx + gy * gy).sqrt()
+                })
+                .collect::<Vec<f32>>()
+        });
+
+        rows.concat()
+    }
+
+    /// Renders the gradient magnitude as a grayscale heatmap scaled so the
+    /// busiest pixel is white. A completely flat image renders black.
+    pub fn render(image: &RgbaImage) -> Result<GrayImage> {
+        let magnitude = Self::gradient_magnitude(image);
+        let max = magnitude.iter().copied().fold(0.0f32, f32::max);
+
+        let pixels = magnitude
+            .iter()
+            .map(|&m| {
+                if max > 0.0 {
+                    (m / max * 255.0).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        GrayImage::from_raw(image.width(), image.height(), pixels).ok_or_else(|| {
+            DeepSceneError::Image("Failed to construct capacity map image".to_string())
+        })
+    }
+}
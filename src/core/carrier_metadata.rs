@@ -0,0 +1,224 @@
+use crate::core::error::{DeepSceneError, Result};
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Tag, Value};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{ImageDecoder, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Write};
+use std::path::Path;
+
+const EXIF_CHUNK: [u8; 4] = *b"eXIf";
+const ICC_CHUNK: [u8; 4] = *b"iCCP";
+const ICC_PROFILE_NAME: &[u8] = b"ICC Profile";
+
+/// Tags describing the source file's pixel layout rather than the photo.
+/// They are meaningless, or wrong, once the pixels are stored as PNG.
+const LAYOUT_TAGS: [Tag; 8] = [
+    Tag::ImageWidth,
+    Tag::ImageLength,
+    Tag::BitsPerSample,
+    Tag::Compression,
+    Tag::PhotometricInterpretation,
+    Tag::SamplesPerPixel,
+    Tag::PlanarConfiguration,
+    Tag::RowsPerStrip,
+];
+
+/// TIFF tag holding an embedded ICC profile; it is carried over as `iCCP`,
+/// or as this tag again in TIFF output.
+const TIFF_ICC_TAG: u16 = 34675;
+
+/// EXIF and ICC metadata of a carrier, copied onto PNG or TIFF output so the
+/// result does not stand out as a camera photo stripped of its metadata.
+#[derive(Debug, Clone, Default)]
+pub struct CarrierMetadata {
+    /// TIFF-structured EXIF data, as stored in a PNG `eXIf` chunk.
+    pub exif: Option<Vec<u8>>,
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+impl CarrierMetadata {
+    /// Reads the EXIF attributes and ICC profile of a JPEG, PNG, TIFF or WebP
+    /// image. Missing metadata is not an error.
+    ///
+    /// Only the primary image's attributes are kept: the thumbnail and the
+    /// source's pixel layout tags cannot be carried over to a re-encoded file.
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(Self {
+            exif: Self::read_exif(path)?,
+            icc_profile: Self::read_icc_profile(path)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc_profile.is_none()
+    }
+
+    /// Ancillary PNG chunks carrying the metadata, `iCCP` before `eXIf`.
+    pub fn png_chunks(&self) -> Result<Vec<([u8; 4], Vec<u8>)>> {
+        let mut chunks = Vec::new();
+
+        if let Some(profile) = &self.icc_profile {
+            let mut data = ICC_PROFILE_NAME.to_vec();
+            data.extend_from_slice(&[0, 0]);
+
+            let mut encoder = ZlibEncoder::new(data, Compression::best());
+            encoder.write_all(profile)?;
+            chunks.push((ICC_CHUNK, encoder.finish()?));
+        }
+
+        if let Some(exif) = &self.exif {
+            chunks.push((EXIF_CHUNK, exif.clone()));
+        }
+
+        Ok(chunks)
+    }
+
+    /// Rewrites an uncompressed, strip-based TIFF with the EXIF attributes
+    /// added to its image directory, those of the EXIF and GPS
+    /// sub-directories in directories of their own, and the ICC profile as
+    /// an `InterColorProfile` tag. The TIFF's own tags, such as its layout
+    /// and resolution, take precedence over attributes with the same tag.
+    pub fn apply_to_tiff(&self, tiff: &[u8]) -> Result<Vec<u8>> {
+        let tiff_error = |e: exif::Error| {
+            DeepSceneError::Image(format!("Cannot add metadata to the TIFF output: {}", e))
+        };
+
+        let image = exif::Reader::new()
+            .read_raw(tiff.to_vec())
+            .map_err(tiff_error)?;
+        let carrier = match &self.exif {
+            Some(exif) => Some(
+                exif::Reader::new()
+                    .read_raw(exif.clone())
+                    .map_err(tiff_error)?,
+            ),
+            None => None,
+        };
+
+        let strip_values = |tag| {
+            image
+                .get_field(tag, In::PRIMARY)
+                .and_then(|field| field.value.iter_uint())
+                .map(|values| values.map(|v| v as usize).collect::<Vec<_>>())
+        };
+        let (Some(offsets), Some(lengths)) = (
+            strip_values(Tag::StripOffsets),
+            strip_values(Tag::StripByteCounts),
+        ) else {
+            return Err(DeepSceneError::Image(
+                "Cannot add metadata to the TIFF output: it has no strips".to_string(),
+            ));
+        };
+        let strips = offsets
+            .iter()
+            .zip(&lengths)
+            .map(|(&offset, &length)| tiff.get(offset..offset + length))
+            .collect::<Option<Vec<&[u8]>>>()
+            .ok_or_else(|| {
+                DeepSceneError::Image(
+                    "Cannot add metadata to the TIFF output: a strip lies outside the file"
+                        .to_string(),
+                )
+            })?;
+
+        let own_fields: Vec<&Field> = image
+            .fields()
+            .filter(|field| field.ifd_num == In::PRIMARY)
+            .collect();
+        let icc_field = self.icc_profile.as_ref().map(|profile| Field {
+            tag: Tag(Context::Tiff, TIFF_ICC_TAG),
+            ifd_num: In::PRIMARY,
+            value: Value::Undefined(profile.clone(), 0),
+        });
+
+        let mut writer = Writer::new();
+        for field in &own_fields {
+            writer.push_field(field);
+        }
+        let copied = carrier
+            .iter()
+            .flat_map(|exif| exif.fields())
+            .chain(&icc_field)
+            .filter(|field| {
+                field.ifd_num == In::PRIMARY && !own_fields.iter().any(|own| own.tag == field.tag)
+            });
+        for field in copied {
+            writer.push_field(field);
+        }
+        writer.set_strips(&strips, In::PRIMARY);
+
+        let mut output = Cursor::new(Vec::new());
+        writer
+            .write(&mut output, image.little_endian())
+            .map_err(tiff_error)?;
+        Ok(output.into_inner())
+    }
+
+    fn read_exif(path: &Path) -> Result<Option<Vec<u8>>> {
+        let file = File::open(path)?;
+        let exif = match exif::Reader::new().read_from_container(&mut BufReader::new(file)) {
+            Ok(exif) => exif,
+            Err(exif::Error::NotFound(_)) => return Ok(None),
+            Err(e) => {
+                return Err(DeepSceneError::Image(format!(
+                    "Cannot read EXIF data from '{}': {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        let mut writer = Writer::new();
+        let mut copied = 0;
+        for field in exif.fields() {
+            if field.ifd_num != In::PRIMARY
+                || LAYOUT_TAGS.contains(&field.tag)
+                || field.tag.number() == TIFF_ICC_TAG
+            {
+                continue;
+            }
+            writer.push_field(field);
+            copied += 1;
+        }
+
+        if copied == 0 {
+            return Ok(None);
+        }
+
+        let mut buffer = Cursor::new(Vec::new());
+        writer
+            .write(&mut buffer, exif.little_endian())
+            .map_err(|e| {
+                DeepSceneError::Image(format!(
+                    "Cannot re-encode EXIF data from '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+        Ok(Some(buffer.into_inner()))
+    }
+
+    fn read_icc_profile(path: &Path) -> Result<Option<Vec<u8>>> {
+        let format = image::io::Reader::open(path)?
+            .with_guessed_format()?
+            .format();
+        let file = BufReader::new(File::open(path)?);
+
+        let profile = match format {
+            Some(ImageFormat::Png) => PngDecoder::new(file)?.icc_profile(),
+            Some(ImageFormat::Jpeg) => JpegDecoder::new(file)?.icc_profile(),
+            Some(ImageFormat::Tiff) => TiffDecoder::new(file)?.icc_profile(),
+            Some(ImageFormat::WebP) => WebPDecoder::new(file)?.icc_profile(),
+            _ => None,
+        };
+
+        Ok(profile.filter(|profile| !profile.is_empty()))
+    }
+}
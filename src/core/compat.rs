@@ -0,0 +1,47 @@
+/// A published wire format selectable with `--compat`, decoupled from the
+/// internal DeepScene framing so other tools can interoperate. Compat payloads
+/// carry only the raw file bytes: no compression, encryption, file name or
+/// metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatSpec {
+    /// `plain-lsb` version 1:
+    ///
+    /// - Carrier pixels are visited in row-major order (left to right, top to
+    ///   bottom), and within each pixel the R, G and B channels in that order.
+    ///   Alpha is never used.
+    /// - Each visited channel stores one bit in its least significant bit.
+    /// - Bytes are stored most significant bit first.
+    /// - The stream is a 32-bit big-endian byte length followed by that many
+    ///   payload bytes. There is no magic, version or checksum.
+    PlainLsb,
+}
+
+impl CompatSpec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompatSpec::PlainLsb => "plain-lsb",
+        }
+    }
+
+    /// Bytes of framing stored before the payload.
+    pub fn header_len(&self) -> usize {
+        match self {
+            CompatSpec::PlainLsb => 4,
+        }
+    }
+
+    pub fn build_header(&self, payload_len: u32) -> Vec<u8> {
+        match self {
+            CompatSpec::PlainLsb => payload_len.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// Reads the payload length from a header of `header_len()` bytes.
+    pub fn parse_header(&self, header: &[u8]) -> usize {
+        match self {
+            CompatSpec::PlainLsb => {
+                u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize
+            }
+        }
+    }
+}
@@ -1,23 +1,61 @@
 use crate::core::error::{DeepSceneError, Result};
-use flate2::Compression;
+#[cfg(feature = "parallel-compression")]
+use crate::core::parallel;
 use flate2::read::DeflateDecoder;
-use flate2::write::DeflateEncoder;
-use std::io::{Read, Write};
+use flate2::{Compress, Compression, FlushCompress, Status};
+use std::io::{self, Read};
+
+/// Best case for deflate: a 258-byte match costs at least 2 bits, so output is
+/// never smaller than about 1/1032 of the input.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// Inputs at least this large are deflated in parallel with the
+/// `parallel-compression` feature; below it the chunking costs more ratio
+/// than the threads save time.
+#[cfg(feature = "parallel-compression")]
+const PARALLEL_THRESHOLD: usize = 4 * 1024 * 1024;
+/// Input bytes per independently deflated chunk.
+#[cfg(feature = "parallel-compression")]
+const PARALLEL_CHUNK_LEN: usize = 1024 * 1024;
+
+/// Largest output `decompress` produces, matching the largest file encode
+/// accepts. A crafted payload of a few hundred kilobytes can otherwise
+/// inflate to gigabytes.
+pub const MAX_DECOMPRESSED_LEN: usize = 256 * 1024 * 1024;
 
 pub struct CompressionEngine;
 
 impl CompressionEngine {
+    /// The only compression backend; payloads are raw deflate streams.
+    pub const ALGORITHM: &'static str = "deflate";
+
+    /// Level and chunking used by `compress` in this build.
+    pub fn parameters() -> String {
+        #[cfg(feature = "parallel-compression")]
+        {
+            format!(
+                "raw stream, level {}, {} MiB chunks in parallel from {} MiB",
+                Compression::best().level(),
+                PARALLEL_CHUNK_LEN >> 20,
+                PARALLEL_THRESHOLD >> 20
+            )
+        }
+        #[cfg(not(feature = "parallel-compression"))]
+        {
+            format!("raw stream, level {}", Compression::best().level())
+        }
+    }
+
+    /// Lower bound on the size `compress` can return for `len` input bytes.
+    pub fn min_compressed_len(len: usize) -> usize {
+        len.div_ceil(MAX_DEFLATE_RATIO)
+    }
+
     pub fn compress(data: &[u8]) -> Result<(Vec<u8>, bool)> {
         let original_size = data.len();
 
-        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
-        encoder
-            .write_all(data)
-            .map_err(|e| DeepSceneError::Compression(format!("Failed to compress data: {}", e)))?;
-
-        let compressed = encoder.finish().map_err(|e| {
-            DeepSceneError::Compression(format!("Failed to finalize compression: {}", e))
-        })?;
+        let compressed = Self::deflate(data)
+            .map_err(|e| DeepSceneError::compression("Failed to compress data", e))?;
 
         let compressed_size = compressed.len();
 
@@ -30,14 +68,186 @@ impl CompressionEngine {
         }
     }
 
+    #[cfg(not(feature = "parallel-compression"))]
+    fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        Self::deflate_chunk(data, true)
+    }
+
+    /// Deflates large inputs as independent chunks on the rayon pool. Every
+    /// chunk but the last ends in a sync flush instead of a final block, so
+    /// the concatenation is one ordinary deflate stream and `decompress`
+    /// needs no changes. Each chunk starts with an empty dictionary, which
+    /// costs a little ratio.
+    #[cfg(feature = "parallel-compression")]
+    fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        if data.len() < PARALLEL_THRESHOLD {
+            return Self::deflate_chunk(data, true);
+        }
+
+        let chunks: Vec<&[u8]> = data.chunks(PARALLEL_CHUNK_LEN).collect();
+        let last = chunks.len() - 1;
+        parallel::map_range(chunks.len(), |index| {
+            Self::deflate_chunk(chunks[index], index == last)
+        })
+        .into_iter()
+        .collect::<io::Result<Vec<_>>>()
+        .map(|parts| parts.concat())
+    }
+
+    /// Deflates `data`, ending with the final block when `last` and with a
+    /// byte-aligned sync flush otherwise, so more chunks can follow it.
+    /// `Compress` is driven directly because `DeflateEncoder::flush` can
+    /// return before the sync flush is complete.
+    fn deflate_chunk(data: &[u8], last: bool) -> io::Result<Vec<u8>> {
+        let mut compress = Compress::new(Compression::best(), false);
+        let flush = if last {
+            FlushCompress::Finish
+        } else {
+            FlushCompress::Sync
+        };
+        // Enough for stored blocks, so a single pass usually suffices.
+        let mut output = Vec::with_capacity(data.len() + data.len() / 16_384 * 5 + 64);
+
+        loop {
+            let consumed = compress.total_in() as usize;
+            let status = compress
+                .compress_vec(&data[consumed..], &mut output, flush)
+                .map_err(io::Error::other)?;
+
+            // As with zlib, a flush is complete once it leaves output space
+            // unused.
+            let done = match status {
+                Status::StreamEnd => true,
+                _ => {
+                    !last
+                        && compress.total_in() as usize == data.len()
+                        && output.len() < output.capacity()
+                }
+            };
+            if done {
+                return Ok(output);
+            }
+            output.reserve(output.capacity().max(64));
+        }
+    }
+
     pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
-        let mut decoder = DeflateDecoder::new(data);
+        Self::decompress_with_limit(data, MAX_DECOMPRESSED_LEN)
+    }
+
+    /// Inflates `data`, stopping with an error as soon as the output would
+    /// exceed `limit` bytes rather than after allocating all of it.
+    pub fn decompress_with_limit(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        let mut decoder = DeflateDecoder::new(data).take(limit as u64 + 1);
         let mut result = Vec::new();
 
-        decoder.read_to_end(&mut result).map_err(|e| {
-            DeepSceneError::Compression(format!("Failed to decompress data: {}", e))
-        })?;
+        decoder
+            .read_to_end(&mut result)
+            .map_err(|e| DeepSceneError::compression("Failed to decompress data", e))?;
+
+        if result.len() > limit {
+            return Err(DeepSceneError::Compression(
+                format!("Decompressed data exceeds maximum size ({} bytes)", limit).into(),
+            ));
+        }
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A deflate stream of `chunks` MiB of zeros plus `extra` more, a few
+    /// hundred kilobytes however large it inflates to: each sync-flushed
+    /// chunk is a complete block sequence, so one can be repeated.
+    fn zero_bomb(chunks: usize, extra: usize) -> Vec<u8> {
+        let chunk = CompressionEngine::deflate_chunk(&[0; 1024 * 1024], false).unwrap();
+        let mut stream = chunk.repeat(chunks);
+        stream.extend(CompressionEngine::deflate_chunk(&vec![0; extra], true).unwrap());
+        stream
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = b"deepscene ".repeat(1000);
+        let (compressed, applied) = CompressionEngine::compress(&data).unwrap();
+
+        assert!(applied);
+        assert_eq!(CompressionEngine::decompress(&compressed).unwrap(), data);
+    }
+
+    /// Compressible data spanning several parallel chunks, with a partial
+    /// chunk at the end.
+    fn multi_chunk_payload() -> Vec<u8> {
+        let line = b"2026-10-16 INFO deepscene chunked deflate round trip\n";
+        let mut data = line.repeat((5 * 1024 * 1024 + 1234) / line.len());
+        data.iter_mut()
+            .step_by(4099)
+            .enumerate()
+            .for_each(|(i, byte)| *byte = i as u8);
+        data
+    }
+
+    #[test]
+    fn concatenated_chunks_decompress_as_one_stream() {
+        let data = multi_chunk_payload();
+        let chunks: Vec<&[u8]> = data.chunks(1024 * 1024).collect();
+        let stream: Vec<u8> = chunks
+            .iter()
+            .enumerate()
+            .flat_map(|(index, chunk)| {
+                CompressionEngine::deflate_chunk(chunk, index == chunks.len() - 1).unwrap()
+            })
+            .collect();
+
+        assert_eq!(CompressionEngine::decompress(&stream).unwrap(), data);
+    }
+
+    #[test]
+    fn large_payload_round_trips() {
+        let data = multi_chunk_payload();
+        let (compressed, applied) = CompressionEngine::compress(&data).unwrap();
+
+        assert!(applied);
+        assert_eq!(CompressionEngine::decompress(&compressed).unwrap(), data);
+
+        // One stream without the feature, independent chunks with it.
+        let single = CompressionEngine::deflate_chunk(&data, true).unwrap();
+        assert_eq!(
+            compressed == single,
+            cfg!(not(feature = "parallel-compression"))
+        );
+    }
+
+    #[test]
+    fn limit_is_inclusive() {
+        let stream = zero_bomb(1, 0);
+
+        assert_eq!(
+            CompressionEngine::decompress_with_limit(&stream, 1024 * 1024)
+                .unwrap()
+                .len(),
+            1024 * 1024
+        );
+        assert!(matches!(
+            CompressionEngine::decompress_with_limit(&stream, 1024 * 1024 - 1),
+            Err(DeepSceneError::Compression(_))
+        ));
+    }
+
+    #[test]
+    fn max_decompressed_len_boundary() {
+        let at_limit = CompressionEngine::decompress(&zero_bomb(MAX_DECOMPRESSED_LEN >> 20, 0));
+        assert_eq!(at_limit.unwrap().len(), MAX_DECOMPRESSED_LEN);
+
+        let stream = zero_bomb(MAX_DECOMPRESSED_LEN >> 20, 1);
+        assert!(stream.len() < MAX_DECOMPRESSED_LEN / 500);
+
+        let Err(DeepSceneError::Compression(error)) = CompressionEngine::decompress(&stream) else {
+            panic!("a stream inflating past the limit was accepted");
+        };
+        assert!(error.to_string().contains("exceeds maximum size"));
+    }
+}
@@ -1,29 +1,142 @@
-use crate::core::error::{DeepSceneError, Result};
+use crate::core::error::{DeepSceneError, ErrorContext, Result};
+use crate::core::messages;
 use argon2::{Argon2, PasswordHasher, password_hash::SaltString};
 use chacha20::ChaCha20;
 use chacha20::cipher::{KeyIvInit, StreamCipher};
-use rand::Rng;
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use subtle::ConstantTimeEq;
+
+/// scrypt cost `N = 2^15`, `r = 8`, `p = 1`: 32 MiB per derivation, the
+/// interactive-login setting from the scrypt paper.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const CHECKSUM_LEN: usize = 16;
+/// Salt, nonce and the wrapped data key with its checksum.
+const RECIPIENT_LEN: usize = CryptoEngine::SALT_LEN + NONCE_LEN + CHECKSUM_LEN + KEY_LEN;
+
+/// Password-based key derivation function. Each variant uses fixed cost
+/// parameters, so only its identifier byte is stored with the encrypted data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    /// Argon2id with the crate defaults (19 MiB, 2 passes, 1 lane).
+    #[default]
+    Argon2,
+    Scrypt,
+}
+
+impl KdfAlgorithm {
+    /// Every supported function, default first.
+    pub const ALL: [KdfAlgorithm; 2] = [KdfAlgorithm::Argon2, KdfAlgorithm::Scrypt];
+
+    pub fn id(self) -> u8 {
+        match self {
+            KdfAlgorithm::Argon2 => 0,
+            KdfAlgorithm::Scrypt => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(KdfAlgorithm::Argon2),
+            1 => Ok(KdfAlgorithm::Scrypt),
+            _ => Err(DeepSceneError::Encryption(messages::unknown_kdf(id).into())),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KdfAlgorithm::Argon2 => "Argon2",
+            KdfAlgorithm::Scrypt => "scrypt",
+        }
+    }
+
+    /// The fixed cost parameters, as used by `derive_key`.
+    pub fn parameters(self) -> String {
+        match self {
+            KdfAlgorithm::Argon2 => format!(
+                "Argon2id, m={} KiB, t={}, p={}",
+                argon2::Params::DEFAULT_M_COST,
+                argon2::Params::DEFAULT_T_COST,
+                argon2::Params::DEFAULT_P_COST
+            ),
+            KdfAlgorithm::Scrypt => format!(
+                "N=2^{}, r={}, p={} ({} MiB)",
+                SCRYPT_LOG_N,
+                SCRYPT_R,
+                SCRYPT_P,
+                (128u64 * SCRYPT_R as u64) << SCRYPT_LOG_N >> 20
+            ),
+        }
+    }
+}
 
 pub struct CryptoEngine;
 
 impl CryptoEngine {
-    pub fn derive_key(password: &str, salt: &[u8; 16]) -> Result<[u8; 32]> {
+    /// Stream cipher applied to every encrypted payload.
+    pub const CIPHER: &'static str = "ChaCha20";
+
+    /// Key, nonce and checksum sizes of `CIPHER`.
+    pub fn cipher_parameters() -> String {
+        format!(
+            "{}-bit key, {}-bit nonce, {}-byte BLAKE3 checksum",
+            KEY_LEN * 8,
+            NONCE_LEN * 8,
+            CHECKSUM_LEN
+        )
+    }
+
+    /// Bytes added to the plaintext: KDF identifier, salt, nonce and the BLAKE3
+    /// checksum prefix.
+    pub const OVERHEAD: usize = Self::KDF_ID_LEN + 16 + 12 + 16;
+
+    /// Length of the KDF identifier in front of the salt.
+    pub const KDF_ID_LEN: usize = 1;
+
+    /// Length of the salt and nonce prefix at the start of encrypted data.
+    pub const SALT_NONCE_LEN: usize = Self::SALT_LEN + 12;
+
+    /// Length of the key derivation salt.
+    pub const SALT_LEN: usize = 16;
+
+    /// Most recipients one envelope can hold; the count is stored in a byte.
+    pub const MAX_RECIPIENTS: usize = u8::MAX as usize;
+
+    /// Bytes `encrypt_for_recipients` adds to the plaintext for `recipients`
+    /// passwords: KDF identifier, recipient count, one wrapped key per
+    /// recipient, then the payload nonce and checksum prefix.
+    pub fn envelope_overhead(recipients: usize) -> usize {
+        Self::KDF_ID_LEN + 1 + recipients * RECIPIENT_LEN + NONCE_LEN + CHECKSUM_LEN
+    }
+
+    pub fn derive_key(password: &str, salt: &[u8; 16], kdf: KdfAlgorithm) -> Result<[u8; 32]> {
+        match kdf {
+            KdfAlgorithm::Argon2 => Self::derive_key_argon2(password, salt),
+            KdfAlgorithm::Scrypt => Self::derive_key_scrypt(password, salt),
+        }
+    }
+
+    fn derive_key_argon2(password: &str, salt: &[u8; 16]) -> Result<[u8; 32]> {
         let argon2 = Argon2::default();
-        let salt_string = SaltString::encode_b64(salt)
-            .map_err(|e| DeepSceneError::Encryption(format!("Salt encoding failed: {}", e)))?;
+        let salt_string = SaltString::encode_b64(salt).map_err(|e| {
+            DeepSceneError::Encryption(ErrorContext::with_source("Salt encoding failed", e))
+        })?;
 
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt_string)
-            .map_err(|e| DeepSceneError::Encryption(format!("Key derivation failed: {}", e)))?;
+        let password_hash = argon2.hash_password(password.as_bytes(), &salt_string)?;
 
         let hash = password_hash
             .hash
-            .ok_or_else(|| DeepSceneError::Encryption("Hash generation failed".to_string()))?;
+            .ok_or_else(|| DeepSceneError::Encryption("Hash generation failed".into()))?;
 
         let bytes = hash.as_bytes();
         if bytes.len() < 32 {
             return Err(DeepSceneError::Encryption(
-                "Insufficient hash length".to_string(),
+                "Insufficient hash length".into(),
             ));
         }
 
@@ -32,85 +145,322 @@ impl CryptoEngine {
         Ok(key)
     }
 
-    pub fn encrypt(data: &[u8], password: &str) -> Result<Vec<u8>> {
+    fn derive_key_scrypt(password: &str, salt: &[u8; 16]) -> Result<[u8; 32]> {
+        let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, 32)?;
+
+        let mut key = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)?;
+        Ok(key)
+    }
+
+    /// Builds a deterministic RNG from arbitrary seed material, such as the
+    /// payload carrier noise is derived from.
+    pub fn seeded_rng(seed_material: &[u8]) -> ChaCha20Rng {
+        ChaCha20Rng::from_seed(*blake3::hash(seed_material).as_bytes())
+    }
+
+    pub fn encrypt(data: &[u8], password: &str, kdf: KdfAlgorithm) -> Result<Vec<u8>> {
+        Self::encrypt_with_rng(data, password, kdf, &mut rand::thread_rng())
+    }
+
+    /// Encrypts `data` as `[kdf id][salt][nonce][ciphertext]`.
+    pub fn encrypt_with_rng<R: RngCore + CryptoRng>(
+        data: &[u8],
+        password: &str,
+        kdf: KdfAlgorithm,
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let salt: [u8; 16] = rng.r#gen();
+        let nonce: [u8; 12] = rng.r#gen();
+
+        Self::encrypt_with_salt_nonce(data, password, kdf, &salt, &nonce)
+    }
+
+    /// Encrypts with a caller-supplied salt, such as one managed outside
+    /// DeepScene. The nonce is still random, so payloads sharing a salt and
+    /// password share a key but never a keystream.
+    pub fn encrypt_with_salt<R: RngCore + CryptoRng>(
+        data: &[u8],
+        password: &str,
+        kdf: KdfAlgorithm,
+        salt: &[u8; Self::SALT_LEN],
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let nonce: [u8; 12] = rng.r#gen();
+        Self::encrypt_with_salt_nonce(data, password, kdf, salt, &nonce)
+    }
+
+    fn encrypt_with_salt_nonce(
+        data: &[u8],
+        password: &str,
+        kdf: KdfAlgorithm,
+        salt: &[u8; Self::SALT_LEN],
+        nonce: &[u8; 12],
+    ) -> Result<Vec<u8>> {
         if password.is_empty() {
             return Err(DeepSceneError::Validation(
-                "Encryption password cannot be empty. Please provide a valid password".to_string(),
+                messages::EMPTY_PASSWORD.to_string(),
             ));
         }
 
-        let mut rng = rand::thread_rng();
-        let salt: [u8; 16] = rng.r#gen();
-        let nonce: [u8; 12] = rng.r#gen();
+        let key = Self::derive_key(password, salt, kdf)?;
 
-        let key = Self::derive_key(password, &salt)?;
+        let mut result = vec![kdf.id()];
+        result.extend_from_slice(salt);
+        result.extend_from_slice(nonce);
+        result.extend_from_slice(&Self::seal(&key, nonce, data));
 
-        let checksum = blake3::hash(data);
-        let checksum_bytes = &checksum.as_bytes()[0..16];
+        Ok(result)
+    }
 
-        let mut data_with_checksum = Vec::new();
-        data_with_checksum.extend_from_slice(checksum_bytes);
-        data_with_checksum.extend_from_slice(data);
+    /// Envelope encryption for several recipients: the data is encrypted once
+    /// under a random data key, and that key is wrapped under a key derived
+    /// from each password with its own salt. Any one password decrypts.
+    ///
+    /// Layout: `[kdf id][count]`, then per recipient `[salt][nonce][wrapped
+    /// key and checksum]`, then `[nonce][ciphertext]`.
+    pub fn encrypt_for_recipients<R: RngCore + CryptoRng>(
+        data: &[u8],
+        passwords: &[String],
+        kdf: KdfAlgorithm,
+        rng: &mut R,
+    ) -> Result<Vec<u8>> {
+        if passwords.is_empty() || passwords.len() > Self::MAX_RECIPIENTS {
+            return Err(DeepSceneError::Validation(format!(
+                "Between 1 and {} recipient passwords are required, but {} were given",
+                Self::MAX_RECIPIENTS,
+                passwords.len()
+            )));
+        }
 
-        let mut cipher = ChaCha20::new(&key.into(), &nonce.into());
-        let mut encrypted = data_with_checksum;
-        cipher.apply_keystream(&mut encrypted);
+        if passwords.iter().any(|p| p.is_empty()) {
+            return Err(DeepSceneError::Validation(
+                messages::EMPTY_PASSWORD.to_string(),
+            ));
+        }
+
+        let data_key: [u8; KEY_LEN] = rng.r#gen();
 
-        let mut result = Vec::new();
-        result.extend_from_slice(&salt);
+        let mut result = vec![kdf.id(), passwords.len() as u8];
+        for password in passwords {
+            let salt: [u8; Self::SALT_LEN] = rng.r#gen();
+            let nonce: [u8; NONCE_LEN] = rng.r#gen();
+            let key_encryption_key = Self::derive_key(password, &salt, kdf)?;
+
+            result.extend_from_slice(&salt);
+            result.extend_from_slice(&nonce);
+            result.extend_from_slice(&Self::seal(&key_encryption_key, &nonce, &data_key));
+        }
+
+        let nonce: [u8; NONCE_LEN] = rng.r#gen();
         result.extend_from_slice(&nonce);
-        result.extend_from_slice(&encrypted);
+        result.extend_from_slice(&Self::seal(&data_key, &nonce, data));
 
         Ok(result)
     }
 
+    /// Decrypts data written by `encrypt_for_recipients`, trying `password`
+    /// against each wrapped key in turn.
+    pub fn decrypt_envelope(data: &[u8], password: &str) -> Result<Vec<u8>> {
+        if password.is_empty() {
+            return Err(DeepSceneError::Validation(
+                messages::EMPTY_PASSWORD.to_string(),
+            ));
+        }
+
+        let corrupted = || DeepSceneError::Data(messages::CORRUPTED_ENCRYPTED_DATA.to_string());
+        let [kdf_id, count, rest @ ..] = data else {
+            return Err(corrupted());
+        };
+        let kdf = KdfAlgorithm::from_id(*kdf_id)?;
+        let count = *count as usize;
+
+        if count == 0 || data.len() < Self::envelope_overhead(count) {
+            return Err(corrupted());
+        }
+        let (recipients, payload) = rest.split_at(count * RECIPIENT_LEN);
+
+        for recipient in recipients.chunks_exact(RECIPIENT_LEN) {
+            let (salt, rest) = recipient.split_at(Self::SALT_LEN);
+            let (nonce, wrapped) = rest.split_at(NONCE_LEN);
+            let salt: &[u8; Self::SALT_LEN] = salt.try_into().map_err(|_| corrupted())?;
+            let nonce: &[u8; NONCE_LEN] = nonce.try_into().map_err(|_| corrupted())?;
+
+            let key_encryption_key = Self::derive_key(password, salt, kdf)?;
+            let Some(data_key) = Self::open(&key_encryption_key, nonce, wrapped) else {
+                continue;
+            };
+            let data_key: [u8; KEY_LEN] = data_key.try_into().map_err(|_| corrupted())?;
+
+            // The password matched a recipient, so a mismatch now means the
+            // ciphertext itself was altered.
+            let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+            let nonce: &[u8; NONCE_LEN] = nonce.try_into().map_err(|_| corrupted())?;
+            return Self::open(&data_key, nonce, ciphertext).ok_or_else(corrupted);
+        }
+
+        Err(DeepSceneError::WrongPassword(format!(
+            "Authentication failed: the password matches none of the {} recipients",
+            count
+        )))
+    }
+
+    /// Encrypts `data` prefixed with the first bytes of its BLAKE3 hash.
+    fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], data: &[u8]) -> Vec<u8> {
+        let checksum = blake3::hash(data);
+
+        let mut encrypted = Vec::with_capacity(CHECKSUM_LEN + data.len());
+        encrypted.extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN]);
+        encrypted.extend_from_slice(data);
+
+        ChaCha20::new(key.into(), nonce.into()).apply_keystream(&mut encrypted);
+        encrypted
+    }
+
+    /// Reverses `seal`, or returns `None` when the checksum does not match,
+    /// i.e. the key is wrong or the data was altered.
+    fn open(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], encrypted: &[u8]) -> Option<Vec<u8>> {
+        if encrypted.len() < CHECKSUM_LEN {
+            return None;
+        }
+
+        let mut decrypted = encrypted.to_vec();
+        ChaCha20::new(key.into(), nonce.into()).apply_keystream(&mut decrypted);
+
+        let (stored_checksum, data) = decrypted.split_at(CHECKSUM_LEN);
+        let computed_checksum = blake3::hash(data);
+
+        // Every password reaches this point after the same derive and decrypt
+        // work, and the comparison itself must not exit early on the first
+        // mismatching byte.
+        bool::from(stored_checksum.ct_eq(&computed_checksum.as_bytes()[..CHECKSUM_LEN]))
+            .then(|| data.to_vec())
+    }
+
+    /// Decrypts data written by `encrypt`, using the KDF named by its first byte.
     pub fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>> {
+        let (&kdf_id, rest) = data
+            .split_first()
+            .ok_or_else(|| DeepSceneError::Data(messages::CORRUPTED_ENCRYPTED_DATA.to_string()))?;
+
+        Self::decrypt_with_kdf(rest, password, KdfAlgorithm::from_id(kdf_id)?)
+    }
+
+    /// Decrypts data from before the KDF identifier was stored, which always
+    /// used Argon2.
+    pub fn decrypt_untagged(data: &[u8], password: &str) -> Result<Vec<u8>> {
+        Self::decrypt_with_kdf(data, password, KdfAlgorithm::Argon2)
+    }
+
+    fn decrypt_with_kdf(data: &[u8], password: &str, kdf: KdfAlgorithm) -> Result<Vec<u8>> {
         if password.is_empty() {
             return Err(DeepSceneError::Validation(
-                "Encryption password cannot be empty. Please provide a valid password".to_string(),
+                messages::EMPTY_PASSWORD.to_string(),
             ));
         }
 
-        if data.len() < 16 + 12 + 16 {
-            return Err(DeepSceneError::Encryption(
-                "Corrupted encrypted data".to_string(),
+        if data.len() < Self::OVERHEAD - Self::KDF_ID_LEN {
+            return Err(DeepSceneError::Data(
+                messages::CORRUPTED_ENCRYPTED_DATA.to_string(),
             ));
         }
 
         let salt: [u8; 16] = data[0..16]
             .try_into()
-            .map_err(|_| DeepSceneError::Encryption("Invalid salt".to_string()))?;
+            .map_err(|_| DeepSceneError::Data(messages::INVALID_SALT.to_string()))?;
 
         let nonce: [u8; 12] = data[16..28]
             .try_into()
-            .map_err(|_| DeepSceneError::Encryption("Invalid nonce".to_string()))?;
+            .map_err(|_| DeepSceneError::Data(messages::INVALID_NONCE.to_string()))?;
 
-        let encrypted = &data[28..];
+        let encrypted = &data[Self::SALT_NONCE_LEN..];
 
-        let key = Self::derive_key(password, &salt)?;
+        let key = Self::derive_key(password, &salt, kdf)?;
 
-        let mut cipher = ChaCha20::new(&key.into(), &nonce.into());
-        let mut decrypted = encrypted.to_vec();
-        cipher.apply_keystream(&mut decrypted);
+        Self::open(&key, &nonce, encrypted).ok_or_else(|| {
+            DeepSceneError::WrongPassword(messages::AUTHENTICATION_FAILED.to_string())
+        })
+    }
+}
 
-        if decrypted.len() < 16 {
-            return Err(DeepSceneError::Encryption(
-                "Authentication failed".to_string(),
-            ));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let stored_checksum = &decrypted[0..16];
-        let actual_data = &decrypted[16..];
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [9; NONCE_LEN];
 
-        let computed_checksum = blake3::hash(actual_data);
-        let computed_checksum_bytes = &computed_checksum.as_bytes()[0..16];
+    #[test]
+    fn open_accepts_sealed_data() {
+        let sealed = CryptoEngine::seal(&KEY, &NONCE, b"payload");
+        assert_eq!(
+            CryptoEngine::open(&KEY, &NONCE, &sealed).as_deref(),
+            Some(&b"payload"[..])
+        );
+    }
 
-        if stored_checksum != computed_checksum_bytes {
-            return Err(DeepSceneError::Encryption(
-                "Authentication failed".to_string(),
-            ));
+    #[test]
+    fn open_rejects_a_mismatch_in_any_checksum_byte() {
+        let sealed = CryptoEngine::seal(&KEY, &NONCE, b"payload");
+
+        // A comparison that stopped at the first differing byte would still
+        // reject these; this pins that every position takes part in it.
+        for index in 0..CHECKSUM_LEN {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 0x01;
+            assert!(CryptoEngine::open(&KEY, &NONCE, &tampered).is_none());
         }
+    }
 
-        Ok(actual_data.to_vec())
+    #[test]
+    fn open_rejects_a_wrong_key() {
+        let sealed = CryptoEngine::seal(&KEY, &NONCE, b"payload");
+        assert!(CryptoEngine::open(&[8; KEY_LEN], &NONCE, &sealed).is_none());
+    }
+
+    #[test]
+    fn wrong_password_runs_the_full_decrypt_path() {
+        let encrypted = CryptoEngine::encrypt_with_rng(
+            b"payload",
+            "correct horse",
+            KdfAlgorithm::Argon2,
+            &mut CryptoEngine::seeded_rng(b"seed"),
+        )
+        .unwrap();
+
+        // Reaching the checksum comparison is the only way to fail with
+        // `WrongPassword`; the length and format checks raise other errors.
+        assert!(matches!(
+            CryptoEngine::decrypt(&encrypted, "battery staple"),
+            Err(DeepSceneError::WrongPassword(message)) if message == messages::AUTHENTICATION_FAILED
+        ));
+        assert_eq!(
+            CryptoEngine::decrypt(&encrypted, "correct horse").unwrap(),
+            b"payload"
+        );
+    }
+
+    #[test]
+    fn every_kdf_round_trips_for_several_recipients() {
+        let passwords = ["first".to_string(), "second".to_string()];
+        for kdf in KdfAlgorithm::ALL {
+            let mut rng = CryptoEngine::seeded_rng(kdf.name().as_bytes());
+            let envelope =
+                CryptoEngine::encrypt_for_recipients(b"payload", &passwords, kdf, &mut rng)
+                    .unwrap();
+
+            assert_eq!(envelope[0], kdf.id());
+            assert_eq!(
+                envelope.len(),
+                b"payload".len() + CryptoEngine::envelope_overhead(passwords.len())
+            );
+            // The last wrapped key is only reached after the first fails.
+            assert_eq!(
+                CryptoEngine::decrypt_envelope(&envelope, "second").unwrap(),
+                b"payload",
+                "{}",
+                kdf.name()
+            );
+        }
     }
 }
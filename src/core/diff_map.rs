@@ -0,0 +1,75 @@
+use crate::core::error::{DeepSceneError, Result};
+use crate::core::parallel;
+use image::{RgbImage, RgbaImage};
+
+const CHANNELS: usize = 3;
+
+/// What LSB embedding would have changed in an image, found without the
+/// original: clearing one bit plane of R, G and B gives the "clean" carrier,
+/// and the bits cleared are the difference. Natural photos show their
+/// structure in the low planes, while an embedded payload or carrier noise
+/// shows up as uniform noise.
+pub struct DiffMap;
+
+impl DiffMap {
+    /// The image with bit `plane` of every R, G and B value cleared. Alpha is
+    /// kept, since it never carries data.
+    pub fn clean(image: &RgbaImage, plane: u8) -> RgbaImage {
+        let mask = !(1u8 << plane);
+        let mut clean = image.clone();
+        for pixel in clean.pixels_mut() {
+            for value in &mut pixel.0[..CHANNELS] {
+                *value &= mask;
+            }
+        }
+        clean
+    }
+
+    /// Renders bit `plane` of each channel at full intensity in that channel:
+    /// a pixel is white where all three bits are set and black where none
+    /// is, which is exactly the image minus its clean version, scaled up.
+    pub fn render(image: &RgbaImage, plane: u8) -> Result<RgbImage> {
+        let pixels: &[u8] = image;
+        let rows = parallel::map_range(image.height() as usize, |y| {
+            let row_len = image.width() as usize * 4;
+            pixels[y * row_len..(y + 1) * row_len]
+                .chunks(4)
+                .flat_map(|pixel| {
+                    let mut rgb = [0u8; CHANNELS];
+                    for (out, &value) in rgb.iter_mut().zip(pixel) {
+                        *out = if (value >> plane) & 1 == 1 {
+                            u8::MAX
+                        } else {
+                            0
+                        };
+                    }
+                    rgb
+                })
+                .collect::<Vec<u8>>()
+        });
+
+        RgbImage::from_raw(image.width(), image.height(), rows.concat()).ok_or_else(|| {
+            DeepSceneError::Image("Failed to construct difference map image".to_string())
+        })
+    }
+
+    /// Share of R, G and B values with bit `plane` set. Random data, and so
+    /// an encrypted or compressed payload, sets about half of them.
+    pub fn set_ratio(image: &RgbaImage, plane: u8) -> f64 {
+        let slots = image.width() as usize * image.height() as usize * CHANNELS;
+        if slots == 0 {
+            return 0.0;
+        }
+
+        let set: usize = image
+            .pixels()
+            .map(|p| {
+                p.0[..CHANNELS]
+                    .iter()
+                    .filter(|&&v| (v >> plane) & 1 == 1)
+                    .count()
+            })
+            .sum();
+        set as f64 / slots as f64
+    }
+}
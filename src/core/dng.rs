@@ -0,0 +1,337 @@
+use crate::core::error::{DeepSceneError, Result};
+use image::RgbaImage;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use tiff::decoder::{ChunkType, Decoder};
+use tiff::tags::Tag;
+use tiff::{ColorType, TiffError};
+
+/// DNGVersion, present in the first IFD of every DNG.
+const DNG_VERSION_TAG: u16 = 50706;
+/// Offsets of the child IFDs holding the raw image and previews.
+const SUB_IFDS_TAG: u16 = 330;
+/// RawImageDigest and NewRawImageDigest: MD5 sums of the raw pixels that
+/// readers check, and embedding would invalidate.
+const RAW_DIGEST_TAGS: [u16; 2] = [50721, 51111];
+/// Byte offset of the first IFD offset in a classic TIFF header.
+const FIRST_IFD_FIELD: Range<usize> = 4..8;
+
+/// The full-resolution image of a DNG, located among the first IFD and its
+/// SubIFDs. Its pixels are uncompressed, so embedding rewrites them in place
+/// and every other byte of the file, including thumbnails, previews and
+/// camera metadata, is kept as it was.
+#[derive(Debug, Clone)]
+pub struct DngImage {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per sample: 1 or 2.
+    sample_len: usize,
+    little_endian: bool,
+    /// Byte range of the pixels in each strip, top to bottom.
+    strips: Vec<Range<usize>>,
+}
+
+impl DngImage {
+    pub fn is_dng_path(path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("dng"))
+    }
+
+    /// Whether `bytes` are a TIFF whose first IFD carries a DNG version.
+    pub fn is_dng(bytes: &[u8]) -> bool {
+        Decoder::new(Cursor::new(bytes))
+            .and_then(|mut decoder| decoder.find_tag(Tag::Unknown(DNG_VERSION_TAG)))
+            .is_ok_and(|version| version.is_some())
+    }
+
+    /// Locates the primary image of the DNG in `bytes`: the IFD whose
+    /// NewSubfileType is 0. Only uncompressed, strip-based RGB images with 8
+    /// or 16 bits per sample can be rewritten in place; anything else is an
+    /// error rather than a silently re-encoded file.
+    pub fn read(bytes: &[u8], source: &Path) -> Result<Self> {
+        let read_error = |e: TiffError| {
+            DeepSceneError::Image(format!(
+                "Failed to read the DNG structure of '{}': {}",
+                source.display(),
+                e
+            ))
+        };
+
+        let mut decoder = Decoder::new(Cursor::new(bytes)).map_err(read_error)?;
+        if decoder
+            .find_tag(Tag::Unknown(DNG_VERSION_TAG))
+            .map_err(read_error)?
+            .is_none()
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "'{}' is a TIFF without a DNG version tag, not a DNG",
+                source.display()
+            )));
+        }
+
+        let little_endian = bytes.starts_with(b"II");
+        let classic =
+            bytes.len() >= FIRST_IFD_FIELD.end && Self::read_u16(&bytes[2..4], little_endian) == 42;
+        if !classic {
+            return Err(DeepSceneError::Image(format!(
+                "'{}' is a BigTIFF, which DNG does not allow",
+                source.display()
+            )));
+        }
+
+        for tag in RAW_DIGEST_TAGS {
+            if decoder
+                .find_tag(Tag::Unknown(tag))
+                .map_err(read_error)?
+                .is_some()
+            {
+                return Err(DeepSceneError::Image(format!(
+                    "'{}' records an MD5 digest of its raw pixels (tag {}), which embedding would invalidate, so the DNG cannot be safely rewritten",
+                    source.display(),
+                    tag
+                )));
+            }
+        }
+
+        let first_ifd = Self::read_u32(&bytes[FIRST_IFD_FIELD], little_endian);
+        let mut ifds = vec![u64::from(first_ifd)];
+        ifds.extend(
+            decoder
+                .find_tag_unsigned_vec::<u64>(Tag::Unknown(SUB_IFDS_TAG))
+                .map_err(read_error)?
+                .unwrap_or_default(),
+        );
+
+        let mut unreadable = None;
+        for ifd in ifds {
+            let reader = RedirectedTiff::new(bytes, ifd, little_endian);
+            let mut decoder = match Decoder::new(reader) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    unreadable.get_or_insert(e);
+                    continue;
+                }
+            };
+
+            let subfile_type = decoder
+                .find_tag_unsigned::<u32>(Tag::NewSubfileType)
+                .map_err(read_error)?
+                .unwrap_or(0);
+            if subfile_type == 0 {
+                return Self::from_primary(&mut decoder, bytes.len(), little_endian, source);
+            }
+        }
+
+        Err(DeepSceneError::Image(match unreadable {
+            Some(e) => format!(
+                "'{}' has no primary image the TIFF decoder can read ({}). Only uncompressed RGB DNGs can carry data; raw mosaic (CFA) and linear raw DNGs cannot",
+                source.display(),
+                e
+            ),
+            None => format!(
+                "'{}' has no primary (full-resolution) image",
+                source.display()
+            ),
+        }))
+    }
+
+    /// Checks the primary image's layout and collects its strip ranges.
+    fn from_primary<R: Read + Seek>(
+        decoder: &mut Decoder<R>,
+        file_len: usize,
+        little_endian: bool,
+        source: &Path,
+    ) -> Result<Self> {
+        let read_error = |e: TiffError| {
+            DeepSceneError::Image(format!(
+                "Failed to read the DNG structure of '{}': {}",
+                source.display(),
+                e
+            ))
+        };
+        let unsupported = |layout: String| {
+            DeepSceneError::Image(format!(
+                "The primary image of '{}' {}, so it cannot be rewritten in place. Only uncompressed, strip-based RGB images with 8 or 16 bits per sample can carry data",
+                source.display(),
+                layout
+            ))
+        };
+
+        let compression = decoder
+            .find_tag_unsigned::<u16>(Tag::Compression)
+            .map_err(read_error)?
+            .unwrap_or(1);
+        if compression != 1 {
+            return Err(unsupported(format!(
+                "is compressed (scheme {})",
+                compression
+            )));
+        }
+        if decoder.get_chunk_type() != ChunkType::Strip {
+            return Err(unsupported("is stored in tiles".to_string()));
+        }
+        let planar = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+            .map_err(read_error)?
+            .unwrap_or(1);
+        if planar != 1 {
+            return Err(unsupported(
+                "stores each channel in a separate plane".to_string(),
+            ));
+        }
+        let integer_samples = decoder
+            .find_tag_unsigned_vec::<u16>(Tag::SampleFormat)
+            .map_err(read_error)?
+            .is_none_or(|formats| formats.iter().all(|&f| f == 1));
+        if !integer_samples {
+            return Err(unsupported(
+                "has signed or floating-point samples".to_string(),
+            ));
+        }
+        let sample_len = match decoder.colortype().map_err(read_error)? {
+            ColorType::RGB(8) => 1,
+            ColorType::RGB(16) => 2,
+            other => return Err(unsupported(format!("has colour type {:?}", other))),
+        };
+
+        let (width, height) = decoder.dimensions().map_err(read_error)?;
+        let rows_per_strip = decoder.chunk_dimensions().1.clamp(1, height);
+        let offsets = decoder
+            .get_tag_u64_vec(Tag::StripOffsets)
+            .map_err(read_error)?;
+        let byte_counts = decoder
+            .get_tag_u64_vec(Tag::StripByteCounts)
+            .map_err(read_error)?;
+        let strip_count = height.div_ceil(rows_per_strip) as usize;
+        if offsets.len() != strip_count || byte_counts.len() != strip_count {
+            return Err(unsupported(format!(
+                "lists {} strip offsets and {} byte counts for {} strips",
+                offsets.len(),
+                byte_counts.len(),
+                strip_count
+            )));
+        }
+
+        let row_len = width as usize * 3 * sample_len;
+        let mut strips = Vec::with_capacity(strip_count);
+        for (index, (&offset, &byte_count)) in offsets.iter().zip(&byte_counts).enumerate() {
+            let first_row = index as u32 * rows_per_strip;
+            let len = rows_per_strip.min(height - first_row) as usize * row_len;
+            let start = usize::try_from(offset).unwrap_or(usize::MAX);
+            let in_file = start >= FIRST_IFD_FIELD.end
+                && start.checked_add(len).is_some_and(|end| end <= file_len);
+            if byte_count < len as u64 || !in_file {
+                return Err(unsupported(format!(
+                    "has strip {} outside the file or shorter than its rows",
+                    index
+                )));
+            }
+            strips.push(start..start + len);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            sample_len,
+            little_endian,
+            strips,
+        })
+    }
+
+    /// Decodes the pixels from `bytes`, the file this image was read from.
+    /// 16-bit samples are read by their high byte, so the payload changes
+    /// them by the same fraction of their range as an 8-bit carrier.
+    pub fn to_rgba(&self, bytes: &[u8]) -> Result<RgbaImage> {
+        let mut rgba = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for (index, position) in self.sample_positions().enumerate() {
+            rgba.push(bytes[position]);
+            if index % 3 == 2 {
+                rgba.push(u8::MAX);
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, rgba).ok_or_else(|| {
+            DeepSceneError::Image("Failed to construct image from DNG strips".to_string())
+        })
+    }
+
+    /// Writes the R, G and B values of `image` back into `bytes`, the file
+    /// this image was read from. The low byte of 16-bit samples is kept.
+    pub fn write_pixels(&self, bytes: &mut [u8], image: &RgbaImage) {
+        let values = image.pixels().flat_map(|p| [p[0], p[1], p[2]]);
+        for (position, value) in self.sample_positions().zip(values) {
+            bytes[position] = value;
+        }
+    }
+
+    /// File offset of the (high) byte of every sample, in pixel order.
+    fn sample_positions(&self) -> impl Iterator<Item = usize> + '_ {
+        let high_byte = usize::from(self.sample_len == 2 && self.little_endian);
+        self.strips
+            .iter()
+            .flat_map(|strip| strip.clone().step_by(self.sample_len))
+            .map(move |position| position + high_byte)
+    }
+
+    fn read_u16(bytes: &[u8], little_endian: bool) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn read_u32(bytes: &[u8], little_endian: bool) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+}
+
+/// Reads a TIFF as if its header pointed at another IFD. The tiff decoder
+/// only follows the main IFD chain, so this is how it opens a SubIFD.
+struct RedirectedTiff<'a> {
+    cursor: Cursor<&'a [u8]>,
+    first_ifd: [u8; 4],
+}
+
+impl<'a> RedirectedTiff<'a> {
+    fn new(bytes: &'a [u8], ifd: u64, little_endian: bool) -> Self {
+        let ifd = u32::try_from(ifd).unwrap_or(u32::MAX);
+        Self {
+            cursor: Cursor::new(bytes),
+            first_ifd: if little_endian {
+                ifd.to_le_bytes()
+            } else {
+                ifd.to_be_bytes()
+            },
+        }
+    }
+}
+
+impl Read for RedirectedTiff<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = self.cursor.position() as usize;
+        let read = self.cursor.read(buf)?;
+        for (index, byte) in buf[..read].iter_mut().enumerate() {
+            if let Some(field) = (start + index).checked_sub(FIRST_IFD_FIELD.start)
+                && field < self.first_ifd.len()
+            {
+                *byte = self.first_ifd[field];
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Seek for RedirectedTiff<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor.seek(pos)
+    }
+}
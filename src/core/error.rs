@@ -1,13 +1,132 @@
+use crate::core::messages;
+use std::error::Error;
 use std::fmt;
 
+/// A message describing what failed, plus the underlying error it came from,
+/// if any, exposed through `Error::source`. Displays as `message: source`.
+#[derive(Debug)]
+pub struct ErrorContext {
+    message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl ErrorContext {
+    pub fn with_source(
+        message: impl Into<String>,
+        source: impl Error + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {}", self.message, source),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl From<String> for ErrorContext {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            source: None,
+        }
+    }
+}
+
+impl From<&str> for ErrorContext {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
 #[derive(Debug)]
 pub enum DeepSceneError {
     Io(std::io::Error),
     Image(String),
-    Encryption(String),
-    Compression(String),
+    Encryption(ErrorContext),
+    Compression(ErrorContext),
     Validation(String),
     Data(String),
+    /// The password did not authenticate the encrypted data.
+    WrongPassword(String),
+    /// The data is encrypted but no password was given.
+    PasswordRequired(String),
+    /// The image carries no embedded payload.
+    NoData(String),
+    /// The payload does not fit the carrier.
+    CapacityExceeded(String),
+    /// The payload was written by a newer format version than this build reads.
+    UnsupportedVersion(String),
+    /// The payload's advisory expiry has passed and expiry is enforced.
+    Expired(String),
+}
+
+/// Machine-readable classification of a `DeepSceneError`, stable across
+/// message wording changes. Obtain it with `DeepSceneError::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Io,
+    Image,
+    InvalidInput,
+    Encryption,
+    Compression,
+    Corrupted,
+    WrongPassword,
+    PasswordRequired,
+    NoData,
+    CapacityExceeded,
+    UnsupportedVersion,
+    Expired,
+}
+
+impl ErrorKind {
+    /// Snake-case identifier for logs and structured output.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Image => "image",
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::Encryption => "encryption",
+            ErrorKind::Compression => "compression",
+            ErrorKind::Corrupted => "corrupted",
+            ErrorKind::WrongPassword => "wrong_password",
+            ErrorKind::PasswordRequired => "password_required",
+            ErrorKind::NoData => "no_data",
+            ErrorKind::CapacityExceeded => "capacity_exceeded",
+            ErrorKind::UnsupportedVersion => "unsupported_version",
+            ErrorKind::Expired => "expired",
+        }
+    }
+}
+
+impl DeepSceneError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DeepSceneError::Io(_) => ErrorKind::Io,
+            DeepSceneError::Image(_) => ErrorKind::Image,
+            DeepSceneError::Encryption(_) => ErrorKind::Encryption,
+            DeepSceneError::Compression(_) => ErrorKind::Compression,
+            DeepSceneError::Validation(_) => ErrorKind::InvalidInput,
+            DeepSceneError::Data(_) => ErrorKind::Corrupted,
+            DeepSceneError::WrongPassword(_) => ErrorKind::WrongPassword,
+            DeepSceneError::PasswordRequired(_) => ErrorKind::PasswordRequired,
+            DeepSceneError::NoData(_) => ErrorKind::NoData,
+            DeepSceneError::CapacityExceeded(_) => ErrorKind::CapacityExceeded,
+            DeepSceneError::UnsupportedVersion(_) => ErrorKind::UnsupportedVersion,
+            DeepSceneError::Expired(_) => ErrorKind::Expired,
+        }
+    }
 }
 
 impl fmt::Display for DeepSceneError {
@@ -16,14 +135,39 @@ impl fmt::Display for DeepSceneError {
             DeepSceneError::Io(e) => write!(f, "IO error: {}", e),
             DeepSceneError::Image(e) => write!(f, "Image error: {}", e),
             DeepSceneError::Encryption(e) => write!(f, "Encryption error: {}", e),
+            DeepSceneError::WrongPassword(e) => write!(f, "Encryption error: {}", e),
             DeepSceneError::Compression(e) => write!(f, "Compression error: {}", e),
-            DeepSceneError::Validation(e) => write!(f, "Validation error: {}", e),
-            DeepSceneError::Data(e) => write!(f, "Data error: {}", e),
+            DeepSceneError::Validation(e)
+            | DeepSceneError::PasswordRequired(e)
+            | DeepSceneError::CapacityExceeded(e)
+            | DeepSceneError::Expired(e) => write!(f, "Validation error: {}", e),
+            DeepSceneError::Data(e)
+            | DeepSceneError::NoData(e)
+            | DeepSceneError::UnsupportedVersion(e) => write!(f, "Data error: {}", e),
         }
     }
 }
 
-impl std::error::Error for DeepSceneError {}
+impl DeepSceneError {
+    /// Wraps a failure from the DEFLATE stream, which flate2 reports as
+    /// `io::Error`; `From<io::Error>` is reserved for real I/O failures.
+    pub fn compression(message: impl Into<String>, source: std::io::Error) -> Self {
+        DeepSceneError::Compression(ErrorContext::with_source(message, source))
+    }
+}
+
+impl Error for DeepSceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DeepSceneError::Io(e) => Some(e),
+            DeepSceneError::Encryption(context) | DeepSceneError::Compression(context) => context
+                .source
+                .as_deref()
+                .map(|source| source as &(dyn Error + 'static)),
+            _ => None,
+        }
+    }
+}
 
 impl From<std::io::Error> for DeepSceneError {
     fn from(err: std::io::Error) -> Self {
@@ -31,6 +175,30 @@ impl From<std::io::Error> for DeepSceneError {
     }
 }
 
+impl From<argon2::password_hash::Error> for DeepSceneError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        DeepSceneError::Encryption(ErrorContext::with_source(
+            messages::KEY_DERIVATION_FAILED,
+            err,
+        ))
+    }
+}
+
+impl From<scrypt::errors::InvalidParams> for DeepSceneError {
+    fn from(err: scrypt::errors::InvalidParams) -> Self {
+        DeepSceneError::Encryption(ErrorContext::with_source("Invalid scrypt parameters", err))
+    }
+}
+
+impl From<scrypt::errors::InvalidOutputLen> for DeepSceneError {
+    fn from(err: scrypt::errors::InvalidOutputLen) -> Self {
+        DeepSceneError::Encryption(ErrorContext::with_source(
+            messages::KEY_DERIVATION_FAILED,
+            err,
+        ))
+    }
+}
+
 impl From<image::ImageError> for DeepSceneError {
     fn from(err: image::ImageError) -> Self {
         DeepSceneError::Image(err.to_string())
@@ -0,0 +1,736 @@
+use crate::core::error::{DeepSceneError, Result};
+use crate::core::steganography::{
+    CHANNEL_BITS_VERSION, CHANNEL_COPIES_VERSION, EmbedParams, FORMAT_VERSION, MAX_BIT_PLANE,
+    MAX_CHANNEL_BITS, PRESERVED_REGION_VERSION, STRIDE_VERSION, TIFF_STRIP_VERSION,
+    VARINT_LENGTH_VERSION,
+};
+
+pub(crate) const HEADER_MAGIC: &[u8; 4] = b"DPSV";
+pub(crate) const LEGACY_HEADER_MAGIC: &[u8; 4] = b"DPSN";
+/// 15 bytes fill exactly 5 groups, so the payload starts group-aligned.
+pub(crate) const HEADER_LEN: usize = 15;
+const PRE_STRIDE_HEADER_LEN: usize = 12;
+pub(crate) const LEGACY_HEADER_LEN: usize = 10;
+const MAX_DATA_LENGTH: usize = 256 * 1024 * 1024;
+
+/// Layout flag: R, G and B each hold a full copy of the header and payload.
+const CHANNEL_COPIES_FLAG: u8 = 0x01;
+/// Layout flag: a region record follows the header and the payload skips the
+/// pixels it covers.
+const PRESERVED_REGION_FLAG: u8 = 0x02;
+/// Layout flag: the header starts a strip of rows, is followed by a region
+/// record of those rows, and the payload stays within them.
+const TIFF_STRIP_FLAG: u8 = 0x04;
+/// Layout flag: an allocation byte follows the length field and the payload
+/// uses the low bits of each channel in the counts it gives.
+const CHANNEL_BITS_FLAG: u8 = 0x08;
+
+/// Offset of the length field in headers since `STRIDE_VERSION`.
+const LENGTH_OFFSET: usize = 9;
+const HEADER_CHECKSUM_LEN: usize = 2;
+const CHANNEL_BITS_LEN: usize = 1;
+/// Longest LEB128 encoding of a u64.
+const MAX_VARINT_LEN: usize = 10;
+pub(crate) const MAX_HEADER_LEN: usize =
+    LENGTH_OFFSET + MAX_VARINT_LEN + CHANNEL_BITS_LEN + HEADER_CHECKSUM_LEN;
+
+/// The header that opens every DeepScene payload, independent of where in
+/// the image it is stored. The magic and checksum are not kept: `to_bytes`
+/// derives them and `from_bytes` checks them.
+///
+/// Fields a format version has no room for take their neutral value: version
+/// 1 (`DPSN`) stores only the length, and versions before `STRIDE_VERSION`
+/// have no flags or stride.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    /// Plane the payload is written in. The header itself is always in plane 0.
+    pub bit_plane: u8,
+    /// Layout flags selecting per-channel copies, a preserved region, a
+    /// TIFF strip or per-channel bit depths.
+    pub flags: u8,
+    pub stride: u16,
+    /// Payload bytes, not counting chunk CRCs.
+    pub length: u64,
+    /// Low bits used in R, G and B, present exactly when the channel bits
+    /// flag is set.
+    pub channel_bits: Option<[u8; 3]>,
+}
+
+impl Header {
+    /// Header for `length` payload bytes in the version and layout `params`
+    /// select. Callers must already have rejected options the version lacks.
+    pub fn for_params(params: &EmbedParams, length: usize) -> Self {
+        let version = params.version();
+        if version == 1 {
+            return Self::legacy(length as u64);
+        }
+
+        let mut flags = 0;
+        if version >= STRIDE_VERSION {
+            if params.channel_copies {
+                flags |= CHANNEL_COPIES_FLAG;
+            }
+            if params.preserve_region.is_some() {
+                flags |= PRESERVED_REGION_FLAG;
+            }
+            if params.tiff_strip.is_some() {
+                flags |= TIFF_STRIP_FLAG;
+            }
+            if params.channel_bits.is_some() {
+                flags |= CHANNEL_BITS_FLAG;
+            }
+        }
+
+        Self {
+            version,
+            bit_plane: params.bit_plane,
+            flags,
+            stride: params.stride.max(1),
+            length: length as u64,
+            channel_bits: params.channel_bits,
+        }
+    }
+
+    fn legacy(length: u64) -> Self {
+        Self {
+            version: 1,
+            bit_plane: 0,
+            flags: 0,
+            stride: 1,
+            length,
+            channel_bits: None,
+        }
+    }
+
+    pub fn channel_copies(&self) -> bool {
+        self.flags & CHANNEL_COPIES_FLAG != 0
+    }
+
+    pub fn preserved_region(&self) -> bool {
+        self.flags & PRESERVED_REGION_FLAG != 0
+    }
+
+    pub fn tiff_strip(&self) -> bool {
+        self.flags & TIFF_STRIP_FLAG != 0
+    }
+
+    /// Bytes `to_bytes` produces for this header.
+    pub fn encoded_len(&self) -> usize {
+        let allocation = if self.flags & CHANNEL_BITS_FLAG != 0 {
+            CHANNEL_BITS_LEN
+        } else {
+            0
+        };
+        Self::len_for(self.version, self.length as usize) + allocation
+    }
+
+    /// Length of the header written for `length` payload bytes in the given
+    /// format version, without the channel bits allocation: version 1
+    /// predates the `DPSV` magic and uses the shorter `DPSN` header, and
+    /// varint lengths vary in size.
+    fn len_for(version: u8, length: usize) -> usize {
+        match version {
+            1 => LEGACY_HEADER_LEN,
+            VARINT_LENGTH_VERSION.. => {
+                LENGTH_OFFSET + Self::varint_len(length as u64) + HEADER_CHECKSUM_LEN
+            }
+            STRIDE_VERSION.. => HEADER_LEN,
+            _ => PRE_STRIDE_HEADER_LEN,
+        }
+    }
+
+    /// Header length and the longest payload it can describe, for each size
+    /// of varint length field, in the layout `params` select. The header
+    /// grows with the length it stores, so capacity has to be worked out for
+    /// every size.
+    pub(crate) fn varint_sizes(params: &EmbedParams) -> impl Iterator<Item = (usize, usize)> {
+        (1..=MAX_VARINT_LEN).map(|field_len| {
+            let max_len = Self::varint_max(field_len);
+            (Self::for_params(params, max_len).encoded_len(), max_len)
+        })
+    }
+
+    /// Length of the header at the start of `bytes`, which must begin with
+    /// either magic and, for `DPSV`, hold at least the version byte. `None`
+    /// when a varint length field does not end within `bytes`.
+    pub(crate) fn peek_len(bytes: &[u8]) -> Option<usize> {
+        if bytes.starts_with(LEGACY_HEADER_MAGIC) {
+            return Some(LEGACY_HEADER_LEN);
+        }
+
+        let version = bytes[4];
+        if version < VARINT_LENGTH_VERSION {
+            return Some(Self::len_for(version, 0));
+        }
+
+        let (_, field_len) = Self::decode_varint(bytes.get(LENGTH_OFFSET..)?)?;
+        let allocation = if version >= CHANNEL_BITS_VERSION && bytes[6] & CHANNEL_BITS_FLAG != 0 {
+            CHANNEL_BITS_LEN
+        } else {
+            0
+        };
+        Some(LENGTH_OFFSET + field_len + allocation + HEADER_CHECKSUM_LEN)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(self.encoded_len());
+
+        if self.version == 1 {
+            header.extend_from_slice(LEGACY_HEADER_MAGIC);
+        } else {
+            header.extend_from_slice(HEADER_MAGIC);
+            header.push(self.version);
+            header.push(self.bit_plane);
+        }
+
+        if self.version >= STRIDE_VERSION {
+            header.push(self.flags);
+            header.extend_from_slice(&self.stride.to_be_bytes());
+        }
+
+        if self.version >= VARINT_LENGTH_VERSION {
+            header.extend_from_slice(&Self::encode_varint(self.length));
+        } else {
+            header.extend_from_slice(&(self.length as u32).to_be_bytes());
+        }
+
+        if self.flags & CHANNEL_BITS_FLAG != 0 {
+            let [r, g, b] = self.channel_bits.unwrap_or([1; 3]);
+            header.push((r - 1) << 4 | (g - 1) << 2 | (b - 1));
+        }
+
+        let checksum = Self::checksum(&header);
+        header.extend_from_slice(&checksum.to_be_bytes());
+        header
+    }
+
+    /// Parses and validates the header at the start of `bytes`. Anything
+    /// after the header is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let header = Self::parse(bytes)?;
+        header.validate()?;
+        Ok(header)
+    }
+
+    /// Reads the header at the start of `bytes` and checks its magic, length
+    /// and checksum, but not whether this build understands its fields; see
+    /// `validate`.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self> {
+        let legacy = bytes.starts_with(LEGACY_HEADER_MAGIC);
+        if !legacy && (bytes.len() <= 4 || !bytes.starts_with(HEADER_MAGIC)) {
+            return Err(DeepSceneError::NoData(
+                "No DeepScene header magic found".to_string(),
+            ));
+        }
+
+        let invalid_length = || DeepSceneError::Data("Invalid length field in header".to_string());
+        let truncated = |needed: usize| {
+            DeepSceneError::Data(format!(
+                "Header is truncated. Got {} bytes, need {}",
+                bytes.len(),
+                needed
+            ))
+        };
+
+        let header_len = match Self::peek_len(bytes) {
+            Some(header_len) if header_len > bytes.len() => return Err(truncated(header_len)),
+            Some(header_len) => header_len,
+            None if bytes.len() < MAX_HEADER_LEN => return Err(truncated(bytes.len() + 1)),
+            None => return Err(invalid_length()),
+        };
+
+        let (fields, checksum) = bytes[..header_len].split_at(header_len - HEADER_CHECKSUM_LEN);
+        if u16::from_be_bytes([checksum[0], checksum[1]]) != Self::checksum(fields) {
+            return Err(DeepSceneError::Data(
+                "Data integrity check failed. The embedded data may be corrupted".to_string(),
+            ));
+        }
+
+        let fixed_length = |fields: &[u8]| {
+            let n = fields.len();
+            u64::from(u32::from_be_bytes([
+                fields[n - 4],
+                fields[n - 3],
+                fields[n - 2],
+                fields[n - 1],
+            ]))
+        };
+
+        if legacy {
+            return Ok(Self::legacy(fixed_length(fields)));
+        }
+
+        // Version 1 is the only one written with `DPSN`, and every `DPSV`
+        // header has a version of 2 or later.
+        let version = fields[4];
+        if version < 2 {
+            return Err(DeepSceneError::Data(format!(
+                "Invalid format version in header ({})",
+                version
+            )));
+        }
+        let (flags, stride) = if version >= STRIDE_VERSION {
+            (fields[6], u16::from_be_bytes([fields[7], fields[8]]))
+        } else {
+            (0, 1)
+        };
+        let length = if version >= VARINT_LENGTH_VERSION {
+            Self::decode_varint(&fields[LENGTH_OFFSET..])
+                .ok_or_else(invalid_length)?
+                .0
+        } else {
+            fixed_length(fields)
+        };
+        // Two bits per channel, each one less than the count, with the top
+        // two bits reserved.
+        let channel_bits = if version >= CHANNEL_BITS_VERSION && flags & CHANNEL_BITS_FLAG != 0 {
+            let allocation = fields[fields.len() - 1];
+            if allocation >> 6 != 0 {
+                return Err(DeepSceneError::Data(format!(
+                    "Invalid channel bit allocation in header ({:#04x})",
+                    allocation
+                )));
+            }
+            Some([
+                (allocation >> 4 & 3) + 1,
+                (allocation >> 2 & 3) + 1,
+                (allocation & 3) + 1,
+            ])
+        } else {
+            None
+        };
+
+        Ok(Self {
+            version,
+            bit_plane: fields[5],
+            flags,
+            stride,
+            length,
+            channel_bits,
+        })
+    }
+
+    /// Fails when a field is out of range, the version or a layout flag is
+    /// newer than this build, or the flags combine layouts that cannot be
+    /// used together.
+    pub fn validate(&self) -> Result<()> {
+        if self.version > FORMAT_VERSION {
+            return Err(DeepSceneError::UnsupportedVersion(format!(
+                "Unsupported format version ({}). This build supports up to version {}",
+                self.version, FORMAT_VERSION
+            )));
+        }
+
+        if self.bit_plane > MAX_BIT_PLANE {
+            return Err(DeepSceneError::Data(format!(
+                "Invalid bit plane in header ({})",
+                self.bit_plane
+            )));
+        }
+
+        let known_flags = [
+            (CHANNEL_COPIES_VERSION, CHANNEL_COPIES_FLAG),
+            (PRESERVED_REGION_VERSION, PRESERVED_REGION_FLAG),
+            (TIFF_STRIP_VERSION, TIFF_STRIP_FLAG),
+            (CHANNEL_BITS_VERSION, CHANNEL_BITS_FLAG),
+        ]
+        .into_iter()
+        .filter(|&(version, _)| self.version >= version)
+        .fold(0, |known, (_, flag)| known | flag);
+
+        if self.flags & !known_flags != 0 {
+            return Err(DeepSceneError::UnsupportedVersion(format!(
+                "Unknown layout flags in header ({:#04x}). The image was written by a newer build",
+                self.flags
+            )));
+        }
+
+        if self.stride == 0 {
+            return Err(DeepSceneError::Data(
+                "Invalid stride in header (0)".to_string(),
+            ));
+        }
+
+        if self.channel_copies() && self.stride != 1 {
+            return Err(DeepSceneError::Data(format!(
+                "Invalid stride in header ({}) for per-channel copies",
+                self.stride
+            )));
+        }
+
+        if self.preserved_region() && (self.channel_copies() || self.stride != 1) {
+            return Err(DeepSceneError::Data(
+                "Header marks a preserved region together with per-channel copies or a stride"
+                    .to_string(),
+            ));
+        }
+
+        if self.tiff_strip()
+            && (self.channel_copies() || self.stride != 1 || self.preserved_region())
+        {
+            return Err(DeepSceneError::Data(
+                "Header marks a TIFF strip together with per-channel copies, a stride or a preserved region"
+                    .to_string(),
+            ));
+        }
+
+        if self.channel_bits.is_some() != (self.flags & CHANNEL_BITS_FLAG != 0) {
+            return Err(DeepSceneError::Data(
+                "Channel bit allocation does not match the header's layout flags".to_string(),
+            ));
+        }
+
+        if let Some(bits) = self.channel_bits {
+            if bits.iter().any(|&n| n == 0 || n > MAX_CHANNEL_BITS) {
+                return Err(DeepSceneError::Data(format!(
+                    "Invalid channel bit allocation in header ({:?})",
+                    bits
+                )));
+            }
+            if self.flags != CHANNEL_BITS_FLAG || self.stride != 1 || self.bit_plane != 0 {
+                return Err(DeepSceneError::Data(
+                    "Header marks per-channel bit depths together with another layout, a stride or a bit plane"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if self.length == 0 {
+            return Err(DeepSceneError::NoData(
+                "No embedded data detected".to_string(),
+            ));
+        }
+
+        if self.length > MAX_DATA_LENGTH as u64 {
+            return Err(DeepSceneError::Data(format!(
+                "Invalid data length detected ({} bytes). Maximum is {} MB.",
+                self.length,
+                MAX_DATA_LENGTH / (1024 * 1024)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn checksum(data: &[u8]) -> u16 {
+        data.iter().fold(0u16, |acc, &b| acc.wrapping_add(b as u16))
+    }
+
+    /// Encodes `value` as unsigned LEB128: seven bits per byte, least
+    /// significant first, with the high bit set on every byte but the last.
+    fn encode_varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::varint_len(value));
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    /// Decodes the LEB128 varint at the start of `bytes`, returning it with
+    /// its length. Overlong encodings and values beyond 64 bits are rejected,
+    /// so every length has exactly one encoding.
+    fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+        let mut value = 0u64;
+
+        for (index, &byte) in bytes.iter().take(MAX_VARINT_LEN).enumerate() {
+            let bits = u64::from(byte & 0x7f);
+            if index == MAX_VARINT_LEN - 1 && bits > 1 {
+                return None;
+            }
+            value |= bits << (7 * index);
+
+            if byte & 0x80 == 0 {
+                return (byte != 0 || index == 0).then_some((value, index + 1));
+            }
+        }
+
+        None
+    }
+
+    fn varint_len(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()).max(1).div_ceil(7) as usize
+    }
+
+    /// Largest value a varint of `len` bytes can hold.
+    fn varint_max(len: usize) -> usize {
+        1u64.checked_shl(7 * len as u32)
+            .map_or(u64::MAX, |limit| limit - 1)
+            .try_into()
+            .unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn header(length: u64) -> Header {
+        Header {
+            length,
+            ..Header::for_params(&EmbedParams::default(), 0)
+        }
+    }
+
+    #[test]
+    fn varint_grows_a_byte_every_seven_bits() {
+        for (value, len) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            (2_097_151, 3),
+            (2_097_152, 4),
+            (u64::from(u32::MAX), 5),
+            (u64::MAX, MAX_VARINT_LEN),
+        ] {
+            let encoded = Header::encode_varint(value);
+            assert_eq!(encoded.len(), len, "{}", value);
+            assert_eq!(Header::varint_len(value), len, "{}", value);
+            assert_eq!(
+                Header::decode_varint(&encoded),
+                Some((value, len)),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn header_length_follows_the_varint() {
+        let base = LENGTH_OFFSET + HEADER_CHECKSUM_LEN;
+        for (length, field_len) in [(0, 1), (127, 1), (128, 2), (16383, 2), (16384, 3)] {
+            let header = header(length);
+            let bytes = header.to_bytes();
+            assert_eq!(bytes.len(), base + field_len, "{}", length);
+            assert_eq!(header.encoded_len(), bytes.len(), "{}", length);
+            assert_eq!(Header::peek_len(&bytes), Some(bytes.len()), "{}", length);
+            assert_eq!(Header::parse(&bytes).unwrap(), header);
+        }
+    }
+
+    #[test]
+    fn header_size_for_every_version() {
+        for version in 1..=FORMAT_VERSION {
+            for (length, field_len) in [(100u64, 1), (200, 2)] {
+                let params = EmbedParams {
+                    format_version: Some(version),
+                    ..EmbedParams::default()
+                };
+                let header = Header::for_params(&params, length as usize);
+                let expected = match version {
+                    1 => LEGACY_HEADER_LEN,
+                    v if v < STRIDE_VERSION => PRE_STRIDE_HEADER_LEN,
+                    v if v < VARINT_LENGTH_VERSION => HEADER_LEN,
+                    _ => LENGTH_OFFSET + field_len + HEADER_CHECKSUM_LEN,
+                };
+
+                let bytes = header.to_bytes();
+                let context = format!("version {} length {}", version, length);
+                assert_eq!(bytes.len(), expected, "{}", context);
+                assert_eq!(header.encoded_len(), expected, "{}", context);
+                assert_eq!(Header::peek_len(&bytes), Some(expected), "{}", context);
+                assert_eq!(Header::from_bytes(&bytes).unwrap(), header, "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn channel_bits_add_a_byte_to_the_header() {
+        for version in CHANNEL_BITS_VERSION..=FORMAT_VERSION {
+            let params = EmbedParams {
+                format_version: Some(version),
+                channel_bits: Some([2, 1, 2]),
+                ..EmbedParams::default()
+            };
+            let header = Header::for_params(&params, 100);
+            let bytes = header.to_bytes();
+            assert_eq!(
+                bytes.len(),
+                LENGTH_OFFSET + 1 + CHANNEL_BITS_LEN + HEADER_CHECKSUM_LEN
+            );
+            assert_eq!(Header::peek_len(&bytes), Some(bytes.len()));
+            assert_eq!(Header::from_bytes(&bytes).unwrap(), header);
+        }
+    }
+
+    #[test]
+    fn multi_gigabyte_lengths_are_encoded_but_rejected() {
+        // No carrier this size is built; the header alone stands in for one.
+        for length in [4 * GIB - 1, 4 * GIB, 5 * GIB, 1 << 40] {
+            let header = header(length);
+            let bytes = header.to_bytes();
+            assert_eq!(bytes.len(), header.encoded_len());
+            assert_eq!(Header::parse(&bytes).unwrap().length, length);
+            assert!(matches!(
+                Header::from_bytes(&bytes),
+                Err(DeepSceneError::Data(message)) if message.contains("Invalid data length")
+            ));
+        }
+    }
+
+    #[test]
+    fn overlong_and_oversized_varints_are_rejected() {
+        assert_eq!(Header::decode_varint(&[0x80, 0x00]), None);
+        assert_eq!(Header::decode_varint(&[0xff, 0x80, 0x00]), None);
+        assert_eq!(Header::decode_varint(&[0x80; MAX_VARINT_LEN]), None);
+
+        let mut too_wide = vec![0xff; MAX_VARINT_LEN - 1];
+        too_wide.push(0x02);
+        assert_eq!(Header::decode_varint(&too_wide), None);
+    }
+
+    #[test]
+    fn checksum_covers_the_varint() {
+        let mut bytes = header(300).to_bytes();
+        bytes[LENGTH_OFFSET + 1] ^= 0x01;
+        assert!(matches!(
+            Header::parse(&bytes),
+            Err(DeepSceneError::Data(message)) if message.contains("integrity")
+        ));
+    }
+
+    /// One header of each layout: legacy, before strides, fixed-length,
+    /// one- and three-byte varints, and with per-channel bit depths.
+    fn sample_headers() -> Vec<Header> {
+        let version = |format_version| EmbedParams {
+            format_version: Some(format_version),
+            ..EmbedParams::default()
+        };
+        vec![
+            Header::for_params(&version(1), 100),
+            Header::for_params(&version(STRIDE_VERSION - 1), 100),
+            Header::for_params(&version(VARINT_LENGTH_VERSION - 1), 100),
+            header(100),
+            header(1 << 20),
+            Header::for_params(
+                &EmbedParams {
+                    channel_bits: Some([2, 1, 2]),
+                    ..EmbedParams::default()
+                },
+                100,
+            ),
+        ]
+    }
+
+    /// Replaces the trailing checksum of an edited header with a valid one.
+    fn reseal(mut bytes: Vec<u8>) -> Vec<u8> {
+        let fields = bytes.len() - HEADER_CHECKSUM_LEN;
+        let checksum = Header::checksum(&bytes[..fields]).to_be_bytes();
+        bytes[fields..].copy_from_slice(&checksum);
+        bytes
+    }
+
+    #[test]
+    fn wrong_magic_is_no_data() {
+        let valid = header(100).to_bytes();
+        let mut lowercase = valid.clone();
+        lowercase[..4].copy_from_slice(b"dpsv");
+        let mut other = valid.clone();
+        other[3] = b'X';
+
+        for bytes in [
+            lowercase,
+            other,
+            vec![0; valid.len()],
+            vec![0xff; MAX_HEADER_LEN],
+            b"PNG\r\n".to_vec(),
+            Vec::new(),
+        ] {
+            assert!(
+                matches!(Header::parse(&bytes), Err(DeepSceneError::NoData(_))),
+                "{:?}",
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn every_truncation_is_rejected() {
+        for header in sample_headers() {
+            let bytes = header.to_bytes();
+            assert_eq!(Header::parse(&bytes).unwrap(), header);
+
+            for len in 0..bytes.len() {
+                let context = format!("version {} cut to {} bytes", header.version, len);
+                let result = Header::parse(&bytes[..len]);
+                // Four bytes are only a magic number for the legacy header,
+                // which has no version byte.
+                if len < 4 || (len == 4 && header.version != 1) {
+                    assert!(
+                        matches!(result, Err(DeepSceneError::NoData(_))),
+                        "{}: {:?}",
+                        context,
+                        result
+                    );
+                } else {
+                    assert!(
+                        matches!(&result, Err(DeepSceneError::Data(message)) if message.contains("truncated")),
+                        "{}: {:?}",
+                        context,
+                        result
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_version_is_unsupported() {
+        let mut bytes = header(100).to_bytes();
+        bytes[4] = FORMAT_VERSION + 1;
+        let bytes = reseal(bytes);
+
+        // Parsing succeeds so callers can report the version; validating it
+        // does not.
+        assert_eq!(Header::parse(&bytes).unwrap().version, FORMAT_VERSION + 1);
+        assert!(matches!(
+            Header::from_bytes(&bytes),
+            Err(DeepSceneError::UnsupportedVersion(message)) if message.contains(&(FORMAT_VERSION + 1).to_string())
+        ));
+
+        // `DPSV` was introduced with version 2, so version 0 cannot follow it.
+        let mut bytes = Header::for_params(
+            &EmbedParams {
+                format_version: Some(STRIDE_VERSION - 1),
+                ..EmbedParams::default()
+            },
+            100,
+        )
+        .to_bytes();
+        bytes[4] = 0;
+        assert!(matches!(
+            Header::parse(&reseal(bytes)),
+            Err(DeepSceneError::Data(message)) if message.contains("Invalid format version in header (0)")
+        ));
+    }
+
+    #[test]
+    fn checksum_mismatch_is_an_integrity_error() {
+        for header in sample_headers() {
+            let bytes = header.to_bytes();
+            // Both checksum bytes and the last field byte before them.
+            for position in bytes.len() - 3..bytes.len() {
+                let mut damaged = bytes.clone();
+                damaged[position] ^= 0x01;
+                let result = Header::parse(&damaged);
+                assert!(
+                    matches!(&result, Err(DeepSceneError::Data(message)) if message.contains("integrity")),
+                    "version {} byte {}: {:?}",
+                    header.version,
+                    position,
+                    result
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Error messages used in more than one place, or that callers may want to
+//! match exactly. Fixed messages are constants and messages with details are
+//! functions, so a message has a single wording and can be compared by name
+//! instead of by copying its text.
+
+use std::fmt::Display;
+use std::path::Path;
+
+pub const EMPTY_PASSWORD: &str =
+    "Encryption password cannot be empty. Please provide a valid password";
+pub const AUTHENTICATION_FAILED: &str = "Authentication failed";
+pub const CORRUPTED_ENCRYPTED_DATA: &str = "Corrupted encrypted data";
+pub const INVALID_SALT: &str = "Invalid salt";
+pub const INVALID_NONCE: &str = "Invalid nonce";
+pub const KEY_DERIVATION_FAILED: &str = "Key derivation failed";
+pub const NO_DATA_FOUND: &str = "No data found in image";
+pub const NO_EMBEDDED_DATA: &str =
+    "No embedded data detected. This image does not appear to contain steganographic content";
+pub const INVALID_FILE_NAME: &str = "Invalid file name";
+pub const EMPTY_FILE_NAME: &str = "File name cannot be empty";
+pub const IMAGE_FROM_RAW_DATA: &str = "Failed to construct image from raw data";
+
+pub fn unknown_kdf(id: u8) -> String {
+    format!("Unknown key derivation function ({})", id)
+}
+
+pub fn file_not_found(path: &Path) -> String {
+    format!("File '{}' not found", path.display())
+}
+
+pub fn not_a_file(path: &Path) -> String {
+    format!("'{}' is not a file", path.display())
+}
+
+pub fn not_a_directory(path: &Path) -> String {
+    format!("'{}' is not a directory", path.display())
+}
+
+pub fn empty_file(path: &Path) -> String {
+    format!("File '{}' is empty", path.display())
+}
+
+pub fn file_name_too_long(max_len: usize) -> String {
+    format!("File name too long (max {} bytes)", max_len)
+}
+
+pub fn file_too_large(path: &Path, max_mb: usize, size_mb: usize) -> String {
+    format!(
+        "File '{}' is too large. Maximum file size is {} MB, but file is {} MB",
+        path.display(),
+        max_mb,
+        size_mb
+    )
+}
+
+pub fn output_exists(path: &Path) -> String {
+    format!(
+        "'{}' already exists. Use --overwrite-policy overwrite or rename",
+        path.display()
+    )
+}
+
+pub fn output_not_a_file(path: &Path) -> String {
+    format!("Output path '{}' exists but is not a file", path.display())
+}
+
+pub fn output_dir_missing(dir: &Path) -> String {
+    format!("Output directory '{}' does not exist", dir.display())
+}
+
+pub fn output_dir_not_a_directory(dir: &Path) -> String {
+    format!("Output directory '{}' is not a directory", dir.display())
+}
+
+pub fn open_image_failed(path: &Path, error: impl Display) -> String {
+    format!("Failed to open image '{}': {}", path.display(), error)
+}
+
+pub fn convert_to_png_failed(error: impl Display) -> String {
+    format!("Failed to convert image to PNG: {}", error)
+}
+
+pub fn encode_output_failed(error: impl Display) -> String {
+    format!("Failed to encode output image: {}", error)
+}
+
+/// `feature` names the option that needs format version `minimum`.
+pub fn version_lacks_feature(version: u8, feature: &str, minimum: u8) -> String {
+    format!(
+        "Format version {} does not support {}. It needs version {} or later",
+        version, feature, minimum
+    )
+}
@@ -1,9 +1,35 @@
+pub mod capacity_map;
+pub mod carrier_metadata;
+pub mod compat;
 pub mod compression;
 pub mod crypto;
+pub mod diff_map;
+pub mod dng;
 pub mod error;
+pub mod header;
+pub mod messages;
+pub mod parallel;
+pub mod png_writer;
+pub mod progress;
+pub mod random;
+pub mod resolution;
+pub mod signing;
 pub mod steganography;
+pub mod tiff_strip;
 
+pub use capacity_map::CapacityMap;
+pub use carrier_metadata::CarrierMetadata;
+pub use compat::CompatSpec;
 pub use compression::CompressionEngine;
-pub use crypto::CryptoEngine;
-pub use error::{DeepSceneError, Result};
-pub use steganography::SteganographyEngine;
+pub use crypto::{CryptoEngine, KdfAlgorithm};
+pub use diff_map::DiffMap;
+pub use dng::DngImage;
+pub use error::{DeepSceneError, ErrorContext, ErrorKind, Result};
+pub use header::Header;
+pub use png_writer::PngWriter;
+pub use progress::ProgressReporter;
+pub use random::RandomSource;
+pub use resolution::Resolution;
+pub use signing::SigningEngine;
+pub use steganography::{EmbedParams, ExtractedData, Region, SteganographyEngine};
+pub use tiff_strip::TiffStrip;
@@ -0,0 +1,76 @@
+use crate::core::error::{DeepSceneError, Result};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Runs `f` inside a dedicated pool of `threads` workers so every parallel
+/// section it reaches is bounded. `None` uses rayon's global pool (all cores).
+#[cfg(feature = "rayon")]
+pub fn run_with_threads<T: Send>(
+    threads: Option<usize>,
+    f: impl FnOnce() -> T + Send,
+) -> Result<T> {
+    match threads {
+        Some(count) => {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(count)
+                .build()
+                .map_err(|e| {
+                    DeepSceneError::Validation(format!("Failed to create thread pool: {}", e))
+                })?;
+            Ok(pool.install(f))
+        }
+        None => Ok(f()),
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+pub fn run_with_threads<T: Send>(
+    threads: Option<usize>,
+    f: impl FnOnce() -> T + Send,
+) -> Result<T> {
+    if threads == Some(0) {
+        return Err(DeepSceneError::Validation(
+            "Thread count must be at least 1".to_string(),
+        ));
+    }
+    Ok(f())
+}
+
+pub fn is_enabled() -> bool {
+    cfg!(feature = "rayon")
+}
+
+/// Collects `f(0..len)` in order, in parallel when rayon is enabled.
+pub fn map_range<T, F>(len: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    let result = (0..len).into_par_iter().map(f).collect();
+
+    #[cfg(not(feature = "rayon"))]
+    let result = (0..len).map(f).collect();
+
+    result
+}
+
+/// Pairs fixed-size chunks of `dst` and `src` and applies `f` to each pair
+/// with its index.
+pub fn zip_chunks<F>(dst: &mut [u8], dst_chunk: usize, src: &[u8], src_chunk: usize, f: F)
+where
+    F: Fn(usize, &mut [u8], &[u8]) + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    dst.par_chunks_mut(dst_chunk)
+        .zip(src.par_chunks(src_chunk))
+        .enumerate()
+        .for_each(|(i, (d, s))| f(i, d, s));
+
+    #[cfg(not(feature = "rayon"))]
+    dst.chunks_mut(dst_chunk)
+        .zip(src.chunks(src_chunk))
+        .enumerate()
+        .for_each(|(i, (d, s))| f(i, d, s));
+}
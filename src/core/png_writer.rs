@@ -0,0 +1,217 @@
+use crate::core::error::{DeepSceneError, Result};
+use crate::core::random::RandomSource;
+use image::RgbaImage;
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, FilterType};
+use rand::RngCore;
+
+/// Private ancillary chunk used to pad output: decoders skip unknown ancillary
+/// chunks, and the lowercase fourth letter marks it safe to copy.
+const PADDING_CHUNK: [u8; 4] = *b"paDd";
+const CHUNK_OVERHEAD: usize = 12;
+/// Largest chunk data length the PNG specification allows.
+const MAX_CHUNK_LEN: u64 = (1 << 31) - 1;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Signature plus the fixed 13-byte `IHDR` chunk, which always comes first.
+const IHDR_END: usize = 8 + 13 + CHUNK_OVERHEAD;
+
+/// Every combination round-trips the pixels exactly, so the search below is
+/// free to pick whichever lands closest to the target size.
+const COMPRESSIONS: [Compression; 3] = [Compression::Fast, Compression::Default, Compression::Best];
+const FILTERS: [FilterType; 5] = [
+    FilterType::NoFilter,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Avg,
+    FilterType::Paeth,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct PngSettings {
+    pub compression: Compression,
+    pub filter: FilterType,
+    pub adaptive: bool,
+    /// Drop the alpha channel; only valid when every pixel is opaque.
+    pub rgb: bool,
+}
+
+impl PngSettings {
+    /// Fixed settings for `--canonical-output`, so equal pixels always encode
+    /// to the same bytes with the pinned `png` crate.
+    pub const CANONICAL: PngSettings = PngSettings {
+        compression: Compression::Default,
+        filter: FilterType::Sub,
+        adaptive: false,
+        rgb: false,
+    };
+}
+
+pub struct PngWriter;
+
+impl PngWriter {
+    /// Encodes `image`, followed by an ancillary chunk holding `padding`
+    /// unless it is empty.
+    pub fn encode(image: &RgbaImage, settings: &PngSettings, padding: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut output, image.width(), image.height());
+        encoder.set_color(if settings.rgb {
+            ColorType::Rgb
+        } else {
+            ColorType::Rgba
+        });
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_compression(settings.compression);
+        encoder.set_filter(settings.filter);
+        encoder.set_adaptive_filter(if settings.adaptive {
+            AdaptiveFilterType::Adaptive
+        } else {
+            AdaptiveFilterType::NonAdaptive
+        });
+
+        let mut writer = encoder.write_header().map_err(Self::png_error)?;
+
+        if settings.rgb {
+            let rgb: Vec<u8> = image
+                .as_raw()
+                .chunks_exact(4)
+                .flat_map(|p| [p[0], p[1], p[2]])
+                .collect();
+            writer.write_image_data(&rgb).map_err(Self::png_error)?;
+        } else {
+            writer
+                .write_image_data(image.as_raw())
+                .map_err(Self::png_error)?;
+        }
+
+        if !padding.is_empty() {
+            writer
+                .write_chunk(png::chunk::ChunkType(PADDING_CHUNK), padding)
+                .map_err(Self::png_error)?;
+        }
+
+        writer.finish().map_err(Self::png_error)?;
+        Ok(output)
+    }
+
+    /// Tries every compression level and filter, keeps the largest encoding
+    /// that does not exceed `target_size`, then pads it up to the target with
+    /// an ancillary chunk of bytes from `random`. If every encoding is too
+    /// large the smallest is used.
+    pub fn encode_matching_size(
+        image: &RgbaImage,
+        target_size: usize,
+        random: &RandomSource,
+    ) -> Result<Vec<u8>> {
+        let opaque = image.pixels().all(|p| p[3] == 255);
+
+        let mut best_fit: Option<(usize, PngSettings)> = None;
+        let mut smallest: Option<(usize, PngSettings)> = None;
+
+        for &rgb in if opaque {
+            &[true, false][..]
+        } else {
+            &[false][..]
+        } {
+            for &compression in &COMPRESSIONS {
+                for &filter in &FILTERS {
+                    for adaptive in [false, true] {
+                        let settings = PngSettings {
+                            compression,
+                            filter,
+                            adaptive,
+                            rgb,
+                        };
+                        let size = Self::encode(image, &settings, &[])?.len();
+
+                        if size <= target_size && best_fit.is_none_or(|(best, _)| size > best) {
+                            best_fit = Some((size, settings));
+                        }
+                        if smallest.is_none_or(|(min, _)| size < min) {
+                            smallest = Some((size, settings));
+                        }
+                    }
+                }
+            }
+        }
+
+        match best_fit.or(smallest) {
+            Some((size, settings)) => {
+                let mut padding = vec![
+                    0u8;
+                    target_size
+                        .saturating_sub(size)
+                        .saturating_sub(CHUNK_OVERHEAD)
+                ];
+                random.rng("png-padding", 0).fill_bytes(&mut padding);
+                Self::encode(image, &settings, &padding)
+            }
+            None => Err(DeepSceneError::Image(
+                "No PNG encoding candidates".to_string(),
+            )),
+        }
+    }
+
+    /// Inserts ancillary chunks straight after `IHDR`, where chunks that must
+    /// precede the image data such as `iCCP` are valid.
+    pub fn insert_chunks(png: &[u8], chunks: &[([u8; 4], Vec<u8>)]) -> Result<Vec<u8>> {
+        if png.len() < IHDR_END || &png[..8] != PNG_SIGNATURE || &png[12..16] != b"IHDR" {
+            return Err(DeepSceneError::Image(
+                "Cannot add chunks: output is not a well-formed PNG".to_string(),
+            ));
+        }
+
+        let mut output = Vec::with_capacity(png.len() + Self::chunks_len(chunks));
+        output.extend_from_slice(&png[..IHDR_END]);
+
+        for (chunk_type, data) in chunks {
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(chunk_type);
+            hasher.update(data);
+
+            output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            output.extend_from_slice(chunk_type);
+            output.extend_from_slice(data);
+            output.extend_from_slice(&hasher.finalize().to_be_bytes());
+        }
+
+        output.extend_from_slice(&png[IHDR_END..]);
+        Ok(output)
+    }
+
+    /// Pads `png` with an ancillary chunk of bytes from `random` up to the
+    /// next multiple of `bucket` bytes, so outputs fall into fixed size
+    /// classes whatever their payload. A PNG already on a multiple is left
+    /// as it is; one too close below it for the chunk's own 12 bytes moves
+    /// up a class.
+    pub fn pad_to_bucket(png: Vec<u8>, bucket: u64, random: &RandomSource) -> Result<Vec<u8>> {
+        let len = png.len() as u64;
+        if bucket == 0 || len.is_multiple_of(bucket) {
+            return Ok(png);
+        }
+
+        let target = (len + CHUNK_OVERHEAD as u64).div_ceil(bucket) * bucket;
+        let padding_len = target - len - CHUNK_OVERHEAD as u64;
+        if padding_len > MAX_CHUNK_LEN {
+            return Err(DeepSceneError::Validation(format!(
+                "A size bucket of {} bytes needs more padding than one PNG chunk holds",
+                bucket
+            )));
+        }
+
+        let mut padding = vec![0u8; padding_len as usize];
+        random.rng("size-bucket", 0).fill_bytes(&mut padding);
+        Self::insert_chunks(&png, &[(PADDING_CHUNK, padding)])
+    }
+
+    /// Bytes the chunks add to a PNG file.
+    pub fn chunks_len(chunks: &[([u8; 4], Vec<u8>)]) -> usize {
+        chunks
+            .iter()
+            .map(|(_, data)| data.len() + CHUNK_OVERHEAD)
+            .sum()
+    }
+
+    fn png_error(e: png::EncodingError) -> DeepSceneError {
+        DeepSceneError::Image(format!("PNG encoding failed: {}", e))
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Bytes a worker thread counts locally before adding them to the shared
+/// total, so tracking costs no contended atomics per byte.
+const FLUSH_LEN: usize = 64 * 1024;
+/// Updates per stage at most, besides the first and last.
+const STEPS: usize = 100;
+
+/// Receives byte counts from the long-running parts of embedding and
+/// extraction, for frontends that draw their own progress.
+pub trait ProgressReporter: Send + Sync {
+    /// `done` of `total` bytes of `stage` are processed. Called from worker
+    /// threads, but never concurrently and with `done` never decreasing
+    /// within a stage. Every stage starts at 0 and ends at `total`.
+    fn update(&self, stage: &str, done: usize, total: usize);
+}
+
+static REPORTER: OnceLock<Box<dyn ProgressReporter>> = OnceLock::new();
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static DONE: AtomicUsize = AtomicUsize::new(0);
+static STAGE: Mutex<StageState> = Mutex::new(StageState {
+    name: "",
+    total: 0,
+    step: 0,
+});
+
+thread_local! {
+    /// Bytes counted on this thread and not yet flushed, with the generation
+    /// of the stage they belong to.
+    static PENDING: Cell<(u64, usize)> = const { Cell::new((0, 0)) };
+}
+
+struct StageState {
+    name: &'static str,
+    total: usize,
+    step: usize,
+}
+
+/// Installs the reporter for the rest of the process. Returns `false` if one
+/// was already installed. Without a reporter nothing is tracked.
+pub fn set_reporter(reporter: Box<dyn ProgressReporter>) -> bool {
+    REPORTER.set(reporter).is_ok()
+}
+
+/// Reports `stage` as started, and as finished when the returned guard is
+/// dropped. `None` when no reporter is installed.
+pub(crate) fn track(name: &'static str, total: usize) -> Option<Tracking> {
+    let reporter = REPORTER.get()?;
+    let mut stage = STAGE.lock().unwrap_or_else(|e| e.into_inner());
+    *stage = StageState {
+        name,
+        total,
+        step: 0,
+    };
+    DONE.store(0, Ordering::Relaxed);
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    TRACKING.store(true, Ordering::Relaxed);
+    reporter.update(name, 0, total);
+    Some(Tracking)
+}
+
+/// Counts `bytes` towards the current stage. A no-op outside one.
+pub(crate) fn advance(bytes: usize) {
+    if !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let generation = GENERATION.load(Ordering::Relaxed);
+    let pending = PENDING.with(|pending| {
+        let (owner, count) = pending.get();
+        let count = if owner == generation {
+            count + bytes
+        } else {
+            bytes
+        };
+        if count < FLUSH_LEN {
+            pending.set((generation, count));
+            0
+        } else {
+            pending.set((generation, 0));
+            count
+        }
+    });
+    if pending == 0 {
+        return;
+    }
+
+    let done = DONE.fetch_add(pending, Ordering::Relaxed) + pending;
+    let mut stage = STAGE.lock().unwrap_or_else(|e| e.into_inner());
+    let done = done.min(stage.total);
+    let step = done * STEPS / stage.total.max(1);
+    if step > stage.step
+        && step < STEPS
+        && let Some(reporter) = REPORTER.get()
+    {
+        stage.step = step;
+        reporter.update(stage.name, done, stage.total);
+    }
+}
+
+/// Ends the stage it was returned for when dropped.
+pub(crate) struct Tracking;
+
+impl Drop for Tracking {
+    fn drop(&mut self) {
+        TRACKING.store(false, Ordering::Relaxed);
+        let stage = STAGE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(reporter) = REPORTER.get() {
+            reporter.update(stage.name, stage.total, stage.total);
+        }
+    }
+}
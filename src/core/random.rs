@@ -0,0 +1,81 @@
+use rand::rngs::ThreadRng;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Where LSB matching, `--match-size` padding and encryption salts and
+/// nonces draw their randomness. The default is the thread RNG. A seeded
+/// source makes all of them reproducible, which is only meant for test
+/// fixtures: identical seeds and passwords reuse the keystream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RandomSource {
+    seed: Option<[u8; 32]>,
+}
+
+impl RandomSource {
+    /// A source seeded by the BLAKE3 hash of `seed_material`.
+    pub fn seeded(seed_material: &[u8]) -> Self {
+        RandomSource {
+            seed: Some(*blake3::hash(seed_material).as_bytes()),
+        }
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.seed.is_some()
+    }
+
+    /// An RNG for `stream`, a label naming its use, starting at `index`, such
+    /// as the pixel byte a parallel chunk begins at. A seeded source gives
+    /// every stream and index its own ChaCha20 sequence, so output does not
+    /// depend on how work is split between threads.
+    pub fn rng(&self, stream: &str, index: u64) -> SourceRng {
+        match self.seed {
+            None => SourceRng::Thread(rand::thread_rng()),
+            Some(seed) => {
+                let mut hasher = blake3::Hasher::new_keyed(&seed);
+                hasher.update(stream.as_bytes());
+                hasher.update(&index.to_le_bytes());
+                SourceRng::Seeded(Box::new(ChaCha20Rng::from_seed(
+                    *hasher.finalize().as_bytes(),
+                )))
+            }
+        }
+    }
+}
+
+/// RNG handed out by `RandomSource::rng`.
+pub enum SourceRng {
+    Thread(ThreadRng),
+    Seeded(Box<ChaCha20Rng>),
+}
+
+impl RngCore for SourceRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SourceRng::Thread(rng) => rng.next_u32(),
+            SourceRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SourceRng::Thread(rng) => rng.next_u64(),
+            SourceRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            SourceRng::Thread(rng) => rng.fill_bytes(dest),
+            SourceRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            SourceRng::Thread(rng) => rng.try_fill_bytes(dest),
+            SourceRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for SourceRng {}
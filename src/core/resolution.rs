@@ -0,0 +1,345 @@
+use crate::core::error::{DeepSceneError, Result};
+use image::ImageFormat;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use tiff::decoder::{Decoder, ifd::Value};
+use tiff::encoder::Rational;
+use tiff::tags::Tag;
+
+const PHYS_CHUNK: [u8; 4] = *b"pHYs";
+const METRES_PER_INCH: f64 = 0.0254;
+const CENTIMETRES_PER_INCH: f64 = 2.54;
+
+/// TIFF `ResolutionUnit` values; inches are the default when the tag is absent.
+const TIFF_UNIT_NONE: u16 = 1;
+const TIFF_UNIT_CENTIMETRE: u16 = 3;
+
+/// Offset of the horizontal pixels-per-metre field in a BMP, present when
+/// the DIB header is at least a 40-byte `BITMAPINFOHEADER`.
+const BMP_X_PELS_OFFSET: usize = 38;
+const BMP_INFO_HEADER_LEN: u32 = 40;
+
+/// Pixel density of a carrier in dots per inch, carried over to the output
+/// so it prints at the same size as the original.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resolution {
+    pub x_dpi: f64,
+    pub y_dpi: f64,
+}
+
+impl Resolution {
+    /// Reads the density of a PNG (`pHYs`), TIFF (resolution tags), BMP
+    /// (pixels per metre) or JPEG (JFIF density). Images without one, or
+    /// with only an aspect ratio, give `None`.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let format = image::io::Reader::open(path)?
+            .with_guessed_format()?
+            .format();
+        let read_error = |e: String| {
+            DeepSceneError::Image(format!(
+                "Cannot read the resolution of '{}': {}",
+                path.display(),
+                e
+            ))
+        };
+
+        let resolution = match format {
+            Some(ImageFormat::Png) => Self::read_png(path).map_err(read_error)?,
+            Some(ImageFormat::Tiff) => Self::read_tiff(path).map_err(read_error)?,
+            Some(ImageFormat::Bmp) => Self::read_bmp(path)?,
+            Some(ImageFormat::Jpeg) => Self::read_jfif(path)?,
+            _ => None,
+        };
+
+        Ok(resolution.filter(|r| r.x_dpi > 0.0 && r.y_dpi > 0.0))
+    }
+
+    /// `pHYs` chunk stating the density in pixels per metre.
+    pub fn png_chunk(&self) -> ([u8; 4], Vec<u8>) {
+        let mut data = Self::pixels_per_metre(self.x_dpi).to_be_bytes().to_vec();
+        data.extend_from_slice(&Self::pixels_per_metre(self.y_dpi).to_be_bytes());
+        data.push(png::Unit::Meter as u8);
+        (PHYS_CHUNK, data)
+    }
+
+    /// Density as TIFF `XResolution` and `YResolution` values in inches, exact
+    /// for whole numbers and to a thousandth otherwise.
+    pub fn tiff_rationals(&self) -> (Rational, Rational) {
+        let rational = |dpi: f64| {
+            if dpi.fract() == 0.0 && dpi <= u32::MAX as f64 {
+                Rational {
+                    n: dpi as u32,
+                    d: 1,
+                }
+            } else {
+                Rational {
+                    n: (dpi * 1000.0).round().min(u32::MAX as f64) as u32,
+                    d: 1000,
+                }
+            }
+        };
+        (rational(self.x_dpi), rational(self.y_dpi))
+    }
+
+    /// Overwrites the pixels-per-metre fields of an encoded BMP. Files with
+    /// a header too old to have them are left as they are.
+    pub fn apply_to_bmp(&self, bmp: &mut [u8]) {
+        if bmp.len() < BMP_X_PELS_OFFSET + 8
+            || u32::from_le_bytes([bmp[14], bmp[15], bmp[16], bmp[17]]) < BMP_INFO_HEADER_LEN
+        {
+            return;
+        }
+
+        let x = Self::pixels_per_metre(self.x_dpi).to_le_bytes();
+        let y = Self::pixels_per_metre(self.y_dpi).to_le_bytes();
+        bmp[BMP_X_PELS_OFFSET..BMP_X_PELS_OFFSET + 4].copy_from_slice(&x);
+        bmp[BMP_X_PELS_OFFSET + 4..BMP_X_PELS_OFFSET + 8].copy_from_slice(&y);
+    }
+
+    fn pixels_per_metre(dpi: f64) -> u32 {
+        (dpi / METRES_PER_INCH).round().min(u32::MAX as f64) as u32
+    }
+
+    fn from_pixels_per_metre(x: u32, y: u32) -> Self {
+        Self {
+            x_dpi: x as f64 * METRES_PER_INCH,
+            y_dpi: y as f64 * METRES_PER_INCH,
+        }
+    }
+
+    fn read_png(path: &Path) -> std::result::Result<Option<Self>, String> {
+        let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let reader = png::Decoder::new(file)
+            .read_info()
+            .map_err(|e| e.to_string())?;
+
+        Ok(reader
+            .info()
+            .pixel_dims
+            .filter(|dims| dims.unit == png::Unit::Meter)
+            .map(|dims| Self::from_pixels_per_metre(dims.xppu, dims.yppu)))
+    }
+
+    fn read_tiff(path: &Path) -> std::result::Result<Option<Self>, String> {
+        let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        let mut decoder = Decoder::new(file).map_err(|e| e.to_string())?;
+
+        let mut rational = |tag| match decoder.find_tag(tag) {
+            Ok(Some(Value::Rational(n, d))) if d != 0 => Ok(Some(n as f64 / d as f64)),
+            Ok(_) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        let (Some(x), Some(y)) = (rational(Tag::XResolution)?, rational(Tag::YResolution)?) else {
+            return Ok(None);
+        };
+
+        let unit = decoder
+            .find_tag_unsigned::<u16>(Tag::ResolutionUnit)
+            .map_err(|e| e.to_string())?;
+        Ok(match unit {
+            Some(TIFF_UNIT_NONE) => None,
+            Some(TIFF_UNIT_CENTIMETRE) => Some(Self {
+                x_dpi: x * CENTIMETRES_PER_INCH,
+                y_dpi: y * CENTIMETRES_PER_INCH,
+            }),
+            _ => Some(Self { x_dpi: x, y_dpi: y }),
+        })
+    }
+
+    fn read_bmp(path: &Path) -> Result<Option<Self>> {
+        let mut header = [0u8; BMP_X_PELS_OFFSET + 8];
+        if File::open(path)?.read_exact(&mut header).is_err()
+            || u32::from_le_bytes([header[14], header[15], header[16], header[17]])
+                < BMP_INFO_HEADER_LEN
+        {
+            return Ok(None);
+        }
+
+        let field = |offset: usize| {
+            i32::from_le_bytes([
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ])
+        };
+        let (x, y) = (field(BMP_X_PELS_OFFSET), field(BMP_X_PELS_OFFSET + 4));
+        if x <= 0 || y <= 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Self::from_pixels_per_metre(x as u32, y as u32)))
+    }
+
+    /// Reads the density from a JFIF `APP0` segment directly after the
+    /// start-of-image marker, where the JFIF specification places it.
+    fn read_jfif(path: &Path) -> Result<Option<Self>> {
+        let mut header = [0u8; 18];
+        if File::open(path)?.read_exact(&mut header).is_err()
+            || header[..4] != [0xFF, 0xD8, 0xFF, 0xE0]
+            || &header[6..11] != b"JFIF\0"
+        {
+            return Ok(None);
+        }
+
+        let x = u16::from_be_bytes([header[14], header[15]]) as f64;
+        let y = u16::from_be_bytes([header[16], header[17]]) as f64;
+        Ok(match header[13] {
+            1 => Some(Self { x_dpi: x, y_dpi: y }),
+            2 => Some(Self {
+                x_dpi: x * CENTIMETRES_PER_INCH,
+                y_dpi: y * CENTIMETRES_PER_INCH,
+            }),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.x_dpi == self.y_dpi {
+            write!(f, "{:.0} dpi", self.x_dpi)
+        } else {
+            write!(f, "{:.0}x{:.0} dpi", self.x_dpi, self.y_dpi)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, RgbImage};
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+    use tiff::encoder::{TiffEncoder, colortype};
+    use tiff::tags::ResolutionUnit;
+
+    fn encoded(format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        RgbImage::from_pixel(8, 8, image::Rgb([10, 20, 30]))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    fn read(dir: &TempDir, name: &str, bytes: &[u8]) -> Option<Resolution> {
+        let path = dir.path().join(name);
+        fs::write(&path, bytes).unwrap();
+        Resolution::read(&path).unwrap()
+    }
+
+    #[test]
+    fn tiff_rationals_are_exact_for_whole_densities() {
+        let (x, y) = Resolution {
+            x_dpi: 300.0,
+            y_dpi: 72.5,
+        }
+        .tiff_rationals();
+        assert_eq!((x.n, x.d), (300, 1));
+        assert_eq!((y.n, y.d), (72_500, 1000));
+    }
+
+    #[test]
+    fn png_chunk_stores_pixels_per_metre() {
+        let (name, data) = Resolution {
+            x_dpi: 300.0,
+            y_dpi: 150.0,
+        }
+        .png_chunk();
+        assert_eq!(name, *b"pHYs");
+        assert_eq!(&data[..4], &11811u32.to_be_bytes());
+        assert_eq!(&data[4..8], &5906u32.to_be_bytes());
+        assert_eq!(data[8], png::Unit::Meter as u8);
+    }
+
+    #[test]
+    fn bmp_density_round_trips_through_its_header() {
+        let dir = TempDir::new().unwrap();
+        let mut bmp = encoded(ImageOutputFormat::Bmp);
+        assert_eq!(read(&dir, "plain.bmp", &bmp), None);
+
+        let resolution = Resolution {
+            x_dpi: 72.0,
+            y_dpi: 96.0,
+        };
+        resolution.apply_to_bmp(&mut bmp);
+        // Whole pixels per metre are within 0.013 dpi of the original.
+        let stored = read(&dir, "dense.bmp", &bmp).unwrap();
+        assert!((stored.x_dpi - 72.0).abs() < 0.013, "{:?}", stored);
+        assert!((stored.y_dpi - 96.0).abs() < 0.013, "{:?}", stored);
+
+        // Too short to hold the fields: left untouched.
+        let mut short = bmp[..BMP_X_PELS_OFFSET].to_vec();
+        resolution.apply_to_bmp(&mut short);
+        assert_eq!(short, bmp[..BMP_X_PELS_OFFSET]);
+    }
+
+    #[test]
+    fn tiff_without_a_unit_has_no_density() {
+        let dir = TempDir::new().unwrap();
+        let tiff = |unit: ResolutionUnit| {
+            let mut bytes = Cursor::new(Vec::new());
+            let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+            let mut image = encoder.new_image::<colortype::RGB8>(2, 2).unwrap();
+            image.resolution_unit(unit);
+            image.x_resolution(Rational { n: 300, d: 1 });
+            image.y_resolution(Rational { n: 300, d: 1 });
+            image.write_data(&[0; 12]).unwrap();
+            bytes.into_inner()
+        };
+
+        assert_eq!(
+            read(&dir, "inch.tiff", &tiff(ResolutionUnit::Inch)),
+            Some(Resolution {
+                x_dpi: 300.0,
+                y_dpi: 300.0,
+            })
+        );
+        assert_eq!(read(&dir, "none.tiff", &tiff(ResolutionUnit::None)), None);
+        assert_eq!(
+            read(&dir, "plain.tiff", &encoded(ImageOutputFormat::Tiff)),
+            None
+        );
+    }
+
+    #[test]
+    fn jfif_density_is_read_in_either_unit() {
+        let dir = TempDir::new().unwrap();
+        let jpeg = encoded(ImageOutputFormat::Jpeg(90));
+        assert_eq!(&jpeg[6..11], b"JFIF\0");
+
+        let with_density = |unit: u8, x: u16, y: u16| {
+            let mut bytes = jpeg.clone();
+            bytes[13] = unit;
+            bytes[14..16].copy_from_slice(&x.to_be_bytes());
+            bytes[16..18].copy_from_slice(&y.to_be_bytes());
+            bytes
+        };
+
+        assert_eq!(
+            read(&dir, "inch.jpg", &with_density(1, 300, 200)),
+            Some(Resolution {
+                x_dpi: 300.0,
+                y_dpi: 200.0,
+            })
+        );
+        let centimetres = read(&dir, "cm.jpg", &with_density(2, 100, 100)).unwrap();
+        assert!(
+            (centimetres.x_dpi - 254.0).abs() < 1e-9,
+            "{:?}",
+            centimetres
+        );
+        // Unit 0 is an aspect ratio, not a density.
+        assert_eq!(read(&dir, "aspect.jpg", &with_density(0, 1, 1)), None);
+    }
+
+    #[test]
+    fn display_collapses_equal_densities() {
+        let resolution = |x_dpi, y_dpi| Resolution { x_dpi, y_dpi }.to_string();
+        assert_eq!(resolution(300.0, 300.0), "300 dpi");
+        assert_eq!(resolution(300.0, 150.0), "300x150 dpi");
+    }
+}
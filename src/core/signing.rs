@@ -0,0 +1,132 @@
+use crate::core::error::{DeepSceneError, Result};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::fs;
+use std::path::Path;
+
+/// Prepended to the hash that is signed, so a DeepScene signature cannot be
+/// passed off as one over some other message.
+const CONTEXT: &[u8] = b"DeepScene payload signature v1\0";
+
+/// Ed25519 signatures over a file's original bytes. Keys are read from
+/// PKCS#8 (private) or SubjectPublicKeyInfo (public) PEM files, as written by
+/// `openssl genpkey -algorithm ed25519`, or from files holding the raw
+/// 32-byte key.
+pub struct SigningEngine;
+
+impl SigningEngine {
+    pub const ALGORITHM: &str = "Ed25519";
+    pub const PUBLIC_KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+    pub const SIGNATURE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+    pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+        let bytes = Self::read_key(path, "signing")?;
+        match Self::pem(&bytes) {
+            Some(pem) => SigningKey::from_pkcs8_pem(pem).map_err(|e| {
+                Self::invalid_key(
+                    path,
+                    "signing",
+                    &format!("not a PKCS#8 Ed25519 key ({})", e),
+                )
+            }),
+            None => bytes
+                .as_slice()
+                .try_into()
+                .map(SigningKey::from_bytes)
+                .map_err(|_| Self::wrong_length(path, "signing", bytes.len())),
+        }
+    }
+
+    /// Reads a public key, returning its raw bytes as stored in a payload.
+    pub fn load_verifying_key(path: &Path) -> Result<[u8; Self::PUBLIC_KEY_LEN]> {
+        let bytes = Self::read_key(path, "verifying")?;
+        let key = match Self::pem(&bytes) {
+            Some(pem) => VerifyingKey::from_public_key_pem(pem).map_err(|e| {
+                Self::invalid_key(
+                    path,
+                    "verifying",
+                    &format!("not an Ed25519 public key ({})", e),
+                )
+            })?,
+            None => {
+                let raw: &[u8; Self::PUBLIC_KEY_LEN] = bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| Self::wrong_length(path, "verifying", bytes.len()))?;
+                VerifyingKey::from_bytes(raw)
+                    .map_err(|_| Self::invalid_key(path, "verifying", "not a point on the curve"))?
+            }
+        };
+        Ok(key.to_bytes())
+    }
+
+    /// Signs `data`, returning the signer's public key and the signature.
+    pub fn sign(
+        key: &SigningKey,
+        data: &[u8],
+    ) -> ([u8; Self::PUBLIC_KEY_LEN], [u8; Self::SIGNATURE_LEN]) {
+        let signature = key.sign(&Self::message(data));
+        (key.verifying_key().to_bytes(), signature.to_bytes())
+    }
+
+    /// Checks that `signature` is `public_key`'s signature over `data`.
+    pub fn verify(
+        public_key: &[u8; Self::PUBLIC_KEY_LEN],
+        signature: &[u8; Self::SIGNATURE_LEN],
+        data: &[u8],
+    ) -> Result<()> {
+        let invalid = || {
+            DeepSceneError::Data(
+                "The signature does not match the extracted file; it was modified after signing"
+                    .to_string(),
+            )
+        };
+        let key = VerifyingKey::from_bytes(public_key).map_err(|_| invalid())?;
+        key.verify_strict(&Self::message(data), &Signature::from_bytes(signature))
+            .map_err(|_| invalid())
+    }
+
+    /// The signed message: the context followed by the BLAKE3 hash of the
+    /// data, so large files are not copied to be signed.
+    fn message(data: &[u8]) -> Vec<u8> {
+        [CONTEXT, blake3::hash(data).as_bytes()].concat()
+    }
+
+    fn read_key(path: &Path, kind: &str) -> Result<Vec<u8>> {
+        fs::read(path).map_err(|e| {
+            DeepSceneError::Validation(format!(
+                "Cannot read {} key '{}': {}",
+                kind,
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    fn pem(bytes: &[u8]) -> Option<&str> {
+        std::str::from_utf8(bytes)
+            .ok()
+            .filter(|text| text.trim_start().starts_with("-----BEGIN"))
+    }
+
+    fn wrong_length(path: &Path, kind: &str, len: usize) -> DeepSceneError {
+        Self::invalid_key(
+            path,
+            kind,
+            &format!(
+                "expected a PEM file or {} raw bytes, found {} bytes",
+                Self::PUBLIC_KEY_LEN,
+                len
+            ),
+        )
+    }
+
+    fn invalid_key(path: &Path, kind: &str, reason: &str) -> DeepSceneError {
+        DeepSceneError::Validation(format!(
+            "Invalid {} key '{}': {}",
+            kind,
+            path.display(),
+            reason
+        ))
+    }
+}
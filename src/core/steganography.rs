@@ -1,42 +1,414 @@
+use crate::core::carrier_metadata::CarrierMetadata;
+use crate::core::compat::CompatSpec;
+use crate::core::crypto::CryptoEngine;
+use crate::core::dng::DngImage;
 use crate::core::error::{DeepSceneError, Result};
-use image::{GenericImageView, RgbaImage};
+use crate::core::header::{
+    HEADER_LEN, HEADER_MAGIC, Header, LEGACY_HEADER_LEN, LEGACY_HEADER_MAGIC, MAX_HEADER_LEN,
+};
+use crate::core::messages;
+use crate::core::parallel;
+use crate::core::png_writer::{PngSettings, PngWriter};
+use crate::core::progress;
+use crate::core::random::{RandomSource, SourceRng};
+use crate::core::resolution::Resolution;
+use crate::core::tiff_strip::TiffStrip;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::codecs::tiff::TiffDecoder;
+use image::{
+    ColorType, DynamicImage, ExtendedColorType, GenericImageView, ImageDecoder, ImageEncoder,
+    ImageError, ImageFormat, RgbaImage,
+};
+use rand::{Rng, RngCore};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
 use std::path::{Path, PathBuf};
+use std::process;
+use tiff::encoder::{TiffEncoder, colortype};
+use tiff::tags::ResolutionUnit;
 
 const MAX_IMAGE_DIMENSION: u32 = 20000;
-const MAX_DATA_LENGTH: usize = 256 * 1024 * 1024;
-const HEADER_MAGIC: &[u8; 4] = b"DPSN";
+const GROUP_SLOTS: usize = 24;
+/// Bytes written between progress updates when a write cannot run in
+/// parallel.
+const SERIAL_CHUNK_LEN: usize = 64 * 1024;
+const VERIFY_ADVICE: &str =
+    "The output format does not preserve pixel values exactly; use PNG, BMP or TIFF";
+
+const COMMON_RESOLUTIONS: [(u32, u32); 8] = [
+    (640, 480),
+    (1280, 720),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+    (6000, 4000),
+    (7680, 4320),
+    (12000, 8000),
+];
+
+pub const FORMAT_VERSION: u8 = 20;
+/// First format version storing a CRC-32 after every payload chunk.
+pub const CHUNK_CRC_VERSION: u8 = 6;
+/// First format version whose header carries layout flags and a group stride.
+pub const STRIDE_VERSION: u8 = 9;
+/// First format version whose layout flags can select per-channel copies.
+pub const CHANNEL_COPIES_VERSION: u8 = 11;
+/// First format version whose layout flags can mark a preserved region.
+pub const PRESERVED_REGION_VERSION: u8 = 13;
+/// First format version that can confine the header and payload to one
+/// strip of rows, found by scanning row starts on extraction.
+pub const TIFF_STRIP_VERSION: u8 = 16;
+/// x, y, width and height as u16, then a CRC-32 of those 8 bytes.
+const REGION_RECORD_LEN: usize = 12;
+/// First format version storing the payload length as an unsigned LEB128
+/// varint, so the header is 12 bytes for payloads under 128 bytes and the
+/// length is not capped at 4 GiB.
+pub const VARINT_LENGTH_VERSION: u8 = 15;
+/// First format version whose header can give each channel its own number
+/// of payload bits.
+pub const CHANNEL_BITS_VERSION: u8 = 18;
+/// Most low bits a channel can give the payload with per-channel bit depths.
+pub const MAX_CHANNEL_BITS: u8 = 4;
+/// Bits per channel (R, G, B) for `--perceptual`. The eye is most sensitive
+/// to green, so it keeps the smallest change.
+pub const PERCEPTUAL_CHANNEL_BITS: [u8; 3] = [2, 1, 2];
+/// Pixels holding the header and region record, which the region may not
+/// cover. 27 bytes fill exactly 9 groups.
+const REGION_HEADER_PIXELS: usize = (HEADER_LEN + REGION_RECORD_LEN) * 8 / 3;
+const CHANNELS: usize = 3;
+const CRC_CHUNK_LEN: usize = 4096;
+const CRC_LEN: usize = 4;
+pub const MAX_BIT_PLANE: u8 = 7;
+/// `RandomSource` streams for LSB matching, indexed by the pixel byte a
+/// chunk starts at. Embedding around a region writes serially from one RNG.
+const LSB_MATCHING_STREAM: &str = "lsb-matching";
+const REGION_MATCHING_STREAM: &str = "lsb-matching-region";
+/// `RandomSource` stream `strip_data` randomizes low bits from.
+const STRIP_STREAM: &str = "strip";
+
+#[derive(Debug, Clone, Default)]
+pub struct EmbedParams {
+    pub bit_plane: u8,
+    /// Adjust mismatching channels by +/-2^plane instead of overwriting the bit,
+    /// which avoids the pairs-of-values histogram artefact of plain replacement.
+    pub lsb_matching: bool,
+    /// Treat the carrier as headerless RGBA bytes of these dimensions.
+    pub raw_dimensions: Option<(u32, u32)>,
+    /// Re-encode the PNG output to land as close as possible to this many
+    /// bytes, typically the size of the original carrier file.
+    pub target_size: Option<u64>,
+    /// Frame the payload with a published interop spec instead of the
+    /// DeepScene header. Compat specs always use bit plane 0.
+    pub compat: Option<CompatSpec>,
+    /// Fill the payload plane after the payload with pseudo-random bits so the
+    /// used and unused regions look alike.
+    pub carrier_noise: bool,
+    /// Write one 3-byte payload group every `stride` groups (8 pixels each)
+    /// instead of contiguously. 0 is treated as 1.
+    pub stride: u16,
+    /// Write a full, independent copy of the header and payload into each of
+    /// the R, G and B channels, dividing capacity by 3.
+    pub channel_copies: bool,
+    /// EXIF and ICC metadata to copy onto PNG or TIFF output. Ignored for
+    /// other output formats.
+    pub carrier_metadata: Option<CarrierMetadata>,
+    /// Leave the pixels of this rectangle untouched, recording it after the
+    /// header so extraction skips the same pixels.
+    pub preserve_region: Option<Region>,
+    /// Write this older format version instead of `FORMAT_VERSION`, for
+    /// readers that predate the current one. Options the version has no
+    /// header field for are rejected.
+    pub format_version: Option<u8>,
+    /// Write the header and payload only into the rows of this strip, and
+    /// keep the carrier's strip layout when the output is a TIFF.
+    pub tiff_strip: Option<TiffStrip>,
+    /// Pixel density written into PNG, TIFF and BMP output.
+    pub resolution: Option<Resolution>,
+    /// Write the payload into the low bits of each channel, this many in R,
+    /// G and B, instead of one bit plane.
+    pub channel_bits: Option<[u8; 3]>,
+    /// Encode PNG output with `PngSettings::CANONICAL` instead of the image
+    /// crate's defaults, so identical pixels give identical bytes.
+    pub canonical_png: bool,
+    /// Pad PNG output with an ancillary chunk up to the next multiple of this
+    /// many bytes, hiding how the payload changed the file size.
+    pub size_bucket: Option<u64>,
+    /// Randomness for LSB matching and `target_size` and `size_bucket`
+    /// padding.
+    pub random: RandomSource,
+}
+
+impl EmbedParams {
+    /// Format version the header is written in.
+    pub fn version(&self) -> u8 {
+        self.format_version.unwrap_or(FORMAT_VERSION)
+    }
+
+    /// The source LSB matching draws from, or `None` to replace bits.
+    fn matching(&self) -> Option<&RandomSource> {
+        self.lsb_matching.then_some(&self.random)
+    }
+}
+
+/// Rectangle of carrier pixels the payload must not touch, such as a
+/// thumbnail or preview area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn area(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Whether the pixel at row-major `index` of an image `image_width`
+    /// pixels wide lies inside the region.
+    fn contains(&self, index: usize, image_width: u32) -> bool {
+        let x = (index % image_width as usize) as u32;
+        let y = (index / image_width as usize) as u32;
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+
+    /// Fails unless the region is non-empty, lies inside a `width`x`height`
+    /// image and leaves the pixels holding the header free. Since the header
+    /// pixels are excluded, the region is always smaller than the image.
+    pub fn validate(&self, width: u32, height: u32) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(DeepSceneError::Validation(format!(
+                "Preserved region {} is empty",
+                self
+            )));
+        }
+
+        let fits = |start: u32, len: u32, limit: u32| {
+            start.checked_add(len).is_some_and(|end| end <= limit)
+        };
+        if !fits(self.x, self.width, width) || !fits(self.y, self.height, height) {
+            return Err(DeepSceneError::Validation(format!(
+                "Preserved region {} extends beyond the {}x{} image",
+                self, width, height
+            )));
+        }
+
+        // The region's first pixel in row-major order is its top-left corner.
+        let first_pixel = self.y as usize * width as usize + self.x as usize;
+        if first_pixel < REGION_HEADER_PIXELS {
+            return Err(DeepSceneError::Validation(format!(
+                "Preserved region {} overlaps the first {} pixels (row-major), which always hold the header",
+                self, REGION_HEADER_PIXELS
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn to_record(self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(REGION_RECORD_LEN);
+        for value in [self.x, self.y, self.width, self.height] {
+            record.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        let crc = crc32fast::hash(&record);
+        record.extend_from_slice(&crc.to_be_bytes());
+        record
+    }
+
+    fn from_record(record: &[u8]) -> Result<Self> {
+        let (fields, crc) = record.split_at(REGION_RECORD_LEN - CRC_LEN);
+        if crc32fast::hash(fields).to_be_bytes() != crc {
+            return Err(DeepSceneError::Data(
+                "Preserved region record is corrupted".to_string(),
+            ));
+        }
+
+        let field = |i: usize| u16::from_be_bytes([fields[i * 2], fields[i * 2 + 1]]) as u32;
+        Ok(Region {
+            x: field(0),
+            y: field(1),
+            width: field(2),
+            height: field(3),
+        })
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ExtractedData {
+    pub format_version: u8,
+    pub bit_plane: u8,
+    pub stride: u16,
+    /// Channel (0 = R, 1 = G, 2 = B) whose copy was used, when the payload
+    /// was written with per-channel copies.
+    pub copy_channel: Option<usize>,
+    /// Pixels the payload skips, when the header recorded a preserved region.
+    pub preserved_region: Option<Region>,
+    /// Full-width rows holding the header and payload, when they were
+    /// confined to a TIFF strip.
+    pub tiff_strip: Option<Region>,
+    /// Low bits per channel (R, G, B), when the payload was written with
+    /// per-channel bit depths.
+    pub channel_bits: Option<[u8; 3]>,
+    pub data: Vec<u8>,
+}
 
 pub struct SteganographyEngine;
 
 impl SteganographyEngine {
     pub fn is_lossless_format(path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            if let Some(ext_str) = ext.to_str() {
-                let ext_lower = ext_str.to_lowercase();
-                return matches!(ext_lower.as_str(), "png" | "bmp" | "tiff" | "tif");
-            }
+        if let Some(ext) = path.extension()
+            && let Some(ext_str) = ext.to_str()
+        {
+            let ext_lower = ext_str.to_lowercase();
+            return matches!(
+                ext_lower.as_str(),
+                "png" | "bmp" | "tiff" | "tif" | "dng" | "ppm" | "pgm" | "pnm"
+            );
         }
         false
     }
 
-    pub fn convert_to_lossless(image_path: &Path) -> Result<PathBuf> {
-        let img = image::open(image_path).map_err(|e| {
-            DeepSceneError::Image(format!(
-                "Failed to open image '{}': {}",
-                image_path.display(),
-                e
-            ))
-        })?;
+    /// Bits per pixel of the BMP at `path`, read from its header, or `None`
+    /// when it is not a readable BMP. Depths below 24 are palette or
+    /// 16-bit colour, which the output cannot keep since it is written with
+    /// 8 bits per channel.
+    pub fn bmp_bit_depth(path: &Path) -> Option<u16> {
+        let mut header = [0u8; 30];
+        File::open(path).ok()?.read_exact(&mut header).ok()?;
+        if header[..2] != *b"BM" {
+            return None;
+        }
+
+        // OS/2 BITMAPCOREHEADER is 12 bytes with 16-bit dimensions, which
+        // moves the bit count forward.
+        let dib_size = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        let offset = if dib_size == 12 { 24 } else { 28 };
+        Some(u16::from_le_bytes([header[offset], header[offset + 1]]))
+    }
+
+    /// Raw carriers are bare RGBA dumps (`width * height * 4` bytes) with no
+    /// container, so their dimensions must be supplied separately.
+    pub fn is_raw_format(path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| matches!(e.to_lowercase().as_str(), "data" | "raw"))
+            .unwrap_or(false)
+    }
+
+    /// Opens an image, falling back to content sniffing when the extension is
+    /// not one the `image` crate maps to a format (e.g. `.pnm`).
+    ///
+    /// CMYK TIFFs are rejected: the decoder silently converts them to RGB with
+    /// a naive formula, so the carrier would no longer match the file's pixels.
+    /// A DNG yields its primary image rather than the thumbnail in its first
+    /// IFD. With the `mmap` feature a TIFF is decoded straight from a mapping
+    /// of the file instead of a copy read into memory.
+    fn open_image(path: &Path) -> Result<DynamicImage> {
+        let open_error =
+            |e: ImageError| DeepSceneError::Image(messages::open_image_failed(path, e));
+
+        let reader = image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| open_error(ImageError::IoError(e)))?;
+
+        if reader.format() != Some(ImageFormat::Tiff) {
+            return reader.decode().map_err(open_error);
+        }
+
+        #[cfg(feature = "mmap")]
+        // SAFETY: the mapping is read-only and dropped before returning. A
+        // file truncated by another process meanwhile faults the read, which
+        // is the accepted cost of the mmap feature.
+        let bytes = File::open(path)
+            .and_then(|file| unsafe { memmap2::Mmap::map(&file) })
+            .map_err(|e| open_error(ImageError::IoError(e)))?;
+        #[cfg(not(feature = "mmap"))]
+        let bytes = fs::read(path).map_err(|e| open_error(ImageError::IoError(e)))?;
+
+        Self::decode_tiff(&bytes, path)
+    }
+
+    fn decode_tiff(bytes: &[u8], source: &Path) -> Result<DynamicImage> {
+        let open_error =
+            |e: ImageError| DeepSceneError::Image(messages::open_image_failed(source, e));
+
+        if DngImage::is_dng(bytes) {
+            let dng = DngImage::read(bytes, source)?;
+            Self::check_dimensions(dng.width, dng.height)?;
+            return dng.to_rgba(bytes).map(DynamicImage::ImageRgba8);
+        }
+
+        let decoder = TiffDecoder::new(Cursor::new(bytes)).map_err(open_error)?;
+
+        if decoder.original_color_type() == ExtendedColorType::Cmyk8 {
+            return Err(DeepSceneError::Image(format!(
+                "'{}' is a CMYK TIFF, which cannot be used without a lossy colour conversion. Convert it to RGB first",
+                source.display()
+            )));
+        }
+
+        DynamicImage::from_decoder(decoder).map_err(open_error)
+    }
+
+    /// Writes a PNG copy of a lossy carrier to `target`, or to the system temp
+    /// directory if that location is not writable, and returns the path used.
+    /// Decode failures surface as `Image` errors and write failures as `Io`
+    /// errors.
+    pub fn convert_to_lossless(image_path: &Path, target: &Path) -> Result<PathBuf> {
+        let img = Self::open_image(image_path)?;
 
-        let temp_path = image_path.with_extension("png");
+        let primary_error = match img.save(target) {
+            Ok(()) => return Ok(target.to_path_buf()),
+            Err(ImageError::IoError(e)) => e,
+            Err(e) => {
+                return Err(DeepSceneError::Image(messages::convert_to_png_failed(e)));
+            }
+        };
 
-        img.save(&temp_path)
-            .map_err(|e| DeepSceneError::Image(format!("Failed to convert image to PNG: {}", e)))?;
+        let stem = image_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("carrier");
+        let fallback_path =
+            env::temp_dir().join(format!("deepscene-{}-{}.png", stem, process::id()));
 
-        Ok(temp_path)
+        match img.save(&fallback_path) {
+            Ok(()) => Ok(fallback_path),
+            Err(ImageError::IoError(e)) => Err(DeepSceneError::Io(io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot write converted PNG to '{}' ({}) or '{}' ({})",
+                    target.display(),
+                    primary_error,
+                    fallback_path.display(),
+                    e
+                ),
+            ))),
+            Err(e) => Err(DeepSceneError::Image(messages::convert_to_png_failed(e))),
+        }
     }
 
     pub fn validate_image(path: &Path) -> Result<(u32, u32)> {
+        Ok(Self::load_image(path)?.dimensions())
+    }
+
+    /// Decodes a carrier once and normalises it to 8-bit RGBA. Any lossless
+    /// encoding of the same pixels (PNG, BMP, TIFF, PNM, 8 or 16 bits per
+    /// sample) yields identical low bits after this conversion.
+    pub fn load_image(path: &Path) -> Result<RgbaImage> {
         if !path.exists() {
             return Err(DeepSceneError::Validation(format!(
                 "Input image '{}' not found",
@@ -51,12 +423,41 @@ impl SteganographyEngine {
             )));
         }
 
-        let img = image::open(path).map_err(|e| {
-            DeepSceneError::Image(format!("Failed to open image '{}': {}", path.display(), e))
-        })?;
+        let img = Self::open_image(path)?;
 
         let (width, height) = img.dimensions();
+        Self::check_dimensions(width, height)?;
+
+        Ok(img.to_rgba8())
+    }
+
+    /// Decodes an image already held in memory, such as one read from
+    /// standard input or a URL. `source` names it in error messages. The
+    /// dimensions are read from the image header and checked before any
+    /// pixels are decoded.
+    pub fn load_image_from_memory(bytes: &[u8], source: &Path) -> Result<RgbaImage> {
+        let open_error =
+            |e: ImageError| DeepSceneError::Image(messages::open_image_failed(source, e));
+        let reader = || {
+            image::io::Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .map_err(|e| open_error(ImageError::IoError(e)))
+        };
 
+        let (width, height) = reader()?.into_dimensions().map_err(open_error)?;
+        Self::check_dimensions(width, height)?;
+
+        let reader = reader()?;
+        let img = if reader.format() == Some(ImageFormat::Tiff) {
+            Self::decode_tiff(bytes, source)?
+        } else {
+            reader.decode().map_err(open_error)?
+        };
+
+        Ok(img.to_rgba8())
+    }
+
+    fn check_dimensions(width: u32, height: u32) -> Result<()> {
         if width == 0 || height == 0 {
             return Err(DeepSceneError::Validation(
                 "Image has invalid dimensions".to_string(),
@@ -70,7 +471,57 @@ impl SteganographyEngine {
             )));
         }
 
-        Ok((width, height))
+        Ok(())
+    }
+
+    pub fn load_raw(path: &Path, width: u32, height: u32) -> Result<RgbaImage> {
+        if !path.is_file() {
+            return Err(DeepSceneError::Validation(format!(
+                "Raw carrier '{}' not found",
+                path.display()
+            )));
+        }
+
+        Self::check_raw_dimensions(width, height)?;
+        Self::load_raw_from_memory(fs::read(path)?, width, height, path)
+    }
+
+    /// Builds a raw carrier from bytes already in memory. `source` names them
+    /// in error messages.
+    pub fn load_raw_from_memory(
+        bytes: Vec<u8>,
+        width: u32,
+        height: u32,
+        source: &Path,
+    ) -> Result<RgbaImage> {
+        Self::check_raw_dimensions(width, height)?;
+        let expected = width as u64 * height as u64 * 4;
+
+        if bytes.len() as u64 != expected {
+            return Err(DeepSceneError::Validation(format!(
+                "Raw carrier '{}' is {} bytes, but {}x{} RGBA requires exactly {} bytes",
+                source.display(),
+                bytes.len(),
+                width,
+                height,
+                expected
+            )));
+        }
+
+        RgbaImage::from_raw(width, height, bytes)
+            .ok_or_else(|| DeepSceneError::Image(messages::IMAGE_FROM_RAW_DATA.to_string()))
+    }
+
+    fn check_raw_dimensions(width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 || width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "Invalid raw dimensions {}x{}. Each side must be between 1 and {} pixels",
+                width, height, MAX_IMAGE_DIMENSION
+            )));
+        }
+
+        Ok(())
     }
 
     pub fn calculate_capacity(width: u32, height: u32) -> usize {
@@ -78,198 +529,2393 @@ impl SteganographyEngine {
         ((total_pixels * 3) / 8) as usize
     }
 
-    fn calculate_header_checksum(data: &[u8]) -> u16 {
-        data.iter().fold(0u16, |acc, &b| acc.wrapping_add(b as u16))
+    /// Payload bytes an image can hold in the layout `params` selects (compat
+    /// framing, stride, per-channel copies or a preserved region), once the
+    /// header and chunk CRCs are accounted for. Except with a preserved
+    /// region, this is the inverse of `required_bytes`: every length up to it
+    /// fits and none above it does.
+    pub fn payload_capacity(width: u32, height: u32, params: &EmbedParams) -> usize {
+        if let Some(spec) = params.compat {
+            return Self::calculate_capacity(width, height).saturating_sub(spec.header_len());
+        }
+
+        let version = params.version();
+        let after_header = |header_len: usize| match (params.tiff_strip, params.channel_bits) {
+            (Some(strip), _) => (strip.rows as usize * width as usize * 3 / 8)
+                .saturating_sub(header_len + REGION_RECORD_LEN),
+            (None, Some(bits)) => Self::channel_bits_capacity(width, height, bits, header_len),
+            (None, None) => Self::strided_capacity(width, height, params.stride, header_len),
+        };
+
+        let available = if let Some(region) = params.preserve_region {
+            Self::free_slot_count(width, height, region) / 8
+        } else if params.channel_copies {
+            let pixels = width as usize * height as usize;
+            (pixels / 8).saturating_sub(HEADER_LEN)
+        } else if version >= VARINT_LENGTH_VERSION {
+            // The header grows with the length it stores, so take the longest
+            // payload that fits next to a length field of each size.
+            return Header::varint_sizes(params)
+                .map(|(header_len, max_length)| {
+                    Self::stored_capacity(version, after_header(header_len)).min(max_length)
+                })
+                .max()
+                .unwrap_or(0);
+        } else {
+            after_header(Header::for_params(params, 0).encoded_len())
+        };
+
+        Self::stored_capacity(version, available)
     }
 
-    pub fn hide_data(image_path: &Path, data: &[u8], output_path: &Path) -> Result<()> {
-        let (width, height) = Self::validate_image(image_path)?;
+    /// Pixel the payload starts at with per-channel bit depths: the first one
+    /// after a `header_len`-byte header written in plane 0.
+    fn channel_bits_start(header_len: usize) -> usize {
+        (header_len * 8).div_ceil(CHANNELS)
+    }
 
-        let img = image::open(image_path)?;
-        let mut rgba_img = img.to_rgba8();
+    /// Payload and CRC bytes that fit after a `header_len`-byte header when
+    /// every pixel gives `bits` low bits of R, G and B.
+    fn channel_bits_capacity(width: u32, height: u32, bits: [u8; 3], header_len: usize) -> usize {
+        let pixels =
+            (width as usize * height as usize).saturating_sub(Self::channel_bits_start(header_len));
+        pixels * Self::bits_per_pixel(bits) / 8
+    }
 
-        let max_bytes = Self::calculate_capacity(width, height);
-        let required_bytes = data.len() + 10;
+    fn bits_per_pixel(bits: [u8; 3]) -> usize {
+        bits.iter().map(|&n| n as usize).sum()
+    }
 
-        if required_bytes > max_bytes {
-            let max_data_size = max_bytes.saturating_sub(10);
-            let min_pixels_needed = ((required_bytes * 8) as f64 / 3.0).ceil() as u64;
-            let min_dimension = (min_pixels_needed as f64).sqrt().ceil() as u32;
+    /// Carrier bytes left for payload and CRCs after a `header_len`-byte
+    /// header when one 3-byte group is written every `stride` groups.
+    fn strided_capacity(width: u32, height: u32, stride: u16, header_len: usize) -> usize {
+        let group_span = 3 * stride.max(1) as usize;
+        let remaining = Self::calculate_capacity(width, height)
+            .saturating_sub(Self::payload_offset(header_len, stride));
+        remaining / group_span * 3 + (remaining % group_span).min(3)
+    }
 
-            return Err(DeepSceneError::Validation(format!(
-                "Data too large for image. Image can hold {} bytes, but {} bytes needed. Try using an image at least {}x{} pixels.",
-                max_data_size,
-                data.len(),
-                min_dimension,
-                min_dimension
-            )));
+    /// Payload bytes that fit in `available` carrier bytes once the chunk
+    /// CRCs of the given format version are added.
+    fn stored_capacity(version: u8, available: usize) -> usize {
+        if version < CHUNK_CRC_VERSION {
+            return available;
         }
 
-        Self::embed_data(&mut rgba_img, data)?;
+        let full_chunks = available / (CRC_CHUNK_LEN + CRC_LEN);
+        let remainder = available % (CRC_CHUNK_LEN + CRC_LEN);
+        full_chunks * CRC_CHUNK_LEN + remainder.saturating_sub(CRC_LEN)
+    }
 
-        rgba_img.save(output_path).map_err(|e| {
-            DeepSceneError::Image(format!(
-                "Failed to save output image '{}': {}",
-                output_path.display(),
-                e
-            ))
-        })?;
+    /// Byte offset of the payload after a `header_len`-byte header. Strided
+    /// payloads start on a group boundary, which only a varint header can
+    /// miss.
+    fn payload_offset(header_len: usize, stride: u16) -> usize {
+        if stride > 1 {
+            header_len.next_multiple_of(3)
+        } else {
+            header_len
+        }
+    }
 
-        Ok(())
+    /// Carrier bytes occupied by `length` payload bytes in the given format
+    /// version, including the per-chunk CRCs.
+    fn stored_len(version: u8, length: usize) -> usize {
+        if version >= CHUNK_CRC_VERSION {
+            length + length.div_ceil(CRC_CHUNK_LEN) * CRC_LEN
+        } else {
+            length
+        }
     }
 
-    fn embed_data(image: &mut RgbaImage, data: &[u8]) -> Result<()> {
-        let length = data.len() as u32;
-        let length_bytes = length.to_be_bytes();
+    fn add_chunk_crcs(data: &[u8]) -> Vec<u8> {
+        let mut stored = Vec::with_capacity(Self::stored_len(CHUNK_CRC_VERSION, data.len()));
+        for chunk in data.chunks(CRC_CHUNK_LEN) {
+            stored.extend_from_slice(chunk);
+            stored.extend_from_slice(&crc32fast::hash(chunk).to_be_bytes());
+        }
+        stored
+    }
 
-        let mut header = Vec::new();
-        header.extend_from_slice(HEADER_MAGIC);
-        header.extend_from_slice(&length_bytes);
+    /// Checks and strips the per-chunk CRCs, pinpointing the first damaged
+    /// region instead of leaving decompression or decryption to fail later.
+    fn strip_chunk_crcs(stored: &[u8]) -> Result<Vec<u8>> {
+        let chunk_count = stored.len().div_ceil(CRC_CHUNK_LEN + CRC_LEN);
+        let mut data = Vec::with_capacity(stored.len());
 
-        let checksum = Self::calculate_header_checksum(&header);
-        header.extend_from_slice(&checksum.to_be_bytes());
+        for (index, chunk) in stored.chunks(CRC_CHUNK_LEN + CRC_LEN).enumerate() {
+            let (payload, crc) = chunk.split_at(chunk.len() - CRC_LEN);
 
-        let mut all_data = Vec::new();
-        all_data.extend_from_slice(&header);
-        all_data.extend_from_slice(data);
+            if crc32fast::hash(payload).to_be_bytes() != crc {
+                let start = index * CRC_CHUNK_LEN;
+                return Err(DeepSceneError::Data(format!(
+                    "Payload corrupted in chunk {} of {} (payload bytes {}-{}). The carrier was damaged in that region",
+                    index + 1,
+                    chunk_count,
+                    start,
+                    start + payload.len() - 1
+                )));
+            }
 
-        let (width, height) = image.dimensions();
-        let mut bit_index = 0;
+            data.extend_from_slice(payload);
+        }
 
-        'outer: for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel_mut(x, y);
+        Ok(data)
+    }
 
-                for channel in 0..3 {
-                    if bit_index >= all_data.len() * 8 {
-                        break 'outer;
-                    }
+    /// Smallest dimensions with the carrier's aspect ratio whose capacity holds
+    /// `required_bytes`, found by scaling and then growing until it fits.
+    pub fn suggest_dimensions(width: u32, height: u32, required_bytes: usize) -> (u32, u32) {
+        let capacity = Self::calculate_capacity(width, height).max(1);
+        let scale = (required_bytes as f64 / capacity as f64).sqrt();
 
-                    let byte_index = bit_index / 8;
-                    let bit_position = 7 - (bit_index % 8);
-                    let bit = (all_data[byte_index] >> bit_position) & 1;
+        let mut suggested_width = ((width as f64 * scale).ceil() as u32).max(1);
+        let mut suggested_height = ((height as f64 * scale).ceil() as u32).max(1);
 
-                    pixel[channel] = (pixel[channel] & 0xFE) | bit;
-                    bit_index += 1;
-                }
+        while Self::calculate_capacity(suggested_width, suggested_height) < required_bytes {
+            if suggested_width as u64 * height as u64 <= suggested_height as u64 * width as u64 {
+                suggested_width += 1;
+            } else {
+                suggested_height += 1;
             }
         }
 
-        Ok(())
+        (suggested_width, suggested_height)
     }
 
-    pub fn extract_data(image_path: &Path) -> Result<Vec<u8>> {
-        let (width, height) = Self::validate_image(image_path)?;
-
-        let img = image::open(image_path)?;
-        let rgba_img = img.to_rgba8();
+    /// Carrier bytes needed to embed `payload_len` bytes in the layout
+    /// `params` selects, header included. With a stride the payload reaches
+    /// further into the carrier, so this is the offset just past its last
+    /// byte. Per-channel copies need three times the space of a single copy.
+    /// A preserved region's pixels count as used, rounded up to whole bytes,
+    /// so the result is approximate and only suited to size suggestions. For
+    /// a TIFF strip it counts only the strip's bytes. With per-channel bit
+    /// depths it is the pixels used, expressed at one bit per channel. A
+    /// stride that spreads the payload beyond `usize` saturates.
+    pub fn required_bytes(payload_len: usize, params: &EmbedParams) -> usize {
+        let stored_len = Self::stored_len(params.version(), payload_len);
+        let header_len = Header::for_params(params, payload_len).encoded_len();
+        match (params.compat, params.preserve_region) {
+            (Some(spec), _) => spec.header_len() + payload_len,
+            (None, Some(region)) => {
+                ((REGION_HEADER_PIXELS + region.area()) * 3).div_ceil(8) + stored_len
+            }
+            (None, None) if params.tiff_strip.is_some() => {
+                header_len + REGION_RECORD_LEN + stored_len
+            }
+            (None, None) if params.channel_bits.is_some() => {
+                let bits_per_pixel = Self::bits_per_pixel(params.channel_bits.unwrap_or_default());
+                let pixels = Self::channel_bits_start(header_len)
+                    + (stored_len * 8).div_ceil(bits_per_pixel);
+                (pixels * CHANNELS).div_ceil(8)
+            }
+            (None, None) if params.channel_copies => {
+                Self::embedded_len(HEADER_LEN, stored_len, params.stride, true)
+                    .unwrap_or(usize::MAX)
+            }
+            (None, None) => Self::embedded_len(
+                Self::payload_offset(header_len, params.stride),
+                stored_len,
+                params.stride,
+                false,
+            )
+            .unwrap_or(usize::MAX),
+        }
+    }
 
-        let total_pixels = width as usize * height as usize;
-        let max_bits = (total_pixels * 3).min(80 + (MAX_DATA_LENGTH * 8));
+    /// Carrier bytes occupied by a header and `stored_length` bytes of
+    /// payload and CRCs. Encode and decode both check capacity with this, so
+    /// a payload accepted at the exact boundary is also extracted. `None`
+    /// when the span overflows `usize`, which a header's length and stride
+    /// can declare on 32-bit targets.
+    fn embedded_len(
+        header_len: usize,
+        stored_length: usize,
+        stride: u16,
+        channel_copies: bool,
+    ) -> Option<usize> {
+        if channel_copies {
+            header_len.checked_add(stored_length)?.checked_mul(CHANNELS)
+        } else {
+            header_len.checked_add(Self::strided_span(stored_length, stride)?)
+        }
+    }
 
-        let mut all_bits = Vec::with_capacity(max_bits.min(100000));
+    /// Carrier bytes spanned by `length` payload bytes written in groups of
+    /// three, one group every `stride` groups, or `None` on overflow.
+    fn strided_span(length: usize, stride: u16) -> Option<usize> {
+        match length {
+            0 => Some(0),
+            _ => ((length - 1) / 3 * 3)
+                .checked_mul(stride.max(1) as usize)?
+                .checked_add((length - 1) % 3 + 1),
+        }
+    }
 
-        'outer: for y in 0..height {
-            for x in 0..width {
-                let pixel = rgba_img.get_pixel(x, y);
+    /// Payload slots left once the header, region record and `region` are
+    /// set aside.
+    fn free_slot_count(width: u32, height: u32, region: Region) -> usize {
+        let pixels = width as usize * height as usize;
+        pixels.saturating_sub(REGION_HEADER_PIXELS + region.area()) * 3
+    }
 
-                for channel in 0..3 {
-                    all_bits.push(pixel[channel] & 1);
-                    if all_bits.len() >= max_bits {
-                        break 'outer;
-                    }
-                }
+    /// Fails when the carrier cannot hold even the header of the layout
+    /// `params` selects, before any payload is considered. Dimension advice
+    /// scaled from the payload size means little at that point, so the error
+    /// states the absolute minimum pixel count instead.
+    pub fn check_header_fits(width: u32, height: u32, params: &EmbedParams) -> Result<()> {
+        let (label, header_len, bits_per_pixel) = match params.compat {
+            Some(spec) => (format!("the {} header", spec.name()), spec.header_len(), 3),
+            None if params.channel_copies => {
+                ("each channel's header copy".to_string(), HEADER_LEN, 1)
             }
+            None if params.preserve_region.is_some() => (
+                "the header and region record".to_string(),
+                HEADER_LEN + REGION_RECORD_LEN,
+                3,
+            ),
+            None => (
+                "the header".to_string(),
+                Header::for_params(params, 0).encoded_len(),
+                3,
+            ),
+        };
+
+        match Self::too_small(width, height, &label, header_len, bits_per_pixel) {
+            Some(message) => Err(DeepSceneError::CapacityExceeded(format!(
+                "{}. Nothing can be embedded",
+                message
+            ))),
+            None => Ok(()),
         }
+    }
 
-        if all_bits.len() < 80 {
-            return Err(DeepSceneError::Data(
-                "Image dimensions insufficient for data extraction".to_string(),
+    /// Describes why a `width`x`height` image cannot hold a `header_len`-byte
+    /// header written at `bits_per_pixel`, or `None` when it can.
+    fn too_small(
+        width: u32,
+        height: u32,
+        label: &str,
+        header_len: usize,
+        bits_per_pixel: usize,
+    ) -> Option<String> {
+        let pixels = width as usize * height as usize;
+        let min_pixels = (header_len * 8).div_ceil(bits_per_pixel);
+
+        (pixels < min_pixels).then(|| {
+            format!(
+                "A {}x{} image has {} pixel{}, but {} ({} bytes) alone needs at least {} pixels",
+                width,
+                height,
+                pixels,
+                if pixels == 1 { "" } else { "s" },
+                label,
+                header_len,
+                min_pixels
+            )
+        })
+    }
+
+    /// Advice for a carrier too small to hold `required_bytes`, naming the
+    /// minimum dimensions and, where one fits, a common resolution.
+    pub fn size_suggestion(width: u32, height: u32, required_bytes: usize) -> String {
+        if required_bytes > Self::calculate_capacity(MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION) {
+            return format!(
+                "No image within the {}x{} limit can hold that; use a smaller payload or stride",
+                MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION
+            );
+        }
+
+        let (min_width, min_height) = Self::suggest_dimensions(width, height, required_bytes);
+        let mut message = format!(
+            "Try using an image at least {}x{} pixels (same aspect ratio)",
+            min_width, min_height
+        );
+
+        if let Some((common_width, common_height)) = Self::common_resolution(required_bytes) {
+            message.push_str(&format!(
+                ", e.g. a {}x{} photo",
+                common_width, common_height
             ));
         }
+        message.push('.');
 
-        Self::validate_and_extract(&all_bits, width, height)
+        message
     }
 
-    fn validate_and_extract(bits: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
-        let mut header = Vec::new();
-        for i in 0..10 {
-            let mut byte = 0u8;
-            for j in 0..8 {
-                byte = (byte << 1) | bits[i * 8 + j];
-            }
-            header.push(byte);
+    /// Smallest common camera/display resolution able to hold `required_bytes`.
+    pub fn common_resolution(required_bytes: usize) -> Option<(u32, u32)> {
+        COMMON_RESOLUTIONS
+            .iter()
+            .copied()
+            .find(|&(w, h)| Self::calculate_capacity(w, h) >= required_bytes)
+    }
+
+    /// Fails when `params` asks for a layout the target format version has no
+    /// header field for.
+    pub fn check_version_supports(params: &EmbedParams) -> Result<()> {
+        let version = params.version();
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(DeepSceneError::Validation(format!(
+                "Invalid format version ({}). Must be between 1 and {}",
+                version, FORMAT_VERSION
+            )));
         }
 
-        if &header[0..4] != HEADER_MAGIC {
-            return Err(DeepSceneError::Data(
-                "No embedded data detected. This image does not appear to contain steganographic content".to_string()
+        if params.compat.is_some() && params.format_version.is_some() {
+            return Err(DeepSceneError::Validation(
+                "A format version cannot be combined with a compat spec, which has its own header"
+                    .to_string(),
             ));
         }
 
-        let stored_checksum = u16::from_be_bytes([header[8], header[9]]);
-        let computed_checksum = Self::calculate_header_checksum(&header[0..8]);
+        let unsupported = [
+            (params.bit_plane > 0, 2, "a bit plane other than 0"),
+            (params.stride > 1, STRIDE_VERSION, "a stride"),
+            (
+                params.channel_copies,
+                CHANNEL_COPIES_VERSION,
+                "per-channel copies",
+            ),
+            (
+                params.preserve_region.is_some(),
+                PRESERVED_REGION_VERSION,
+                "a preserved region",
+            ),
+            (
+                params.tiff_strip.is_some(),
+                TIFF_STRIP_VERSION,
+                "a TIFF strip",
+            ),
+            (
+                params.channel_bits.is_some(),
+                CHANNEL_BITS_VERSION,
+                "per-channel bit depths",
+            ),
+        ];
 
-        if stored_checksum != computed_checksum {
-            return Err(DeepSceneError::Data(
-                "Data integrity check failed. The embedded data may be corrupted".to_string(),
-            ));
+        match unsupported
+            .into_iter()
+            .find(|&(used, minimum, _)| used && version < minimum)
+        {
+            Some((_, minimum, label)) => Err(DeepSceneError::Validation(
+                messages::version_lacks_feature(version, label, minimum),
+            )),
+            None => Ok(()),
         }
+    }
 
-        let data_length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    pub fn hide_data(
+        image_path: &Path,
+        data: &[u8],
+        output_path: &Path,
+        params: &EmbedParams,
+    ) -> Result<()> {
+        let format = Self::output_format(output_path);
+        let (encoded, width, height) = Self::embed_and_encode(image_path, data, &format, params)?;
 
-        if data_length == 0 {
-            return Err(DeepSceneError::Data(
-                "No embedded data detected".to_string(),
-            ));
+        fs::write(output_path, encoded).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Failed to save output image '{}': {}",
+                output_path.display(),
+                e
+            ))
+        })?;
+
+        let reloaded = if Self::is_raw_format(output_path) {
+            Self::load_raw(output_path, width, height)
+        } else {
+            Self::load_image(output_path)
+        };
+        Self::verify_output(reloaded, data, params.compat).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Verification of saved image '{}' failed ({}). {}",
+                output_path.display(),
+                e,
+                VERIFY_ADVICE
+            ))
+        })
+    }
+
+    /// Writes the image at `image_path` to `output_path` with the low
+    /// `planes` bit planes of every R, G and B value cleared, or filled from
+    /// `random` when `randomize` is set. Every header and payload is destroyed
+    /// while no value moves by 2^planes or more. Fails if a header survives
+    /// the output encoder.
+    pub fn strip_data(
+        image_path: &Path,
+        output_path: &Path,
+        planes: u8,
+        randomize: Option<&RandomSource>,
+    ) -> Result<()> {
+        if planes == 0 || planes > MAX_BIT_PLANE + 1 {
+            return Err(DeepSceneError::Validation(format!(
+                "Invalid number of bit planes ({}). Must be between 1 and {}",
+                planes,
+                MAX_BIT_PLANE + 1
+            )));
         }
 
-        if data_length > MAX_DATA_LENGTH {
-            return Err(DeepSceneError::Data(format!(
-                "Invalid data length detected ({} bytes). Maximum is {} MB.",
-                data_length,
-                MAX_DATA_LENGTH / (1024 * 1024)
+        let mut image = Self::load_image(image_path)?;
+        let mask = (0xFFu16 << planes) as u8;
+        let mut rng = randomize.map(|random| random.rng(STRIP_STREAM, 0));
+        let _tracking = progress::track("strip", image.len());
+        for pixel in image.chunks_exact_mut(4) {
+            for value in &mut pixel[..CHANNELS] {
+                let low = rng.as_mut().map_or(0, |rng| rng.r#gen::<u8>() & !mask);
+                *value = (*value & mask) | low;
+            }
+            progress::advance(4);
+        }
+
+        let format = Self::output_format(output_path);
+        let encoded = if format == "dng" {
+            Self::encode_dng(image_path, &image, &EmbedParams::default())?
+        } else {
+            Self::encode_image(image, &format, &EmbedParams::default())?
+        };
+        fs::write(output_path, encoded).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Failed to save output image '{}': {}",
+                output_path.display(),
+                e
+            ))
+        })?;
+
+        if Self::has_header(&Self::load_image(output_path)?) {
+            return Err(DeepSceneError::Image(format!(
+                "'{}' still holds a DeepScene header after stripping",
+                output_path.display()
             )));
         }
+        Ok(())
+    }
 
-        let total_bits_needed = 80 + (data_length * 8);
-        let available_bits = width as usize * height as usize * 3;
+    /// Like `hide_data`, but returns the encoded image instead of writing it.
+    /// `format` is an output file extension such as `"png"` or `"data"`.
+    pub fn hide_data_to_bytes(
+        image_path: &Path,
+        data: &[u8],
+        format: &str,
+        params: &EmbedParams,
+    ) -> Result<Vec<u8>> {
+        let format = format.to_lowercase();
+        let (encoded, width, height) = Self::embed_and_encode(image_path, data, &format, params)?;
 
-        if total_bits_needed > available_bits {
-            return Err(DeepSceneError::Data(format!(
-                "Image capacity exceeded. Required: {} bits. Available: {} bits",
-                total_bits_needed, available_bits
+        let reloaded = if matches!(format.as_str(), "data" | "raw") {
+            RgbaImage::from_raw(width, height, encoded.clone())
+                .ok_or_else(|| DeepSceneError::Image(messages::IMAGE_FROM_RAW_DATA.to_string()))
+        } else {
+            Self::load_image_from_memory(&encoded, Path::new("encoded output"))
+        };
+        Self::verify_output(reloaded, data, params.compat).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Verification of encoded image failed ({}). {}",
+                e, VERIFY_ADVICE
+            ))
+        })?;
+
+        Ok(encoded)
+    }
+
+    fn embed_and_encode(
+        image_path: &Path,
+        data: &[u8],
+        format: &str,
+        params: &EmbedParams,
+    ) -> Result<(Vec<u8>, u32, u32)> {
+        if params.bit_plane > MAX_BIT_PLANE {
+            return Err(DeepSceneError::Validation(format!(
+                "Invalid bit plane ({}). Must be between 0 and {}",
+                params.bit_plane, MAX_BIT_PLANE
             )));
         }
 
-        if bits.len() < total_bits_needed {
-            return Err(DeepSceneError::Data(format!(
-                "Cannot extract data: need {} bits but only {} bits available",
-                total_bits_needed,
-                bits.len()
+        if let Some(spec) = params.compat
+            && params.bit_plane != 0
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "The {} compat spec only uses bit plane 0",
+                spec.name()
             )));
         }
 
-        Self::extract_bytes(bits, data_length)
-    }
+        Self::check_version_supports(params)?;
 
-    fn extract_bytes(bits: &[u8], length: usize) -> Result<Vec<u8>> {
-        let mut data = Vec::with_capacity(length);
+        if let Some(bucket) = params.size_bucket {
+            if format != "png" {
+                return Err(DeepSceneError::Validation(format!(
+                    "Size buckets require PNG output, not '{}'",
+                    format
+                )));
+            }
+            if bucket == 0 {
+                return Err(DeepSceneError::Validation(
+                    "The size bucket must be at least 1 byte".to_string(),
+                ));
+            }
+            if params.target_size.is_some() {
+                return Err(DeepSceneError::Validation(
+                    "Size buckets cannot be combined with size matching, which sets the size itself"
+                        .to_string(),
+                ));
+            }
+        }
 
-        for i in 0..length {
-            let mut byte = 0u8;
-            for j in 0..8 {
-                let bit_pos = 80 + (i * 8) + j;
-                if bit_pos >= bits.len() {
-                    return Err(DeepSceneError::Data(
-                        "Unexpected end of data while extracting".to_string(),
-                    ));
-                }
-                byte = (byte << 1) | bits[bit_pos];
+        let mut rgba_img = match params.raw_dimensions {
+            Some((width, height)) => Self::load_raw(image_path, width, height)?,
+            None => Self::load_image(image_path)?,
+        };
+        let (width, height) = rgba_img.dimensions();
+
+        if let Some(spec) = params.compat
+            && params.stride > 1
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "The {} compat spec has no header field for a stride",
+                spec.name()
+            )));
+        }
+
+        if params.channel_copies && (params.compat.is_some() || params.stride > 1) {
+            return Err(DeepSceneError::Validation(
+                "Per-channel copies cannot be combined with a compat spec or a stride".to_string(),
+            ));
+        }
+
+        if let Some(region) = params.preserve_region {
+            if params.compat.is_some() || params.stride > 1 || params.channel_copies {
+                return Err(DeepSceneError::Validation(
+                    "A preserved region cannot be combined with a compat spec, a stride or per-channel copies"
+                        .to_string(),
+                ));
             }
-            data.push(byte);
+            region.validate(width, height)?;
         }
 
-        Ok(data)
+        if let Some(strip) = params.tiff_strip {
+            if params.compat.is_some()
+                || params.stride > 1
+                || params.channel_copies
+                || params.preserve_region.is_some()
+            {
+                return Err(DeepSceneError::Validation(
+                    "A TIFF strip cannot be combined with a compat spec, a stride, per-channel copies or a preserved region"
+                        .to_string(),
+                ));
+            }
+            if !matches!(format, "tiff" | "tif") {
+                return Err(DeepSceneError::Validation(format!(
+                    "Embedding into a TIFF strip requires TIFF output, not '{}'",
+                    format
+                )));
+            }
+            if strip.rows == 0 || strip.first_row + strip.rows > height {
+                return Err(DeepSceneError::Validation(format!(
+                    "TIFF strip {} lies outside the {}x{} image",
+                    strip.index, width, height
+                )));
+            }
+        }
+
+        if let Some(bits) = params.channel_bits {
+            if params.compat.is_some()
+                || params.stride > 1
+                || params.channel_copies
+                || params.preserve_region.is_some()
+                || params.tiff_strip.is_some()
+                || params.bit_plane != 0
+            {
+                return Err(DeepSceneError::Validation(
+                    "Per-channel bit depths cannot be combined with a compat spec, a stride, per-channel copies, a preserved region, a TIFF strip or a bit plane"
+                        .to_string(),
+                ));
+            }
+            if bits.iter().any(|&n| n == 0 || n > MAX_CHANNEL_BITS) {
+                return Err(DeepSceneError::Validation(format!(
+                    "Invalid channel bit depths {:?}. Each must be between 1 and {}",
+                    bits, MAX_CHANNEL_BITS
+                )));
+            }
+        }
+
+        Self::check_header_fits(width, height, params)?;
+
+        let capacity = Self::payload_capacity(width, height, params);
+
+        if let Some(strip) = params.tiff_strip
+            && data.len() > capacity
+        {
+            return Err(DeepSceneError::CapacityExceeded(format!(
+                "Data too large for TIFF strip {} (rows {}-{}). The strip can hold {} bytes, but {} bytes needed. Choose a carrier with more rows per strip",
+                strip.index,
+                strip.first_row,
+                strip.first_row + strip.rows - 1,
+                capacity,
+                data.len()
+            )));
+        }
+
+        if data.len() > capacity {
+            let message = format!(
+                "Data too large for image. Image can hold {} bytes, but {} bytes needed. {}",
+                capacity,
+                data.len(),
+                Self::size_suggestion(width, height, Self::required_bytes(data.len(), params))
+            );
+
+            return Err(DeepSceneError::CapacityExceeded(message));
+        }
+
+        Self::embed_data(&mut rgba_img, data, params)?;
+        let encoded = if format == "dng" {
+            Self::encode_dng(image_path, &rgba_img, params)?
+        } else {
+            Self::encode_image(rgba_img, format, params)?
+        };
+        Ok((encoded, width, height))
+    }
+
+    /// Writes the pixels back into a copy of the carrier DNG, leaving every
+    /// other byte of it, and so all of its metadata, unchanged.
+    fn encode_dng(image_path: &Path, image: &RgbaImage, params: &EmbedParams) -> Result<Vec<u8>> {
+        if params.target_size.is_some() {
+            return Err(DeepSceneError::Validation(
+                "Matching the carrier size requires PNG output, not 'dng'".to_string(),
+            ));
+        }
+        if params.canonical_png {
+            return Err(DeepSceneError::Validation(
+                "Canonical output requires PNG output, not 'dng'".to_string(),
+            ));
+        }
+        if params.resolution.is_some() {
+            return Err(DeepSceneError::Validation(
+                "DNG output keeps the carrier's own tags, so no resolution can be written to it"
+                    .to_string(),
+            ));
+        }
+
+        let mut bytes = fs::read(image_path)?;
+        if params.raw_dimensions.is_some() || !DngImage::is_dng(&bytes) {
+            return Err(DeepSceneError::Validation(format!(
+                "DNG output requires a DNG carrier, since everything but the pixels is copied from it, and '{}' is not one",
+                image_path.display()
+            )));
+        }
+
+        let dng = DngImage::read(&bytes, image_path)?;
+        dng.write_pixels(&mut bytes, image);
+        Ok(bytes)
+    }
+
+    fn output_format(output_path: &Path) -> String {
+        output_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default()
+    }
+
+    /// Checks that the header and payload survived the encoder, catching
+    /// codecs that alter pixel values at encode time rather than when the
+    /// recipient tries to decode.
+    fn verify_output(
+        reloaded: Result<RgbaImage>,
+        data: &[u8],
+        compat: Option<CompatSpec>,
+    ) -> Result<()> {
+        let image = reloaded?;
+        let intact = match compat {
+            Some(spec) => Self::extract_compat(&image, spec)? == data,
+            // Another TIFF strip may come first with an earlier payload.
+            None => {
+                Self::validate_and_extract(&image)?.data == data
+                    || Self::extract_all(&image)
+                        .into_iter()
+                        .flatten()
+                        .any(|extracted| extracted.data == data)
+            }
+        };
+        if !intact {
+            return Err(DeepSceneError::Data(
+                "payload bits differ from what was embedded".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode_image(image: RgbaImage, format: &str, params: &EmbedParams) -> Result<Vec<u8>> {
+        let mut metadata_chunks = match &params.carrier_metadata {
+            Some(metadata) if format == "png" => metadata.png_chunks()?,
+            _ => Vec::new(),
+        };
+        if let Some(resolution) = params.resolution
+            && format == "png"
+        {
+            metadata_chunks.push(resolution.png_chunk());
+        }
+
+        let rows_per_strip = params.tiff_strip.map(|strip| strip.rows_per_strip);
+        let mut encoded = if params.canonical_png {
+            Self::encode_canonical(&image, format, params)?
+        } else {
+            Self::encode_pixels(
+                image,
+                format,
+                params.target_size,
+                &params.random,
+                rows_per_strip,
+                params.resolution,
+                &metadata_chunks,
+            )?
+        };
+
+        if let Some(resolution) = params.resolution
+            && format == "bmp"
+        {
+            resolution.apply_to_bmp(&mut encoded);
+        }
+
+        if let Some(metadata) = &params.carrier_metadata
+            && matches!(format, "tiff" | "tif")
+        {
+            encoded = metadata.apply_to_tiff(&encoded)?;
+        }
+
+        if !metadata_chunks.is_empty() {
+            encoded = PngWriter::insert_chunks(&encoded, &metadata_chunks)?;
+        }
+
+        match params.size_bucket {
+            Some(bucket) => PngWriter::pad_to_bucket(encoded, bucket, &params.random),
+            None => Ok(encoded),
+        }
+    }
+
+    /// Encodes a PNG with fixed settings. Size matching tries every setting,
+    /// and LSB matching and size buckets add random bytes unless the random
+    /// source is seeded, so none of them can give repeatable output.
+    fn encode_canonical(image: &RgbaImage, format: &str, params: &EmbedParams) -> Result<Vec<u8>> {
+        if format != "png" {
+            return Err(DeepSceneError::Validation(format!(
+                "Canonical output requires PNG output, not '{}'",
+                format
+            )));
+        }
+        let random = params.lsb_matching || params.size_bucket.is_some();
+        if params.target_size.is_some() || (random && !params.random.is_seeded()) {
+            return Err(DeepSceneError::Validation(
+                "Canonical output cannot be combined with size matching, or with LSB matching or size buckets unless they are seeded"
+                    .to_string(),
+            ));
+        }
+
+        PngWriter::encode(image, &PngSettings::CANONICAL, &[])
+    }
+
+    /// Encodes the pixels in `format`. A TIFF gets `rows_per_strip` rows per
+    /// strip and the resolution when given, instead of the encoder's defaults.
+    fn encode_pixels(
+        image: RgbaImage,
+        format: &str,
+        target_size: Option<u64>,
+        random: &RandomSource,
+        rows_per_strip: Option<u32>,
+        resolution: Option<Resolution>,
+        metadata_chunks: &[([u8; 4], Vec<u8>)],
+    ) -> Result<Vec<u8>> {
+        if let Some(target_size) = target_size {
+            if format != "png" {
+                return Err(DeepSceneError::Validation(format!(
+                    "Matching the carrier size requires PNG output, not '{}'",
+                    format
+                )));
+            }
+
+            // Leave room for the metadata chunks added afterwards.
+            let target_size =
+                (target_size as usize).saturating_sub(PngWriter::chunks_len(metadata_chunks));
+            return PngWriter::encode_matching_size(&image, target_size, random);
+        }
+
+        if matches!(format, "tiff" | "tif") && (rows_per_strip.is_some() || resolution.is_some()) {
+            return Self::encode_tiff(&image, rows_per_strip, resolution);
+        }
+
+        let mut encoded = Vec::new();
+
+        // PNM has no RGBA variant, so colour output drops the (unused) alpha
+        // channel. PGM is single-channel and would discard the G and B bits.
+        let result = match format {
+            "data" | "raw" => return Ok(image.into_raw()),
+            "pgm" => {
+                return Err(DeepSceneError::Validation(
+                    "Output format PGM is grayscale and cannot hold embedded data. Use .ppm or .png instead"
+                        .to_string(),
+                ));
+            }
+            "ppm" | "pnm" => {
+                let rgb = DynamicImage::ImageRgba8(image).to_rgb8();
+                PnmEncoder::new(&mut encoded)
+                    .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                    .write_image(rgb.as_raw(), rgb.width(), rgb.height(), ColorType::Rgb8)
+            }
+            _ => match ImageFormat::from_extension(format) {
+                Some(image_format) => image.write_to(&mut Cursor::new(&mut encoded), image_format),
+                None => {
+                    return Err(DeepSceneError::Validation(format!(
+                        "Unsupported output format '{}'. Use .png, .bmp, .tiff or .ppm",
+                        format
+                    )));
+                }
+            },
+        };
+
+        result.map_err(|e| DeepSceneError::Image(messages::encode_output_failed(e)))?;
+        Ok(encoded)
+    }
+
+    /// Encodes an uncompressed RGBA TIFF, with `rows_per_strip` rows in each
+    /// strip instead of the encoder's default of about 1 MB, and resolution
+    /// tags in inches.
+    fn encode_tiff(
+        image: &RgbaImage,
+        rows_per_strip: Option<u32>,
+        resolution: Option<Resolution>,
+    ) -> Result<Vec<u8>> {
+        let encode_error =
+            |e: tiff::TiffError| DeepSceneError::Image(messages::encode_output_failed(e));
+
+        let mut output = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut output).map_err(encode_error)?;
+        let mut tiff_image = encoder
+            .new_image::<colortype::RGBA8>(image.width(), image.height())
+            .map_err(encode_error)?;
+        if let Some(rows_per_strip) = rows_per_strip {
+            tiff_image
+                .rows_per_strip(rows_per_strip)
+                .map_err(encode_error)?;
+        }
+        if let Some(resolution) = resolution {
+            let (x, y) = resolution.tiff_rationals();
+            tiff_image.resolution_unit(ResolutionUnit::Inch);
+            tiff_image.x_resolution(x);
+            tiff_image.y_resolution(y);
+        }
+        tiff_image
+            .write_data(image.as_raw())
+            .map_err(encode_error)?;
+
+        Ok(output.into_inner())
+    }
+
+    fn embed_data(image: &mut RgbaImage, data: &[u8], params: &EmbedParams) -> Result<()> {
+        if let Some(spec) = params.compat {
+            let header = spec.build_header(data.len() as u32);
+            Self::write_bits(image, &header, 0, 0, params.matching());
+            let tracking = progress::track("embed", data.len());
+            Self::write_bits(image, data, header.len() * 8, 0, params.matching());
+            drop(tracking);
+
+            if params.carrier_noise {
+                Self::add_carrier_noise(image, data, (header.len() + data.len()) * 8, 0, params);
+            }
+            return Ok(());
+        }
+
+        let stride = params.stride.max(1);
+        let header = Header::for_params(params, data.len()).to_bytes();
+
+        if params.channel_copies {
+            Self::embed_channel_copies(image, &header, data, params);
+            return Ok(());
+        }
+
+        if let Some(region) = params.preserve_region {
+            Self::embed_around_region(image, &header, data, region, params);
+            return Ok(());
+        }
+
+        if let Some(strip) = params.tiff_strip {
+            Self::embed_in_strip(image, &header, data, strip, params);
+            return Ok(());
+        }
+
+        if let Some(bits) = params.channel_bits {
+            Self::embed_channel_bits(image, &header, data, bits, params);
+            return Ok(());
+        }
+
+        // The header always lives in plane 0 so extraction can locate it before
+        // knowing which plane carries the payload.
+        Self::write_bits(image, &header, 0, 0, params.matching());
+        let stored = if params.version() >= CHUNK_CRC_VERSION {
+            Self::add_chunk_crcs(data)
+        } else {
+            data.to_vec()
+        };
+        let payload_slot = Self::payload_offset(header.len(), stride) * 8;
+
+        if params.carrier_noise {
+            // The payload is spliced into the noise so the gaps a stride leaves
+            // are filled too, and every slot is written once.
+            let total_slots = image.width() as usize * image.height() as usize * 3;
+            // Encode has checked the span fits the carrier.
+            let span = Self::strided_span(stored.len(), stride).unwrap_or(usize::MAX);
+            let mut region = vec![0u8; ((total_slots - payload_slot) / 8).max(span)];
+            CryptoEngine::seeded_rng(data).fill_bytes(&mut region);
+
+            for (index, group) in stored.chunks(3).enumerate() {
+                let offset = index * 3 * stride as usize;
+                region[offset..offset + group.len()].copy_from_slice(group);
+            }
+
+            let _tracking = progress::track("embed", region.len());
+            Self::write_bits(
+                image,
+                &region,
+                payload_slot,
+                params.bit_plane,
+                params.matching(),
+            );
+        } else {
+            let _tracking = progress::track("embed", stored.len());
+            Self::write_strided(
+                image,
+                &stored,
+                payload_slot,
+                params.bit_plane,
+                params.matching(),
+                stride,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes the header and payload once into each colour channel, one bit
+    /// per pixel, so damage confined to one or two channels (a colour filter,
+    /// a channel-selective recompression) leaves an intact copy. Each header
+    /// copy occupies at most the first `HEADER_LEN * 8` pixels of its channel
+    /// in plane 0 and the payload follows them in the selected plane.
+    fn embed_channel_copies(
+        image: &mut RgbaImage,
+        header: &[u8],
+        data: &[u8],
+        params: &EmbedParams,
+    ) {
+        let stored = Self::add_chunk_crcs(data);
+        let payload_pixel = HEADER_LEN * 8;
+
+        let tracking = progress::track("embed", stored.len() * CHANNELS);
+        for channel in 0..CHANNELS {
+            Self::write_channel(image, header, 0, channel, 0, params.matching());
+            Self::write_channel(
+                image,
+                &stored,
+                payload_pixel,
+                channel,
+                params.bit_plane,
+                params.matching(),
+            );
+        }
+        drop(tracking);
+
+        if params.carrier_noise {
+            let end_pixel = payload_pixel + stored.len() * 8;
+            Self::add_carrier_noise(image, data, end_pixel * CHANNELS, params.bit_plane, params);
+        }
+    }
+
+    /// Writes the header and region record into the first
+    /// `REGION_HEADER_PIXELS` pixels in plane 0, then the payload into the
+    /// R, G and B slots of the remaining pixels in row-major order, skipping
+    /// every pixel inside `region` so it stays bit-identical to the carrier.
+    fn embed_around_region(
+        image: &mut RgbaImage,
+        header: &[u8],
+        data: &[u8],
+        region: Region,
+        params: &EmbedParams,
+    ) {
+        let mut prefix = header.to_vec();
+        prefix.extend_from_slice(&region.to_record());
+        Self::write_bits(image, &prefix, 0, 0, params.matching());
+
+        let stored = Self::add_chunk_crcs(data);
+        let payload = if params.carrier_noise {
+            // Noise fills the free slots after the payload; the region itself
+            // is never written.
+            let (width, height) = image.dimensions();
+            let mut filled = vec![0u8; Self::free_slot_count(width, height, region) / 8];
+            CryptoEngine::seeded_rng(data).fill_bytes(&mut filled);
+            filled[..stored.len()].copy_from_slice(&stored);
+            filled
+        } else {
+            stored
+        };
+
+        let width = image.width();
+        let pixels: &mut [u8] = image;
+        let mut rng = params
+            .matching()
+            .map(|random| random.rng(REGION_MATCHING_STREAM, 0));
+        let bits = payload
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |j| (byte >> j) & 1));
+
+        let _tracking = progress::track("embed", payload.len());
+        let slots = Self::free_slots(width, pixels.len() / 4, region);
+        for (written, (index, bit)) in slots.zip(bits).enumerate() {
+            if written % 8 == 7 {
+                progress::advance(1);
+            }
+            pixels[index] = Self::set_bit(pixels[index], bit, params.bit_plane, rng.as_mut());
+        }
+    }
+
+    /// Writes the header and a record of the strip's rows in plane 0 from the
+    /// strip's first pixel, then the payload after them in the selected
+    /// plane, leaving every pixel outside the strip untouched. Carrier noise
+    /// fills the rest of the strip only.
+    fn embed_in_strip(
+        image: &mut RgbaImage,
+        header: &[u8],
+        data: &[u8],
+        strip: TiffStrip,
+        params: &EmbedParams,
+    ) {
+        let width = image.width();
+        let start_slot = strip.first_row as usize * width as usize * 3;
+        let mut prefix = header.to_vec();
+        prefix.extend_from_slice(&Self::strip_rows(strip, width).to_record());
+        Self::write_bits(image, &prefix, start_slot, 0, params.matching());
+
+        let stored = Self::add_chunk_crcs(data);
+        let payload = if params.carrier_noise {
+            let strip_len = strip.rows as usize * width as usize * 3 / 8;
+            let mut filled = vec![0u8; strip_len - prefix.len()];
+            CryptoEngine::seeded_rng(data).fill_bytes(&mut filled);
+            filled[..stored.len()].copy_from_slice(&stored);
+            filled
+        } else {
+            stored
+        };
+
+        let _tracking = progress::track("embed", payload.len());
+        Self::write_bits(
+            image,
+            &payload,
+            start_slot + prefix.len() * 8,
+            params.bit_plane,
+            params.matching(),
+        );
+    }
+
+    /// Writes the header in plane 0 from the first slot, then the payload
+    /// from the next whole pixel, taking `bits` low bits of R, G and B in
+    /// turn from every pixel. Carrier noise fills the remaining pixels the
+    /// same way.
+    fn embed_channel_bits(
+        image: &mut RgbaImage,
+        header: &[u8],
+        data: &[u8],
+        bits: [u8; 3],
+        params: &EmbedParams,
+    ) {
+        Self::write_bits(image, header, 0, 0, params.matching());
+
+        let stored = Self::add_chunk_crcs(data);
+        let payload = if params.carrier_noise {
+            let (width, height) = image.dimensions();
+            let mut filled =
+                vec![0u8; Self::channel_bits_capacity(width, height, bits, header.len())];
+            CryptoEngine::seeded_rng(data).fill_bytes(&mut filled);
+            filled[..stored.len()].copy_from_slice(&stored);
+            filled
+        } else {
+            stored
+        };
+
+        // Every 8 pixels hold exactly `bits_per_pixel` bytes, so the groups
+        // are independent and can be written in parallel.
+        let start = Self::channel_bits_start(header.len()) * 4;
+        let matching = params.matching();
+        let pixels: &mut [u8] = image;
+        let _tracking = progress::track("embed", payload.len());
+        parallel::zip_chunks(
+            &mut pixels[start..],
+            8 * 4,
+            &payload,
+            Self::bits_per_pixel(bits),
+            |index, group, bytes| {
+                let mut rng = matching
+                    .map(|random| random.rng(LSB_MATCHING_STREAM, (start + index * 8 * 4) as u64));
+                Self::write_channel_bits(group, bytes, bits, rng.as_mut());
+                progress::advance(bytes.len());
+            },
+        );
+    }
+
+    /// Writes `data` most significant bit first into the low `bits[c]` bits
+    /// of channel `c` of each pixel in `group`. A channel the data runs out
+    /// in keeps its remaining carrier bits.
+    fn write_channel_bits(
+        group: &mut [u8],
+        data: &[u8],
+        bits: [u8; 3],
+        mut rng: Option<&mut SourceRng>,
+    ) {
+        let mut stream = data
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |j| (byte >> j) & 1));
+
+        for pixel in group.chunks_mut(4) {
+            for (channel, &count) in bits.iter().enumerate() {
+                let mask = (1u8 << count) - 1;
+                let mut low = pixel[channel] & mask;
+                let mut written = false;
+                for plane in (0..count).rev() {
+                    let Some(bit) = stream.next() else { break };
+                    low = (low & !(1 << plane)) | (bit << plane);
+                    written = true;
+                }
+                if !written {
+                    return;
+                }
+                pixel[channel] = Self::set_low_bits(pixel[channel], low, count, rng.as_deref_mut());
+            }
+        }
+    }
+
+    /// `value` with its low `count` bits set to `low`. With an LSB matching
+    /// `rng` the result is instead the nearest value ending in `low`, which
+    /// moves the channel by less than 2^count, picking a direction at random
+    /// on ties.
+    fn set_low_bits(value: u8, low: u8, count: u8, rng: Option<&mut SourceRng>) -> u8 {
+        let mask = (1u8 << count) - 1;
+        let replaced = (value & !mask) | low;
+        let Some(rng) = rng else {
+            return replaced;
+        };
+
+        let step = 1i16 << count;
+        let candidates = [
+            replaced as i16 - step,
+            replaced as i16,
+            replaced as i16 + step,
+        ];
+        let distance = |candidate: i16| (candidate - value as i16).abs();
+        let best = candidates
+            .into_iter()
+            .filter(|candidate| (0..=255).contains(candidate))
+            .map(distance)
+            .min()
+            .unwrap_or(0);
+        let nearest: Vec<i16> = candidates
+            .into_iter()
+            .filter(|&candidate| (0..=255).contains(&candidate) && distance(candidate) == best)
+            .collect();
+        nearest[rng.gen_range(0..nearest.len())] as u8
+    }
+
+    /// The strip's rows across the full image width, as stored in the record
+    /// after a strip header.
+    fn strip_rows(strip: TiffStrip, image_width: u32) -> Region {
+        Region {
+            x: 0,
+            y: strip.first_row,
+            width: image_width,
+            height: strip.rows,
+        }
+    }
+
+    /// Byte indices of the R, G and B slots of every pixel after the header
+    /// and region record that lies outside `region`, in row-major order.
+    fn free_slots(width: u32, pixel_count: usize, region: Region) -> impl Iterator<Item = usize> {
+        (REGION_HEADER_PIXELS..pixel_count)
+            .filter(move |&pixel| !region.contains(pixel, width))
+            .flat_map(|pixel| pixel * 4..pixel * 4 + CHANNELS)
+    }
+
+    /// Overwrites every slot from `end_slot` onwards in `plane` with bits from
+    /// an RNG seeded by the payload hash. A lone payload region with encrypted
+    /// or compressed, near-random bits stands out against the structured LSBs
+    /// of the rest of the image; noise removes that boundary. Extraction never
+    /// reads these slots.
+    fn add_carrier_noise(
+        image: &mut RgbaImage,
+        data: &[u8],
+        end_slot: usize,
+        plane: u8,
+        params: &EmbedParams,
+    ) {
+        let total_slots = image.width() as usize * image.height() as usize * 3;
+        // Starting on a group boundary keeps the write parallel; the few slots
+        // skipped are not enough to matter statistically.
+        let start_slot = end_slot.next_multiple_of(GROUP_SLOTS);
+        if start_slot >= total_slots {
+            return;
+        }
+
+        let mut noise = vec![0u8; (total_slots - start_slot) / 8];
+        CryptoEngine::seeded_rng(data).fill_bytes(&mut noise);
+        Self::write_bits(image, &noise, start_slot, plane, params.matching());
+    }
+
+    fn write_bits(
+        image: &mut RgbaImage,
+        data: &[u8],
+        start_slot: usize,
+        plane: u8,
+        matching: Option<&RandomSource>,
+    ) {
+        Self::write_strided(image, data, start_slot, plane, matching, 1);
+    }
+
+    /// Writes `data` three bytes at a time into every `stride`-th group from
+    /// `start_slot`. Strides above 1 require a group-aligned start.
+    fn write_strided(
+        image: &mut RgbaImage,
+        data: &[u8],
+        start_slot: usize,
+        plane: u8,
+        matching: Option<&RandomSource>,
+        stride: u16,
+    ) {
+        let pixels: &mut [u8] = image;
+
+        if start_slot.is_multiple_of(GROUP_SLOTS) {
+            // Every 8 pixels hold exactly 3 bytes, so aligned groups are
+            // independent and can be written in parallel. Each chunk spans
+            // `stride` groups and its payload goes into the first of them.
+            let start = start_slot / 3 * 4;
+            let chunk_len = GROUP_SLOTS / 3 * 4 * stride as usize;
+            parallel::zip_chunks(
+                &mut pixels[start..],
+                chunk_len,
+                data,
+                GROUP_SLOTS / 8,
+                |index, group, bytes| {
+                    let mut rng = matching.map(|random| {
+                        random.rng(LSB_MATCHING_STREAM, (start + index * chunk_len) as u64)
+                    });
+                    Self::write_group(group, bytes, 0, plane, rng.as_mut());
+                    progress::advance(bytes.len());
+                },
+            );
+        } else {
+            debug_assert_eq!(stride, 1);
+            for (index, chunk) in data.chunks(SERIAL_CHUNK_LEN).enumerate() {
+                let slot = start_slot + index * SERIAL_CHUNK_LEN * 8;
+                let mut rng =
+                    matching.map(|random| random.rng(LSB_MATCHING_STREAM, (slot / 3 * 4) as u64));
+                Self::write_group(pixels, chunk, slot, plane, rng.as_mut());
+                progress::advance(chunk.len());
+            }
+        }
+    }
+
+    fn write_group(
+        pixels: &mut [u8],
+        data: &[u8],
+        start_slot: usize,
+        plane: u8,
+        mut rng: Option<&mut SourceRng>,
+    ) {
+        for (byte_index, &byte) in data.iter().enumerate() {
+            for j in 0..8 {
+                let slot = start_slot + byte_index * 8 + j;
+                let index = (slot / 3) * 4 + slot % 3;
+                let bit = (byte >> (7 - j)) & 1;
+                pixels[index] = Self::set_bit(pixels[index], bit, plane, rng.as_deref_mut());
+            }
+        }
+    }
+
+    /// Writes `data` into a single channel, one bit per pixel from
+    /// `start_pixel`, leaving the other channels untouched.
+    fn write_channel(
+        image: &mut RgbaImage,
+        data: &[u8],
+        start_pixel: usize,
+        channel: usize,
+        plane: u8,
+        matching: Option<&RandomSource>,
+    ) {
+        let pixels: &mut [u8] = image;
+
+        parallel::zip_chunks(
+            &mut pixels[start_pixel * 4..],
+            8 * 4,
+            data,
+            1,
+            |chunk, group, byte| {
+                let offset = (start_pixel + chunk * 8) * 4 + channel;
+                let mut rng = matching.map(|random| random.rng(LSB_MATCHING_STREAM, offset as u64));
+                for j in 0..8 {
+                    let index = j * 4 + channel;
+                    let bit = (byte[0] >> (7 - j)) & 1;
+                    group[index] = Self::set_bit(group[index], bit, plane, rng.as_mut());
+                }
+                progress::advance(1);
+            },
+        );
+    }
+
+    /// `value` with bit `plane` set to `bit`, by LSB matching when given an
+    /// `rng` and by replacement otherwise.
+    fn set_bit(value: u8, bit: u8, plane: u8, rng: Option<&mut SourceRng>) -> u8 {
+        let mask = 1u8 << plane;
+        let Some(rng) = rng else {
+            return (value & !mask) | (bit << plane);
+        };
+
+        if (value >> plane) & 1 == bit {
+            value
+        } else if value < mask {
+            // Bit is 0 here and value + mask cannot overflow.
+            value + mask
+        } else if value > 255 - mask {
+            value - mask
+        } else if rng.r#gen::<bool>() {
+            value + mask
+        } else {
+            value - mask
+        }
+    }
+
+    fn read_bits(image: &RgbaImage, start_slot: usize, length: usize, plane: u8) -> Vec<u8> {
+        Self::read_strided(image, start_slot, length, plane, 1)
+    }
+
+    fn read_strided(
+        image: &RgbaImage,
+        start_slot: usize,
+        length: usize,
+        plane: u8,
+        stride: u16,
+    ) -> Vec<u8> {
+        let pixels: &[u8] = image;
+        let group_span = 3 * stride as usize;
+
+        parallel::map_range(length, |byte_index| {
+            let position = byte_index / 3 * group_span + byte_index % 3;
+            let mut byte = 0u8;
+            for j in 0..8 {
+                let slot = start_slot + position * 8 + j;
+                let index = (slot / 3) * 4 + slot % 3;
+                byte = (byte << 1) | ((pixels[index] >> plane) & 1);
+            }
+            progress::advance(1);
+            byte
+        })
+    }
+
+    fn read_channel(
+        image: &RgbaImage,
+        start_pixel: usize,
+        length: usize,
+        channel: usize,
+        plane: u8,
+    ) -> Vec<u8> {
+        let pixels: &[u8] = image;
+
+        parallel::map_range(length, |byte_index| {
+            let mut byte = 0u8;
+            for j in 0..8 {
+                let index = (start_pixel + byte_index * 8 + j) * 4 + channel;
+                byte = (byte << 1) | ((pixels[index] >> plane) & 1);
+            }
+            progress::advance(1);
+            byte
+        })
+    }
+
+    /// Reads `length` bytes from the low bits `embed_channel_bits` writes,
+    /// starting at `start_pixel`.
+    fn read_channel_bits(
+        image: &RgbaImage,
+        start_pixel: usize,
+        length: usize,
+        bits: [u8; 3],
+    ) -> Vec<u8> {
+        let pixels: &[u8] = image;
+        let pixels = &pixels[start_pixel * 4..];
+        let group_len = Self::bits_per_pixel(bits);
+
+        let groups = parallel::map_range(length.div_ceil(group_len), |group| {
+            let start = group * 8 * 4;
+            let end = (start + 8 * 4).min(pixels.len());
+            let mut bytes = Vec::with_capacity(group_len);
+            let (mut byte, mut filled) = (0u8, 0);
+
+            for pixel in pixels[start..end].chunks(4) {
+                for (channel, &count) in bits.iter().enumerate() {
+                    for plane in (0..count).rev() {
+                        byte = (byte << 1) | ((pixel[channel] >> plane) & 1);
+                        filled += 1;
+                        if filled == 8 {
+                            bytes.push(byte);
+                            (byte, filled) = (0, 0);
+                        }
+                    }
+                }
+            }
+            progress::advance(bytes.len());
+            bytes
+        });
+
+        let mut data = groups.concat();
+        data.truncate(length);
+        data
+    }
+
+    /// Reads `length` bytes from the slots `embed_around_region` writes.
+    fn read_around_region(image: &RgbaImage, length: usize, region: Region, plane: u8) -> Vec<u8> {
+        let pixels: &[u8] = image;
+        let mut slots = Self::free_slots(image.width(), pixels.len() / 4, region);
+
+        (0..length)
+            .map(|_| {
+                progress::advance(1);
+                slots.by_ref().take(8).fold(0u8, |byte, index| {
+                    (byte << 1) | ((pixels[index] >> plane) & 1)
+                })
+            })
+            .collect()
+    }
+
+    /// First per-channel header copy with a valid checksum and the channel
+    /// copies flag set, for images where the interleaved header is absent.
+    fn find_channel_header(image: &RgbaImage) -> Option<Header> {
+        let pixels = image.width() as usize * image.height() as usize;
+        if pixels < HEADER_LEN * 8 {
+            return None;
+        }
+
+        (0..CHANNELS)
+            .map(|channel| Self::read_channel(image, 0, HEADER_LEN, channel, 0))
+            .find_map(|bytes| {
+                Header::parse(&bytes)
+                    .ok()
+                    .filter(|h| h.version >= CHANNEL_COPIES_VERSION && h.channel_copies())
+            })
+    }
+
+    /// Looks for a strip header at the start of every row after the first,
+    /// returning it with the slot it starts at. Only headers marking a TIFF
+    /// strip count, so a stray magic in ordinary rows is ignored.
+    fn find_strip_header(image: &RgbaImage) -> Option<(Header, usize)> {
+        Self::strip_headers(image).next()
+    }
+
+    /// Every strip header of `find_strip_header`, top to bottom.
+    fn strip_headers(image: &RgbaImage) -> impl Iterator<Item = (Header, usize)> + '_ {
+        let (width, height) = image.dimensions();
+        let total_slots = width as usize * height as usize * 3;
+
+        (1..height as usize).filter_map(move |row| {
+            let start_slot = row * width as usize * 3;
+            let available = (total_slots - start_slot) / 8;
+            if available < 5 {
+                return None;
+            }
+
+            let prefix = Self::read_bits(image, start_slot, 5, 0);
+            if prefix[..4] != *HEADER_MAGIC || prefix[4] < TIFF_STRIP_VERSION {
+                return None;
+            }
+
+            let window = Self::read_bits(image, start_slot, MAX_HEADER_LEN.min(available), 0);
+            Header::parse(&window)
+                .ok()
+                .filter(|h| h.tiff_strip())
+                .map(|h| (h, start_slot))
+        })
+    }
+
+    /// Reads each channel's copy in turn and returns the first whose chunk
+    /// CRCs all match, together with its channel.
+    fn extract_channel_copies(
+        image: &RgbaImage,
+        length: usize,
+        plane: u8,
+    ) -> Result<(Vec<u8>, usize)> {
+        let mut first_error = None;
+
+        for channel in 0..CHANNELS {
+            let stored = Self::read_channel(image, HEADER_LEN * 8, length, channel, plane);
+            match Self::strip_chunk_crcs(&stored) {
+                Ok(data) => return Ok((data, channel)),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        Err(match first_error {
+            Some(DeepSceneError::Data(message)) => DeepSceneError::Data(format!(
+                "All {} channel copies are damaged. Red channel: {}",
+                CHANNELS, message
+            )),
+            Some(e) => e,
+            None => unreachable!("at least one channel is read"),
+        })
+    }
+
+    pub fn extract_data_raw(image_path: &Path, width: u32, height: u32) -> Result<ExtractedData> {
+        let rgba_img = Self::load_raw(image_path, width, height)?;
+        Self::validate_and_extract(&rgba_img)
+    }
+
+    pub fn extract_data(image_path: &Path) -> Result<ExtractedData> {
+        let rgba_img = Self::load_image(image_path)?;
+        Self::validate_and_extract(&rgba_img)
+    }
+
+    /// Extracts from encoded image bytes, or raw RGBA bytes when dimensions
+    /// are given, that were never written to disk.
+    pub fn extract_data_from_memory(
+        bytes: Vec<u8>,
+        raw_dimensions: Option<(u32, u32)>,
+        source: &Path,
+    ) -> Result<ExtractedData> {
+        let rgba_img = match raw_dimensions {
+            Some((width, height)) => Self::load_raw_from_memory(bytes, width, height, source)?,
+            None => Self::load_image_from_memory(&bytes, source)?,
+        };
+        Self::validate_and_extract(&rgba_img)
+    }
+
+    /// Extracts a payload framed with a compat spec. Without a magic or
+    /// checksum, only the length field can be sanity-checked.
+    pub fn extract_compat(image: &RgbaImage, spec: CompatSpec) -> Result<Vec<u8>> {
+        let (width, height) = image.dimensions();
+        let capacity = Self::calculate_capacity(width, height);
+        let header_len = spec.header_len();
+
+        let label = format!("the {} header", spec.name());
+        if let Some(message) = Self::too_small(width, height, &label, header_len, 3) {
+            return Err(DeepSceneError::NoData(format!(
+                "{}. It cannot contain embedded data",
+                message
+            )));
+        }
+
+        let data_length = spec.parse_header(&Self::read_bits(image, 0, header_len, 0));
+
+        if data_length == 0 || data_length > capacity - header_len {
+            return Err(DeepSceneError::NoData(format!(
+                "No {} payload found (length field {} does not fit the image)",
+                spec.name(),
+                data_length
+            )));
+        }
+
+        let _tracking = progress::track("extract", data_length);
+        Ok(Self::read_bits(image, header_len * 8, data_length, 0))
+    }
+
+    /// Whether the image starts with a DeepScene header whose checksum is
+    /// valid, without reading the payload.
+    pub fn has_header(image: &RgbaImage) -> bool {
+        let available = Self::calculate_capacity(image.width(), image.height());
+        let window = Self::read_bits(image, 0, MAX_HEADER_LEN.min(available), 0);
+        if window.starts_with(HEADER_MAGIC) || window.starts_with(LEGACY_HEADER_MAGIC) {
+            Header::parse(&window).is_ok()
+        } else {
+            Self::find_channel_header(image).is_some() || Self::find_strip_header(image).is_some()
+        }
+    }
+
+    fn validate_and_extract(image: &RgbaImage) -> Result<ExtractedData> {
+        let (width, height) = image.dimensions();
+        let available_bits = width as usize * height as usize * 3;
+        let too_small = |label: &str, header_len: usize| {
+            let message = Self::too_small(width, height, label, header_len, 3).unwrap_or_default();
+            DeepSceneError::NoData(format!("{}. It cannot contain embedded data", message))
+        };
+
+        if available_bits < LEGACY_HEADER_LEN * 8 {
+            return Err(too_small("even the smallest header", LEGACY_HEADER_LEN));
+        }
+
+        let window = Self::read_bits(image, 0, MAX_HEADER_LEN.min(available_bits / 8), 0);
+
+        let (header, header_slot, channel_copies) =
+            if window.starts_with(HEADER_MAGIC) || window.starts_with(LEGACY_HEADER_MAGIC) {
+                match Header::peek_len(&window) {
+                    Some(header_len) if header_len > window.len() => {
+                        return Err(too_small("the header", header_len));
+                    }
+                    None if window.len() < MAX_HEADER_LEN => {
+                        return Err(too_small("the header", window.len() + 1));
+                    }
+                    _ => {}
+                }
+                (Header::parse(&window)?, 0, false)
+            } else if let Some(header) = Self::find_channel_header(image) {
+                (header, 0, true)
+            } else if let Some((header, header_slot)) = Self::find_strip_header(image) {
+                (header, header_slot, false)
+            } else {
+                return Err(DeepSceneError::NoData(
+                    messages::NO_EMBEDDED_DATA.to_string(),
+                ));
+            };
+
+        Self::extract_at(image, header, header_slot, channel_copies)
+    }
+
+    /// Extracts every payload in the image: the one at its start and one per
+    /// TIFF strip holding its own header, each with its own result, so a
+    /// damaged payload does not hide the others. Empty when there is none.
+    pub fn extract_all(image: &RgbaImage) -> Vec<Result<ExtractedData>> {
+        let available = image.width() as usize * image.height() as usize * 3 / 8;
+        let window = Self::read_bits(image, 0, MAX_HEADER_LEN.min(available), 0);
+
+        let mut results = Vec::new();
+        if window.starts_with(HEADER_MAGIC) || window.starts_with(LEGACY_HEADER_MAGIC) {
+            results.push(Self::validate_and_extract(image));
+        } else if let Some(header) = Self::find_channel_header(image) {
+            results.push(Self::extract_at(image, header, 0, true));
+        }
+        results.extend(
+            Self::strip_headers(image)
+                .map(|(header, header_slot)| Self::extract_at(image, header, header_slot, false)),
+        );
+        results
+    }
+
+    /// Extracts the payload of `header`, found at bit slot `header_slot`.
+    fn extract_at(
+        image: &RgbaImage,
+        header: Header,
+        header_slot: usize,
+        channel_copies: bool,
+    ) -> Result<ExtractedData> {
+        let (width, height) = image.dimensions();
+        let available_bits = width as usize * height as usize * 3;
+        let too_small = |label: &str, header_len: usize| {
+            let message = Self::too_small(width, height, label, header_len, 3).unwrap_or_default();
+            DeepSceneError::NoData(format!("{}. It cannot contain embedded data", message))
+        };
+
+        header.validate()?;
+        if header.channel_copies() != channel_copies {
+            return Err(DeepSceneError::Data(
+                "Header layout flags do not match where the header was found".to_string(),
+            ));
+        }
+
+        let Header {
+            version,
+            bit_plane,
+            stride,
+            ..
+        } = header;
+        let header_len = header.encoded_len();
+        let data_length = header.length as usize;
+
+        let stored_length = Self::stored_len(version, data_length);
+
+        let preserved_region = if header.preserved_region() {
+            let record_len = header_len + REGION_RECORD_LEN;
+            if available_bits < record_len * 8 {
+                return Err(too_small("the header and region record", record_len));
+            }
+
+            let record = Self::read_bits(image, header_len * 8, REGION_RECORD_LEN, 0);
+            let region = Region::from_record(&record)?;
+            region.validate(width, height).map_err(|e| {
+                DeepSceneError::Data(format!("Invalid preserved region in header: {}", e))
+            })?;
+
+            let available = Self::free_slot_count(width, height, region) / 8;
+            if stored_length > available {
+                return Err(DeepSceneError::Data(format!(
+                    "Image capacity exceeded. Required: {} bytes. Available: {} bytes outside the preserved region",
+                    stored_length, available
+                )));
+            }
+            Some(region)
+        } else {
+            None
+        };
+
+        let tiff_strip = if header.tiff_strip() {
+            let record_len = header_len + REGION_RECORD_LEN;
+            if available_bits < header_slot + record_len * 8 {
+                return Err(too_small("the header and strip record", record_len));
+            }
+
+            let record = Self::read_bits(image, header_slot + header_len * 8, REGION_RECORD_LEN, 0);
+            let rows = Region::from_record(&record).map_err(|e| {
+                DeepSceneError::Data(format!("TIFF strip record is corrupted: {}", e))
+            })?;
+            if rows.x != 0
+                || rows.width != width
+                || rows.y as usize * width as usize * 3 != header_slot
+                || rows.height == 0
+                || rows.y + rows.height > height
+            {
+                return Err(DeepSceneError::Data(
+                    "TIFF strip record does not match the rows the header was found in".to_string(),
+                ));
+            }
+
+            let available =
+                (rows.height as usize * width as usize * 3 / 8).saturating_sub(record_len);
+            if stored_length > available {
+                return Err(DeepSceneError::Data(format!(
+                    "Image capacity exceeded. Required: {} bytes. Available: {} bytes in the TIFF strip",
+                    stored_length, available
+                )));
+            }
+            Some(rows)
+        } else {
+            None
+        };
+
+        if let Some(bits) = header.channel_bits {
+            let available = Self::channel_bits_capacity(width, height, bits, header_len);
+            if stored_length > available {
+                return Err(DeepSceneError::Data(format!(
+                    "Image capacity exceeded. Required: {} bytes. Available: {} bytes at the header's channel bit depths",
+                    stored_length, available
+                )));
+            }
+        }
+
+        // Channel copies always start their payload after a full-length header.
+        let payload_offset = if channel_copies {
+            HEADER_LEN
+        } else {
+            Self::payload_offset(header_len, stride)
+        };
+        let required_bytes =
+            Self::embedded_len(payload_offset, stored_length, stride, channel_copies)
+                .ok_or_else(|| {
+                    DeepSceneError::Data(format!(
+                        "Image capacity exceeded. The header declares {} bytes at a stride of {}, more than any image can hold",
+                        data_length, stride
+                    ))
+                })?;
+        let max_bytes = Self::calculate_capacity(width, height);
+
+        if preserved_region.is_none()
+            && tiff_strip.is_none()
+            && header.channel_bits.is_none()
+            && required_bytes > max_bytes
+        {
+            return Err(DeepSceneError::Data(format!(
+                "Image capacity exceeded. Required: {} bytes. Available: {} bytes",
+                required_bytes, max_bytes
+            )));
+        }
+
+        let tracking = progress::track("extract", stored_length);
+        let (data, copy_channel) = if channel_copies {
+            let (data, channel) = Self::extract_channel_copies(image, stored_length, bit_plane)?;
+            (data, Some(channel))
+        } else if let Some(region) = preserved_region {
+            let stored = Self::read_around_region(image, stored_length, region, bit_plane);
+            (Self::strip_chunk_crcs(&stored)?, None)
+        } else if let Some(bits) = header.channel_bits {
+            let start_pixel = Self::channel_bits_start(header_len);
+            let stored = Self::read_channel_bits(image, start_pixel, stored_length, bits);
+            (Self::strip_chunk_crcs(&stored)?, None)
+        } else if tiff_strip.is_some() {
+            let payload_slot = header_slot + (header_len + REGION_RECORD_LEN) * 8;
+            let stored = Self::read_bits(image, payload_slot, stored_length, bit_plane);
+            (Self::strip_chunk_crcs(&stored)?, None)
+        } else {
+            let stored =
+                Self::extract_bytes(image, payload_offset, stored_length, bit_plane, stride)?;
+            if version >= CHUNK_CRC_VERSION {
+                (Self::strip_chunk_crcs(&stored)?, None)
+            } else {
+                (stored, None)
+            }
+        };
+        drop(tracking);
+
+        Ok(ExtractedData {
+            format_version: version,
+            bit_plane,
+            stride,
+            copy_channel,
+            preserved_region,
+            tiff_strip,
+            channel_bits: header.channel_bits,
+            data,
+        })
+    }
+
+    /// Reads `length` payload bytes starting after the first `header_len`
+    /// carrier bytes, the encoded length of the parsed header rather than a
+    /// fixed size, failing if they would run past the end of the image.
+    fn extract_bytes(
+        image: &RgbaImage,
+        header_len: usize,
+        length: usize,
+        plane: u8,
+        stride: u16,
+    ) -> Result<Vec<u8>> {
+        let capacity = Self::calculate_capacity(image.width(), image.height());
+        if Self::embedded_len(header_len, length, stride, false).is_none_or(|end| end > capacity) {
+            return Err(DeepSceneError::Data(format!(
+                "A {}-byte header followed by {} payload bytes at a stride of {} runs past the end of the image, which holds {} bytes",
+                header_len, length, stride, capacity
+            )));
+        }
+
+        Ok(Self::read_strided(
+            image,
+            header_len * 8,
+            length,
+            plane,
+            stride,
+        ))
+    }
+}
+
+/// Direct access to the bit-level embedding and extraction routines, so
+/// integration tests and benches can exercise them on in-memory images
+/// without going through image files. Not part of the stable API.
+#[cfg(feature = "testing")]
+#[doc(hidden)]
+pub mod testing {
+    use super::{
+        CHUNK_CRC_VERSION, DeepSceneError, EmbedParams, ExtractedData, Header, Result, RgbaImage,
+        SteganographyEngine,
+    };
+
+    pub const HEADER_LEN: usize = super::HEADER_LEN;
+
+    pub fn embed_data(image: &mut RgbaImage, data: &[u8], params: &EmbedParams) -> Result<()> {
+        SteganographyEngine::embed_data(image, data, params)
+    }
+
+    pub fn validate_and_extract(image: &RgbaImage) -> Result<ExtractedData> {
+        SteganographyEngine::validate_and_extract(image)
+    }
+
+    /// Reads back the `length` payload bytes `embed_data` wrote with
+    /// `params`, from the offset, bit plane and stride those select, checking
+    /// the chunk CRCs of versions that store them. Only the contiguous and
+    /// strided layouts are supported.
+    pub fn extract_bytes(
+        image: &RgbaImage,
+        length: usize,
+        params: &EmbedParams,
+    ) -> Result<Vec<u8>> {
+        if params.compat.is_some()
+            || params.channel_copies
+            || params.preserve_region.is_some()
+            || params.tiff_strip.is_some()
+            || params.channel_bits.is_some()
+        {
+            return Err(DeepSceneError::Validation(
+                "testing::extract_bytes only reads the contiguous and strided layouts".to_string(),
+            ));
+        }
+
+        let version = params.version();
+        let stride = params.stride.max(1);
+        let header_len = Header::for_params(params, length).encoded_len();
+        let stored = SteganographyEngine::extract_bytes(
+            image,
+            SteganographyEngine::payload_offset(header_len, stride),
+            SteganographyEngine::stored_len(version, length),
+            params.bit_plane,
+            stride,
+        )?;
+
+        if version >= CHUNK_CRC_VERSION {
+            SteganographyEngine::strip_chunk_crcs(&stored)
+        } else {
+            Ok(stored)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const DIMENSIONS: [(u32, u32); 4] = [(8, 8), (61, 47), (200, 3), (1, 500)];
+
+    fn carrier(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 7 + y) as u8, (x + y * 5) as u8, (x ^ y) as u8, 255])
+        })
+    }
+
+    /// Every combination of the layout options `payload_capacity` accounts
+    /// for that `check_version_supports` and encode's own checks accept.
+    fn parameter_matrix() -> Vec<EmbedParams> {
+        let mut matrix = Vec::new();
+        for format_version in [None, Some(1), Some(5), Some(9), Some(14)] {
+            for bit_plane in [0, 3] {
+                for stride in [1, 2, 7] {
+                    for channel_copies in [false, true] {
+                        matrix.push(EmbedParams {
+                            format_version,
+                            bit_plane,
+                            stride,
+                            channel_copies,
+                            ..EmbedParams::default()
+                        });
+                    }
+                }
+            }
+        }
+        for bits in [PERCEPTUAL_CHANNEL_BITS, [1, 1, 1], [4, 4, 4], [3, 1, 2]] {
+            matrix.push(EmbedParams {
+                channel_bits: Some(bits),
+                ..EmbedParams::default()
+            });
+        }
+        for bit_plane in [0, 3] {
+            matrix.push(EmbedParams {
+                bit_plane,
+                preserve_region: Some(Region {
+                    x: 0,
+                    y: 2,
+                    width: 1,
+                    height: 1,
+                }),
+                ..EmbedParams::default()
+            });
+        }
+
+        matrix
+            .into_iter()
+            .filter(|params| {
+                SteganographyEngine::check_version_supports(params).is_ok()
+                    && !(params.channel_copies && params.stride > 1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn capacity_is_exact_for_every_parameter_combination() {
+        for (width, height) in DIMENSIONS {
+            for params in parameter_matrix() {
+                if SteganographyEngine::check_header_fits(width, height, &params).is_err()
+                    || params
+                        .preserve_region
+                        .is_some_and(|region| region.validate(width, height).is_err())
+                {
+                    continue;
+                }
+                let capacity = SteganographyEngine::payload_capacity(width, height, &params);
+                let context = format!("{}x{} {:?}", width, height, params);
+
+                // A zero length field reads as "no data", so only a
+                // carrier with room for at least one byte can round-trip.
+                if capacity > 0 {
+                    let data: Vec<u8> = (0..capacity).map(|i| (i * 37 + 11) as u8).collect();
+                    let mut image = carrier(width, height);
+                    SteganographyEngine::embed_data(&mut image, &data, &params).unwrap();
+                    let extracted = SteganographyEngine::validate_and_extract(&image)
+                        .unwrap_or_else(|e| panic!("{}: {}", context, e));
+                    assert_eq!(extracted.data, data, "{}", context);
+                }
+
+                let pixels = width as usize * height as usize;
+                let needed = SteganographyEngine::required_bytes(capacity + 1, &params);
+                assert!(
+                    needed > SteganographyEngine::calculate_capacity(width, height)
+                        || params.channel_bits.is_some() && needed * 8 > pixels * CHANNELS,
+                    "{}: {} bytes still fit",
+                    context,
+                    capacity + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn capacity_scales_with_the_layout() {
+        let capacity =
+            |params: EmbedParams| SteganographyEngine::payload_capacity(600, 400, &params);
+        let plain = capacity(EmbedParams::default());
+
+        let strided = capacity(EmbedParams {
+            stride: 4,
+            ..EmbedParams::default()
+        });
+        assert!(strided.abs_diff(plain / 4) <= plain / 100);
+
+        let copies = capacity(EmbedParams {
+            channel_copies: true,
+            ..EmbedParams::default()
+        });
+        assert!(copies.abs_diff(plain / 3) <= plain / 100);
+
+        let four_bits = capacity(EmbedParams {
+            channel_bits: Some([4, 4, 4]),
+            ..EmbedParams::default()
+        });
+        assert!(four_bits.abs_diff(plain * 4) <= plain / 100);
+
+        // The bit plane moves the payload, not its size.
+        assert_eq!(
+            capacity(EmbedParams {
+                bit_plane: 5,
+                ..EmbedParams::default()
+            }),
+            plain
+        );
+    }
+
+    #[test]
+    fn compat_capacity_is_the_carrier_less_its_header() {
+        let params = EmbedParams {
+            compat: Some(CompatSpec::PlainLsb),
+            ..EmbedParams::default()
+        };
+        assert_eq!(
+            SteganographyEngine::payload_capacity(61, 47, &params),
+            SteganographyEngine::calculate_capacity(61, 47) - CompatSpec::PlainLsb.header_len()
+        );
+    }
+
+    /// Peak signal-to-noise ratio of `stego` against `original` in dB, with
+    /// each channel's squared error scaled by `weights`.
+    fn psnr(original: &RgbaImage, stego: &RgbaImage, weights: [f64; 3]) -> f64 {
+        let error: f64 = original
+            .pixels()
+            .zip(stego.pixels())
+            .map(|(a, b)| {
+                (0..CHANNELS)
+                    .map(|c| weights[c] * (f64::from(a[c]) - f64::from(b[c])).powi(2))
+                    .sum::<f64>()
+            })
+            .sum();
+        let mse = error / (original.pixels().len() as f64 * weights.iter().sum::<f64>());
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+
+    #[test]
+    fn perceptual_bits_distort_less_than_uniform_two_bits() {
+        const UNIFORM: f64 = 1.0;
+        // Rec. 601 luma weights: green dominates perceived brightness.
+        const LUMA: [f64; 3] = [0.299, 0.587, 0.114];
+
+        // A keyed stream stands in for compressed or encrypted data, whose
+        // bits are as likely to flip a carrier bit as to keep it.
+        let mut data = vec![0u8; 20_000];
+        blake3::Hasher::new()
+            .update(b"psnr")
+            .finalize_xof()
+            .fill(&mut data);
+        let original = RgbaImage::from_fn(200, 200, |x, y| {
+            let hash = blake3::hash(&[x.to_le_bytes(), y.to_le_bytes()].concat());
+            let [r, g, b, ..] = *hash.as_bytes();
+            Rgba([r, g, b, 255])
+        });
+
+        let embed = |bits| {
+            let mut stego = original.clone();
+            let params = EmbedParams {
+                channel_bits: Some(bits),
+                ..EmbedParams::default()
+            };
+            SteganographyEngine::embed_data(&mut stego, &data, &params).unwrap();
+            stego
+        };
+        let perceptual = embed(PERCEPTUAL_CHANNEL_BITS);
+        let uniform = embed([2, 2, 2]);
+
+        let uniform_weights = [UNIFORM; 3];
+        let plain = (
+            psnr(&original, &perceptual, uniform_weights),
+            psnr(&original, &uniform, uniform_weights),
+        );
+        let luma = (
+            psnr(&original, &perceptual, LUMA),
+            psnr(&original, &uniform, LUMA),
+        );
+
+        // Fewer changes per pixel in green outweigh touching more pixels,
+        // and more so once green's weight is taken into account.
+        assert!(plain.0 > plain.1, "{:?}", plain);
+        assert!(luma.0 > luma.1 + 0.5, "{:?}", luma);
+        assert!(
+            luma.0 - luma.1 > plain.0 - plain.1,
+            "{:?} {:?}",
+            luma,
+            plain
+        );
+    }
+
+    #[test]
+    fn payload_follows_the_header_of_every_version() {
+        for version in 1..=FORMAT_VERSION {
+            let mut layouts = vec![None];
+            if version >= CHANNEL_BITS_VERSION {
+                layouts.push(Some(PERCEPTUAL_CHANNEL_BITS));
+            }
+            for channel_bits in layouts {
+                // 100 bytes fit a one-byte varint length, 200 need two.
+                for len in [100, 200] {
+                    let params = EmbedParams {
+                        format_version: Some(version),
+                        channel_bits,
+                        ..EmbedParams::default()
+                    };
+                    let data: Vec<u8> = (0..len).map(|i| (i * 53 + 7) as u8).collect();
+                    let mut image = carrier(61, 47);
+                    SteganographyEngine::embed_data(&mut image, &data, &params).unwrap();
+
+                    let extracted = SteganographyEngine::validate_and_extract(&image).unwrap();
+                    let context = format!("version {} {:?} {} bytes", version, channel_bits, len);
+                    assert_eq!(extracted.format_version, version, "{}", context);
+                    assert_eq!(extracted.data, data, "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn span_arithmetic_reports_overflow() {
+        assert_eq!(
+            SteganographyEngine::strided_span(usize::MAX, u16::MAX),
+            None
+        );
+        assert_eq!(
+            SteganographyEngine::strided_span(usize::MAX, 1),
+            Some(usize::MAX)
+        );
+        assert_eq!(
+            SteganographyEngine::embedded_len(HEADER_LEN, usize::MAX, 1, false),
+            None
+        );
+        assert_eq!(
+            SteganographyEngine::embedded_len(HEADER_LEN, usize::MAX / 2, 1, true),
+            None
+        );
+    }
+
+    #[test]
+    fn header_lengths_near_u32_max_are_rejected() {
+        let fixed = [u64::from(u32::MAX) - 1, u64::from(u32::MAX)];
+        let varint = [u64::from(u32::MAX) + 1, u64::MAX];
+
+        for version in [
+            1,
+            2,
+            STRIDE_VERSION,
+            VARINT_LENGTH_VERSION - 1,
+            FORMAT_VERSION,
+        ] {
+            let strides: &[u16] = if version >= STRIDE_VERSION {
+                &[1, 2, u16::MAX]
+            } else {
+                &[1]
+            };
+            let lengths = if version >= VARINT_LENGTH_VERSION {
+                [fixed, varint].concat()
+            } else {
+                fixed.to_vec()
+            };
+
+            for &stride in strides {
+                for &length in &lengths {
+                    let header = Header {
+                        stride,
+                        length,
+                        ..Header::for_params(
+                            &EmbedParams {
+                                format_version: Some(version),
+                                ..EmbedParams::default()
+                            },
+                            0,
+                        )
+                    };
+                    let mut image = carrier(64, 64);
+                    SteganographyEngine::write_bits(&mut image, &header.to_bytes(), 0, 0, None);
+
+                    let result = SteganographyEngine::validate_and_extract(&image);
+                    assert!(
+                        matches!(result, Err(DeepSceneError::Data(_))),
+                        "version {} stride {} length {}: {:?}",
+                        version,
+                        stride,
+                        length,
+                        result.map(|e| e.data.len())
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strided_length_beyond_the_image_is_a_capacity_error() {
+        let header = Header {
+            stride: u16::MAX,
+            length: 1_000_000,
+            ..Header::for_params(&EmbedParams::default(), 0)
+        };
+        let mut image = carrier(64, 64);
+        SteganographyEngine::write_bits(&mut image, &header.to_bytes(), 0, 0, None);
+
+        assert!(matches!(
+            SteganographyEngine::validate_and_extract(&image),
+            Err(DeepSceneError::Data(message)) if message.contains("capacity exceeded")
+        ));
+    }
+
+    #[test]
+    fn chunk_crcs_round_trip() {
+        for len in [
+            0,
+            1,
+            CRC_CHUNK_LEN - 1,
+            CRC_CHUNK_LEN,
+            CRC_CHUNK_LEN + 1,
+            3 * CRC_CHUNK_LEN + 17,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 31 + 7) as u8).collect();
+            let stored = SteganographyEngine::add_chunk_crcs(&data);
+
+            assert_eq!(
+                stored.len(),
+                SteganographyEngine::stored_len(CHUNK_CRC_VERSION, len),
+                "{}",
+                len
+            );
+            assert_eq!(stored.len(), len + len.div_ceil(CRC_CHUNK_LEN) * CRC_LEN);
+            assert_eq!(
+                SteganographyEngine::strip_chunk_crcs(&stored).unwrap(),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn damaged_chunk_is_reported_with_its_payload_bytes() {
+        let len = 3 * CRC_CHUNK_LEN + 100;
+        let data: Vec<u8> = (0..len).map(|i| (i * 31 + 7) as u8).collect();
+        let stored = SteganographyEngine::add_chunk_crcs(&data);
+        let stored_chunk = CRC_CHUNK_LEN + CRC_LEN;
+
+        for index in 0..4 {
+            let start = index * CRC_CHUNK_LEN;
+            let end = (start + CRC_CHUNK_LEN).min(len) - 1;
+            let expected = format!("chunk {} of 4 (payload bytes {}-{})", index + 1, start, end);
+
+            // The first, last and a CRC byte of the chunk.
+            let chunk_end = ((index + 1) * stored_chunk).min(stored.len());
+            for position in [index * stored_chunk, chunk_end - CRC_LEN - 1, chunk_end - 1] {
+                let mut damaged = stored.clone();
+                damaged[position] ^= 0x10;
+                match SteganographyEngine::strip_chunk_crcs(&damaged) {
+                    Err(DeepSceneError::Data(message)) => {
+                        assert!(message.contains(&expected), "{}: {}", position, message)
+                    }
+                    other => panic!("{}: expected a data error, got {:?}", position, other),
+                }
+            }
+        }
+
+        // With several damaged chunks, the first is reported.
+        let mut damaged = stored.clone();
+        damaged[2 * stored_chunk] ^= 0x01;
+        damaged[stored_chunk] ^= 0x01;
+        assert!(matches!(
+            SteganographyEngine::strip_chunk_crcs(&damaged),
+            Err(DeepSceneError::Data(message)) if message.contains("chunk 2 of 4")
+        ));
+    }
+
+    #[test]
+    fn extraction_reports_the_damaged_chunk() {
+        let params = EmbedParams::default();
+        let data: Vec<u8> = (0..3 * CRC_CHUNK_LEN).map(|i| (i * 31 + 7) as u8).collect();
+        let mut image = carrier(200, 200);
+        SteganographyEngine::embed_data(&mut image, &data, &params).unwrap();
+
+        // Flip a bit of the first stored byte of the second chunk.
+        let header_len = Header::for_params(&params, data.len()).encoded_len();
+        let bit = (header_len + CRC_CHUNK_LEN + CRC_LEN) * 8;
+        image.as_mut()[bit / CHANNELS * 4 + bit % CHANNELS] ^= 0x01;
+
+        assert!(matches!(
+            SteganographyEngine::validate_and_extract(&image),
+            Err(DeepSceneError::Data(message)) if message.contains(&format!(
+                "chunk 2 of 3 (payload bytes {}-{})",
+                CRC_CHUNK_LEN,
+                2 * CRC_CHUNK_LEN - 1
+            ))
+        ));
+    }
+
+    #[test]
+    fn capacity_leaves_room_for_the_chunk_crcs() {
+        for available in [
+            0,
+            CRC_LEN,
+            CRC_LEN + 1,
+            CRC_CHUNK_LEN + CRC_LEN - 1,
+            CRC_CHUNK_LEN + CRC_LEN,
+            CRC_CHUNK_LEN + CRC_LEN + 1,
+            CRC_CHUNK_LEN + 2 * CRC_LEN,
+            3 * (CRC_CHUNK_LEN + CRC_LEN) + 123,
+        ] {
+            let capacity = SteganographyEngine::stored_capacity(CHUNK_CRC_VERSION, available);
+            assert!(
+                SteganographyEngine::stored_len(CHUNK_CRC_VERSION, capacity) <= available,
+                "{}",
+                available
+            );
+            assert!(
+                SteganographyEngine::stored_len(CHUNK_CRC_VERSION, capacity + 1) > available,
+                "{}",
+                available
+            );
+            assert_eq!(
+                SteganographyEngine::stored_capacity(CHUNK_CRC_VERSION - 1, available),
+                available
+            );
+        }
+
+        // Across the versions that add CRCs, the carrier holds the header,
+        // the payload and one CRC per started chunk, and not a byte more.
+        let (width, height) = (250, 180);
+        for version in [CHUNK_CRC_VERSION, VARINT_LENGTH_VERSION - 1, FORMAT_VERSION] {
+            let params = EmbedParams {
+                format_version: Some(version),
+                ..EmbedParams::default()
+            };
+            let capacity = SteganographyEngine::payload_capacity(width, height, &params);
+            assert!(capacity > 3 * CRC_CHUNK_LEN, "{}", version);
+
+            let used = |length: usize| {
+                Header::for_params(&params, length).encoded_len()
+                    + length
+                    + length.div_ceil(CRC_CHUNK_LEN) * CRC_LEN
+            };
+            let carrier_bytes = SteganographyEngine::calculate_capacity(width, height);
+            assert!(used(capacity) <= carrier_bytes, "{}", version);
+            assert!(used(capacity + 1) > carrier_bytes, "{}", version);
+        }
+
+        // Before the CRCs, every byte after the header holds payload.
+        let params = EmbedParams {
+            format_version: Some(CHUNK_CRC_VERSION - 1),
+            ..EmbedParams::default()
+        };
+        assert_eq!(
+            SteganographyEngine::payload_capacity(width, height, &params),
+            SteganographyEngine::calculate_capacity(width, height)
+                - Header::for_params(&params, 0).encoded_len()
+        );
     }
 }
@@ -0,0 +1,74 @@
+use crate::core::error::{DeepSceneError, Result};
+use image::ImageFormat;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::TiffError;
+use tiff::decoder::{ChunkType, Decoder};
+
+/// One strip of a TIFF carrier: a run of whole rows the file stores
+/// separately from the others, so edits confined to other strips leave its
+/// pixels intact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TiffStrip {
+    pub index: u32,
+    pub first_row: u32,
+    pub rows: u32,
+    /// Rows in every strip but the last, kept when writing the output so the
+    /// payload lands in the same strip.
+    pub rows_per_strip: u32,
+}
+
+impl TiffStrip {
+    /// Reads the strip layout of the TIFF at `path` and returns strip `index`.
+    pub fn read(path: &Path, index: u32) -> Result<Self> {
+        let format = image::io::Reader::open(path)?
+            .with_guessed_format()?
+            .format();
+        if format != Some(ImageFormat::Tiff) {
+            return Err(DeepSceneError::Validation(format!(
+                "'{}' is not a TIFF, so it has no strips to embed into",
+                path.display()
+            )));
+        }
+
+        let read_error = |e: TiffError| {
+            DeepSceneError::Image(format!(
+                "Failed to read the strip layout of '{}': {}",
+                path.display(),
+                e
+            ))
+        };
+
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?)).map_err(read_error)?;
+        if decoder.get_chunk_type() != ChunkType::Strip {
+            return Err(DeepSceneError::Validation(format!(
+                "'{}' stores its pixels in tiles, not strips",
+                path.display()
+            )));
+        }
+
+        let (_, height) = decoder.dimensions().map_err(read_error)?;
+        let (_, rows_per_strip) = decoder.chunk_dimensions();
+        let rows_per_strip = rows_per_strip.clamp(1, height.max(1));
+        let strip_count = height.div_ceil(rows_per_strip);
+
+        if index >= strip_count {
+            return Err(DeepSceneError::Validation(format!(
+                "'{}' has {} strips of {} rows, numbered from 0, so there is no strip {}",
+                path.display(),
+                strip_count,
+                rows_per_strip,
+                index
+            )));
+        }
+
+        let first_row = index * rows_per_strip;
+        Ok(Self {
+            index,
+            first_row,
+            rows: rows_per_strip.min(height - first_row),
+            rows_per_strip,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use crate::core::error::{DeepSceneError, ErrorKind, Result};
+use crate::io::{json_string, timestamp};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// What an operation touched, filled in from its options and result. Only
+/// paths, sizes and flags are recorded: there is deliberately no field that
+/// could hold a password, hint or payload contents.
+#[derive(Debug, Default)]
+pub struct AuditRecord {
+    pub command: &'static str,
+    /// Carrier image, or the directory for batch and verify-all.
+    pub input: Option<PathBuf>,
+    /// File embedded by encode.
+    pub file: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// Size of the embedded or extracted file.
+    pub file_size: Option<usize>,
+    /// Bytes embedded in or read from the carrier.
+    pub payload_size: Option<usize>,
+    pub encrypted: Option<bool>,
+    pub compressed: Option<bool>,
+}
+
+/// How an operation failed, as logged. DeepScene errors are recorded by kind
+/// only, since their messages can quote the password hint; other errors are
+/// plain messages from argument handling and are kept.
+#[derive(Debug)]
+pub struct AuditFailure {
+    kind: &'static str,
+    message: Option<String>,
+}
+
+impl AuditFailure {
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        match error.downcast_ref::<DeepSceneError>() {
+            Some(e) => Self::from_kind(e.kind()),
+            None => Self {
+                kind: "other",
+                message: Some(error.to_string()),
+            },
+        }
+    }
+
+    pub fn from_kind(kind: ErrorKind) -> Self {
+        Self {
+            kind: kind.code(),
+            message: None,
+        }
+    }
+
+    /// A fingerprint comparison that completed but found a different file.
+    pub fn mismatch() -> Self {
+        Self {
+            kind: "mismatch",
+            message: None,
+        }
+    }
+}
+
+impl AuditRecord {
+    /// One JSON object, without a trailing newline. Absent values are `null`
+    /// so every line has the same keys.
+    pub fn to_json(&self, time: u64, failure: Option<&AuditFailure>) -> String {
+        let path = |p: &Option<PathBuf>| {
+            p.as_ref().map_or("null".to_string(), |p| {
+                json_string(&p.display().to_string())
+            })
+        };
+        let number = |n: Option<usize>| n.map_or("null".to_string(), |n| n.to_string());
+        let flag = |b: Option<bool>| b.map_or("null".to_string(), |b| b.to_string());
+
+        format!(
+            "{{\"timestamp\":{},\"command\":{},\"input\":{},\"file\":{},\"output\":{},\"file_size\":{},\"payload_size\":{},\"encrypted\":{},\"compressed\":{},\"success\":{},\"error_kind\":{},\"error_message\":{}}}",
+            json_string(&timestamp::format_rfc3339(time)),
+            json_string(self.command),
+            path(&self.input),
+            path(&self.file),
+            path(&self.output),
+            number(self.file_size),
+            number(self.payload_size),
+            flag(self.encrypted),
+            flag(self.compressed),
+            failure.is_none(),
+            failure.map_or("null".to_string(), |f| json_string(f.kind)),
+            failure
+                .and_then(|f| f.message.as_deref())
+                .map_or("null".to_string(), json_string)
+        )
+    }
+}
+
+/// Append-only JSON Lines log of operations for `--audit-log`.
+pub struct AuditLog {
+    file: File,
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Opens (creating if needed) the log before the operation runs, so an
+    /// unwritable log stops it instead of leaving it unrecorded.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                DeepSceneError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Cannot open audit log '{}': {}", path.display(), e),
+                ))
+            })?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Appends one line for `record`, marked failed if `failure` is given.
+    /// The line is written in a single call so concurrent runs do not
+    /// interleave.
+    pub fn append(&mut self, record: &AuditRecord, failure: Option<&AuditFailure>) -> Result<()> {
+        let mut line = record.to_json(timestamp::now(), failure);
+        line.push('\n');
+
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|()| self.file.sync_data())
+            .map_err(|e| {
+                DeepSceneError::Io(io::Error::new(
+                    e.kind(),
+                    format!("Failed to write audit log '{}': {}", self.path.display(), e),
+                ))
+            })
+    }
+}
@@ -0,0 +1,97 @@
+use crate::core::{DeepSceneError, Result, messages};
+use crate::io::file_handler::{FileData, MAX_FILE_SIZE};
+use std::path::Path;
+
+/// Name the clipboard text is embedded under.
+pub const CLIPBOARD_FILE_NAME: &str = "clipboard.txt";
+
+/// Reads the clipboard's text as a file named `CLIPBOARD_FILE_NAME`, with
+/// the same size limits as a file on disk.
+pub fn read_text() -> Result<FileData> {
+    let text = get_text()?;
+    let path = Path::new(CLIPBOARD_FILE_NAME);
+
+    if text.is_empty() {
+        return Err(DeepSceneError::Validation(
+            "The clipboard text is empty".to_string(),
+        ));
+    }
+
+    if text.len() > MAX_FILE_SIZE {
+        return Err(DeepSceneError::Validation(messages::file_too_large(
+            path,
+            MAX_FILE_SIZE / (1024 * 1024),
+            text.len() / (1024 * 1024),
+        )));
+    }
+
+    Ok(FileData {
+        name: CLIPBOARD_FILE_NAME.to_string(),
+        data: text.into_bytes(),
+    })
+}
+
+/// Places `data` on the clipboard as text. Fails unless it is valid UTF-8.
+pub fn write_text(data: &[u8]) -> Result<()> {
+    let text = std::str::from_utf8(data).map_err(|_| {
+        DeepSceneError::Validation(
+            "The extracted file is not UTF-8 text, so it cannot be placed on the clipboard. Write it to a file with -o instead"
+                .to_string(),
+        )
+    })?;
+    set_text(text)
+}
+
+#[cfg(feature = "clipboard")]
+fn get_text() -> Result<String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| match e {
+            arboard::Error::ContentNotAvailable => DeepSceneError::Validation(
+                "The clipboard holds no text. Copy some text first".to_string(),
+            ),
+            e => clipboard_error(e),
+        })
+}
+
+/// On Linux the clipboard is served by the process that set it, so this
+/// waits until a clipboard manager or another program has taken it over.
+#[cfg(feature = "clipboard")]
+fn set_text(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(clipboard_error)?;
+
+    #[cfg(target_os = "linux")]
+    let result = {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().text(text)
+    };
+    #[cfg(not(target_os = "linux"))]
+    let result = clipboard.set_text(text);
+
+    result.map_err(clipboard_error)
+}
+
+#[cfg(feature = "clipboard")]
+fn clipboard_error(e: arboard::Error) -> DeepSceneError {
+    DeepSceneError::Io(std::io::Error::other(format!(
+        "Clipboard access failed: {}",
+        e
+    )))
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn get_text() -> Result<String> {
+    Err(no_clipboard_support())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn set_text(_text: &str) -> Result<()> {
+    Err(no_clipboard_support())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn no_clipboard_support() -> DeepSceneError {
+    DeepSceneError::Validation(
+        "This build has no clipboard support. Rebuild with --features clipboard".to_string(),
+    )
+}
@@ -0,0 +1,103 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+static RAW_BYTES: AtomicBool = AtomicBool::new(false);
+
+/// Forces `format_bytes` to print exact byte counts, for scripts parsing output.
+pub fn set_raw_bytes(raw: bool) {
+    RAW_BYTES.store(raw, Ordering::Relaxed);
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    if RAW_BYTES.load(Ordering::Relaxed) || bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+
+    // Compared after rounding to the two decimals printed, so a value just
+    // under a unit boundary shows as 1.00 of the next unit, not 1024.00.
+    for next in &UNITS[1..] {
+        if value < 1024.0 - 0.005 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    format!("{:.2} {}", value, unit)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        format!("{} ms", duration.as_millis())
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+/// `value` as a quoted JSON string, for the hand-written JSON the audit log
+/// and `--json` output use.
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: usize = 1024;
+    const MIB: usize = 1024 * KIB;
+    const GIB: usize = 1024 * MIB;
+
+    #[test]
+    fn small_sizes_are_exact_bytes() {
+        assert_eq!(format_bytes(0), "0 bytes");
+        assert_eq!(format_bytes(1), "1 bytes");
+        assert_eq!(format_bytes(1023), "1023 bytes");
+    }
+
+    #[test]
+    fn each_unit_starts_at_its_boundary() {
+        for (bytes, expected) in [
+            (KIB, "1.00 KiB"),
+            (KIB + KIB / 2, "1.50 KiB"),
+            (MIB - KIB, "1023.00 KiB"),
+            (MIB - 1, "1.00 MiB"),
+            (MIB, "1.00 MiB"),
+            (10 * MIB + MIB / 4, "10.25 MiB"),
+            (GIB - 1, "1.00 GiB"),
+            (GIB, "1.00 GiB"),
+            (5 * GIB, "5.00 GiB"),
+        ] {
+            assert_eq!(format_bytes(bytes), expected, "{}", bytes);
+        }
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn gib_is_the_largest_unit() {
+        assert_eq!(format_bytes(1024 * GIB), "1024.00 GiB");
+        assert_eq!(format_bytes(usize::MAX), "17179869184.00 GiB");
+    }
+}
@@ -1,10 +1,87 @@
 use crate::core::error::{DeepSceneError, Result};
+use crate::core::messages;
+use crate::progress;
+use rand::RngCore;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
+/// Output path that selects standard output instead of a file.
+pub const STDOUT_PATH: &str = "-";
+/// Image path that selects standard input instead of a file.
+pub const STDIN_PATH: &str = "-";
+
+pub const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
+/// Largest encoded image accepted from standard input or a URL. Checked
+/// while reading, before anything is decoded.
+pub const MAX_IMAGE_INPUT_SIZE: usize = 512 * 1024 * 1024;
 const MAX_FILENAME_LENGTH: usize = 255;
 
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+static FOLLOW_SYMLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Forbids network access for the rest of the run, even in builds with the
+/// `net` feature. A no-op otherwise, since those builds never use the network.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Allows writing to an output path that is a symbolic link, which then
+/// replaces the file the link points to. Refused by default.
+pub fn set_follow_symlinks(follow: bool) {
+    FOLLOW_SYMLINKS.store(follow, Ordering::Relaxed);
+}
+
+/// What encode does when a file it is about to create already exists. Applies
+/// to the converted carrier PNG as well as the output image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Fail before any work is done.
+    Never,
+    /// Write to the first free `<stem>-<N>.<ext>` next to it instead.
+    Rename,
+}
+
+impl OverwritePolicy {
+    /// Returns the path to write to under this policy.
+    pub fn resolve(self, path: &Path) -> Result<PathBuf> {
+        if !FileHandler::is_occupied(path) {
+            return Ok(path.to_path_buf());
+        }
+
+        match self {
+            OverwritePolicy::Overwrite => Ok(path.to_path_buf()),
+            OverwritePolicy::Never => {
+                Err(DeepSceneError::Validation(messages::output_exists(path)))
+            }
+            OverwritePolicy::Rename => {
+                let stem = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("output");
+                let ext = path.extension().and_then(|e| e.to_str());
+
+                (1u32..)
+                    .map(|n| match ext {
+                        Some(ext) => path.with_file_name(format!("{}-{}.{}", stem, n, ext)),
+                        None => path.with_file_name(format!("{}-{}", stem, n)),
+                    })
+                    .find(|candidate| !FileHandler::is_occupied(candidate))
+                    .ok_or_else(|| {
+                        DeepSceneError::Validation(format!(
+                            "No free file name next to '{}'",
+                            path.display()
+                        ))
+                    })
+            }
+        }
+    }
+}
+
 pub struct FileData {
     pub name: String,
     pub data: Vec<u8>,
@@ -14,54 +91,45 @@ pub struct FileHandler;
 
 impl FileHandler {
     pub fn read_file(path: &Path) -> Result<FileData> {
+        Self::note_symlink(path);
+
         if !path.exists() {
-            return Err(DeepSceneError::Validation(format!(
-                "File '{}' not found",
-                path.display()
-            )));
+            return Err(DeepSceneError::Validation(messages::file_not_found(path)));
         }
 
         if !path.is_file() {
-            return Err(DeepSceneError::Validation(format!(
-                "'{}' is not a file",
-                path.display()
-            )));
+            return Err(DeepSceneError::Validation(messages::not_a_file(path)));
         }
 
         let file_name = path
             .file_name()
             .and_then(|n| n.to_str())
-            .ok_or_else(|| DeepSceneError::Validation("Invalid file name".to_string()))?
+            .ok_or_else(|| DeepSceneError::Validation(messages::INVALID_FILE_NAME.to_string()))?
             .to_string();
 
         if file_name.is_empty() {
             return Err(DeepSceneError::Validation(
-                "File name cannot be empty".to_string(),
+                messages::EMPTY_FILE_NAME.to_string(),
             ));
         }
 
         if file_name.len() > MAX_FILENAME_LENGTH {
-            return Err(DeepSceneError::Validation(format!(
-                "File name too long (max {} bytes)",
-                MAX_FILENAME_LENGTH
+            return Err(DeepSceneError::Validation(messages::file_name_too_long(
+                MAX_FILENAME_LENGTH,
             )));
         }
 
         let data = fs::read(path)?;
 
         if data.is_empty() {
-            return Err(DeepSceneError::Validation(format!(
-                "File '{}' is empty",
-                path.display()
-            )));
+            return Err(DeepSceneError::Validation(messages::empty_file(path)));
         }
 
         if data.len() > MAX_FILE_SIZE {
-            return Err(DeepSceneError::Validation(format!(
-                "File '{}' is too large. Maximum file size is {} MB, but file is {} MB",
-                path.display(),
+            return Err(DeepSceneError::Validation(messages::file_too_large(
+                path,
                 MAX_FILE_SIZE / (1024 * 1024),
-                data.len() / (1024 * 1024)
+                data.len() / (1024 * 1024),
             )));
         }
 
@@ -72,43 +140,281 @@ impl FileHandler {
     }
 
     pub fn write_file(path: &Path, data: &[u8]) -> Result<()> {
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() && !parent.exists() {
+        Self::check_output_dir(path)?;
+        Self::check_not_symlink(path, "Output path")?;
+
+        if path.exists() && !path.is_file() {
+            return Err(DeepSceneError::Validation(messages::output_not_a_file(
+                path,
+            )));
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Overwrites a file with random bytes, flushes that to disk and then
+    /// removes it. On copy-on-write filesystems and SSDs the old blocks may
+    /// survive the overwrite; removal is still guaranteed. For a symbolic
+    /// link, the file it points to is shredded and the link removed too.
+    pub fn shred_file(path: &Path) -> Result<()> {
+        if Self::is_symlink(path) {
+            Self::shred_file(&fs::canonicalize(path)?)?;
+            fs::remove_file(path)?;
+            return Ok(());
+        }
+
+        let len = fs::metadata(path)?.len();
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        let mut rng = rand::thread_rng();
+        let mut buffer = [0u8; 64 * 1024];
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let n = remaining.min(buffer.len() as u64) as usize;
+            rng.fill_bytes(&mut buffer[..n]);
+            file.write_all(&buffer[..n])?;
+            remaining -= n as u64;
+        }
+
+        file.sync_all()?;
+        drop(file);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Whether both paths exist and name the same file.
+    pub fn is_same_file(a: &Path, b: &Path) -> bool {
+        match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Whether `path` is itself a symbolic link, dangling or not.
+    pub fn is_symlink(path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+    }
+
+    /// Whether anything, including a dangling symbolic link, already exists
+    /// at `path`.
+    fn is_occupied(path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok()
+    }
+
+    /// Fails when `path` is a symbolic link, unless `--follow-symlinks` was
+    /// given. Writing through a link replaces whatever it points to, which
+    /// `exists()` and `is_file()` hide since they follow it; a dangling link
+    /// would even create its target. `role` names the path in the error.
+    pub fn check_not_symlink(path: &Path, role: &str) -> Result<()> {
+        if FOLLOW_SYMLINKS.load(Ordering::Relaxed) || !Self::is_symlink(path) {
+            return Ok(());
+        }
+
+        let target = fs::read_link(path)
+            .map(|target| target.display().to_string())
+            .unwrap_or_else(|_| "an unreadable target".to_string());
+        Err(DeepSceneError::Validation(format!(
+            "{} '{}' is a symbolic link to '{}'. Refusing to write through it; use --follow-symlinks to allow this",
+            role,
+            path.display(),
+            target
+        )))
+    }
+
+    /// Reports which file an input path that is a symbolic link resolves
+    /// to, so reading it is never a surprise. Inputs are always followed.
+    pub fn note_symlink(path: &Path) {
+        if !Self::is_symlink(path) {
+            return;
+        }
+
+        match fs::canonicalize(path) {
+            Ok(target) => progress!(
+                "      > '{}' is a symbolic link to '{}'",
+                path.display(),
+                target.display()
+            ),
+            Err(_) => progress!(
+                "      > '{}' is a symbolic link whose target does not exist",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn is_stdout(path: &Path) -> bool {
+        path.as_os_str() == STDOUT_PATH
+    }
+
+    /// Whether an image path names standard input or an `http(s)://` URL
+    /// rather than a file on disk.
+    pub fn is_streamed_input(path: &Path) -> bool {
+        Self::is_stdin(path) || Self::input_url(path).is_some()
+    }
+
+    pub fn is_stdin(path: &Path) -> bool {
+        path.as_os_str() == STDIN_PATH
+    }
+
+    fn input_url(path: &Path) -> Option<&str> {
+        path.to_str()
+            .filter(|p| p.starts_with("http://") || p.starts_with("https://"))
+    }
+
+    /// Reads image bytes from standard input (`-`) or a URL, refusing input
+    /// larger than `MAX_IMAGE_INPUT_SIZE`.
+    pub fn read_streamed_input(path: &Path) -> Result<Vec<u8>> {
+        let data = match Self::input_url(path) {
+            Some(url) if OFFLINE.load(Ordering::Relaxed) => {
                 return Err(DeepSceneError::Validation(format!(
-                    "Output directory '{}' does not exist",
-                    parent.display()
+                    "Cannot fetch '{}': network access is disabled by --offline",
+                    url
                 )));
             }
+            Some(url) => Self::fetch_url(url)?,
+            None => {
+                let mut data = Vec::new();
+                io::stdin()
+                    .lock()
+                    .take(MAX_IMAGE_INPUT_SIZE as u64 + 1)
+                    .read_to_end(&mut data)?;
+                data
+            }
+        };
+
+        if data.len() > MAX_IMAGE_INPUT_SIZE {
+            return Err(Self::input_too_large(path));
         }
 
-        if path.exists() && !path.is_file() {
+        if data.is_empty() {
             return Err(DeepSceneError::Validation(format!(
-                "Output path '{}' exists but is not a file",
+                "No image data received from '{}'",
                 path.display()
             )));
         }
 
-        fs::write(path, data)?;
+        Ok(data)
+    }
+
+    #[cfg(feature = "net")]
+    fn fetch_url(url: &str) -> Result<Vec<u8>> {
+        let fetch_error =
+            |e: ureq::Error| io::Error::other(format!("Failed to fetch '{}': {}", url, e));
+
+        let mut response = ureq::get(url).call().map_err(fetch_error)?;
+        let body = response.body_mut();
+
+        if body
+            .content_length()
+            .is_some_and(|len| len > MAX_IMAGE_INPUT_SIZE as u64)
+        {
+            return Err(Self::input_too_large(Path::new(url)));
+        }
+
+        match body
+            .with_config()
+            .limit(MAX_IMAGE_INPUT_SIZE as u64)
+            .read_to_vec()
+        {
+            Ok(data) => Ok(data),
+            Err(ureq::Error::BodyExceedsLimit(_)) => Err(Self::input_too_large(Path::new(url))),
+            Err(e) => Err(fetch_error(e).into()),
+        }
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn fetch_url(url: &str) -> Result<Vec<u8>> {
+        Err(DeepSceneError::Validation(format!(
+            "Cannot fetch '{}': this build has no network support. Rebuild with --features net",
+            url
+        )))
+    }
+
+    fn input_too_large(path: &Path) -> DeepSceneError {
+        DeepSceneError::Validation(format!(
+            "Image from '{}' is too large. Maximum input size is {} MB",
+            path.display(),
+            MAX_IMAGE_INPUT_SIZE / (1024 * 1024)
+        ))
+    }
+
+    pub fn write_stdout(data: &[u8]) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
         Ok(())
     }
 
-    pub fn validate_output_path(path: &Path) -> Result<()> {
-        if let Some(parent) = path.parent() {
-            if !parent.as_os_str().is_empty() && !parent.exists() {
-                return Err(DeepSceneError::Validation(format!(
-                    "Output directory '{}' does not exist",
-                    parent.display()
+    /// Checks that `path` names a file inside an existing directory. A bare
+    /// name lives in the current directory; every form is resolved through
+    /// `canonicalize`, so `out.png`, `./out.png`, `dir/../out.png` and
+    /// absolute paths are checked the same way.
+    fn check_output_dir(path: &Path) -> Result<()> {
+        if path.file_name().is_none() {
+            return Err(DeepSceneError::Validation(format!(
+                "Output path '{}' does not name a file",
+                path.display()
+            )));
+        }
+
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let dir = match fs::canonicalize(parent) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(DeepSceneError::Validation(messages::output_dir_missing(
+                    parent,
                 )));
             }
+            Err(e) if e.kind() == io::ErrorKind::NotADirectory => {
+                return Err(DeepSceneError::Validation(
+                    messages::output_dir_not_a_directory(parent),
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !dir.is_dir() {
+            return Err(DeepSceneError::Validation(
+                messages::output_dir_not_a_directory(parent),
+            ));
         }
 
+        Ok(())
+    }
+
+    pub fn validate_output_path(path: &Path) -> Result<()> {
+        Self::check_output_dir(path)?;
+        Self::check_not_symlink(path, "Output path")?;
+
         if path.exists() && !path.is_file() {
-            return Err(DeepSceneError::Validation(format!(
-                "Output path '{}' exists but is not a file",
-                path.display()
+            return Err(DeepSceneError::Validation(messages::output_not_a_file(
+                path,
             )));
         }
 
         Ok(())
     }
+
+    pub fn list_files(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+        if !dir.is_dir() {
+            return Err(DeepSceneError::Validation(messages::not_a_directory(dir)));
+        }
+
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_file() {
+                files.push(path);
+            } else if recursive && path.is_dir() {
+                files.extend(Self::list_files(&path, true)?);
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
 }
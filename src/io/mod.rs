@@ -1,3 +1,10 @@
+pub mod audit;
+pub mod clipboard;
+pub mod display;
 pub mod file_handler;
+pub mod password;
+pub mod progress;
+pub mod timestamp;
 
-pub use file_handler::FileHandler;
+pub use display::{format_bytes, format_duration, json_string};
+pub use file_handler::{FileHandler, OverwritePolicy};
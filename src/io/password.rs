@@ -0,0 +1,184 @@
+use crate::core::error::{DeepSceneError, Result};
+use rand::RngCore;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+const UTF8_BOM: &str = "\u{feff}";
+
+/// Random bytes behind a generated mnemonic: 128 bits, which BIP39 encodes
+/// as 12 words.
+pub const MNEMONIC_ENTROPY_LEN: usize = 16;
+
+/// Most candidates a password list may hold. Each costs a full key
+/// derivation, so a longer list would run for days.
+pub const MAX_PASSWORD_CANDIDATES: usize = 10_000;
+
+/// Reads a password from a file.
+///
+/// Unless `raw` is set, a leading UTF-8 byte order mark and a single trailing
+/// `\n` or `\r\n` are removed, so files written by editors and `echo` yield
+/// the same key on encode and decode. Other whitespace is kept as part of
+/// the password.
+pub fn read_password_file(path: &Path, raw: bool) -> Result<String> {
+    let bytes = fs::read(path).map_err(|e| {
+        DeepSceneError::Validation(format!(
+            "Cannot read password file '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    normalize(bytes, raw, &format!("password file '{}'", path.display()))
+}
+
+/// Reads a password from standard input until end of file, trimmed as in
+/// `read_password_file`.
+pub fn read_password_stdin(raw: bool) -> Result<String> {
+    let mut bytes = Vec::new();
+    io::stdin().lock().read_to_end(&mut bytes)?;
+
+    normalize(bytes, raw, "standard input")
+}
+
+/// Reads candidate passwords from a file, one per line. A leading UTF-8 byte
+/// order mark and line endings are removed and empty lines are skipped; all
+/// other whitespace is kept.
+pub fn read_password_list(path: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(path).map_err(|e| {
+        DeepSceneError::Validation(format!(
+            "Cannot read password list '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let text = String::from_utf8(bytes).map_err(|_| {
+        DeepSceneError::Validation(format!(
+            "Password list '{}' is not valid UTF-8",
+            path.display()
+        ))
+    })?;
+
+    let candidates: Vec<String> = text
+        .strip_prefix(UTF8_BOM)
+        .unwrap_or(&text)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if candidates.is_empty() {
+        return Err(DeepSceneError::Validation(format!(
+            "Password list '{}' holds no passwords",
+            path.display()
+        )));
+    }
+    if candidates.len() > MAX_PASSWORD_CANDIDATES {
+        return Err(DeepSceneError::Validation(format!(
+            "Password list '{}' holds {} passwords, more than the {} allowed. Split it into several lists",
+            path.display(),
+            candidates.len(),
+            MAX_PASSWORD_CANDIDATES
+        )));
+    }
+
+    Ok(candidates)
+}
+
+/// Generates a BIP39 English mnemonic from `MNEMONIC_ENTROPY_LEN` random
+/// bytes, for use as a password. The words are separated by single spaces,
+/// which is how decode must be given them.
+pub fn generate_mnemonic() -> Result<String> {
+    let mut entropy = [0u8; MNEMONIC_ENTROPY_LEN];
+    rand::thread_rng().fill_bytes(&mut entropy);
+
+    // BIP39 only defines 16 to 32 bytes of entropy in steps of 4.
+    bip39::Mnemonic::from_entropy(&entropy)
+        .map(|mnemonic| mnemonic.to_string())
+        .map_err(|e| {
+            DeepSceneError::Validation(format!(
+                "Cannot generate a mnemonic from {} bytes of entropy: {}",
+                MNEMONIC_ENTROPY_LEN, e
+            ))
+        })
+}
+
+fn normalize(bytes: Vec<u8>, raw: bool, source: &str) -> Result<String> {
+    let text = String::from_utf8(bytes).map_err(|_| {
+        DeepSceneError::Validation(format!("Password from {} is not valid UTF-8", source))
+    })?;
+
+    if raw {
+        return Ok(text);
+    }
+
+    let text = text.strip_prefix(UTF8_BOM).unwrap_or(&text);
+    let text = text
+        .strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text);
+
+    Ok(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(bytes: &[u8], raw: bool) -> String {
+        normalize(bytes.to_vec(), raw, "test").unwrap()
+    }
+
+    #[test]
+    fn trims_one_trailing_line_ending() {
+        assert_eq!(normalized(b"secret\n", false), "secret");
+        assert_eq!(normalized(b"secret\r\n", false), "secret");
+        assert_eq!(normalized(b"secret\n\n", false), "secret\n");
+        assert_eq!(normalized(b"secret", false), "secret");
+    }
+
+    #[test]
+    fn strips_a_leading_bom() {
+        assert_eq!(normalized(b"\xef\xbb\xbfsecret\r\n", false), "secret");
+    }
+
+    #[test]
+    fn keeps_other_whitespace() {
+        assert_eq!(normalized(b" secret \t\n", false), " secret \t");
+        assert_eq!(normalized(b"secret\r", false), "secret\r");
+    }
+
+    #[test]
+    fn raw_keeps_everything() {
+        assert_eq!(
+            normalized(b"\xef\xbb\xbfsecret\r\n", true),
+            "\u{feff}secret\r\n"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        assert!(matches!(
+            normalize(vec![0xff, 0xfe], false, "test"),
+            Err(DeepSceneError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn file_and_echo_give_the_same_password() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let typed = dir.path().join("typed");
+        let echoed = dir.path().join("echoed");
+        fs::write(&typed, "secret").unwrap();
+        fs::write(&echoed, "secret\n").unwrap();
+
+        assert_eq!(
+            read_password_file(&typed, false).unwrap(),
+            read_password_file(&echoed, false).unwrap()
+        );
+        assert_ne!(
+            read_password_file(&typed, true).unwrap(),
+            read_password_file(&echoed, true).unwrap()
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::core::ProgressReporter;
+use crate::core::error::{DeepSceneError, Result};
+use crate::io::json_string;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static STRICT: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses step-by-step progress output. Warnings, results and errors are
+/// still printed.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Sends all human-readable output to stderr, for when stdout carries binary
+/// data such as an encoded image.
+pub fn set_stderr(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+/// Turns every warning into a `Validation` error, for automation that must
+/// not proceed past anything questionable.
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Enables extra detail such as the files an operation will create or
+/// replace, printed before it starts.
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+pub fn emit(message: fmt::Arguments) {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+pub fn report(message: fmt::Arguments) {
+    if !is_quiet() {
+        emit(message);
+    }
+}
+
+/// Prints a warning, or fails with it in strict mode.
+pub fn warn(message: fmt::Arguments) -> Result<()> {
+    if STRICT.load(Ordering::Relaxed) {
+        return Err(DeepSceneError::Validation(format!(
+            "{} (warnings are errors with --strict)",
+            message.to_string().trim_end()
+        )));
+    }
+
+    emit(format_args!("Warning: {}", message));
+    Ok(())
+}
+
+/// Writes each progress update as one JSON object per line on stderr, such
+/// as `{"stage":"embed","done":1234,"total":5000}`, for frontends that draw
+/// their own progress.
+pub struct JsonProgress;
+
+impl ProgressReporter for JsonProgress {
+    fn update(&self, stage: &str, done: usize, total: usize) {
+        eprintln!(
+            "{{\"stage\":{},\"done\":{},\"total\":{}}}",
+            json_string(stage),
+            done,
+            total
+        );
+    }
+}
+
+/// `println!` for progress messages, silenced by `set_quiet(true)`.
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        $crate::io::progress::report(format_args!($($arg)*))
+    };
+}
+
+/// `println!` for warnings and results, which quiet mode does not silence.
+#[macro_export]
+macro_rules! message {
+    ($($arg:tt)*) => {
+        $crate::io::progress::emit(format_args!($($arg)*))
+    };
+}
+
+/// Reports a warning through `warn`; evaluates to a `Result` that is an error
+/// in strict mode.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::io::progress::warn(format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,133 @@
+use crate::core::error::{DeepSceneError, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Parses `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM[:SS][Z]` as a UTC time and
+/// returns seconds since the Unix epoch. A bare date means midnight UTC at
+/// the start of that day.
+pub fn parse_timestamp(value: &str) -> Result<u64> {
+    let invalid = || {
+        DeepSceneError::Validation(format!(
+            "Invalid date '{}'. Expected YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]Z (UTC)",
+            value
+        ))
+    };
+
+    let value = value.trim();
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z').unwrap_or(time))),
+        None => (value, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<u32>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (date_parts.next(), date_parts.next(), date_parts.next())
+    else {
+        return Err(invalid());
+    };
+
+    if year < 1970 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+
+    let seconds_of_day = match time {
+        Some(time) => {
+            let mut parts = time.splitn(3, ':').map(|p| p.parse::<u64>().ok());
+            let hour = parts.next().flatten().ok_or_else(invalid)?;
+            let minute = parts.next().flatten().ok_or_else(invalid)?;
+            let second = match parts.next() {
+                Some(second) => second.ok_or_else(invalid)?,
+                None => 0,
+            };
+
+            if hour > 23 || minute > 59 || second > 59 {
+                return Err(invalid());
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+
+    Ok(days_from_civil(year, month, day) * SECONDS_PER_DAY + seconds_of_day)
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days(timestamp / SECONDS_PER_DAY);
+    let seconds = timestamp % SECONDS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Formats seconds since the Unix epoch as RFC 3339, `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_rfc3339(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days(timestamp / SECONDS_PER_DAY);
+    let seconds = timestamp % SECONDS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`, restricted to years from 1970).
+fn days_from_civil(year: u32, month: u32, day: u32) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year } as u64;
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = month as u64;
+    let day_of_year =
+        (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
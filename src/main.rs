@@ -1,22 +1,80 @@
 use clap::Parser;
 use deepscene::cli;
-use deepscene::processor::{DataProcessor, DecodeResult, EncodeResult};
+use deepscene::core::{CompatSpec, ErrorKind, KdfAlgorithm, RandomSource};
+use deepscene::io::audit::{AuditFailure, AuditLog, AuditRecord};
+use deepscene::io::{
+    FileHandler, OverwritePolicy, clipboard, display, file_handler, format_bytes, password,
+    progress, timestamp,
+};
+use deepscene::message;
+use deepscene::processor::archive::ArchiveJob;
+use deepscene::processor::batch::{BatchOptions, BatchProcessor, BatchReport, PairingStrategy};
+use deepscene::processor::verify::{VerifyLevel, VerifyOptions, VerifyProcessor, VerifyReport};
+use deepscene::processor::{
+    AlgorithmList, CapacityMapOptions, CapacityMapResult, CapacityTable, CheckOptions, CheckResult,
+    DataProcessor, DecodeAllOptions, DecodeAllReport, DecodeOptions, DecodeResult, DiffMapOptions,
+    DiffMapResult, EncodeOptions, EncodeResult, EstimateResult, FingerprintOptions,
+    FingerprintResult, FitEstimate, ListOptions, ListResult, StripOptions, StripResult,
+    TransferOptions, TransferResult,
+};
+use std::path::PathBuf;
 
 fn print_encode_result(result: &EncodeResult) {
-    println!(
-        "File hidden successfully in '{}'",
-        result.output_path.display()
-    );
+    if FileHandler::is_stdout(&result.output_path) {
+        message!("File hidden successfully, image written to standard output");
+    } else {
+        message!(
+            "File hidden successfully in '{}'",
+            result.output_path.display()
+        );
+    }
 
-    println!("File: {}", result.file_name);
-    println!("Encrypted: {}", if result.encrypted { "Yes" } else { "No" });
-    println!(
+    message!("File: {}", result.file_name);
+    message!("Encrypted: {}", if result.encrypted { "Yes" } else { "No" });
+    message!(
         "Compressed: {}",
         if result.compressed { "Yes" } else { "No" }
     );
 
     if result.converted_to_png {
-        println!("Converted to PNG: Yes");
+        message!("Converted to PNG: Yes");
+    }
+
+    if result.bit_plane > 0 {
+        message!("Bit plane: {}", result.bit_plane);
+    }
+
+    if result.stride > 1 {
+        message!("Stride: every {} pixel groups", result.stride);
+    }
+
+    if result.channel_copies {
+        message!("Channel copies: Yes");
+    }
+
+    if let Some([r, g, b]) = result.channel_bits {
+        message!("Channel bits: R{} G{} B{}", r, g, b);
+    }
+
+    if let Some(region) = result.preserve_region {
+        message!("Preserved region: {}", region);
+    }
+
+    if let Some(strip) = result.tiff_strip {
+        message!(
+            "TIFF strip: {} (rows {}-{})",
+            strip.index,
+            strip.first_row,
+            strip.first_row + strip.rows - 1
+        );
+    }
+
+    if let Some(version) = result.format_version {
+        message!("Format version: {}", version);
+    }
+
+    if let Some(fingerprint) = result.fingerprint {
+        message!("Fingerprint: {}", fingerprint);
     }
 
     let percentage = if result.final_size < result.original_size {
@@ -26,83 +84,1212 @@ fn print_encode_result(result: &EncodeResult) {
     };
 
     if result.compressed {
+        message!(
+            "Original size: {}, Final size: {} ({:.2}% reduction)",
+            format_bytes(result.original_size),
+            format_bytes(result.final_size),
+            percentage
+        );
+    } else {
+        message!("Payload size: {}", format_bytes(result.original_size));
+    }
+
+    message!(
+        "\nNOTE:\nOnly lossless formats (PNG, BMP, TIFF, DNG, PPM/PGM/PNM) preserve hidden data."
+    );
+    message!("Lossy formats (JPEG, WebP) will corrupt the embedded information.\n");
+}
+
+fn print_estimate_result(result: &EstimateResult) {
+    println!("Estimate for '{}'", result.file_name);
+    println!("Original size: {}", format_bytes(result.original_size));
+
+    if result.compressed {
+        let reduction = ((result.original_size - result.compressed_size) as f64
+            / result.original_size as f64)
+            * 100.0;
         println!(
-            "Original size: {} bytes, Final size: {} bytes ({:.2}% reduction)",
-            result.original_size, result.final_size, percentage
+            "Compressed size: {} ({:.2}% reduction)",
+            format_bytes(result.compressed_size),
+            reduction
         );
     } else {
-        println!("Payload size: {} bytes", result.original_size);
+        println!("Compressed size: skipped, would not reduce size");
     }
 
-    println!("\nNOTE:\nOnly lossless formats (PNG, BMP, TIFF) preserve hidden data.");
-    println!("Lossy formats (JPEG, WebP) will corrupt the embedded information.\n");
+    println!("Embedded payload: {}", format_bytes(result.payload_size));
+    println!("Carrier capacity: {}", format_bytes(result.capacity));
+
+    if result.fits() {
+        println!("Fits: Yes\n");
+    } else {
+        println!(
+            "Fits: No ({} over capacity)\n",
+            format_bytes(result.payload_size - result.capacity)
+        );
+    }
 }
 
-fn print_decode_result(result: &DecodeResult) {
+fn print_fit_estimate(result: &FitEstimate) {
+    println!("Fit estimate for '{}'", result.file_name);
     println!(
-        "File extracted successfully to '{}'",
-        result.output_path.display()
+        "Original size: {} ({} sampled)",
+        format_bytes(result.original_size),
+        format_bytes(result.sampled_size)
+    );
+    println!(
+        "Embedded payload: {} expected, {} to {}",
+        format_bytes(result.expected_payload_size),
+        format_bytes(result.best_payload_size),
+        format_bytes(result.raw_payload_size)
     );
+    println!("Carrier capacity: {}", format_bytes(result.capacity));
+    println!("Verdict: {}\n", result.verdict().code());
+}
+
+fn print_decode_result(result: &DecodeResult) {
+    match result.checked {
+        Some(VerifyLevel::ChecksumsOnly) => {
+            println!("Check: OK (checksums only)");
+            println!(
+                "The payload is encrypted; give its password to also check decryption, decompression and metadata\n"
+            );
+            return;
+        }
+        Some(VerifyLevel::Full) => println!("Check: OK (nothing written)"),
+        None if result.on_clipboard => println!("File extracted successfully to the clipboard"),
+        None => println!(
+            "File extracted successfully to '{}'",
+            result.output_path.display()
+        ),
+    }
     println!("File name: {}", result.file_name);
     println!("Encrypted: {}", if result.encrypted { "Yes" } else { "No" });
-    println!("Extracted {} bytes\n", result.file_size);
+
+    if let Some(signer) = result.signer {
+        println!(
+            "Signed by: {} ({})",
+            signer,
+            if result.signer_trusted {
+                "matches --verify-key"
+            } else {
+                "unverified key; pass --verify-key to require it"
+            }
+        );
+    }
+
+    if let Some(expires) = result.expires {
+        println!("Expires: {}", timestamp::format_timestamp(expires));
+    }
+
+    if !result.metadata.is_empty() {
+        println!("Metadata:");
+        for (key, value) in &result.metadata {
+            println!("  {}: {}", key, value);
+        }
+    }
+
+    if let Some(ref carrier) = result.consumed_carrier {
+        println!("Image '{}' overwritten and deleted", carrier.display());
+    }
+
+    if result.checked.is_some() {
+        println!("Checked {}\n", format_bytes(result.file_size));
+    } else {
+        println!("Extracted {}\n", format_bytes(result.file_size));
+    }
+}
+
+fn print_decode_all_report(report: &DecodeAllReport) {
+    println!("Payloads:");
+
+    for (index, entry) in report.entries.iter().enumerate() {
+        let location = match entry.tiff_strip {
+            Some(rows) => format!(
+                "{} (rows {}-{})",
+                index + 1,
+                rows.y,
+                rows.y + rows.height - 1
+            ),
+            None => (index + 1).to_string(),
+        };
+        match &entry.result {
+            Ok(payload) => println!(
+                "  OK      {} -> '{}' ({}, {})",
+                location,
+                payload.output_path.display(),
+                format_bytes(payload.file_size),
+                match payload.password_index {
+                    Some(password) => format!("password {}", password + 1),
+                    None => "not encrypted".to_string(),
+                }
+            ),
+            Err(e) => println!("  FAILED  {}: {}", location, e),
+        }
+    }
+
+    println!(
+        "\n{} extracted, {} failed\n",
+        report.succeeded(),
+        report.failed()
+    );
+}
+
+fn print_batch_report(report: &BatchReport) {
+    println!("Batch summary:");
+
+    for entry in &report.entries {
+        match &entry.result {
+            Ok(Some(result)) => println!(
+                "  OK      '{}' -> '{}'",
+                entry.file_path.display(),
+                result.output_path.display()
+            ),
+            Ok(None) => println!(
+                "  SKIPPED '{}' -> '{}' (completed in a previous run)",
+                entry.file_path.display(),
+                entry.output_path.display()
+            ),
+            Err(e) => println!("  FAILED  '{}': {}", entry.file_path.display(), e),
+        }
+    }
+
+    println!(
+        "\n{} succeeded ({} skipped), {} failed\n",
+        report.succeeded(),
+        report.skipped(),
+        report.failed()
+    );
+}
+
+fn print_verify_report(report: &VerifyReport) {
+    println!("Verify summary:");
+
+    for entry in &report.entries {
+        match &entry.result {
+            Ok(VerifyLevel::Full) => println!("  OK      '{}'", entry.image_path.display()),
+            Ok(VerifyLevel::ChecksumsOnly) => println!(
+                "  OK      '{}' (encrypted; checksums only, no password given)",
+                entry.image_path.display()
+            ),
+            Err(e) => println!("  FAILED  '{}': {}", entry.image_path.display(), e),
+        }
+    }
+
+    println!(
+        "\n{} passed ({} checksums only), {} failed\n",
+        report.passed(),
+        report.checksums_only(),
+        report.failed()
+    );
+}
+
+fn print_list_result(result: &ListResult) {
+    println!("Format version: {}", result.format_version);
+    if result.stride > 1 {
+        println!("Stride: every {} pixel groups", result.stride);
+    }
+    if result.channel_copies {
+        println!("Channel copies: one per colour channel");
+    }
+    if let Some([r, g, b]) = result.channel_bits {
+        println!("Channel bits: R{} G{} B{}", r, g, b);
+    }
+    if let Some(region) = result.preserved_region {
+        println!("Preserved region: {}", region);
+    }
+    if let Some(rows) = result.tiff_strip {
+        println!("TIFF strip: rows {}-{}", rows.y, rows.y + rows.height - 1);
+    }
+    if let Some(hint) = &result.hint {
+        println!("Password hint: {}", hint);
+    }
+    println!();
+
+    let name_width = result
+        .entries
+        .iter()
+        .map(|e| e.file_name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+
+    println!(
+        "{:<width$}  {:>12}  {:<9}  COMPRESSED",
+        "NAME",
+        "STORED SIZE",
+        "ENCRYPTED",
+        width = name_width
+    );
+
+    for entry in &result.entries {
+        println!(
+            "{:<width$}  {:>12}  {:<9}  {}",
+            entry.file_name,
+            format_bytes(entry.stored_size),
+            if entry.encrypted { "Yes" } else { "No" },
+            if entry.compressed { "Yes" } else { "No" },
+            width = name_width
+        );
+    }
+
+    if result.metadata_encrypted {
+        println!(
+            "\nThe file name and metadata are encrypted; decode with the password to read them."
+        );
+    }
+
+    if let Some(expires) = result.expires {
+        println!("\nExpires: {}", timestamp::format_timestamp(expires));
+    }
+
+    if !result.metadata.is_empty() {
+        println!("\nMetadata:");
+        for (key, value) in &result.metadata {
+            println!("  {}: {}", key, value);
+        }
+    }
+
+    println!("\n{} file(s)", result.entries.len());
+}
+
+fn print_capacity_map_result(result: &CapacityMapResult) {
+    println!("Capacity map written to '{}'", result.output_path.display());
+    println!("Dimensions: {}x{}", result.width, result.height);
+    println!("Capacity: {}", format_bytes(result.capacity));
+    println!(
+        "Flat regions: {:.1}% of pixels (dark in the map; changes there are easiest to detect)",
+        result.flat_ratio * 100.0
+    );
+    println!("Payloads are embedded in row-major order from the top-left pixel.\n");
+}
+
+fn print_diff_map_result(result: &DiffMapResult) {
+    println!(
+        "Difference map written to '{}'",
+        result.output_path.display()
+    );
+    if let Some(ref clean_path) = result.clean_path {
+        println!("Clean image written to '{}'", clean_path.display());
+    }
+    println!("Dimensions: {}x{}", result.width, result.height);
+    println!(
+        "Bit plane {} set: {:.1}% of R, G and B values (about 50% where random data is embedded)",
+        result.bit_plane,
+        result.set_ratio * 100.0
+    );
+    println!(
+        "DeepScene header: {}\n",
+        if result.has_header {
+            "Found"
+        } else {
+            "Not found"
+        }
+    );
+}
+
+fn print_strip_result(result: &StripResult) {
+    println!("Clean image written to '{}'", result.output_path.display());
+    println!("Dimensions: {}x{}", result.width, result.height);
+    let planes = if result.planes == 1 {
+        "Bit plane 0".to_string()
+    } else {
+        format!("Bit planes 0-{}", result.planes - 1)
+    };
+    println!(
+        "{} of R, G and B {}",
+        planes,
+        if result.randomize {
+            "randomized"
+        } else {
+            "cleared"
+        }
+    );
+    println!(
+        "DeepScene header in the input: {}\n",
+        if result.had_header {
+            "Found, now destroyed"
+        } else {
+            "Not found"
+        }
+    );
+}
+
+fn print_mnemonic(mnemonic: &str) {
+    message!("Password mnemonic: {}", mnemonic);
+    message!(
+        "Write it down now: it is not stored anywhere, and decoding needs it as the password, words separated by single spaces\n"
+    );
+}
+
+fn print_transfer_result(result: &TransferResult) {
+    message!(
+        "Payload transferred successfully to '{}'",
+        result.output_path.display()
+    );
+    message!("Format version: {}", result.format_version);
+    if result.bit_plane > 0 {
+        message!("Bit plane: {}", result.bit_plane);
+    }
+    message!(
+        "Payload: {} of {} capacity",
+        format_bytes(result.payload_size),
+        format_bytes(result.capacity)
+    );
+}
+
+fn print_fingerprint_result(result: &FingerprintResult, compare: Option<&PathBuf>) {
+    println!("Fingerprint: {}", result.fingerprint);
+    if let (Some(path), Some(matches)) = (compare, result.matches) {
+        println!(
+            "Matches '{}': {}",
+            path.display(),
+            if matches { "Yes" } else { "No" }
+        );
+    }
+}
+
+fn print_algorithms(algorithms: &AlgorithmList) {
+    let name_width = algorithms
+        .entries
+        .iter()
+        .map(|a| a.name.len() + if a.default { " (default)".len() } else { 0 })
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+
+    println!(
+        "{:<11}  {:<width$}  {:<7}  PARAMETERS",
+        "CATEGORY",
+        "NAME",
+        "FORMAT",
+        width = name_width
+    );
+    for a in &algorithms.entries {
+        let name = if a.default {
+            format!("{} (default)", a.name)
+        } else {
+            a.name.to_string()
+        };
+        println!(
+            "{:<11}  {:<width$}  {:<7}  {}",
+            a.category,
+            name,
+            format!("v{}+", a.min_version),
+            a.parameters,
+            width = name_width
+        );
+    }
+}
+
+fn print_capacity_table(table: &CapacityTable) {
+    let file_width = table
+        .entries
+        .iter()
+        .map(|e| e.image_path.display().to_string().chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("FILE".len());
+
+    println!(
+        "{:<width$}  {:>11}  {:<6}  {:<8}  CAPACITY",
+        "FILE",
+        "DIMENSIONS",
+        "FORMAT",
+        "LOSSLESS",
+        width = file_width
+    );
+
+    for entry in &table.entries {
+        let file = entry.image_path.display().to_string();
+        match &entry.result {
+            Ok(carrier) => println!(
+                "{:<width$}  {:>11}  {:<6}  {:<8}  {}",
+                file,
+                format!("{}x{}", carrier.width, carrier.height),
+                carrier.format,
+                if carrier.lossless { "Yes" } else { "No" },
+                format_bytes(carrier.capacity),
+                width = file_width
+            ),
+            Err(e) => println!("{:<width$}  unreadable: {}", file, e, width = file_width),
+        }
+    }
+
+    println!(
+        "\n{} image(s), {} unreadable",
+        table.entries.len(),
+        table.unreadable()
+    );
+}
+
+fn print_check_result(result: &CheckResult) {
+    println!("Dimensions: {}x{}", result.width, result.height);
+    println!("Capacity: {}", format_bytes(result.capacity));
+    println!("Needs: {}", format_bytes(result.needs));
+    println!("Fits: {}", if result.fits() { "Yes" } else { "No" });
 }
 
 fn handle_encode(
-    input: std::path::PathBuf,
-    file: std::path::PathBuf,
-    output: Option<std::path::PathBuf>,
-    password: Option<String>,
+    options: EncodeOptions,
+    audit: &mut AuditRecord,
 ) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let options = deepscene::processor::EncodeOptions {
-        file_path: file,
-        image_path: input,
-        output_path: output,
-        password,
-    };
+    audit.input = Some(options.image_path.clone());
+    audit.file = Some(options.file_path.clone());
+    audit.output = options.output_path.clone();
+    audit.encrypted = Some(options.password.is_some() || !options.recipient_passwords.is_empty());
 
     let result = DataProcessor::encode(options)?;
+    audit.output = Some(result.output_path.clone());
+    audit.file_size = Some(result.original_size);
+    audit.payload_size = Some(result.final_size);
+    audit.compressed = Some(result.compressed);
     print_encode_result(&result);
 
     Ok(())
 }
 
+fn handle_estimate(
+    options: &EncodeOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.command = "estimate";
+    audit.input = Some(options.image_path.clone());
+    audit.file = Some(options.file_path.clone());
+    audit.encrypted = Some(options.password.is_some() || !options.recipient_passwords.is_empty());
+
+    let result = DataProcessor::estimate(options)?;
+    audit.file_size = Some(result.original_size);
+    audit.payload_size = Some(result.payload_size);
+    audit.compressed = Some(result.compressed);
+    print_estimate_result(&result);
+
+    Ok(())
+}
+
+fn handle_estimate_fit(
+    options: &EncodeOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.command = "estimate";
+    audit.input = Some(options.image_path.clone());
+    audit.file = Some(options.file_path.clone());
+    audit.encrypted = Some(options.password.is_some() || !options.recipient_passwords.is_empty());
+
+    let result = DataProcessor::estimate_fit(options)?;
+    audit.file_size = Some(result.original_size);
+    audit.payload_size = Some(result.expected_payload_size);
+    print_fit_estimate(&result);
+
+    Ok(())
+}
+
+fn handle_batch_encode(
+    options: BatchOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.command = "batch-encode";
+    audit.input = Some(options.input_dir.clone());
+    audit.output = Some(options.output_dir.clone());
+    audit.encrypted = Some(options.password.is_some());
+
+    let report = BatchProcessor::encode(options)?;
+    print_batch_report(&report);
+
+    if report.failed() > 0 {
+        return Err(format!(
+            "{} of {} files failed",
+            report.failed(),
+            report.entries.len()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
 fn handle_decode(
-    input: std::path::PathBuf,
-    output: Option<std::path::PathBuf>,
-    password: Option<String>,
+    options: DecodeOptions,
+    audit: &mut AuditRecord,
 ) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let options = deepscene::processor::DecodeOptions {
-        image_path: input,
-        output_path: output,
-        password,
-    };
+    audit.input = Some(options.image_path.clone());
+    audit.output = options.output_path.clone();
 
-    let result = DataProcessor::decode(options)?;
+    let check = options.check;
+    let result = match DataProcessor::decode(options) {
+        Ok(result) => result,
+        Err(e) if check => {
+            println!("Check: FAIL");
+            return Err(e.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    audit.output = result.checked.is_none().then(|| result.output_path.clone());
+    audit.file_size = Some(result.file_size);
+    audit.encrypted = Some(result.encrypted);
     print_decode_result(&result);
 
     Ok(())
 }
 
-fn main() {
-    let cli = cli::Cli::parse();
+fn handle_decode_all(
+    options: DecodeAllOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+
+    let report = DataProcessor::decode_all(options)?;
+    print_decode_all_report(&report);
+
+    if report.failed() > 0 {
+        return Err(format!(
+            "{} of {} payloads could not be extracted",
+            report.failed(),
+            report.entries.len()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn handle_verify_all(
+    options: VerifyOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.input_dir.clone());
+
+    let report = VerifyProcessor::verify_all(options)?;
+    print_verify_report(&report);
+
+    if report.failed() > 0 {
+        return Err(format!(
+            "{} of {} images failed verification",
+            report.failed(),
+            report.entries.len()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn handle_list(
+    options: ListOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+
+    let result = DataProcessor::list(options)?;
+    if let [entry] = result.entries.as_slice() {
+        audit.payload_size = Some(entry.stored_size);
+        audit.encrypted = Some(entry.encrypted);
+        audit.compressed = Some(entry.compressed);
+    }
+    print_list_result(&result);
+
+    Ok(())
+}
+
+fn handle_capacity_map(
+    options: CapacityMapOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.output = options.output_path.clone();
+
+    let result = DataProcessor::capacity_map(options)?;
+    audit.output = Some(result.output_path.clone());
+    print_capacity_map_result(&result);
+
+    Ok(())
+}
+
+fn handle_diff_map(
+    options: DiffMapOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.output = options.output_path.clone();
+
+    let result = DataProcessor::diff_map(options)?;
+    audit.output = Some(result.output_path.clone());
+    print_diff_map_result(&result);
+
+    Ok(())
+}
+
+fn handle_strip(
+    options: StripOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.output = options.output_path.clone();
 
-    let result = match cli.command {
+    let result = DataProcessor::strip(options)?;
+    audit.output = Some(result.output_path.clone());
+    print_strip_result(&result);
+
+    Ok(())
+}
+
+fn handle_transfer(
+    options: TransferOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.output = Some(options.output_path.clone());
+
+    let result = DataProcessor::transfer(options)?;
+    audit.payload_size = Some(result.payload_size);
+    print_transfer_result(&result);
+
+    Ok(())
+}
+
+/// Returns whether the stored fingerprint matches the `--compare` file, or
+/// `true` without one.
+fn handle_fingerprint(
+    options: &FingerprintOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.file = options.compare.clone();
+
+    let result = DataProcessor::fingerprint(options)?;
+    print_fingerprint_result(&result, options.compare.as_ref());
+
+    Ok(result.matches != Some(false))
+}
+
+fn handle_capacity_table(
+    image_paths: &[PathBuf],
+    json: bool,
+    audit: &mut AuditRecord,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    if let [image_path] = image_paths {
+        audit.input = Some(image_path.clone());
+    }
+
+    let table = DataProcessor::capacity_table(image_paths)?;
+    if json {
+        println!("{}", table.to_json());
+    } else {
+        print_capacity_table(&table);
+    }
+
+    Ok(())
+}
+
+/// Reports through the exit status only, so scripts can branch on it
+/// without parsing output; details are printed with --verbose. Returns
+/// whether the payload fits.
+fn handle_check(
+    options: &CheckOptions,
+    audit: &mut AuditRecord,
+) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+    audit.input = Some(options.image_path.clone());
+    audit.payload_size = Some(options.needs);
+
+    let result = DataProcessor::check(options)?;
+    if progress::is_verbose() {
+        print_check_result(&result);
+    }
+
+    Ok(result.fits())
+}
+
+fn compat_spec(compat: cli::Compat) -> CompatSpec {
+    match compat {
+        cli::Compat::PlainLsb => CompatSpec::PlainLsb,
+    }
+}
+
+fn kdf_algorithm(kdf: cli::Kdf) -> KdfAlgorithm {
+    match kdf {
+        cli::Kdf::Argon2 => KdfAlgorithm::Argon2,
+        cli::Kdf::Scrypt => KdfAlgorithm::Scrypt,
+    }
+}
+
+/// Seeds every random choice of an encode from `--seed-file`, when given.
+fn random_source(
+    seed_file: Option<PathBuf>,
+) -> std::result::Result<RandomSource, Box<dyn std::error::Error>> {
+    match seed_file {
+        Some(path) => Ok(RandomSource::seeded(&FileHandler::read_file(&path)?.data)),
+        None => Ok(RandomSource::default()),
+    }
+}
+
+fn read_password(
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    raw: bool,
+) -> std::result::Result<Option<String>, Box<dyn std::error::Error>> {
+    if let Some(path) = password_file {
+        Ok(Some(password::read_password_file(&path, raw)?))
+    } else if password_stdin {
+        Ok(Some(password::read_password_stdin(raw)?))
+    } else {
+        Ok(password)
+    }
+}
+
+/// Runs the command, filling in `audit` as it goes. Returns `false` when the
+/// command completed but should exit with a failure status without an error
+/// message, as `check` does when the payload does not fit and `fingerprint`
+/// does when the compared file differs.
+fn run(
+    cli: cli::Cli,
+    audit: &mut AuditRecord,
+) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+    match cli.command {
         cli::Commands::Encode {
             input,
             file,
+            from_clipboard,
             output,
+            output_suffix,
+            in_place,
             password,
-        } => handle_encode(input, file, output, password),
+            password_file,
+            password_stdin,
+            generate_mnemonic,
+            raw_password,
+            recipient_password,
+            kdf,
+            min_password_length,
+            encrypt_metadata,
+            bit_plane,
+            batch,
+            carrier_dir,
+            archive,
+            pairing,
+            resume,
+            output_template,
+            seed_file,
+            salt_file,
+            lsb_matching,
+            carrier_noise,
+            stride,
+            channel_copies,
+            preserve_region,
+            tiff_strip,
+            perceptual,
+            match_size,
+            confirm_convert,
+            max_output_size,
+            size_bucket,
+            canonical_output,
+            preserve_exif,
+            preserve_dpi,
+            no_name,
+            compat,
+            overwrite_policy,
+            estimate,
+            estimate_fit,
+            meta: metadata,
+            expires,
+            hint,
+            sign_key,
+            format_version,
+            dimensions,
+        } => {
+            let mnemonic = generate_mnemonic
+                .then(password::generate_mnemonic)
+                .transpose()?;
+            let password = match mnemonic {
+                Some(ref mnemonic) => Some(mnemonic.clone()),
+                None => read_password(password, password_file, password_stdin, raw_password)?,
+            };
+
+            // Keeps the unpacked archive alive until encoding is done.
+            let job = archive.as_deref().map(ArchiveJob::unpack).transpose()?;
+
+            let (input, file, output) = match (&job, archive) {
+                (Some(job), Some(archive)) => (
+                    Some(job.carrier_path.clone()),
+                    Some(job.payload_path.clone()),
+                    output.or_else(|| Some(job.default_output(&archive, &output_suffix))),
+                ),
+                _ if from_clipboard => (
+                    input,
+                    Some(PathBuf::from(clipboard::CLIPBOARD_FILE_NAME)),
+                    output,
+                ),
+                _ => (input, file, output),
+            };
+
+            let result = match (batch, carrier_dir, output, input, file) {
+                (Some(input_dir), Some(carrier_dir), Some(output_dir), _, _) => {
+                    handle_batch_encode(
+                        BatchOptions {
+                            input_dir,
+                            carrier_dir,
+                            output_dir,
+                            password,
+                            kdf: kdf_algorithm(kdf),
+                            min_password_length,
+                            encrypt_metadata,
+                            bit_plane,
+                            salt_file,
+                            lsb_matching,
+                            carrier_noise,
+                            match_size,
+                            confirm_convert,
+                            max_output_size,
+                            size_bucket,
+                            preserve_exif,
+                            preserve_dpi,
+                            metadata,
+                            expires,
+                            hint,
+                            threads: cli.threads,
+                            pairing: match pairing {
+                                cli::Pairing::RoundRobin => PairingStrategy::RoundRobin,
+                                cli::Pairing::Fit => PairingStrategy::BestFit,
+                            },
+                            output_template,
+                            resume,
+                        },
+                        audit,
+                    )
+                }
+                (_, _, output, Some(input), Some(file)) => {
+                    if output.as_deref().is_some_and(FileHandler::is_stdout) {
+                        progress::set_stderr(true);
+                    }
+
+                    let options = EncodeOptions {
+                        file_path: file,
+                        from_clipboard,
+                        image_path: input,
+                        output_path: output,
+                        password,
+                        recipient_passwords: recipient_password,
+                        kdf: kdf_algorithm(kdf),
+                        min_password_length,
+                        encrypt_metadata,
+                        bit_plane,
+                        random: random_source(seed_file)?,
+                        salt_file,
+                        lsb_matching,
+                        carrier_noise,
+                        stride,
+                        channel_copies,
+                        perceptual,
+                        preserve_region,
+                        match_size,
+                        confirm_convert,
+                        max_output_size,
+                        size_bucket,
+                        canonical_output,
+                        preserve_exif,
+                        preserve_dpi,
+                        no_name,
+                        metadata,
+                        threads: cli.threads,
+                        raw_dimensions: dimensions,
+                        compat: compat.map(compat_spec),
+                        overwrite_policy: match overwrite_policy {
+                            cli::Overwrite::Overwrite => OverwritePolicy::Overwrite,
+                            cli::Overwrite::Never => OverwritePolicy::Never,
+                            cli::Overwrite::Rename => OverwritePolicy::Rename,
+                        },
+                        output_suffix,
+                        in_place,
+                        expires,
+                        hint,
+                        format_version,
+                        tiff_strip,
+                        sign_key,
+                    };
+
+                    if estimate {
+                        handle_estimate(&options, audit)
+                    } else if estimate_fit {
+                        handle_estimate_fit(&options, audit)
+                    } else {
+                        handle_encode(options, audit)
+                    }
+                }
+                _ => Err("Missing carrier image or file to embed".into()),
+            };
+
+            if result.is_ok()
+                && let Some(mnemonic) = mnemonic
+            {
+                print_mnemonic(&mnemonic);
+            }
+            result
+        }
         cli::Commands::Decode {
             input,
             output,
             password,
-        } => handle_decode(input, output, password),
+            password_file,
+            password_stdin,
+            password_list,
+            raw_password,
+            dimensions,
+            compat,
+            enforce_expiry,
+            consume,
+            recover,
+            check,
+            all,
+            to_clipboard,
+            verify_after_write,
+            verify_key,
+        } => {
+            if password_stdin && FileHandler::is_stdin(&input) {
+                return Err(
+                    "The image and the password cannot both be read from standard input. Use --password-file instead".into(),
+                );
+            }
+
+            if all {
+                let mut passwords = password;
+                passwords.extend(read_password(
+                    None,
+                    password_file,
+                    password_stdin,
+                    raw_password,
+                )?);
+                return handle_decode_all(
+                    DecodeAllOptions {
+                        image_path: input,
+                        passwords,
+                        threads: cli.threads,
+                        raw_dimensions: dimensions,
+                        enforce_expiry,
+                    },
+                    audit,
+                )
+                .map(|()| true);
+            }
+            if password.len() > 1 {
+                return Err("Several passwords can only be given with --all".into());
+            }
+
+            handle_decode(
+                DecodeOptions {
+                    image_path: input,
+                    output_path: output,
+                    password: read_password(
+                        password.into_iter().next(),
+                        password_file,
+                        password_stdin,
+                        raw_password,
+                    )?,
+                    password_list: match password_list {
+                        Some(path) => password::read_password_list(&path)?,
+                        None => Vec::new(),
+                    },
+                    threads: cli.threads,
+                    raw_dimensions: dimensions,
+                    compat: compat.map(compat_spec),
+                    enforce_expiry,
+                    consume,
+                    recover,
+                    check,
+                    to_clipboard,
+                    verify_after_write,
+                    verify_key,
+                },
+                audit,
+            )
+        }
+        cli::Commands::List { input, dimensions } => handle_list(
+            ListOptions {
+                image_path: input,
+                threads: cli.threads,
+                raw_dimensions: dimensions,
+            },
+            audit,
+        ),
+        cli::Commands::VerifyAll {
+            input_dir,
+            password,
+            password_file,
+            password_stdin,
+            raw_password,
+            recursive,
+        } => handle_verify_all(
+            VerifyOptions {
+                input_dir,
+                password: read_password(password, password_file, password_stdin, raw_password)?,
+                recursive,
+                threads: cli.threads,
+            },
+            audit,
+        ),
+        cli::Commands::Fingerprint {
+            input,
+            compare,
+            password,
+            password_file,
+            password_stdin,
+            raw_password,
+            dimensions,
+        } => {
+            if password_stdin && FileHandler::is_stdin(&input) {
+                return Err(
+                    "The image and the password cannot both be read from standard input. Use --password-file instead".into(),
+                );
+            }
+
+            return handle_fingerprint(
+                &FingerprintOptions {
+                    image_path: input,
+                    password: read_password(password, password_file, password_stdin, raw_password)?,
+                    threads: cli.threads,
+                    raw_dimensions: dimensions,
+                    compare,
+                },
+                audit,
+            );
+        }
+        cli::Commands::Transfer {
+            input,
+            carrier,
+            output,
+            lsb_matching,
+            carrier_noise,
+        } => handle_transfer(
+            TransferOptions {
+                image_path: input,
+                carrier_path: carrier,
+                output_path: output,
+                lsb_matching,
+                carrier_noise,
+                threads: cli.threads,
+            },
+            audit,
+        ),
+        cli::Commands::CapacityMap {
+            input,
+            output,
+            dimensions,
+        } => handle_capacity_map(
+            CapacityMapOptions {
+                image_path: input,
+                output_path: output,
+                threads: cli.threads,
+                raw_dimensions: dimensions,
+            },
+            audit,
+        ),
+        cli::Commands::DiffMap {
+            input,
+            output,
+            clean,
+            bit_plane,
+            dimensions,
+        } => handle_diff_map(
+            DiffMapOptions {
+                image_path: input,
+                output_path: output,
+                clean_path: clean,
+                bit_plane,
+                threads: cli.threads,
+                raw_dimensions: dimensions,
+            },
+            audit,
+        ),
+        cli::Commands::Strip {
+            input,
+            output,
+            planes,
+            randomize,
+        } => handle_strip(
+            StripOptions {
+                image_path: input,
+                output_path: output,
+                planes,
+                randomize,
+            },
+            audit,
+        ),
+        cli::Commands::CapacityTable { inputs, json } => {
+            handle_capacity_table(&inputs, json, audit)
+        }
+        cli::Commands::ListAlgorithms { json } => {
+            let algorithms = DataProcessor::algorithms();
+            if json {
+                println!("{}", algorithms.to_json());
+            } else {
+                print_algorithms(&algorithms);
+            }
+            Ok(())
+        }
+        cli::Commands::Check {
+            input,
+            needs,
+            dimensions,
+        } => {
+            return handle_check(
+                &CheckOptions {
+                    image_path: input,
+                    raw_dimensions: dimensions,
+                    needs,
+                },
+                audit,
+            );
+        }
+    }?;
+
+    Ok(true)
+}
+
+fn main() {
+    let cli = cli::Cli::parse();
+    display::set_raw_bytes(cli.bytes);
+    progress::set_quiet(cli.quiet || cli.progress_json);
+    if cli.progress_json {
+        deepscene::core::progress::set_reporter(Box::new(progress::JsonProgress));
+    }
+    progress::set_verbose(cli.verbose);
+    progress::set_strict(cli.strict);
+    file_handler::set_offline(cli.offline);
+    file_handler::set_follow_symlinks(cli.follow_symlinks);
+
+    // Opened up front so an unwritable log stops the operation before it runs.
+    let mut audit_log = match cli.audit_log.as_deref().map(AuditLog::open).transpose() {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     };
 
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    let mut audit = AuditRecord {
+        command: cli.command.name(),
+        ..AuditRecord::default()
+    };
+    let result = run(cli, &mut audit);
+
+    if let Some(log) = &mut audit_log {
+        let failure = match &result {
+            Ok(true) => None,
+            Ok(false) if audit.command == "fingerprint" => Some(AuditFailure::mismatch()),
+            Ok(false) => Some(AuditFailure::from_kind(ErrorKind::CapacityExceeded)),
+            Err(e) => Some(AuditFailure::from_error(e.as_ref())),
+        };
+        if let Err(e) = log.append(&audit, failure.as_ref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
 }
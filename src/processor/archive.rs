@@ -0,0 +1,212 @@
+use crate::core::{DeepSceneError, Result, SteganographyEngine};
+use crate::io::file_handler::MAX_FILE_SIZE;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use zip::ZipArchive;
+
+/// Name of the manifest entry at the root of a job archive.
+pub const MANIFEST_NAME: &str = "manifest.txt";
+
+/// A carrier and payload unpacked from a job archive into a private temp
+/// directory, which is removed again when the job is dropped.
+///
+/// The archive must contain a `manifest.txt` naming both entries:
+///
+/// ```text
+/// carrier = photo.png
+/// payload = report.pdf
+/// ```
+pub struct ArchiveJob {
+    temp_dir: PathBuf,
+    pub carrier_path: PathBuf,
+    pub payload_path: PathBuf,
+}
+
+impl ArchiveJob {
+    pub fn unpack(archive_path: &Path) -> Result<Self> {
+        let file = File::open(archive_path).map_err(|e| {
+            DeepSceneError::Validation(format!(
+                "Cannot open archive '{}': {}",
+                archive_path.display(),
+                e
+            ))
+        })?;
+        let mut archive =
+            ZipArchive::new(file).map_err(|e| Self::archive_error(archive_path, e))?;
+
+        let manifest = Self::read_entry(&mut archive, archive_path, MANIFEST_NAME)?;
+        let manifest = String::from_utf8(manifest).map_err(|_| {
+            DeepSceneError::Validation(format!("{} is not valid UTF-8", MANIFEST_NAME))
+        })?;
+        let (carrier_name, payload_name) = Self::parse_manifest(&manifest)?;
+
+        if !SteganographyEngine::is_lossless_format(Path::new(&carrier_name)) {
+            return Err(DeepSceneError::Validation(format!(
+                "Carrier '{}' in archive must be lossless (PNG, BMP, TIFF or PNM)",
+                carrier_name
+            )));
+        }
+
+        let temp_dir = env::temp_dir().join(format!("deepscene-job-{}", process::id()));
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir)?;
+        }
+        fs::create_dir_all(&temp_dir)?;
+
+        // Constructed before extracting so a failure below still cleans up.
+        let mut job = ArchiveJob {
+            temp_dir,
+            carrier_path: PathBuf::new(),
+            payload_path: PathBuf::new(),
+        };
+        job.carrier_path = job.extract(&mut archive, archive_path, &carrier_name)?;
+        job.payload_path = job.extract(&mut archive, archive_path, &payload_name)?;
+
+        if job.carrier_path.file_name() == job.payload_path.file_name() {
+            return Err(DeepSceneError::Validation(
+                "Carrier and payload in archive must have different file names".to_string(),
+            ));
+        }
+
+        Ok(job)
+    }
+
+    /// Default output for an archive job: `<carrier><suffix>.png` next to the
+    /// archive rather than in the temp directory.
+    pub fn default_output(&self, archive_path: &Path, suffix: &str) -> PathBuf {
+        let stem = self
+            .carrier_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        archive_path.with_file_name(format!("{}{}.png", stem, suffix))
+    }
+
+    fn parse_manifest(manifest: &str) -> Result<(String, String)> {
+        let mut carrier = None;
+        let mut payload = None;
+
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                DeepSceneError::Validation(format!(
+                    "{} line {}: expected 'key = value'",
+                    MANIFEST_NAME,
+                    index + 1
+                ))
+            })?;
+
+            let slot = match key.trim() {
+                "carrier" => &mut carrier,
+                "payload" => &mut payload,
+                other => {
+                    return Err(DeepSceneError::Validation(format!(
+                        "{} line {}: unknown key '{}'. Expected 'carrier' or 'payload'",
+                        MANIFEST_NAME,
+                        index + 1,
+                        other
+                    )));
+                }
+            };
+
+            if slot.replace(value.trim().to_string()).is_some() {
+                return Err(DeepSceneError::Validation(format!(
+                    "{} line {}: duplicate key '{}'",
+                    MANIFEST_NAME,
+                    index + 1,
+                    key.trim()
+                )));
+            }
+        }
+
+        match (carrier, payload) {
+            (Some(carrier), Some(payload)) => Ok((carrier, payload)),
+            _ => Err(DeepSceneError::Validation(format!(
+                "{} must name both a 'carrier' and a 'payload' entry",
+                MANIFEST_NAME
+            ))),
+        }
+    }
+
+    /// Extracts one entry into the temp directory under its base name only,
+    /// so entry paths cannot escape the directory.
+    fn extract(
+        &self,
+        archive: &mut ZipArchive<File>,
+        archive_path: &Path,
+        name: &str,
+    ) -> Result<PathBuf> {
+        let file_name = Path::new(name)
+            .file_name()
+            .ok_or_else(|| {
+                DeepSceneError::Validation(format!("Invalid entry name '{}' in manifest", name))
+            })?
+            .to_owned();
+
+        let data = Self::read_entry(archive, archive_path, name)?;
+        let path = self.temp_dir.join(file_name);
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    fn read_entry(
+        archive: &mut ZipArchive<File>,
+        archive_path: &Path,
+        name: &str,
+    ) -> Result<Vec<u8>> {
+        let entry = archive.by_name(name).map_err(|e| match e {
+            zip::result::ZipError::FileNotFound => DeepSceneError::Validation(format!(
+                "Archive '{}' has no entry '{}'",
+                archive_path.display(),
+                name
+            )),
+            e => Self::archive_error(archive_path, e),
+        })?;
+
+        if entry.size() > MAX_FILE_SIZE as u64 {
+            return Err(DeepSceneError::Validation(format!(
+                "Entry '{}' is too large. Maximum file size is {} MB",
+                name,
+                MAX_FILE_SIZE / (1024 * 1024)
+            )));
+        }
+
+        // The declared size is untrusted, so cap the read as well.
+        let mut data = Vec::new();
+        entry
+            .take(MAX_FILE_SIZE as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|e: io::Error| Self::archive_error(archive_path, e.into()))?;
+
+        if data.len() > MAX_FILE_SIZE {
+            return Err(DeepSceneError::Validation(format!(
+                "Entry '{}' is too large. Maximum file size is {} MB",
+                name,
+                MAX_FILE_SIZE / (1024 * 1024)
+            )));
+        }
+
+        Ok(data)
+    }
+
+    fn archive_error(archive_path: &Path, e: zip::result::ZipError) -> DeepSceneError {
+        DeepSceneError::Validation(format!(
+            "Invalid archive '{}': {}",
+            archive_path.display(),
+            e
+        ))
+    }
+}
+
+impl Drop for ArchiveJob {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.temp_dir);
+    }
+}
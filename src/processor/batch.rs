@@ -0,0 +1,370 @@
+use crate::core::{
+    DeepSceneError, EmbedParams, KdfAlgorithm, RandomSource, Result, SteganographyEngine,
+};
+use crate::io::{FileHandler, OverwritePolicy};
+use crate::processor::output_template::{OutputTemplate, TemplateValues};
+use crate::processor::{DataProcessor, EncodeOptions, EncodeResult};
+use crate::progress;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Progress manifest kept in the output directory while a batch runs. Each
+/// line records a finished entry as `<index>\t<BLAKE3 of the file>\t<output>`.
+pub const MANIFEST_NAME: &str = ".deepscene-batch";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PairingStrategy {
+    #[default]
+    RoundRobin,
+    BestFit,
+}
+
+#[derive(Debug)]
+pub struct BatchOptions {
+    pub input_dir: PathBuf,
+    pub carrier_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub password: Option<String>,
+    pub kdf: KdfAlgorithm,
+    pub min_password_length: usize,
+    pub encrypt_metadata: bool,
+    pub bit_plane: u8,
+    pub salt_file: Option<PathBuf>,
+    pub lsb_matching: bool,
+    pub carrier_noise: bool,
+    pub match_size: bool,
+    pub confirm_convert: bool,
+    pub max_output_size: Option<u64>,
+    pub size_bucket: Option<u64>,
+    pub preserve_exif: bool,
+    pub preserve_dpi: bool,
+    pub metadata: Vec<(String, String)>,
+    pub expires: Option<u64>,
+    pub hint: Option<String>,
+    pub threads: Option<usize>,
+    pub pairing: PairingStrategy,
+    /// Output file name pattern, see `OutputTemplate`.
+    pub output_template: String,
+    /// Skip entries a previous, interrupted run already completed.
+    pub resume: bool,
+}
+
+#[derive(Debug)]
+pub struct BatchEntry {
+    pub file_path: PathBuf,
+    pub carrier_path: PathBuf,
+    pub output_path: PathBuf,
+    /// `Ok(None)` when the entry was skipped because a previous run finished it.
+    pub result: Result<Option<EncodeResult>>,
+}
+
+#[derive(Debug)]
+pub struct BatchReport {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    pub fn succeeded(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries.len() - self.succeeded()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.result, Ok(None)))
+            .count()
+    }
+}
+
+pub struct BatchProcessor;
+
+impl BatchProcessor {
+    pub fn encode(options: BatchOptions) -> Result<BatchReport> {
+        if !options.output_dir.is_dir() {
+            return Err(DeepSceneError::Validation(format!(
+                "Output directory '{}' does not exist",
+                options.output_dir.display()
+            )));
+        }
+
+        DataProcessor::check_password_length(
+            options.password.as_deref(),
+            options.min_password_length,
+        )?;
+        let template = OutputTemplate::parse(&options.output_template)?;
+
+        let files = FileHandler::list_files(&options.input_dir, true)?;
+        if files.is_empty() {
+            return Err(DeepSceneError::Validation(format!(
+                "No files found in '{}'",
+                options.input_dir.display()
+            )));
+        }
+
+        let carriers: Vec<PathBuf> = FileHandler::list_files(&options.carrier_dir, false)?
+            .into_iter()
+            .filter(|p| image::ImageFormat::from_path(p).is_ok())
+            .collect();
+        if carriers.is_empty() {
+            return Err(DeepSceneError::Validation(format!(
+                "No carrier images found in '{}'",
+                options.carrier_dir.display()
+            )));
+        }
+
+        let pairs = match options.pairing {
+            PairingStrategy::RoundRobin => Self::pair_round_robin(&files, &carriers),
+            PairingStrategy::BestFit => Self::pair_best_fit(&files, &carriers),
+        };
+        let output_names = Self::output_names(&template, &options.output_template, &pairs)?;
+
+        // Fail before any work on a bad salt file; each encode warns about
+        // the reuse itself.
+        if let Some(ref salt_path) = options.salt_file {
+            DataProcessor::read_salt_file(salt_path)?;
+        }
+
+        let manifest_path = options.output_dir.join(MANIFEST_NAME);
+        let completed = if options.resume {
+            Self::read_manifest(&manifest_path)?
+        } else {
+            HashSet::new()
+        };
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(options.resume)
+            .truncate(!options.resume)
+            .open(&manifest_path)?;
+
+        let mut entries = Vec::with_capacity(pairs.len());
+        let mut used_salt_nonces = HashMap::new();
+
+        for (index, ((file_path, carrier_path), output_name)) in
+            pairs.into_iter().zip(output_names).enumerate()
+        {
+            progress!(
+                "=== [{}/{}] '{}' -> '{}' ===\n",
+                index + 1,
+                files.len(),
+                file_path.display(),
+                carrier_path.display()
+            );
+
+            let output_path = options.output_dir.join(&output_name);
+
+            let file_hash = FileHandler::read_file(&file_path)
+                .map(|file| blake3::hash(&file.data).to_hex().to_string());
+
+            if let Ok(ref hash) = file_hash
+                && output_path.is_file()
+                && completed.contains(&(index, hash.clone(), output_name.clone()))
+            {
+                progress!("      > Already completed in a previous run, skipping\n");
+                entries.push(BatchEntry {
+                    file_path,
+                    carrier_path,
+                    output_path,
+                    result: Ok(None),
+                });
+                continue;
+            }
+
+            let result = DataProcessor::encode(EncodeOptions {
+                file_path: file_path.clone(),
+                from_clipboard: false,
+                image_path: carrier_path.clone(),
+                output_path: Some(output_path.clone()),
+                password: options.password.clone(),
+                recipient_passwords: Vec::new(),
+                kdf: options.kdf,
+                min_password_length: options.min_password_length,
+                encrypt_metadata: options.encrypt_metadata,
+                bit_plane: options.bit_plane,
+                random: RandomSource::default(),
+                salt_file: options.salt_file.clone(),
+                lsb_matching: options.lsb_matching,
+                carrier_noise: options.carrier_noise,
+                stride: 1,
+                channel_copies: false,
+                perceptual: false,
+                preserve_region: None,
+                match_size: options.match_size,
+                confirm_convert: options.confirm_convert,
+                max_output_size: options.max_output_size,
+                size_bucket: options.size_bucket,
+                canonical_output: false,
+                preserve_exif: options.preserve_exif,
+                preserve_dpi: options.preserve_dpi,
+                no_name: false,
+                metadata: options.metadata.clone(),
+                threads: options.threads,
+                raw_dimensions: None,
+                compat: None,
+                overwrite_policy: OverwritePolicy::Overwrite,
+                output_suffix: String::new(),
+                in_place: false,
+                expires: options.expires,
+                hint: options.hint.clone(),
+                format_version: None,
+                tiff_strip: None,
+                sign_key: None,
+            });
+
+            match result {
+                Ok(EncodeResult {
+                    salt_nonce: Some(salt_nonce),
+                    ..
+                }) => {
+                    // Fresh random salts and nonces never collide in practice;
+                    // a repeat means the RNG is broken and both outputs share a
+                    // ChaCha20 keystream, so stop before producing more.
+                    if let Some(previous) = used_salt_nonces.insert(salt_nonce, file_path.clone()) {
+                        return Err(DeepSceneError::Encryption(format!(
+                            "Salt and nonce reused for '{}' and '{}'. The random number generator is broken; do not distribute either output",
+                            previous.display(),
+                            file_path.display()
+                        ).into()));
+                    }
+                }
+                Err(ref e) => progress!("      > Failed: {}\n", e),
+                _ => {}
+            }
+
+            // Recorded as soon as the output is written, so an interrupted run
+            // keeps everything finished before the interruption.
+            if result.is_ok()
+                && let Ok(hash) = file_hash
+            {
+                writeln!(manifest, "{}\t{}\t{}", index, hash, output_name)?;
+                manifest.sync_data()?;
+            }
+
+            entries.push(BatchEntry {
+                file_path,
+                carrier_path,
+                output_path,
+                result: result.map(Some),
+            });
+        }
+
+        drop(manifest);
+        let report = BatchReport { entries };
+        if report.failed() == 0 {
+            fs::remove_file(&manifest_path)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Entries recorded by a previous run. A missing manifest means nothing
+    /// was completed; malformed lines are ignored.
+    fn read_manifest(path: &Path) -> Result<HashSet<(usize, String, String)>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let index = fields.next()?.parse().ok()?;
+                let hash = fields.next()?.to_string();
+                let output_name = fields.next()?.to_string();
+                Some((index, hash, output_name))
+            })
+            .collect())
+    }
+
+    /// Expands the template for every pair, rejecting templates that would
+    /// give two files the same output and silently overwrite one of them.
+    fn output_names(
+        template: &OutputTemplate,
+        pattern: &str,
+        pairs: &[(PathBuf, PathBuf)],
+    ) -> Result<Vec<String>> {
+        let name_of = |path: &Path| {
+            path.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        let mut seen: HashMap<String, &Path> = HashMap::new();
+        let mut names = Vec::with_capacity(pairs.len());
+
+        for (index, (file_path, carrier_path)) in pairs.iter().enumerate() {
+            let name = template.expand(TemplateValues {
+                stem: carrier_path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("output"),
+                ext: carrier_path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default(),
+                index: index + 1,
+                name: &name_of(file_path),
+            });
+
+            if let Some(previous) = seen.insert(name.clone(), file_path) {
+                return Err(DeepSceneError::Validation(format!(
+                    "Output template '{}' gives '{}' and '{}' the same output name '{}'. Include {{index}} or {{name}} to keep outputs apart",
+                    pattern,
+                    previous.display(),
+                    file_path.display(),
+                    name
+                )));
+            }
+            names.push(name);
+        }
+
+        Ok(names)
+    }
+
+    fn pair_round_robin(files: &[PathBuf], carriers: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+        files
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.clone(), carriers[i % carriers.len()].clone()))
+            .collect()
+    }
+
+    /// Pairs each file with the smallest carrier whose payload capacity can
+    /// hold it uncompressed, falling back to the largest carrier when none is
+    /// big enough.
+    fn pair_best_fit(files: &[PathBuf], carriers: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+        let mut capacities: Vec<(usize, &PathBuf)> = carriers
+            .iter()
+            .map(|c| (Self::carrier_capacity(c), c))
+            .collect();
+        capacities.sort_by_key(|(capacity, _)| *capacity);
+
+        files
+            .iter()
+            .map(|f| {
+                let size = fs::metadata(f).map(|m| m.len() as usize).unwrap_or(0);
+                let carrier = capacities
+                    .iter()
+                    .find(|(capacity, _)| *capacity >= size)
+                    .or(capacities.last())
+                    .map(|(_, c)| (*c).clone())
+                    .unwrap_or_default();
+                (f.clone(), carrier)
+            })
+            .collect()
+    }
+
+    fn carrier_capacity(path: &Path) -> usize {
+        SteganographyEngine::validate_image(path)
+            .map(|(w, h)| SteganographyEngine::payload_capacity(w, h, &EmbedParams::default()))
+            .unwrap_or(0)
+    }
+}
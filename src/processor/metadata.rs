@@ -0,0 +1,550 @@
+use crate::core::{DeepSceneError, Result, SigningEngine};
+use ed25519_dalek::SigningKey;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// First format version that compresses the file before encrypting it and
+/// stores the metadata outside the compressed region.
+pub const COMPRESS_THEN_ENCRYPT_VERSION: u8 = 3;
+
+/// First format version carrying a key/value table after the encryption flag.
+pub const METADATA_TABLE_VERSION: u8 = 4;
+
+/// First format version storing the file extension separately from the name,
+/// which may then be empty.
+pub const EXTENSION_VERSION: u8 = 5;
+
+/// First format version whose encrypted data starts with the identifier of
+/// the key derivation function used.
+pub const KDF_ID_VERSION: u8 = 7;
+
+/// First format version storing an optional expiry time after the metadata
+/// table.
+pub const EXPIRY_VERSION: u8 = 8;
+
+/// First format version whose leading payload byte is a set of flags rather
+/// than a plain compression flag, allowing the metadata to be encrypted.
+pub const PAYLOAD_FLAGS_VERSION: u8 = 10;
+
+/// First format version storing a password hint in the clear, right after the
+/// payload flags and outside any encrypted region.
+pub const HINT_VERSION: u8 = 12;
+
+/// First format version whose payload flags can mark envelope encryption for
+/// several recipients.
+pub const RECIPIENTS_VERSION: u8 = 14;
+
+/// First format version storing a fingerprint of the original file after the
+/// expiry.
+pub const FINGERPRINT_VERSION: u8 = 17;
+
+/// First format version that, when the file data is encrypted but the
+/// metadata is not, stores the fingerprint in front of the encrypted file
+/// data rather than in the clear metadata.
+pub const SEALED_FINGERPRINT_VERSION: u8 = 19;
+
+/// First format version storing an optional signature of the original file
+/// after the fingerprint, sealed along with it when the file is encrypted.
+pub const SIGNATURE_VERSION: u8 = 20;
+
+/// Payload flag: the file data is DEFLATE-compressed.
+pub const COMPRESSED_FLAG: u8 = 0x01;
+/// Payload flag: the metadata is encrypted together with the file data.
+pub const ENCRYPTED_METADATA_FLAG: u8 = 0x02;
+/// Payload flag: the encrypted data is an envelope with one wrapped key per
+/// recipient password.
+pub const RECIPIENTS_FLAG: u8 = 0x04;
+
+/// Base name used for extracted files whose name was not stored.
+const UNNAMED_FILE: &str = "extracted";
+
+/// Base name used for files recovered from corrupted metadata, and the
+/// extension used when none could be read either.
+const RECOVERED_FILE: &str = "recovered";
+const RECOVERED_EXTENSION: &str = "bin";
+
+const MAX_ENTRIES: usize = 255;
+const MAX_KEY_LENGTH: usize = 255;
+const MAX_VALUE_LENGTH: usize = u16::MAX as usize;
+const MAX_HINT_LENGTH: usize = 255;
+
+/// Short BLAKE3 hash of a file's original bytes, stored in the metadata so an
+/// image can be matched against a file without extracting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint(pub [u8; Fingerprint::LEN]);
+
+impl Fingerprint {
+    /// Bytes kept of the 32-byte hash.
+    pub const LEN: usize = 16;
+
+    pub fn of(data: &[u8]) -> Self {
+        Self::truncate(blake3::hash(data))
+    }
+
+    /// Hashes everything `reader` yields without holding it in memory.
+    pub fn of_reader(reader: impl Read) -> Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(reader)?;
+        Ok(Self::truncate(hasher.finalize()))
+    }
+
+    fn truncate(hash: blake3::Hash) -> Self {
+        let mut bytes = [0u8; Self::LEN];
+        bytes.copy_from_slice(&hash.as_bytes()[..Self::LEN]);
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Signature algorithm byte: no signature follows.
+const NO_SIGNATURE: u8 = 0;
+/// Signature algorithm byte: an Ed25519 public key and signature follow.
+const ED25519_SIGNATURE: u8 = 1;
+
+/// Public key of the Ed25519 key that signed a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignerKey(pub [u8; SigningEngine::PUBLIC_KEY_LEN]);
+
+impl fmt::Display for SignerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// Signature of the original file together with the key that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadSignature {
+    pub signer: SignerKey,
+    pub signature: [u8; SigningEngine::SIGNATURE_LEN],
+}
+
+impl PayloadSignature {
+    /// Bytes a signature adds after its algorithm byte.
+    pub const LEN: usize = SigningEngine::PUBLIC_KEY_LEN + SigningEngine::SIGNATURE_LEN;
+
+    pub fn sign(key: &SigningKey, data: &[u8]) -> Self {
+        let (signer, signature) = SigningEngine::sign(key, data);
+        Self {
+            signer: SignerKey(signer),
+            signature,
+        }
+    }
+
+    /// Checks the signature against the extracted file.
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        SigningEngine::verify(&self.signer.0, &self.signature, data)
+    }
+
+    /// The algorithm byte followed by the key and signature, if any.
+    pub fn to_bytes(signature: Option<&Self>) -> Vec<u8> {
+        match signature {
+            Some(signature) => [
+                &[ED25519_SIGNATURE][..],
+                &signature.signer.0,
+                &signature.signature,
+            ]
+            .concat(),
+            None => vec![NO_SIGNATURE],
+        }
+    }
+}
+
+/// Metadata stored in front of the (optionally encrypted) file data, in the
+/// clear unless `--encrypt-metadata` moved it into the encrypted region.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadMetadata {
+    pub file_name: String,
+    /// Extension of the original file without the leading dot, kept even when
+    /// the name itself is omitted.
+    pub extension: String,
+    pub encrypted: bool,
+    pub entries: Vec<(String, String)>,
+    /// Advisory expiry as seconds since the Unix epoch, checked on decode.
+    pub expires: Option<u64>,
+    /// Fingerprint of the original file, from `FINGERPRINT_VERSION` on.
+    pub fingerprint: Option<Fingerprint>,
+    /// Signature of the original file, from `SIGNATURE_VERSION` on.
+    pub signature: Option<PayloadSignature>,
+}
+
+impl PayloadMetadata {
+    /// Parses a `key=value` pair as given on the command line.
+    pub fn parse_entry(entry: &str) -> Result<(String, String)> {
+        let (key, value) = entry.split_once('=').ok_or_else(|| {
+            DeepSceneError::Validation(format!("Invalid metadata '{}'. Expected KEY=VALUE", entry))
+        })?;
+
+        Ok((key.trim().to_string(), value.to_string()))
+    }
+
+    /// File name to write when no output path is given, rebuilt from the
+    /// stored extension if the name was omitted.
+    pub fn output_name(&self) -> String {
+        if !self.file_name.is_empty() {
+            self.file_name.clone()
+        } else if self.extension.is_empty() {
+            UNNAMED_FILE.to_string()
+        } else {
+            format!("{}.{}", UNNAMED_FILE, self.extension)
+        }
+    }
+
+    /// `output_name` reduced to its last component, so a crafted name such
+    /// as `../x` or `/etc/x` cannot leave the directory it is written to.
+    /// Fails if nothing usable is left, as with an empty name, `.` or `..`.
+    pub fn local_output_name(&self) -> Result<PathBuf> {
+        let name = self.output_name();
+        match Path::new(&name).file_name() {
+            Some(file_name) if file_name != "." && file_name != ".." => {
+                Ok(PathBuf::from(file_name))
+            }
+            _ => Err(DeepSceneError::Data(format!(
+                "Stored file name '{}' does not name a file",
+                name.escape_debug()
+            ))),
+        }
+    }
+
+    pub fn validate_entries(entries: &[(String, String)]) -> Result<()> {
+        if entries.len() > MAX_ENTRIES {
+            return Err(DeepSceneError::Validation(format!(
+                "Too many metadata entries ({}). Maximum is {}",
+                entries.len(),
+                MAX_ENTRIES
+            )));
+        }
+
+        for (index, (key, value)) in entries.iter().enumerate() {
+            if key.is_empty() {
+                return Err(DeepSceneError::Validation(
+                    "Metadata key cannot be empty".to_string(),
+                ));
+            }
+
+            if key.len() > MAX_KEY_LENGTH {
+                return Err(DeepSceneError::Validation(format!(
+                    "Metadata key '{}' too long (max {} bytes)",
+                    key, MAX_KEY_LENGTH
+                )));
+            }
+
+            if value.len() > MAX_VALUE_LENGTH {
+                return Err(DeepSceneError::Validation(format!(
+                    "Metadata value for '{}' too long (max {} bytes)",
+                    key, MAX_VALUE_LENGTH
+                )));
+            }
+
+            if entries[..index].iter().any(|(k, _)| k == key) {
+                return Err(DeepSceneError::Validation(format!(
+                    "Duplicate metadata key '{}'",
+                    key
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_hint(hint: &str) -> Result<()> {
+        if hint.is_empty() {
+            return Err(DeepSceneError::Validation(
+                "Password hint cannot be empty".to_string(),
+            ));
+        }
+
+        if hint.len() > MAX_HINT_LENGTH {
+            return Err(DeepSceneError::Validation(format!(
+                "Password hint too long (max {} bytes)",
+                MAX_HINT_LENGTH
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Length-prefixed hint stored after the payload flags; an empty hint is
+    /// a single zero byte.
+    pub fn hint_to_bytes(hint: Option<&str>) -> Vec<u8> {
+        let hint = hint.unwrap_or_default();
+        let mut bytes = vec![hint.len() as u8];
+        bytes.extend_from_slice(hint.as_bytes());
+        bytes
+    }
+
+    /// Splits the clear password hint off the data following the payload
+    /// flags. Versions before `HINT_VERSION` have none.
+    pub fn split_hint(data: &[u8], version: u8) -> Result<(Option<String>, &[u8])> {
+        if version < HINT_VERSION {
+            return Ok((None, data));
+        }
+
+        let mut pos = 0;
+        let hint_len = Self::take(data, &mut pos, 1)?[0] as usize;
+        let hint = Self::take_string(data, &mut pos, hint_len)?;
+
+        Ok((Some(hint).filter(|hint| !hint.is_empty()), &data[pos..]))
+    }
+
+    /// The fingerprint and, from `SIGNATURE_VERSION` on, the signature that
+    /// `SEALED_FINGERPRINT_VERSION` stores in front of encrypted file data.
+    pub fn sealed_to_bytes(
+        fingerprint: Option<Fingerprint>,
+        signature: Option<&PayloadSignature>,
+        version: u8,
+    ) -> Vec<u8> {
+        let mut bytes = fingerprint.map(|f| f.0).unwrap_or_default().to_vec();
+        if version >= SIGNATURE_VERSION {
+            bytes.extend_from_slice(&PayloadSignature::to_bytes(signature));
+        }
+        bytes
+    }
+
+    /// Splits what `sealed_to_bytes` wrote off the decrypted data.
+    pub fn split_sealed(
+        data: &[u8],
+        version: u8,
+    ) -> Result<(Option<Fingerprint>, Option<PayloadSignature>, &[u8])> {
+        if data.len() < Fingerprint::LEN {
+            return Err(DeepSceneError::Data(
+                "Invalid data structure: missing fingerprint".to_string(),
+            ));
+        }
+
+        let (fingerprint, rest) = data.split_at(Fingerprint::LEN);
+        let fingerprint = Fingerprint(fingerprint.try_into().unwrap_or_default());
+        let mut pos = 0;
+        let signature = if version >= SIGNATURE_VERSION {
+            Self::take_signature(rest, &mut pos)?
+        } else {
+            None
+        };
+        Ok((
+            Some(fingerprint).filter(|f| f.0 != [0u8; Fingerprint::LEN]),
+            signature,
+            &rest[pos..],
+        ))
+    }
+
+    pub fn to_bytes(&self, version: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.push(self.file_name.len() as u8);
+        bytes.extend_from_slice(self.file_name.as_bytes());
+
+        if version >= EXTENSION_VERSION {
+            bytes.push(self.extension.len() as u8);
+            bytes.extend_from_slice(self.extension.as_bytes());
+        }
+
+        bytes.push(if self.encrypted { 1 } else { 0 });
+
+        if version >= METADATA_TABLE_VERSION {
+            bytes.push(self.entries.len() as u8);
+            for (key, value) in &self.entries {
+                bytes.push(key.len() as u8);
+                bytes.extend_from_slice(key.as_bytes());
+                bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+            }
+        }
+
+        if version >= EXPIRY_VERSION {
+            bytes.extend_from_slice(&self.expires.unwrap_or(0).to_be_bytes());
+        }
+
+        if version >= FINGERPRINT_VERSION {
+            let fingerprint = self.fingerprint.map(|f| f.0).unwrap_or_default();
+            bytes.extend_from_slice(&fingerprint);
+        }
+
+        if version >= SIGNATURE_VERSION {
+            bytes.extend_from_slice(&PayloadSignature::to_bytes(self.signature.as_ref()));
+        }
+
+        bytes
+    }
+
+    /// Parses the metadata at the start of `data`, returning it together with
+    /// the remaining bytes (the stored file data).
+    pub fn from_bytes(data: &[u8], version: u8) -> Result<(Self, &[u8])> {
+        if data.is_empty() {
+            return Err(DeepSceneError::Data(
+                "Invalid data structure: missing metadata".to_string(),
+            ));
+        }
+
+        Self::parse(data, version, data[0] as usize, false)
+    }
+
+    /// Parses the metadata like `from_bytes`, but for each name length in
+    /// turn, starting with the stored one, and with the name replaced by a
+    /// synthesized one. Lengths whose remaining fields do not parse are
+    /// skipped. Used to recover the file data when the name length or name
+    /// is corrupt; only checks further down, such as decryption, tell which
+    /// candidate is right.
+    pub fn recover(data: &[u8], version: u8) -> impl Iterator<Item = (Self, &[u8])> {
+        let stored_len = data.first().map(|&len| len as usize);
+
+        stored_len
+            .into_iter()
+            .chain((0..=u8::MAX as usize).filter(move |&len| Some(len) != stored_len))
+            .filter_map(move |name_len| Self::parse(data, version, name_len, true).ok())
+    }
+
+    /// Parses the metadata with a name of `name_len` bytes. When `recovering`,
+    /// the name is skipped rather than read, and the encryption flag must be
+    /// exactly 0 or 1 to reject misaligned guesses.
+    fn parse(data: &[u8], version: u8, name_len: usize, recovering: bool) -> Result<(Self, &[u8])> {
+        if name_len == 0 && version < EXTENSION_VERSION {
+            return Err(DeepSceneError::Data(
+                "Invalid file name length (0)".to_string(),
+            ));
+        }
+
+        let mut pos = 1;
+        let mut file_name = if recovering {
+            Self::take(data, &mut pos, name_len)?;
+            String::new()
+        } else {
+            Self::take_string(data, &mut pos, name_len)?
+        };
+
+        if file_name.contains('\0') {
+            return Err(DeepSceneError::Data(
+                "File name contains null bytes".to_string(),
+            ));
+        }
+
+        let extension = if version >= EXTENSION_VERSION {
+            let ext_len = Self::take(data, &mut pos, 1)?[0] as usize;
+            Self::take_string(data, &mut pos, ext_len)?
+        } else {
+            String::new()
+        };
+
+        if extension.contains(['\0', '/', '\\']) {
+            return Err(DeepSceneError::Data(
+                "File extension contains invalid characters".to_string(),
+            ));
+        }
+
+        if pos >= data.len() {
+            return Err(DeepSceneError::Data(
+                "Invalid data structure: missing encryption flag".to_string(),
+            ));
+        }
+
+        if recovering {
+            if data[pos] > 1 {
+                return Err(DeepSceneError::Data("Invalid encryption flag".to_string()));
+            }
+
+            let extension = if extension.is_empty() {
+                RECOVERED_EXTENSION
+            } else {
+                &extension
+            };
+            file_name = format!("{}.{}", RECOVERED_FILE, extension);
+        }
+
+        let encrypted = data[pos] == 1;
+        pos += 1;
+        let mut entries = Vec::new();
+
+        if version >= METADATA_TABLE_VERSION {
+            let count = Self::take(data, &mut pos, 1)?[0] as usize;
+
+            for _ in 0..count {
+                let key_len = Self::take(data, &mut pos, 1)?[0] as usize;
+                let key = Self::take_string(data, &mut pos, key_len)?;
+
+                let value_len_bytes = Self::take(data, &mut pos, 2)?;
+                let value_len = u16::from_be_bytes([value_len_bytes[0], value_len_bytes[1]]);
+                let value = Self::take_string(data, &mut pos, value_len as usize)?;
+
+                entries.push((key, value));
+            }
+        }
+
+        let expires = if version >= EXPIRY_VERSION {
+            let mut expiry = [0u8; 8];
+            expiry.copy_from_slice(Self::take(data, &mut pos, 8)?);
+            Some(u64::from_be_bytes(expiry)).filter(|&expiry| expiry != 0)
+        } else {
+            None
+        };
+
+        let fingerprint = if version >= FINGERPRINT_VERSION {
+            let mut fingerprint = [0u8; Fingerprint::LEN];
+            fingerprint.copy_from_slice(Self::take(data, &mut pos, Fingerprint::LEN)?);
+            Some(Fingerprint(fingerprint)).filter(|f| f.0 != [0u8; Fingerprint::LEN])
+        } else {
+            None
+        };
+
+        let signature = if version >= SIGNATURE_VERSION {
+            Self::take_signature(data, &mut pos)?
+        } else {
+            None
+        };
+
+        Ok((
+            PayloadMetadata {
+                file_name,
+                extension,
+                encrypted,
+                entries,
+                expires,
+                fingerprint,
+                signature,
+            },
+            &data[pos..],
+        ))
+    }
+
+    fn take_signature(data: &[u8], pos: &mut usize) -> Result<Option<PayloadSignature>> {
+        match Self::take(data, pos, 1)?[0] {
+            NO_SIGNATURE => Ok(None),
+            ED25519_SIGNATURE => {
+                let (signer, signature) = Self::take(data, pos, PayloadSignature::LEN)?
+                    .split_at(SigningEngine::PUBLIC_KEY_LEN);
+                Ok(Some(PayloadSignature {
+                    signer: SignerKey(signer.try_into().unwrap_or_default()),
+                    signature: signature
+                        .try_into()
+                        .unwrap_or([0u8; SigningEngine::SIGNATURE_LEN]),
+                }))
+            }
+            algorithm => Err(DeepSceneError::Data(format!(
+                "Unknown signature algorithm {}",
+                algorithm
+            ))),
+        }
+    }
+
+    fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+        let end = *pos + len;
+
+        if end > data.len() {
+            return Err(DeepSceneError::Data(
+                "Invalid data structure: metadata table truncated".to_string(),
+            ));
+        }
+
+        let slice = &data[*pos..end];
+        *pos = end;
+        Ok(slice)
+    }
+
+    fn take_string(data: &[u8], pos: &mut usize, len: usize) -> Result<String> {
+        String::from_utf8(Self::take(data, pos, len)?.to_vec())
+            .map_err(|e| DeepSceneError::Data(format!("Failed to decode metadata: {}", e)))
+    }
+}
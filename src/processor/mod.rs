@@ -1,13 +1,109 @@
-use crate::core::{CompressionEngine, CryptoEngine, Result, SteganographyEngine};
-use crate::io::FileHandler;
-use std::path::PathBuf;
+pub mod archive;
+pub mod batch;
+pub mod metadata;
+pub mod output_template;
+pub mod verify;
+
+use crate::core::capacity_map::FLAT_THRESHOLD;
+use crate::core::messages;
+use crate::core::steganography::{FORMAT_VERSION, PERCEPTUAL_CHANNEL_BITS};
+use crate::core::{
+    CapacityMap, CarrierMetadata, CompatSpec, CompressionEngine, CryptoEngine, DeepSceneError,
+    DiffMap, DngImage, EmbedParams, ExtractedData, KdfAlgorithm, RandomSource, Region, Resolution,
+    Result, SigningEngine, SteganographyEngine, TiffStrip, parallel,
+};
+use crate::io::file_handler::FileData;
+use crate::io::{
+    FileHandler, OverwritePolicy, clipboard, format_bytes, format_duration, json_string, progress,
+    timestamp,
+};
+use crate::{message, progress, warning};
+use image::RgbaImage;
+use metadata::{
+    COMPRESS_THEN_ENCRYPT_VERSION, COMPRESSED_FLAG, ENCRYPTED_METADATA_FLAG, EXPIRY_VERSION,
+    EXTENSION_VERSION, FINGERPRINT_VERSION, Fingerprint, HINT_VERSION, KDF_ID_VERSION,
+    METADATA_TABLE_VERSION, PAYLOAD_FLAGS_VERSION, PayloadMetadata, PayloadSignature,
+    RECIPIENTS_FLAG, RECIPIENTS_VERSION, SEALED_FINGERPRINT_VERSION, SIGNATURE_VERSION, SignerKey,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use verify::VerifyLevel;
 
 #[derive(Debug)]
 pub struct EncodeOptions {
     pub file_path: PathBuf,
+    /// Embed the clipboard text instead of reading `file_path`, which then
+    /// only names the payload in messages.
+    pub from_clipboard: bool,
     pub image_path: PathBuf,
     pub output_path: Option<PathBuf>,
     pub password: Option<String>,
+    /// Encrypt for each of these passwords instead of `password`, so any one
+    /// of them decrypts.
+    pub recipient_passwords: Vec<String>,
+    pub kdf: KdfAlgorithm,
+    /// Reject passwords with fewer characters than this (0 disables the check).
+    pub min_password_length: usize,
+    /// Encrypt the file name and metadata along with the data, so the
+    /// password is needed even to list them.
+    pub encrypt_metadata: bool,
+    pub bit_plane: u8,
+    /// Randomness for LSB matching, `--match-size` padding and encryption.
+    /// Seeded only for reproducible test output.
+    pub random: RandomSource,
+    /// Use the 16-byte salt in this file instead of a random one.
+    pub salt_file: Option<PathBuf>,
+    pub lsb_matching: bool,
+    pub carrier_noise: bool,
+    /// Write one payload group every `stride` 8-pixel groups (1 = contiguous).
+    pub stride: u16,
+    /// Store a full copy of the payload in each of the R, G and B channels.
+    pub channel_copies: bool,
+    /// Write two bits in R and B and one in G instead of one in every channel.
+    pub perceptual: bool,
+    /// Leave these pixels of the carrier untouched.
+    pub preserve_region: Option<Region>,
+    pub match_size: bool,
+    /// Convert a lossy carrier even when the PNG may exceed
+    /// `CONFIRM_CONVERT_SIZE`.
+    pub confirm_convert: bool,
+    /// Fail, and remove the output, if the encoded image is larger than this
+    /// many bytes.
+    pub max_output_size: Option<u64>,
+    /// Pad the PNG output up to the next multiple of this many bytes.
+    pub size_bucket: Option<u64>,
+    /// Write the PNG output with fixed encoder settings, for byte-identical
+    /// output from identical inputs.
+    pub canonical_output: bool,
+    /// Copy the carrier's EXIF and ICC metadata onto PNG output.
+    pub preserve_exif: bool,
+    /// Copy the carrier's pixel density onto PNG, TIFF or BMP output.
+    pub preserve_dpi: bool,
+    pub no_name: bool,
+    pub metadata: Vec<(String, String)>,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+    pub compat: Option<CompatSpec>,
+    pub overwrite_policy: OverwritePolicy,
+    /// Appended to the carrier's stem to name the output when no output path
+    /// is given.
+    pub output_suffix: String,
+    /// Replace the carrier with the output, keeping its name and format.
+    pub in_place: bool,
+    /// Advisory expiry (seconds since the Unix epoch) stored in the metadata.
+    pub expires: Option<u64>,
+    /// Password hint stored in the clear and shown when decode is given no
+    /// password or the wrong one.
+    pub hint: Option<String>,
+    /// Write this older format version instead of the current one, for
+    /// readers that predate it.
+    pub format_version: Option<u8>,
+    /// Confine the header and payload to this strip of a TIFF carrier.
+    pub tiff_strip: Option<u32>,
+    /// Sign the file with the Ed25519 private key in this file.
+    pub sign_key: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -15,6 +111,82 @@ pub struct DecodeOptions {
     pub image_path: PathBuf,
     pub output_path: Option<PathBuf>,
     pub password: Option<String>,
+    /// Candidates tried in turn when `password` is unset, for recovering a
+    /// forgotten password.
+    pub password_list: Vec<String>,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+    pub compat: Option<CompatSpec>,
+    /// Refuse to extract a payload whose expiry has passed instead of warning.
+    pub enforce_expiry: bool,
+    /// Overwrite and delete the image once the extracted file is safely on
+    /// disk, so the payload can only be extracted once.
+    pub consume: bool,
+    /// On corrupted metadata, extract the file data under a synthesized name
+    /// instead of failing.
+    pub recover: bool,
+    /// Run every step of extraction but write nothing, reporting how much of
+    /// the payload could be checked.
+    pub check: bool,
+    /// Place the extracted file on the clipboard as text instead of writing
+    /// it.
+    pub to_clipboard: bool,
+    /// Read the written file back and compare its hash with the stored
+    /// fingerprint, removing it on a mismatch.
+    pub verify_after_write: bool,
+    /// Require the payload to be signed by the Ed25519 public key in this
+    /// file.
+    pub verify_key: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct DecodeAllOptions {
+    pub image_path: PathBuf,
+    /// Tried in turn on every encrypted payload.
+    pub passwords: Vec<String>,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+    pub enforce_expiry: bool,
+}
+
+#[derive(Debug)]
+pub struct RecoveredPayload {
+    pub output_path: PathBuf,
+    pub file_name: String,
+    pub file_size: usize,
+    /// Index into `DecodeAllOptions::passwords` of the password that
+    /// decrypted it; `None` for an unencrypted payload.
+    pub password_index: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct DecodeAllEntry {
+    /// Rows of the TIFF strip holding the payload, when it was confined to
+    /// one and its header could be read.
+    pub tiff_strip: Option<Region>,
+    pub result: Result<RecoveredPayload>,
+}
+
+#[derive(Debug)]
+pub struct DecodeAllReport {
+    pub entries: Vec<DecodeAllEntry>,
+}
+
+impl DecodeAllReport {
+    pub fn succeeded(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries.len() - self.succeeded()
+    }
+}
+
+#[derive(Debug)]
+pub struct ListOptions {
+    pub image_path: PathBuf,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
 }
 
 #[derive(Debug)]
@@ -26,6 +198,89 @@ pub struct EncodeResult {
     pub encrypted: bool,
     pub compressed: bool,
     pub converted_to_png: bool,
+    pub bit_plane: u8,
+    pub stride: u16,
+    pub channel_copies: bool,
+    /// Low bits written per channel (R, G, B) with `--perceptual`.
+    pub channel_bits: Option<[u8; 3]>,
+    pub preserve_region: Option<Region>,
+    /// Older format version written on request; `None` for the current one.
+    pub format_version: Option<u8>,
+    /// Strip of the TIFF carrier the payload was confined to.
+    pub tiff_strip: Option<TiffStrip>,
+    /// Fingerprint of the file stored in the metadata; `None` for compat
+    /// specs and format versions without one.
+    pub fingerprint: Option<Fingerprint>,
+    /// Salt and nonce of the encrypted payload, so callers encoding many files
+    /// can check they never repeat.
+    pub salt_nonce: Option<[u8; CryptoEngine::SALT_NONCE_LEN]>,
+}
+
+#[derive(Debug)]
+pub struct EstimateResult {
+    pub file_name: String,
+    pub original_size: usize,
+    pub compressed_size: usize,
+    pub compressed: bool,
+    pub payload_size: usize,
+    pub capacity: usize,
+}
+
+impl EstimateResult {
+    pub fn fits(&self) -> bool {
+        self.payload_size <= self.capacity
+    }
+}
+
+/// How sure `estimate_fit` is that a file fits its carrier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitVerdict {
+    /// Fits even if compression saves nothing.
+    DefinitelyFits,
+    /// Fits if the whole file compresses as well as the samples did.
+    LikelyFits,
+    /// Does not fit at the sampled compression ratio. Only certain when even
+    /// the best sample's ratio would not fit.
+    WontFit,
+}
+
+impl FitVerdict {
+    pub fn code(self) -> &'static str {
+        match self {
+            FitVerdict::DefinitelyFits => "definitely_fits",
+            FitVerdict::LikelyFits => "likely_fits",
+            FitVerdict::WontFit => "wont_fit",
+        }
+    }
+}
+
+/// Projected embedded payload sizes from compressing samples of the file.
+#[derive(Debug)]
+pub struct FitEstimate {
+    pub file_name: String,
+    pub original_size: usize,
+    /// Bytes of the file that were compressed; all of it for small files,
+    /// which makes the three payload sizes exact.
+    pub sampled_size: usize,
+    /// Payload size at the best compression ratio among the samples.
+    pub best_payload_size: usize,
+    /// Payload size at the ratio of all samples together.
+    pub expected_payload_size: usize,
+    /// Payload size if the file is stored uncompressed.
+    pub raw_payload_size: usize,
+    pub capacity: usize,
+}
+
+impl FitEstimate {
+    pub fn verdict(&self) -> FitVerdict {
+        if self.raw_payload_size <= self.capacity {
+            FitVerdict::DefinitelyFits
+        } else if self.expected_payload_size <= self.capacity {
+            FitVerdict::LikelyFits
+        } else {
+            FitVerdict::WontFit
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,252 +289,2845 @@ pub struct DecodeResult {
     pub file_name: String,
     pub file_size: usize,
     pub encrypted: bool,
+    pub metadata: Vec<(String, String)>,
+    pub expires: Option<u64>,
+    /// The image that was overwritten and deleted with `consume`.
+    pub consumed_carrier: Option<PathBuf>,
+    /// Set when `check` was given, in which case nothing was written and
+    /// `output_path` is where the file would have gone.
+    pub checked: Option<VerifyLevel>,
+    /// The file was placed on the clipboard instead of `output_path`.
+    pub on_clipboard: bool,
+    /// Key that signed the file; its signature has been checked.
+    pub signer: Option<SignerKey>,
+    /// `signer` is the key given as `verify_key`.
+    pub signer_trusted: bool,
 }
 
-pub struct DataProcessor;
+#[derive(Debug)]
+pub struct ListEntry {
+    pub file_name: String,
+    pub stored_size: usize,
+    pub encrypted: bool,
+    pub compressed: bool,
+}
 
-impl DataProcessor {
-    pub fn encode(options: EncodeOptions) -> Result<EncodeResult> {
-        println!("> DeepScene is here \n");
+#[derive(Debug)]
+pub struct ListResult {
+    pub format_version: u8,
+    pub stride: u16,
+    pub channel_copies: bool,
+    /// Low bits per channel (R, G, B), when the payload uses per-channel bit
+    /// depths.
+    pub channel_bits: Option<[u8; 3]>,
+    pub preserved_region: Option<Region>,
+    /// Rows holding the payload, when it was confined to a TIFF strip.
+    pub tiff_strip: Option<Region>,
+    pub hint: Option<String>,
+    /// Names and metadata are encrypted and were not read.
+    pub metadata_encrypted: bool,
+    pub entries: Vec<ListEntry>,
+    pub metadata: Vec<(String, String)>,
+    pub expires: Option<u64>,
+}
 
-        let mut working_image_path = options.image_path.clone();
-        let mut converted_to_png = false;
+#[derive(Debug)]
+pub struct CheckOptions {
+    pub image_path: PathBuf,
+    pub raw_dimensions: Option<(u32, u32)>,
+    /// Payload bytes the carrier must be able to hold.
+    pub needs: usize,
+}
 
-        if !SteganographyEngine::is_lossless_format(&options.image_path) {
-            println!("[1/6] Converting image to lossless format (PNG)...");
-            working_image_path = SteganographyEngine::convert_to_lossless(&options.image_path)?;
-            converted_to_png = true;
-            println!("      > Converted to PNG format");
-        }
+#[derive(Debug)]
+pub struct CheckResult {
+    pub width: u32,
+    pub height: u32,
+    pub capacity: usize,
+    pub needs: usize,
+}
 
-        let step_offset = if converted_to_png { 1 } else { 0 };
+impl CheckResult {
+    pub fn fits(&self) -> bool {
+        self.needs <= self.capacity
+    }
+}
 
-        println!("[{}/{}] Reading file...", 1 + step_offset, 5 + step_offset);
-        let file_data = FileHandler::read_file(&options.file_path)?;
+#[derive(Debug)]
+pub struct FingerprintOptions {
+    pub image_path: PathBuf,
+    /// Needed only when the fingerprint is encrypted: with the metadata, or
+    /// with the file data from `SEALED_FINGERPRINT_VERSION` on.
+    pub password: Option<String>,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+    /// File whose fingerprint is compared with the stored one.
+    pub compare: Option<PathBuf>,
+}
 
-        println!(
-            "      > File read successfully: {} bytes",
-            file_data.data.len()
-        );
-        println!(
-            "[{}/{}] Preparing payload...",
-            2 + step_offset,
-            5 + step_offset
-        );
+#[derive(Debug)]
+pub struct FingerprintResult {
+    pub fingerprint: Fingerprint,
+    /// Whether the file given with `compare` has the same fingerprint.
+    pub matches: Option<bool>,
+}
 
-        let mut payload = Vec::new();
-        let name_len = file_data.name.len() as u8;
+#[derive(Debug)]
+pub struct CapacityMapOptions {
+    pub image_path: PathBuf,
+    pub output_path: Option<PathBuf>,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+}
 
-        payload.push(name_len);
-        payload.extend_from_slice(file_data.name.as_bytes());
+#[derive(Debug)]
+pub struct CapacityMapResult {
+    pub output_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub capacity: usize,
+    /// Share of pixels whose normalised score is below `FLAT_THRESHOLD`.
+    pub flat_ratio: f64,
+}
 
-        let encryption_flag = if options.password.is_some() { 1u8 } else { 0u8 };
-        payload.push(encryption_flag);
+#[derive(Debug)]
+pub struct DiffMapOptions {
+    pub image_path: PathBuf,
+    pub output_path: Option<PathBuf>,
+    /// Also write the image with the plane cleared here.
+    pub clean_path: Option<PathBuf>,
+    pub bit_plane: u8,
+    pub threads: Option<usize>,
+    pub raw_dimensions: Option<(u32, u32)>,
+}
 
-        let file_data_to_store = if let Some(ref pwd) = options.password {
-            CryptoEngine::encrypt(&file_data.data, pwd)?
-        } else {
-            file_data.data.clone()
-        };
+#[derive(Debug)]
+pub struct DiffMapResult {
+    pub output_path: PathBuf,
+    pub clean_path: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub bit_plane: u8,
+    /// Share of R, G and B values with the plane's bit set.
+    pub set_ratio: f64,
+    /// Whether a DeepScene header with a valid checksum was found.
+    pub has_header: bool,
+}
 
-        payload.extend_from_slice(&file_data_to_store);
+#[derive(Debug)]
+pub struct StripOptions {
+    pub image_path: PathBuf,
+    /// Defaults to `<stem>_clean.png` next to the input.
+    pub output_path: Option<PathBuf>,
+    /// Low bit planes to clear, from 1 (plane 0 only) to 8.
+    pub planes: u8,
+    /// Fill the planes with random bits instead of zeros, so the output does
+    /// not show an unnaturally empty plane.
+    pub randomize: bool,
+}
 
-        println!("      > Payload prepared");
-        println!(
-            "[{}/{}] Analyzing and compressing data...",
-            3 + step_offset,
-            5 + step_offset
-        );
+#[derive(Debug)]
+pub struct StripResult {
+    pub output_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub planes: u8,
+    pub randomize: bool,
+    /// Whether the input held a DeepScene header with a valid checksum.
+    pub had_header: bool,
+}
 
-        let original_payload_size = payload.len();
-        let (processed_data, compression_applied) = CompressionEngine::compress(&payload)?;
+#[derive(Debug)]
+pub struct CarrierCapacity {
+    pub width: u32,
+    pub height: u32,
+    /// Format detected from the file contents, such as `PNG` or `JPG`.
+    pub format: String,
+    /// Whether the carrier is used as is; other formats are converted to PNG
+    /// on encode.
+    pub lossless: bool,
+    /// Payload bytes the carrier holds with the default layout.
+    pub capacity: usize,
+}
 
-        let compression_flag = if compression_applied { 1u8 } else { 0u8 };
-        let mut final_payload = vec![compression_flag];
-        final_payload.extend_from_slice(&processed_data);
+#[derive(Debug)]
+pub struct CapacityTableEntry {
+    pub image_path: PathBuf,
+    pub result: Result<CarrierCapacity>,
+}
 
-        let final_size = final_payload.len();
+#[derive(Debug)]
+pub struct AlgorithmInfo {
+    /// `cipher`, `kdf`, `compression` or `signature`.
+    pub category: &'static str,
+    pub name: &'static str,
+    pub parameters: String,
+    /// Used when no option selects another one of the same category.
+    pub default: bool,
+    /// Oldest format version whose decoder understands it.
+    pub min_version: u8,
+}
 
-        if compression_applied {
-            let reduction = ((original_payload_size - processed_data.len()) as f64
-                / original_payload_size as f64)
-                * 100.0;
-            println!(
-                "      > Compression applied: {} bytes -> {} bytes ({:.2}% reduction)",
-                original_payload_size,
-                processed_data.len(),
-                reduction
-            );
-        } else {
-            println!(
-                "      > Compression skipped: would not reduce size ({} bytes)",
-                original_payload_size
-            );
-        }
+#[derive(Debug)]
+pub struct AlgorithmList {
+    pub entries: Vec<AlgorithmInfo>,
+}
 
-        println!(
-            "[{}/{}] Validating output path...",
-            4 + step_offset,
-            5 + step_offset
-        );
-        let output_path = options.output_path.unwrap_or_else(|| {
-            let mut path = options.image_path.clone();
-            let stem = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("output");
-            path.set_file_name(format!("{}_steg.png", stem));
-            path
-        });
+impl AlgorithmList {
+    /// A JSON array with one object per algorithm.
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .entries
+            .iter()
+            .map(|a| {
+                format!(
+                    "{{\"category\":{},\"name\":{},\"parameters\":{},\"default\":{},\"min_version\":{}}}",
+                    json_string(a.category),
+                    json_string(a.name),
+                    json_string(&a.parameters),
+                    a.default,
+                    a.min_version
+                )
+            })
+            .collect();
+        format!("[{}]", rows.join(","))
+    }
+}
 
-        FileHandler::validate_output_path(&output_path)?;
+#[derive(Debug)]
+pub struct CapacityTable {
+    pub entries: Vec<CapacityTableEntry>,
+}
 
-        println!("      > Output path validated");
-        println!(
-            "[{}/{}] Embedding data into image...",
-            5 + step_offset,
-            5 + step_offset
-        );
+impl CapacityTable {
+    pub fn unreadable(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_err()).count()
+    }
 
-        SteganographyEngine::hide_data(&working_image_path, &final_payload, &output_path)?;
+    /// A JSON array with one object per image. Every object has the same
+    /// keys; an unreadable image has `null` values and an `error` message.
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let file = json_string(&entry.image_path.display().to_string());
+                match &entry.result {
+                    Ok(c) => format!(
+                        "{{\"file\":{},\"width\":{},\"height\":{},\"format\":{},\"lossless\":{},\"capacity\":{},\"error\":null}}",
+                        file,
+                        c.width,
+                        c.height,
+                        json_string(&c.format),
+                        c.lossless,
+                        c.capacity
+                    ),
+                    Err(e) => format!(
+                        "{{\"file\":{},\"width\":null,\"height\":null,\"format\":null,\"lossless\":null,\"capacity\":null,\"error\":{}}}",
+                        file,
+                        json_string(&e.to_string())
+                    ),
+                }
+            })
+            .collect();
 
-        println!("      > Data embedded successfully \n");
-        println!("> Encoding complete \n");
+        format!("[{}]", rows.join(","))
+    }
+}
 
-        Ok(EncodeResult {
-            output_path,
-            file_name: file_data.name,
-            original_size: original_payload_size,
-            final_size,
-            encrypted: options.password.is_some(),
-            compressed: compression_applied,
-            converted_to_png,
-        })
+#[derive(Debug)]
+pub struct TransferOptions {
+    /// Image holding the payload to move.
+    pub image_path: PathBuf,
+    /// New carrier the payload is written into.
+    pub carrier_path: PathBuf,
+    pub output_path: PathBuf,
+    pub lsb_matching: bool,
+    pub carrier_noise: bool,
+    pub threads: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct TransferResult {
+    pub output_path: PathBuf,
+    pub format_version: u8,
+    pub bit_plane: u8,
+    /// Stored payload bytes, as extracted and re-embedded.
+    pub payload_size: usize,
+    pub capacity: usize,
+}
+
+/// Default for `--min-password-length`; shorter passwords are rejected on encode.
+pub const DEFAULT_MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Passwords shorter than this trigger a warning on encode when the minimum
+/// has been lowered below it.
+const RECOMMENDED_PASSWORD_LENGTH: usize = 8;
+
+/// Default `--output-suffix`, the naming photo apps use for edited exports.
+pub const DEFAULT_OUTPUT_SUFFIX: &str = "-edited";
+
+/// Payloads above this fraction of the carrier's capacity trigger a warning.
+const NEAR_CAPACITY_RATIO: f64 = 0.9;
+
+/// Lossy carriers whose PNG conversion may exceed this many bytes need
+/// `confirm_convert`.
+const CONFIRM_CONVERT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Shortest time each password list candidate takes, so a list cannot be
+/// run at full Argon2 speed and hog the machine.
+const PASSWORD_ATTEMPT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Slices of the file `estimate_fit` compresses, and the length of each.
+const FIT_SAMPLE_COUNT: usize = 8;
+const FIT_SAMPLE_LEN: usize = 128 * 1024;
+
+/// Removes the file when dropped, so a temporary copy does not outlive an
+/// operation that fails partway.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
     }
+}
 
-    pub fn decode(options: DecodeOptions) -> Result<DecodeResult> {
-        println!("> DeepScene is here \n");
+pub struct DataProcessor;
 
-        println!("[1/4] Extracting data from image...");
+impl DataProcessor {
+    pub fn encode(options: EncodeOptions) -> Result<EncodeResult> {
+        progress!("> DeepScene is here \n");
 
-        let embedded_data = SteganographyEngine::extract_data(&options.image_path)?;
-        println!("      > Extracted {} bytes", embedded_data.len());
+        if options.bit_plane > 2 {
+            warning!(
+                "bit plane {} alters pixel values by up to {} levels and may be visible\n",
+                options.bit_plane,
+                1u16 << options.bit_plane
+            )?;
+        }
 
-        if embedded_data.is_empty() {
-            return Err(crate::core::DeepSceneError::Data(
-                "No data found in image".to_string(),
-            ));
+        if options.random.is_seeded() {
+            warning!(
+                "--seed-file makes encryption deterministic and is insecure. Never use it outside testing\n"
+            )?;
         }
 
-        let compression_flag = embedded_data[0];
-        let payload_data = &embedded_data[1..];
+        if !options.recipient_passwords.is_empty() {
+            if options.password.is_some() {
+                return Err(DeepSceneError::Validation(
+                    "Give either a password or recipient passwords, not both".to_string(),
+                ));
+            }
+            if options.salt_file.is_some() {
+                return Err(DeepSceneError::Validation(
+                    "Recipient passwords each get their own salt and cannot be combined with a salt file"
+                        .to_string(),
+                ));
+            }
+            if options.recipient_passwords.len() > CryptoEngine::MAX_RECIPIENTS {
+                return Err(DeepSceneError::Validation(format!(
+                    "At most {} recipient passwords are supported, but {} were given",
+                    CryptoEngine::MAX_RECIPIENTS,
+                    options.recipient_passwords.len()
+                )));
+            }
+        }
 
-        println!("[2/4] Processing data...");
+        for pwd in options.password.iter().chain(&options.recipient_passwords) {
+            Self::check_password_length(Some(pwd), options.min_password_length)?;
 
-        let decompressed_data = if compression_flag == 1 {
-            let decompressed = CompressionEngine::decompress(payload_data)?;
-            println!(
-                "      > Decompressed: {} bytes -> {} bytes",
-                payload_data.len(),
-                decompressed.len()
-            );
-            decompressed
-        } else {
-            println!("      > No compression detected");
-            payload_data.to_vec()
-        };
+            if pwd.chars().count() < RECOMMENDED_PASSWORD_LENGTH {
+                warning!(
+                    "password is shorter than {} characters and is easy to brute-force\n",
+                    RECOMMENDED_PASSWORD_LENGTH
+                )?;
+            }
+        }
 
-        if decompressed_data.is_empty() {
-            return Err(crate::core::DeepSceneError::Data(
-                "Processed data is empty".to_string(),
-            ));
+        // The salt file is the same on every run, so each encrypt with it
+        // reuses the salt and, for one password, the key.
+        if let Some(ref salt_path) = options.salt_file
+            && options.password.is_some()
+        {
+            Self::read_salt_file(salt_path)?;
+            warning!(
+                "--salt-file reuses its salt on every encrypt, so payloads encrypted with the same password share one key. Only the random nonces keep them apart\n"
+            )?;
         }
 
-        println!("[3/4] Parsing metadata...");
+        PayloadMetadata::validate_entries(&options.metadata)?;
+        Self::note_ignored_threads(options.threads);
 
-        let name_len = decompressed_data[0] as usize;
+        if let Some(expires) = options.expires
+            && expires <= timestamp::now()
+        {
+            warning!(
+                "--expires {} is already in the past; decode will report the payload as expired\n",
+                timestamp::format_timestamp(expires)
+            )?;
+        }
 
-        if name_len == 0 {
-            return Err(crate::core::DeepSceneError::Data(
-                "Invalid file name length (0)".to_string(),
+        if options.encrypt_metadata && !Self::encrypts(&options) {
+            return Err(DeepSceneError::Validation(
+                "--encrypt-metadata requires a password".to_string(),
             ));
         }
 
-        if name_len > 255 {
-            return Err(crate::core::DeepSceneError::Data(format!(
-                "Invalid file name length ({}). Maximum is 255",
-                name_len
-            )));
+        if let Some(ref hint) = options.hint {
+            if !Self::encrypts(&options) {
+                return Err(DeepSceneError::Validation(
+                    "--hint requires a password".to_string(),
+                ));
+            }
+            PayloadMetadata::validate_hint(hint)?;
         }
 
-        if decompressed_data.len() < 1 + name_len + 1 {
-            return Err(crate::core::DeepSceneError::Data(
-                "Invalid data structure: missing encryption flag".to_string(),
-            ));
+        Self::check_version_supports(&options)?;
+
+        if let Some(spec) = options.compat
+            && (Self::encrypts(&options)
+                || !options.metadata.is_empty()
+                || options.sign_key.is_some())
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "The {} compat spec stores only the file bytes and cannot carry a password, metadata or signature",
+                spec.name()
+            )));
         }
 
-        let file_name =
-            String::from_utf8(decompressed_data[1..1 + name_len].to_vec()).map_err(|e| {
-                crate::core::DeepSceneError::Data(format!("Failed to decode file name: {}", e))
-            })?;
+        // Read up front so a bad key fails before the carrier is touched.
+        let signing_key = options
+            .sign_key
+            .as_deref()
+            .map(SigningEngine::load_signing_key)
+            .transpose()?;
 
-        if file_name.is_empty() {
-            return Err(crate::core::DeepSceneError::Data(
-                "File name is empty".to_string(),
-            ));
+        let needs_conversion = options.raw_dimensions.is_none()
+            && !SteganographyEngine::is_lossless_format(&options.image_path);
+
+        if options.in_place && needs_conversion {
+            return Err(DeepSceneError::Validation(format!(
+                "--in-place requires a lossless carrier; '{}' would have to be converted to PNG and could not keep its name",
+                options.image_path.display()
+            )));
         }
 
-        if file_name.contains('\0') {
-            return Err(crate::core::DeepSceneError::Data(
-                "File name contains null bytes".to_string(),
-            ));
+        if needs_conversion && !options.confirm_convert {
+            Self::check_conversion_size(&options.image_path)?;
         }
 
-        let encryption_flag = decompressed_data[1 + name_len];
-        let encrypted_data = &decompressed_data[1 + name_len + 1..];
+        if options.output_suffix.contains(['/', '\\']) {
+            return Err(DeepSceneError::Validation(format!(
+                "Output suffix '{}' cannot contain path separators",
+                options.output_suffix
+            )));
+        }
+
+        // Both targets are resolved before anything is written so that
+        // `OverwritePolicy::Never` fails without leaving files behind.
+        let converted_path = if needs_conversion {
+            let converted_path = options
+                .overwrite_policy
+                .resolve(&options.image_path.with_extension("png"))?;
+            FileHandler::check_not_symlink(&converted_path, "Converted carrier path")?;
+            Some(converted_path)
+        } else {
+            None
+        };
 
-        let file_data = if encryption_flag == 1 {
-            match options.password {
-                Some(ref pwd) => CryptoEngine::decrypt(encrypted_data, pwd)?,
-                None => {
-                    return Err(crate::core::DeepSceneError::Validation(
-                        "File is password-protected. Please provide the decryption password using -p or --password flag".to_string()
-                    ));
+        let output_path = match options.output_path {
+            Some(ref path) if FileHandler::is_stdout(path) => path.clone(),
+            Some(ref path) => options.overwrite_policy.resolve(path)?,
+            // Renaming onto a link would replace the link, so a followed
+            // link is resolved to the file it points to.
+            None if options.in_place => {
+                FileHandler::check_not_symlink(&options.image_path, "Carrier")?;
+                if FileHandler::is_symlink(&options.image_path) {
+                    fs::canonicalize(&options.image_path)?
+                } else {
+                    options.image_path.clone()
                 }
             }
-        } else {
-            if options.password.is_some() {
-                return Err(crate::core::DeepSceneError::Validation(
-                    "Password provided for unencrypted file. This file does not require a password"
-                        .to_string(),
-                ));
+            None => {
+                let default_path = Self::default_output_path(&options);
+                if default_path == options.image_path {
+                    return Err(DeepSceneError::Validation(format!(
+                        "The output would replace the carrier '{}'. Use a non-empty --output-suffix, or --in-place to replace it deliberately",
+                        options.image_path.display()
+                    )));
+                }
+                options.overwrite_policy.resolve(&default_path)?
             }
-            encrypted_data.to_vec()
         };
 
-        if file_data.is_empty() {
-            return Err(crate::core::DeepSceneError::Data(
-                "Extracted file data is empty".to_string(),
-            ));
+        if progress::is_verbose() {
+            Self::print_planned_writes(converted_path.as_deref(), &output_path);
         }
 
-        println!("      > Metadata parsed successfully");
-        println!("[4/4] Writing output file...");
+        let mut working_image_path = options.image_path.clone();
+        let mut converted_to_png = false;
+        // A conversion that fell back to the temp directory is an
+        // implementation detail, removed however the encode ends; one
+        // written next to the input is kept as before.
+        let mut _fallback_carrier = None;
 
-        let output_path = options
-            .output_path
-            .unwrap_or_else(|| PathBuf::from(&file_name));
+        if let Some(ref converted_path) = converted_path {
+            warning!(
+                "'{}' is not a lossless format and will be converted to PNG\n",
+                options.image_path.display()
+            )?;
+            progress!("[1/6] Converting image to lossless format (PNG)...");
+            working_image_path =
+                SteganographyEngine::convert_to_lossless(&options.image_path, converted_path)?;
+            converted_to_png = true;
+            if working_image_path != *converted_path {
+                _fallback_carrier = Some(TempFile(working_image_path.clone()));
+            }
+            progress!(
+                "      > Converted to PNG format ('{}')",
+                working_image_path.display()
+            );
+        }
 
-        FileHandler::write_file(&output_path, &file_data)?;
+        let step_offset = if converted_to_png { 1 } else { 0 };
+
+        let carrier = Self::load_carrier(&working_image_path, options.raw_dimensions)?;
+        if SteganographyEngine::has_header(&carrier) {
+            warning!("the carrier already contains embedded data, which will be overwritten\n")?;
+        }
+        if options.raw_dimensions.is_none()
+            && let Some(depth) = SteganographyEngine::bmp_bit_depth(&options.image_path)
+            && depth < 24
+        {
+            warning!(
+                "'{}' is a BMP with {} bits per pixel; the output is written with 8 bits per channel (32-bit for BMP output), so it will not keep that depth and will be larger\n",
+                options.image_path.display(),
+                depth
+            )?;
+        }
+        let (width, height) = carrier.dimensions();
+        let layout = Self::layout_params(&options)?;
+        let capacity = SteganographyEngine::payload_capacity(width, height, &layout);
+        drop(carrier);
 
-        println!("      > File written: {} bytes \n", file_data.len());
-        println!("> Decoding complete \n");
+        Self::check_minimum_fits(&options, &layout, width, height)?;
 
-        Ok(DecodeResult {
-            output_path,
-            file_name,
-            file_size: file_data.len(),
-            encrypted: encryption_flag == 1,
-        })
+        progress!("[{}/{}] Reading file...", 1 + step_offset, 5 + step_offset);
+        let file_data = Self::read_payload_file(&options)?;
+
+        progress!(
+            "      > File read successfully: {}",
+            format_bytes(file_data.data.len())
+        );
+
+        // Taken from the original bytes, before compression and encryption,
+        // so it can later be compared with a copy of the file.
+        let version = Self::target_version(&options);
+        let fingerprint = (options.compat.is_none() && version >= FINGERPRINT_VERSION)
+            .then(|| Fingerprint::of(&file_data.data));
+        let signature = signing_key
+            .as_ref()
+            .map(|key| PayloadSignature::sign(key, &file_data.data));
+        if let Some(signature) = &signature {
+            progress!("      > Signed by {}", signature.signer);
+        }
+        progress!(
+            "[{}/{}] Analyzing and compressing data...",
+            2 + step_offset,
+            5 + step_offset
+        );
+
+        // Compression runs on the plaintext: ciphertext is indistinguishable from
+        // random data and never shrinks, so compressing after encryption is useless.
+        let original_size = file_data.data.len();
+        let (processed_data, compression_applied) = match options.compat {
+            Some(spec) => {
+                progress!(
+                    "      > Compression skipped: the {} compat spec stores file bytes as-is",
+                    spec.name()
+                );
+                (file_data.data.clone(), false)
+            }
+            None if version < COMPRESS_THEN_ENCRYPT_VERSION => {
+                progress!(
+                    "      > Compression deferred: format version {} compresses the whole payload",
+                    version
+                );
+                (file_data.data.clone(), false)
+            }
+            None => {
+                let (compressed, applied) = parallel::run_with_threads(options.threads, || {
+                    CompressionEngine::compress(&file_data.data)
+                })??;
+
+                if applied {
+                    let reduction =
+                        ((original_size - compressed.len()) as f64 / original_size as f64) * 100.0;
+                    progress!(
+                        "      > Compression applied: {} -> {} ({:.2}% reduction)",
+                        format_bytes(original_size),
+                        format_bytes(compressed.len()),
+                        reduction
+                    );
+                } else {
+                    progress!(
+                        "      > Compression skipped: would not reduce size ({})",
+                        format_bytes(original_size)
+                    );
+                }
+
+                (compressed, applied)
+            }
+        };
+
+        progress!(
+            "[{}/{}] Preparing payload...",
+            3 + step_offset,
+            5 + step_offset
+        );
+
+        let (final_payload, salt_nonce, compression_applied) = match options.compat {
+            Some(_) => (processed_data, None, false),
+            None if version < COMPRESS_THEN_ENCRYPT_VERSION => {
+                Self::build_legacy_payload(&processed_data, &file_data.name, &options)?
+            }
+            None => {
+                let (payload, salt_nonce) = Self::build_payload(
+                    processed_data,
+                    compression_applied,
+                    &file_data.name,
+                    fingerprint,
+                    signature,
+                    &options,
+                )?;
+                (payload, salt_nonce, compression_applied)
+            }
+        };
+
+        let final_size = final_payload.len();
+
+        if final_size <= capacity && final_size as f64 > capacity as f64 * NEAR_CAPACITY_RATIO {
+            warning!(
+                "payload fills {:.0}% of the carrier's capacity; densely embedded images are easier to detect",
+                final_size as f64 / capacity as f64 * 100.0
+            )?;
+        }
+
+        progress!("      > Payload prepared");
+        progress!(
+            "[{}/{}] Validating output path...",
+            4 + step_offset,
+            5 + step_offset
+        );
+        let to_stdout = FileHandler::is_stdout(&output_path);
+        if !to_stdout {
+            FileHandler::validate_output_path(&output_path)?;
+        }
+
+        progress!("      > Output path validated");
+        progress!(
+            "[{}/{}] Embedding data into image...",
+            5 + step_offset,
+            5 + step_offset
+        );
+
+        // Measured on the original carrier, not the intermediate PNG, since
+        // that is the file an observer would compare against.
+        let target_size = if options.match_size {
+            Some(fs::metadata(&options.image_path)?.len())
+        } else {
+            None
+        };
+
+        let carrier_metadata = if options.preserve_exif {
+            Self::carrier_metadata(&options, &output_path)?
+        } else {
+            None
+        };
+
+        let resolution = if options.preserve_dpi {
+            Self::carrier_resolution(&options, &output_path)?
+        } else {
+            None
+        };
+
+        let params = EmbedParams {
+            bit_plane: options.bit_plane,
+            lsb_matching: options.lsb_matching,
+            carrier_noise: options.carrier_noise,
+            stride: options.stride,
+            channel_copies: options.channel_copies,
+            preserve_region: options.preserve_region,
+            raw_dimensions: options.raw_dimensions,
+            target_size,
+            compat: options.compat,
+            carrier_metadata,
+            format_version: options.format_version,
+            tiff_strip: layout.tiff_strip,
+            resolution,
+            channel_bits: layout.channel_bits,
+            canonical_png: options.canonical_output,
+            size_bucket: options.size_bucket,
+            random: options.random,
+        };
+
+        let output_size = if to_stdout {
+            let format = if options.raw_dimensions.is_some() {
+                "data"
+            } else if options.tiff_strip.is_some() {
+                "tiff"
+            } else {
+                "png"
+            };
+            let encoded = parallel::run_with_threads(options.threads, || {
+                SteganographyEngine::hide_data_to_bytes(
+                    &working_image_path,
+                    &final_payload,
+                    format,
+                    &params,
+                )
+            })??;
+            Self::check_output_size(encoded.len() as u64, options.max_output_size)?;
+            FileHandler::write_stdout(&encoded)?;
+            encoded.len() as u64
+        } else {
+            let write_path = if options.in_place {
+                Self::in_place_temp_path(&output_path)
+            } else {
+                output_path.clone()
+            };
+
+            let written = parallel::run_with_threads(options.threads, || {
+                SteganographyEngine::hide_data(
+                    &working_image_path,
+                    &final_payload,
+                    &write_path,
+                    &params,
+                )
+            })
+            .and_then(|result| result)
+            .and_then(|()| {
+                let size = fs::metadata(&write_path)?.len();
+                let checked = Self::check_output_size(size, options.max_output_size);
+                if checked.is_err() {
+                    let _ = fs::remove_file(&write_path);
+                }
+                checked
+            });
+
+            if options.in_place {
+                // The carrier is only replaced once the new image has been
+                // written and verified, so a failure leaves it untouched.
+                match written {
+                    Ok(()) => fs::rename(&write_path, &output_path)?,
+                    Err(e) => {
+                        let _ = fs::remove_file(&write_path);
+                        return Err(e);
+                    }
+                }
+            } else {
+                written?;
+            }
+            fs::metadata(&output_path)?.len()
+        };
+
+        if let Some(target_size) = target_size {
+            progress!(
+                "      > Output size: {} (carrier: {})",
+                format_bytes(output_size as usize),
+                format_bytes(target_size as usize)
+            );
+
+            if output_size > target_size {
+                warning!(
+                    "smallest PNG encoding is still {} larger than the carrier",
+                    format_bytes((output_size - target_size) as usize)
+                )?;
+            }
+        }
+
+        progress!("      > Data embedded successfully \n");
+        progress!("> Encoding complete \n");
+
+        Ok(EncodeResult {
+            output_path,
+            file_name: file_data.name,
+            original_size,
+            final_size,
+            encrypted: Self::encrypts(&options),
+            compressed: compression_applied,
+            converted_to_png,
+            bit_plane: options.bit_plane,
+            stride: options.stride,
+            channel_copies: options.channel_copies,
+            channel_bits: layout.channel_bits,
+            preserve_region: options.preserve_region,
+            format_version: options.format_version,
+            tiff_strip: layout.tiff_strip,
+            fingerprint,
+            salt_nonce,
+        })
+    }
+
+    /// Reads the metadata `--preserve-exif` copies from the original carrier,
+    /// not the intermediate PNG of a converted one, which has none.
+    fn carrier_metadata(
+        options: &EncodeOptions,
+        output_path: &Path,
+    ) -> Result<Option<CarrierMetadata>> {
+        if options.raw_dimensions.is_some() {
+            warning!("--preserve-exif is ignored: raw carriers have no metadata")?;
+            return Ok(None);
+        }
+
+        if DngImage::is_dng_path(output_path) {
+            progress!("      > DNG output keeps all of the carrier's metadata");
+            return Ok(None);
+        }
+
+        let supported_output = FileHandler::is_stdout(output_path)
+            || output_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| matches!(e.to_lowercase().as_str(), "png" | "tiff" | "tif"));
+        if !supported_output {
+            warning!(
+                "--preserve-exif is ignored: metadata can only be copied to PNG or TIFF output"
+            )?;
+            return Ok(None);
+        }
+
+        let metadata = CarrierMetadata::read(&options.image_path)?;
+        if metadata.is_empty() {
+            progress!("      > No EXIF or ICC metadata found in the carrier");
+            return Ok(None);
+        }
+
+        progress!(
+            "      > Copying carrier metadata:{}{}",
+            if metadata.exif.is_some() { " EXIF" } else { "" },
+            if metadata.icc_profile.is_some() {
+                " ICC"
+            } else {
+                ""
+            }
+        );
+        Ok(Some(metadata))
+    }
+
+    /// Reads the pixel density `--preserve-dpi` copies from the original
+    /// carrier, not the intermediate PNG of a converted one.
+    fn carrier_resolution(
+        options: &EncodeOptions,
+        output_path: &Path,
+    ) -> Result<Option<Resolution>> {
+        if options.raw_dimensions.is_some() {
+            warning!("--preserve-dpi is ignored: raw carriers have no resolution")?;
+            return Ok(None);
+        }
+
+        if DngImage::is_dng_path(output_path) {
+            progress!("      > DNG output keeps the carrier's resolution");
+            return Ok(None);
+        }
+
+        let supported_output = FileHandler::is_stdout(output_path)
+            || output_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| {
+                    matches!(e.to_lowercase().as_str(), "png" | "tiff" | "tif" | "bmp")
+                });
+        if !supported_output {
+            warning!(
+                "--preserve-dpi is ignored: resolution can only be written to PNG, TIFF or BMP output"
+            )?;
+            return Ok(None);
+        }
+
+        let resolution = Resolution::read(&options.image_path)?;
+        match resolution {
+            Some(resolution) => progress!("      > Copying carrier resolution: {}", resolution),
+            None => progress!("      > No resolution found in the carrier"),
+        }
+        Ok(resolution)
+    }
+
+    /// Runs only the compression stage and projects the embedded payload size
+    /// against the carrier's capacity, without encrypting or writing anything.
+    pub fn estimate(options: &EncodeOptions) -> Result<EstimateResult> {
+        PayloadMetadata::validate_entries(&options.metadata)?;
+
+        let (width, height) = match options.raw_dimensions {
+            Some((width, height)) => {
+                SteganographyEngine::load_raw(&options.image_path, width, height)?;
+                (width, height)
+            }
+            None => SteganographyEngine::validate_image(&options.image_path)?,
+        };
+
+        let file_data = Self::read_payload_file(options)?;
+        let (compressed_data, compressed) = parallel::run_with_threads(options.threads, || {
+            CompressionEngine::compress(&file_data.data)
+        })??;
+
+        // Versions that compress after encrypting cannot shrink encrypted data.
+        let stored_len = if Self::compresses_before_encrypting(options) {
+            compressed_data.len()
+        } else {
+            file_data.data.len()
+        };
+        let payload_size = Self::projected_payload_size(&file_data.name, stored_len, options);
+
+        Ok(EstimateResult {
+            file_name: file_data.name,
+            original_size: file_data.data.len(),
+            compressed_size: compressed_data.len(),
+            compressed,
+            payload_size,
+            capacity: SteganographyEngine::payload_capacity(
+                width,
+                height,
+                &Self::layout_params(options)?,
+            ),
+        })
+    }
+
+    /// A quicker, rougher `estimate` for large files: compresses
+    /// `FIT_SAMPLE_COUNT` slices spread across the file and projects the
+    /// payload size at the best and the overall sampled ratio. Files no larger
+    /// than the samples are compressed whole.
+    pub fn estimate_fit(options: &EncodeOptions) -> Result<FitEstimate> {
+        PayloadMetadata::validate_entries(&options.metadata)?;
+
+        let (width, height) = match options.raw_dimensions {
+            Some((width, height)) => {
+                SteganographyEngine::load_raw(&options.image_path, width, height)?;
+                (width, height)
+            }
+            None => SteganographyEngine::validate_image(&options.image_path)?,
+        };
+
+        let file_data = Self::read_payload_file(options)?;
+        let data = &file_data.data;
+        let samples: Vec<&[u8]> = if data.len() <= FIT_SAMPLE_COUNT * FIT_SAMPLE_LEN {
+            vec![data.as_slice()]
+        } else {
+            let spacing = (data.len() - FIT_SAMPLE_LEN) / (FIT_SAMPLE_COUNT - 1);
+            (0..FIT_SAMPLE_COUNT)
+                .map(|index| &data[index * spacing..index * spacing + FIT_SAMPLE_LEN])
+                .collect()
+        };
+
+        let compressed_lens = parallel::run_with_threads(options.threads, || {
+            samples
+                .iter()
+                .map(|sample| {
+                    CompressionEngine::compress(sample)
+                        .map(|(compressed, _)| compressed.len().min(sample.len()))
+                })
+                .collect::<Result<Vec<usize>>>()
+        })??;
+
+        let sampled_size: usize = samples.iter().map(|sample| sample.len()).sum();
+        let best_ratio = samples
+            .iter()
+            .zip(&compressed_lens)
+            .map(|(sample, &len)| len as f64 / sample.len().max(1) as f64)
+            .fold(1.0, f64::min);
+        let expected_ratio =
+            compressed_lens.iter().sum::<usize>() as f64 / sampled_size.max(1) as f64;
+
+        // Mirrors `compress`, which keeps the data as is unless it shrinks by
+        // at least 5%, and versions that compress after encrypting.
+        let stored_len = |ratio: f64| {
+            let projected = ((data.len() as f64 * ratio).ceil() as usize)
+                .max(CompressionEngine::min_compressed_len(data.len()));
+            if Self::compresses_before_encrypting(options)
+                && projected < (data.len() as f64 * 0.95) as usize
+            {
+                projected
+            } else {
+                data.len()
+            }
+        };
+
+        Ok(FitEstimate {
+            best_payload_size: Self::projected_payload_size(
+                &file_data.name,
+                stored_len(best_ratio),
+                options,
+            ),
+            expected_payload_size: Self::projected_payload_size(
+                &file_data.name,
+                stored_len(expected_ratio),
+                options,
+            ),
+            raw_payload_size: Self::projected_payload_size(&file_data.name, data.len(), options),
+            capacity: SteganographyEngine::payload_capacity(
+                width,
+                height,
+                &Self::layout_params(options)?,
+            ),
+            file_name: file_data.name,
+            original_size: data.len(),
+            sampled_size,
+        })
+    }
+
+    pub fn decode(options: DecodeOptions) -> Result<DecodeResult> {
+        progress!("> DeepScene is here \n");
+
+        Self::note_ignored_threads(options.threads);
+
+        if options.to_clipboard && (options.consume || options.check || options.compat.is_some()) {
+            return Err(DeepSceneError::Validation(
+                "Placing the file on the clipboard cannot be combined with consume, check or a compat spec"
+                    .to_string(),
+            ));
+        }
+
+        if options.check && options.consume {
+            return Err(DeepSceneError::Validation(
+                "Checking writes no output, so the image cannot be consumed".to_string(),
+            ));
+        }
+
+        if let Some(spec) = options.compat
+            && options.verify_key.is_some()
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "The {} compat spec stores no signature to verify",
+                spec.name()
+            )));
+        }
+
+        if options.consume {
+            Self::check_consumable(&options.image_path, options.output_path.as_deref())?;
+        }
+
+        if let Some(spec) = options.compat {
+            return Self::decode_compat(options, spec);
+        }
+
+        let (metadata, file_data) = match Self::decode_payload(&options) {
+            // Raised only once the header and chunk CRCs have passed.
+            Err(DeepSceneError::PasswordRequired(_)) if options.check => {
+                progress!("> Check complete: payload is encrypted, checksums only \n");
+                return Ok(DecodeResult {
+                    output_path: options.output_path.unwrap_or_default(),
+                    file_name: String::new(),
+                    file_size: 0,
+                    encrypted: true,
+                    metadata: Vec::new(),
+                    expires: None,
+                    consumed_carrier: None,
+                    checked: Some(VerifyLevel::ChecksumsOnly),
+                    on_clipboard: false,
+                    signer: None,
+                    signer_trusted: false,
+                });
+            }
+            result => result?,
+        };
+
+        let signer = Self::check_signer(&metadata, options.verify_key.as_deref())?;
+        let signer_trusted = options.verify_key.is_some();
+
+        let output_path = options
+            .output_path
+            .unwrap_or_else(|| PathBuf::from(metadata.output_name()));
+
+        if options.check {
+            progress!("> Check complete, nothing written \n");
+            return Ok(DecodeResult {
+                output_path,
+                file_name: metadata.output_name(),
+                file_size: file_data.len(),
+                encrypted: metadata.encrypted,
+                metadata: metadata.entries,
+                expires: metadata.expires,
+                consumed_carrier: None,
+                checked: Some(VerifyLevel::Full),
+                on_clipboard: false,
+                signer,
+                signer_trusted,
+            });
+        }
+
+        if options.to_clipboard {
+            progress!("[4/4] Placing the file on the clipboard...");
+            clipboard::write_text(&file_data)?;
+            progress!("> Decoding complete \n");
+
+            return Ok(DecodeResult {
+                output_path,
+                file_name: metadata.output_name(),
+                file_size: file_data.len(),
+                encrypted: metadata.encrypted,
+                metadata: metadata.entries,
+                expires: metadata.expires,
+                consumed_carrier: None,
+                checked: None,
+                on_clipboard: true,
+                signer,
+                signer_trusted,
+            });
+        }
+
+        progress!("[4/4] Writing output file...");
+
+        if options.consume {
+            Self::check_consumable(&options.image_path, Some(&output_path))?;
+        }
+
+        FileHandler::write_file(&output_path, &file_data)?;
+
+        progress!("      > File written: {} \n", format_bytes(file_data.len()));
+        if options.verify_after_write {
+            let expected = metadata
+                .fingerprint
+                .unwrap_or_else(|| Fingerprint::of(&file_data));
+            Self::verify_written(&output_path, expected)?;
+        }
+        let consumed_carrier = if options.consume {
+            Self::consume_carrier(&options.image_path, &output_path, &file_data)?;
+            Some(options.image_path)
+        } else {
+            None
+        };
+        progress!("> Decoding complete \n");
+
+        Ok(DecodeResult {
+            output_path,
+            file_name: metadata.output_name(),
+            file_size: file_data.len(),
+            encrypted: metadata.encrypted,
+            metadata: metadata.entries,
+            expires: metadata.expires,
+            consumed_carrier,
+            checked: None,
+            on_clipboard: false,
+            signer,
+            signer_trusted,
+        })
+    }
+
+    /// The key that signed the payload, whose signature `open_payload` has
+    /// already checked. With `verify_key`, fails unless it is that key.
+    fn check_signer(
+        metadata: &PayloadMetadata,
+        verify_key: Option<&Path>,
+    ) -> Result<Option<SignerKey>> {
+        let signer = metadata.signature.map(|signature| signature.signer);
+        let Some(path) = verify_key else {
+            return Ok(signer);
+        };
+
+        let trusted = SignerKey(SigningEngine::load_verifying_key(path)?);
+        match signer {
+            Some(signer) if signer == trusted => Ok(Some(signer)),
+            Some(signer) => Err(DeepSceneError::Data(format!(
+                "The payload is signed by {}, not by the key in '{}'",
+                signer,
+                path.display()
+            ))),
+            None => Err(DeepSceneError::Data(format!(
+                "The payload is not signed, but --verify-key '{}' requires a signature",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Extracts every payload in the image, the one at its start and one per
+    /// TIFF strip with its own header, trying each password on the encrypted
+    /// ones. Recovered files are written in the current directory under the
+    /// last component of their stored names, renamed rather than overwriting
+    /// anything. A payload
+    /// that fails is reported and does not stop the others.
+    pub fn decode_all(options: DecodeAllOptions) -> Result<DecodeAllReport> {
+        progress!("> DeepScene is here \n");
+        progress!("[1/2] Extracting every payload from the image...");
+
+        let results = parallel::run_with_threads(options.threads, || {
+            Self::load_input(&options.image_path, options.raw_dimensions)
+                .map(|image| SteganographyEngine::extract_all(&image))
+        })??;
+        if results.is_empty() {
+            return Err(DeepSceneError::NoData(
+                messages::NO_EMBEDDED_DATA.to_string(),
+            ));
+        }
+        progress!("      > Found {} payloads", results.len());
+
+        progress!("[2/2] Decrypting and writing each payload...");
+        let entries = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, extracted)| {
+                progress!("=== Payload {} ===", index + 1);
+                let tiff_strip = extracted.as_ref().ok().and_then(|e| e.tiff_strip);
+                let result = extracted.and_then(|extracted| {
+                    let (metadata, file_data, password_index) =
+                        Self::open_with_passwords(&extracted, &options)?;
+                    let output_path =
+                        OverwritePolicy::Rename.resolve(&metadata.local_output_name()?)?;
+                    FileHandler::write_file(&output_path, &file_data)?;
+                    Ok(RecoveredPayload {
+                        output_path,
+                        file_name: metadata.output_name(),
+                        file_size: file_data.len(),
+                        password_index,
+                    })
+                });
+
+                match &result {
+                    Ok(payload) => {
+                        progress!("      > Written to '{}'\n", payload.output_path.display())
+                    }
+                    Err(e) => progress!("      > Failed: {}\n", e),
+                }
+                DecodeAllEntry { tiff_strip, result }
+            })
+            .collect();
+        progress!("> Decoding complete \n");
+
+        Ok(DecodeAllReport { entries })
+    }
+
+    /// Opens the payload without a password, then, if it is encrypted, with
+    /// each password in turn until one authenticates. Returns the index of
+    /// that password as well.
+    fn open_with_passwords(
+        extracted: &ExtractedData,
+        options: &DecodeAllOptions,
+    ) -> Result<(PayloadMetadata, Vec<u8>, Option<usize>)> {
+        let mut attempt = DecodeOptions {
+            image_path: options.image_path.clone(),
+            output_path: None,
+            password: None,
+            password_list: Vec::new(),
+            threads: options.threads,
+            raw_dimensions: options.raw_dimensions,
+            compat: None,
+            enforce_expiry: options.enforce_expiry,
+            consume: false,
+            recover: false,
+            check: false,
+            to_clipboard: false,
+            verify_after_write: false,
+            verify_key: None,
+        };
+
+        match Self::open_payload(extracted, &attempt) {
+            Err(DeepSceneError::PasswordRequired(message)) if options.passwords.is_empty() => {
+                Err(DeepSceneError::PasswordRequired(message))
+            }
+            Err(DeepSceneError::PasswordRequired(_)) => {
+                for (index, password) in options.passwords.iter().enumerate() {
+                    attempt.password = Some(password.clone());
+                    match Self::open_payload(extracted, &attempt) {
+                        Ok((metadata, file_data)) => {
+                            return Ok((metadata, file_data, Some(index)));
+                        }
+                        Err(DeepSceneError::WrongPassword(_)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(DeepSceneError::WrongPassword(
+                    match options.passwords.len() {
+                        1 => "The password does not decrypt this payload".to_string(),
+                        count => format!("None of the {} passwords decrypts this payload", count),
+                    },
+                ))
+            }
+            result => result.map(|(metadata, file_data)| (metadata, file_data, None)),
+        }
+    }
+
+    /// Recovers the embedded file's name and contents without writing
+    /// anything, for callers that handle the bytes themselves. Compat specs
+    /// store no name, so theirs is the file name of `output_path`, or empty.
+    /// `consume` is rejected, since the image is only destroyed once the
+    /// output is safely on disk.
+    pub fn decode_to_bytes(options: &DecodeOptions) -> Result<(String, Vec<u8>)> {
+        if options.consume {
+            return Err(DeepSceneError::Validation(
+                "Consuming the image needs the extracted file written to disk; use decode instead of decoding to memory"
+                    .to_string(),
+            ));
+        }
+
+        if let Some(spec) = options.compat {
+            let name = options
+                .output_path
+                .as_deref()
+                .and_then(Path::file_name)
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            return Ok((name, Self::extract_compat_payload(options, spec)?));
+        }
+
+        let (metadata, file_data) = Self::decode_payload(options)?;
+        Ok((metadata.output_name(), file_data))
+    }
+
+    /// `extract_payload`, refusing a password given for an unencrypted
+    /// payload.
+    fn decode_payload(options: &DecodeOptions) -> Result<(PayloadMetadata, Vec<u8>)> {
+        let (metadata, file_data) = Self::extract_payload(options)?;
+
+        if (options.password.is_some() || !options.password_list.is_empty()) && !metadata.encrypted
+        {
+            return Err(DeepSceneError::Validation(
+                "Password provided for unencrypted file. This file does not require a password"
+                    .to_string(),
+            ));
+        }
+
+        Ok((metadata, file_data))
+    }
+
+    /// Extracts, decrypts and decompresses the payload, checking the header,
+    /// chunk CRCs and the decryption checksum along the way, without writing
+    /// anything. A password given for an unencrypted payload is ignored.
+    pub(crate) fn extract_payload(options: &DecodeOptions) -> Result<(PayloadMetadata, Vec<u8>)> {
+        progress!("[1/4] Extracting data from image...");
+
+        let extracted = parallel::run_with_threads(options.threads, || {
+            Self::extract(&options.image_path, options.raw_dimensions)
+        })??;
+        Self::open_payload(&extracted, options)
+    }
+
+    /// The steps of `extract_payload` after the embedded bytes are read.
+    fn open_payload(
+        extracted: &ExtractedData,
+        options: &DecodeOptions,
+    ) -> Result<(PayloadMetadata, Vec<u8>)> {
+        let embedded_data = &extracted.data;
+        progress!("      > Extracted {}", format_bytes(embedded_data.len()));
+        if let Some(channel) = extracted.copy_channel
+            && channel > 0
+        {
+            progress!(
+                "      > Earlier channel copies were damaged; recovered from the {} channel",
+                ["red", "green", "blue"][channel]
+            );
+        }
+
+        if embedded_data.is_empty() {
+            return Err(DeepSceneError::NoData(messages::NO_DATA_FOUND.to_string()));
+        }
+
+        let (compression_flag, metadata_encrypted, recipients) =
+            Self::payload_flags(embedded_data[0], extracted.format_version);
+        let (hint, payload_data) =
+            PayloadMetadata::split_hint(&embedded_data[1..], extracted.format_version)?;
+
+        let (metadata, file_data) = if metadata_encrypted {
+            progress!("[2/4] Decrypting metadata and data...");
+            let decrypted = Self::decrypt_data(
+                payload_data,
+                true,
+                recipients,
+                &options.password,
+                &options.password_list,
+                hint.as_deref(),
+                extracted.format_version,
+            )?;
+
+            progress!("[3/4] Processing data...");
+            Self::parse_metadata(
+                &decrypted,
+                extracted.format_version,
+                options.recover,
+                |metadata, stored_data| {
+                    Self::check_expiry(metadata.expires, options.enforce_expiry)?;
+                    Self::decompress_data(stored_data, compression_flag)
+                },
+            )?
+        } else if extracted.format_version >= COMPRESS_THEN_ENCRYPT_VERSION {
+            progress!("[2/4] Parsing metadata and processing data...");
+            let mut sealed = (None, None);
+            let (mut metadata, file_data) = Self::parse_metadata(
+                payload_data,
+                extracted.format_version,
+                options.recover,
+                |metadata, stored_data| {
+                    Self::check_expiry(metadata.expires, options.enforce_expiry)?;
+                    let decrypted = Self::decrypt_data(
+                        stored_data,
+                        metadata.encrypted,
+                        recipients,
+                        &options.password,
+                        &options.password_list,
+                        hint.as_deref(),
+                        extracted.format_version,
+                    )?;
+                    let compressed = if Self::has_sealed_fingerprint(metadata, extracted) {
+                        let (fingerprint, signature, rest) =
+                            PayloadMetadata::split_sealed(&decrypted, extracted.format_version)?;
+                        sealed = (fingerprint, signature);
+                        rest
+                    } else {
+                        &decrypted
+                    };
+                    Self::decompress_data(compressed, compression_flag)
+                },
+            )?;
+            metadata.fingerprint = metadata.fingerprint.or(sealed.0);
+            metadata.signature = metadata.signature.or(sealed.1);
+            (metadata, file_data)
+        } else {
+            progress!("[2/4] Processing data...");
+            let decompressed_data = Self::decompress_data(payload_data, compression_flag)?;
+
+            progress!("[3/4] Parsing metadata...");
+            Self::parse_metadata(
+                &decompressed_data,
+                extracted.format_version,
+                options.recover,
+                |metadata, stored_data| {
+                    Self::decrypt_data(
+                        stored_data,
+                        metadata.encrypted,
+                        recipients,
+                        &options.password,
+                        &options.password_list,
+                        hint.as_deref(),
+                        extracted.format_version,
+                    )
+                },
+            )?
+        };
+
+        if file_data.is_empty() {
+            return Err(DeepSceneError::Data(
+                "Extracted file data is empty".to_string(),
+            ));
+        }
+
+        if let Some(signature) = &metadata.signature {
+            signature.verify(&file_data)?;
+            progress!("      > Signature verified ({})", signature.signer);
+        }
+
+        Ok((metadata, file_data))
+    }
+
+    /// Whether the decrypted file data of a payload with clear `metadata`
+    /// starts with the fingerprint.
+    fn has_sealed_fingerprint(metadata: &PayloadMetadata, extracted: &ExtractedData) -> bool {
+        metadata.encrypted && extracted.format_version >= SEALED_FINGERPRINT_VERSION
+    }
+
+    /// Parses the metadata in front of the stored file data and turns that data
+    /// into the file with `process`. When the metadata does not parse and
+    /// `recover` is set, tries each way of reading it with a synthesized name
+    /// instead, keeping the first whose data `process` accepts, so a corrupt
+    /// name does not cost the file.
+    fn parse_metadata(
+        data: &[u8],
+        version: u8,
+        recover: bool,
+        mut process: impl FnMut(&PayloadMetadata, &[u8]) -> Result<Vec<u8>>,
+    ) -> Result<(PayloadMetadata, Vec<u8>)> {
+        let error = match PayloadMetadata::from_bytes(data, version) {
+            Ok((metadata, stored_data)) => {
+                progress!("      > Metadata parsed successfully");
+                let file_data = process(&metadata, stored_data)?;
+                return Ok((metadata, file_data));
+            }
+            Err(e) if recover => e,
+            Err(e) => return Err(e),
+        };
+
+        progress!("      > Metadata is corrupted; trying to recover the file data");
+        for (metadata, stored_data) in PayloadMetadata::recover(data, version) {
+            if let Ok(file_data) = process(&metadata, stored_data) {
+                warning!(
+                    "metadata is corrupted ({}); recovered the file data under the name '{}', but its metadata and expiry may be wrong\n",
+                    error,
+                    metadata.file_name
+                )?;
+                return Ok((metadata, file_data));
+            }
+        }
+
+        warning!("no readable file data could be recovered from the corrupted metadata\n")?;
+        Err(error)
+    }
+
+    fn extract_compat_payload(options: &DecodeOptions, spec: CompatSpec) -> Result<Vec<u8>> {
+        progress!("[1/2] Extracting {} payload from image...", spec.name());
+
+        let file_data = parallel::run_with_threads(options.threads, || {
+            let image = Self::load_input(&options.image_path, options.raw_dimensions)?;
+            SteganographyEngine::extract_compat(&image, spec)
+        })??;
+        progress!("      > Extracted {}", format_bytes(file_data.len()));
+        Ok(file_data)
+    }
+
+    /// Compat payloads are bare file bytes with no name, flags or metadata, so
+    /// they are written out unchanged to the explicit output path.
+    fn decode_compat(options: DecodeOptions, spec: CompatSpec) -> Result<DecodeResult> {
+        let output_path = options.output_path.clone().ok_or_else(|| {
+            DeepSceneError::Validation(format!(
+                "The {} compat spec stores no file name. Please provide an output path using -o",
+                spec.name()
+            ))
+        })?;
+
+        let file_data = Self::extract_compat_payload(&options, spec)?;
+
+        if options.check {
+            progress!("> Check complete, nothing written \n");
+        } else {
+            progress!("[2/2] Writing output file...");
+            FileHandler::write_file(&output_path, &file_data)?;
+            progress!("      > File written: {} \n", format_bytes(file_data.len()));
+            if options.verify_after_write {
+                Self::verify_written(&output_path, Fingerprint::of(&file_data))?;
+            }
+        }
+        let consumed_carrier = if options.consume {
+            Self::consume_carrier(&options.image_path, &output_path, &file_data)?;
+            Some(options.image_path)
+        } else {
+            None
+        };
+        progress!("> Decoding complete \n");
+
+        Ok(DecodeResult {
+            file_name: output_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            output_path,
+            file_size: file_data.len(),
+            encrypted: false,
+            metadata: Vec::new(),
+            expires: None,
+            consumed_carrier,
+            checked: options.check.then_some(VerifyLevel::Full),
+            on_clipboard: false,
+            signer: None,
+            signer_trusted: false,
+        })
+    }
+
+    /// Fails unless the image is a file that `consume` can delete without
+    /// losing the output. Checked before extraction and again once the
+    /// output path is known, so nothing is written when it would fail.
+    fn check_consumable(image_path: &Path, output_path: Option<&Path>) -> Result<()> {
+        if FileHandler::is_streamed_input(image_path) {
+            return Err(DeepSceneError::Validation(
+                "--consume needs an image file; standard input and URLs cannot be deleted"
+                    .to_string(),
+            ));
+        }
+
+        FileHandler::check_not_symlink(image_path, "Image")?;
+
+        if let Some(output_path) = output_path
+            && FileHandler::is_same_file(image_path, output_path)
+        {
+            return Err(DeepSceneError::Validation(format!(
+                "--consume would delete the extracted file: the output '{}' is the image itself",
+                output_path.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Destroys the image only after the output has been read back intact
+    /// and flushed to disk. Any failure before the overwrite leaves the
+    /// image untouched.
+    /// Flushes `output_path` to disk, reads it back and compares its hash
+    /// with `expected`: the fingerprint stored at encode time, or the hash of
+    /// the extracted data for payloads without one. A mismatching file is
+    /// removed rather than left looking like a good extraction.
+    fn verify_written(output_path: &Path, expected: Fingerprint) -> Result<()> {
+        fs::File::open(output_path)?.sync_all()?;
+        let written = Fingerprint::of_reader(fs::File::open(output_path)?)?;
+
+        if written != expected {
+            fs::remove_file(output_path)?;
+            return Err(DeepSceneError::Data(format!(
+                "'{}' read back as fingerprint {} instead of {}, so it was removed",
+                output_path.display(),
+                written,
+                expected
+            )));
+        }
+
+        progress!("      > Verified after writing: fingerprint {}", written);
+        Ok(())
+    }
+
+    fn consume_carrier(image_path: &Path, output_path: &Path, file_data: &[u8]) -> Result<()> {
+        let written = fs::read(output_path)?;
+        if written != file_data {
+            return Err(DeepSceneError::Data(format!(
+                "'{}' does not match the extracted data after writing. The image was kept",
+                output_path.display()
+            )));
+        }
+        fs::File::open(output_path)?.sync_all()?;
+
+        FileHandler::shred_file(image_path)?;
+        progress!("      > Image overwritten and deleted");
+        Ok(())
+    }
+
+    /// Reads the embedded metadata without decrypting or writing anything.
+    /// Names are stored outside the encrypted region, so no password is needed.
+    pub fn list(options: ListOptions) -> Result<ListResult> {
+        let extracted = parallel::run_with_threads(options.threads, || {
+            Self::extract(&options.image_path, options.raw_dimensions)
+        })??;
+
+        if extracted.data.is_empty() {
+            return Err(DeepSceneError::NoData(messages::NO_DATA_FOUND.to_string()));
+        }
+
+        let (compression_flag, metadata_encrypted, _) =
+            Self::payload_flags(extracted.data[0], extracted.format_version);
+        let compressed = compression_flag == 1;
+        let (hint, payload_data) =
+            PayloadMetadata::split_hint(&extracted.data[1..], extracted.format_version)?;
+
+        if metadata_encrypted {
+            return Ok(ListResult {
+                format_version: extracted.format_version,
+                stride: extracted.stride,
+                channel_copies: extracted.copy_channel.is_some(),
+                channel_bits: extracted.channel_bits,
+                preserved_region: extracted.preserved_region,
+                tiff_strip: extracted.tiff_strip,
+                hint,
+                metadata_encrypted,
+                entries: vec![ListEntry {
+                    file_name: "(encrypted)".to_string(),
+                    stored_size: payload_data.len(),
+                    encrypted: true,
+                    compressed,
+                }],
+                metadata: Vec::new(),
+                expires: None,
+            });
+        }
+
+        let (metadata, stored_size) = if extracted.format_version >= COMPRESS_THEN_ENCRYPT_VERSION {
+            let (metadata, stored_data) =
+                PayloadMetadata::from_bytes(payload_data, extracted.format_version)?;
+            (metadata, stored_data.len())
+        } else {
+            let decompressed = if compressed {
+                CompressionEngine::decompress(payload_data)?
+            } else {
+                payload_data.to_vec()
+            };
+            let (metadata, stored_data) =
+                PayloadMetadata::from_bytes(&decompressed, extracted.format_version)?;
+            (metadata, stored_data.len())
+        };
+
+        Ok(ListResult {
+            format_version: extracted.format_version,
+            stride: extracted.stride,
+            channel_copies: extracted.copy_channel.is_some(),
+            channel_bits: extracted.channel_bits,
+            preserved_region: extracted.preserved_region,
+            tiff_strip: extracted.tiff_strip,
+            hint,
+            metadata_encrypted,
+            entries: vec![ListEntry {
+                file_name: metadata.output_name(),
+                stored_size,
+                encrypted: metadata.encrypted,
+                compressed,
+            }],
+            metadata: metadata.entries,
+            expires: metadata.expires,
+        })
+    }
+
+    /// Reads the fingerprint of the embedded file from the metadata, and
+    /// compares it with another file's when asked. The file data is never
+    /// decompressed, and only decrypted when the fingerprint is stored in the
+    /// encrypted region: with the metadata, or, from
+    /// `SEALED_FINGERPRINT_VERSION`, whenever the file data is encrypted.
+    pub fn fingerprint(options: &FingerprintOptions) -> Result<FingerprintResult> {
+        let extracted = parallel::run_with_threads(options.threads, || {
+            Self::extract(&options.image_path, options.raw_dimensions)
+        })??;
+
+        if extracted.data.is_empty() {
+            return Err(DeepSceneError::NoData(messages::NO_DATA_FOUND.to_string()));
+        }
+
+        let version = extracted.format_version;
+        if version < FINGERPRINT_VERSION {
+            return Err(DeepSceneError::NoData(format!(
+                "Format version {} stores no fingerprint. It needs version {} or later; decode the file and hash it instead",
+                version, FINGERPRINT_VERSION
+            )));
+        }
+
+        let (_, metadata_encrypted, recipients) = Self::payload_flags(extracted.data[0], version);
+        let (hint, payload_data) = PayloadMetadata::split_hint(&extracted.data[1..], version)?;
+
+        let decrypt = |data| {
+            Self::decrypt_data(
+                data,
+                true,
+                recipients,
+                &options.password,
+                &[],
+                hint.as_deref(),
+                version,
+            )
+        };
+
+        let fingerprint = if metadata_encrypted {
+            PayloadMetadata::from_bytes(&decrypt(payload_data)?, version)?
+                .0
+                .fingerprint
+        } else {
+            let (metadata, stored_data) = PayloadMetadata::from_bytes(payload_data, version)?;
+            if Self::has_sealed_fingerprint(&metadata, &extracted) {
+                PayloadMetadata::split_sealed(&decrypt(stored_data)?, version)?.0
+            } else {
+                metadata.fingerprint
+            }
+        };
+
+        let fingerprint = fingerprint.ok_or_else(|| {
+            DeepSceneError::NoData("The embedded file was stored without a fingerprint".to_string())
+        })?;
+
+        let matches = match &options.compare {
+            Some(path) => {
+                let file = fs::File::open(path).map_err(|e| {
+                    DeepSceneError::Validation(format!("Cannot read '{}': {}", path.display(), e))
+                })?;
+                Some(Fingerprint::of_reader(file)? == fingerprint)
+            }
+            None => None,
+        };
+
+        Ok(FingerprintResult {
+            fingerprint,
+            matches,
+        })
+    }
+
+    /// Moves the payload of one image into a new carrier exactly as stored, so
+    /// it is never decrypted or decompressed and no password is needed. The
+    /// format version and bit plane are kept, since the payload bytes are laid
+    /// out for that version. A stride, per-channel copies, a preserved region
+    /// or a TIFF strip belonged to the old carrier and are not carried over.
+    pub fn transfer(options: TransferOptions) -> Result<TransferResult> {
+        progress!("> DeepScene is here \n");
+        progress!("[1/3] Extracting payload...");
+
+        let extracted = parallel::run_with_threads(options.threads, || {
+            Self::extract(&options.image_path, None)
+        })??;
+        if extracted.data.is_empty() {
+            return Err(DeepSceneError::NoData(messages::NO_DATA_FOUND.to_string()));
+        }
+
+        progress!(
+            "      > {} payload in format version {}",
+            format_bytes(extracted.data.len()),
+            extracted.format_version
+        );
+        progress!("[2/3] Checking the new carrier...");
+
+        let carrier = Self::load_carrier(&options.carrier_path, None)?;
+        if SteganographyEngine::has_header(&carrier) {
+            warning!("the carrier already contains embedded data, which will be overwritten\n")?;
+        }
+        let (width, height) = carrier.dimensions();
+        drop(carrier);
+
+        let params = EmbedParams {
+            bit_plane: extracted.bit_plane,
+            lsb_matching: options.lsb_matching,
+            carrier_noise: options.carrier_noise,
+            format_version: Some(extracted.format_version),
+            ..EmbedParams::default()
+        };
+        let payload_size = extracted.data.len();
+        let capacity = SteganographyEngine::payload_capacity(width, height, &params);
+
+        if payload_size > capacity {
+            return Err(DeepSceneError::CapacityExceeded(format!(
+                "The payload of '{}' ({} bytes) does not fit in '{}', which can hold {} bytes. {}",
+                options.image_path.display(),
+                payload_size,
+                options.carrier_path.display(),
+                capacity,
+                SteganographyEngine::size_suggestion(
+                    width,
+                    height,
+                    SteganographyEngine::required_bytes(payload_size, &params)
+                )
+            )));
+        }
+
+        if payload_size as f64 > capacity as f64 * NEAR_CAPACITY_RATIO {
+            warning!(
+                "payload fills {:.0}% of the carrier's capacity; densely embedded images are easier to detect",
+                payload_size as f64 / capacity as f64 * 100.0
+            )?;
+        }
+
+        progress!(
+            "      > {} of {} used",
+            format_bytes(payload_size),
+            format_bytes(capacity)
+        );
+        FileHandler::validate_output_path(&options.output_path)?;
+        progress!("[3/3] Embedding payload into the new carrier...");
+
+        parallel::run_with_threads(options.threads, || {
+            SteganographyEngine::hide_data(
+                &options.carrier_path,
+                &extracted.data,
+                &options.output_path,
+                &params,
+            )
+        })??;
+
+        progress!("      > Data embedded successfully \n");
+        progress!("> Transfer complete \n");
+
+        Ok(TransferResult {
+            output_path: options.output_path,
+            format_version: extracted.format_version,
+            bit_plane: extracted.bit_plane,
+            payload_size,
+            capacity,
+        })
+    }
+
+    /// Compares the carrier's payload capacity, after header and chunk CRC
+    /// overhead, with a required size. Nothing is extracted or written.
+    pub fn check(options: &CheckOptions) -> Result<CheckResult> {
+        let (width, height) = match options.raw_dimensions {
+            Some((width, height)) => {
+                SteganographyEngine::load_raw(&options.image_path, width, height)?;
+                (width, height)
+            }
+            None => SteganographyEngine::validate_image(&options.image_path)?,
+        };
+
+        Ok(CheckResult {
+            width,
+            height,
+            capacity: SteganographyEngine::payload_capacity(width, height, &EmbedParams::default()),
+            needs: options.needs,
+        })
+    }
+
+    /// Writes a grayscale heatmap of where the carrier hides changes best
+    /// (bright) and worst (dark), without embedding anything.
+    pub fn capacity_map(options: CapacityMapOptions) -> Result<CapacityMapResult> {
+        let carrier = Self::load_carrier(&options.image_path, options.raw_dimensions)?;
+        let (width, height) = carrier.dimensions();
+
+        let output_path = options.output_path.unwrap_or_else(|| {
+            let stem = options
+                .image_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("output");
+            options
+                .image_path
+                .with_file_name(format!("{}_capacity.png", stem))
+        });
+        FileHandler::validate_output_path(&output_path)?;
+
+        let map = parallel::run_with_threads(options.threads, || CapacityMap::render(&carrier))??;
+
+        let flat = map.pixels().filter(|p| p[0] < FLAT_THRESHOLD).count();
+        let flat_ratio = if map.is_empty() {
+            0.0
+        } else {
+            flat as f64 / map.len() as f64
+        };
+
+        map.save(&output_path).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Failed to write capacity map '{}': {}",
+                output_path.display(),
+                e
+            ))
+        })?;
+
+        Ok(CapacityMapResult {
+            output_path,
+            width,
+            height,
+            capacity: SteganographyEngine::payload_capacity(width, height, &EmbedParams::default()),
+            flat_ratio,
+        })
+    }
+
+    /// Writes where bit `bit_plane` is set in an image, and optionally the
+    /// image with that plane cleared, which is what the carrier looked like
+    /// if every set bit came from embedding.
+    pub fn diff_map(options: DiffMapOptions) -> Result<DiffMapResult> {
+        let image = Self::load_carrier(&options.image_path, options.raw_dimensions)?;
+        let (width, height) = image.dimensions();
+
+        let output_path = options.output_path.unwrap_or_else(|| {
+            let stem = options
+                .image_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("output");
+            options
+                .image_path
+                .with_file_name(format!("{}_diff.png", stem))
+        });
+        FileHandler::validate_output_path(&output_path)?;
+        if let Some(ref clean_path) = options.clean_path {
+            FileHandler::validate_output_path(clean_path)?;
+        }
+
+        let plane = options.bit_plane;
+        let map = parallel::run_with_threads(options.threads, || DiffMap::render(&image, plane))??;
+        map.save(&output_path).map_err(|e| {
+            DeepSceneError::Image(format!(
+                "Failed to write difference map '{}': {}",
+                output_path.display(),
+                e
+            ))
+        })?;
+
+        if let Some(ref clean_path) = options.clean_path {
+            DiffMap::clean(&image, plane)
+                .save(clean_path)
+                .map_err(|e| {
+                    DeepSceneError::Image(format!(
+                        "Failed to write clean image '{}': {}",
+                        clean_path.display(),
+                        e
+                    ))
+                })?;
+        }
+
+        Ok(DiffMapResult {
+            output_path,
+            clean_path: options.clean_path,
+            width,
+            height,
+            bit_plane: plane,
+            set_ratio: DiffMap::set_ratio(&image, plane),
+            has_header: SteganographyEngine::has_header(&image),
+        })
+    }
+
+    /// Writes a copy of the image with its low bit planes cleared or
+    /// randomized, destroying any embedded data.
+    pub fn strip(options: StripOptions) -> Result<StripResult> {
+        let image = Self::load_carrier(&options.image_path, None)?;
+        let (width, height) = image.dimensions();
+        let had_header = SteganographyEngine::has_header(&image);
+        drop(image);
+
+        let output_path = options.output_path.unwrap_or_else(|| {
+            let stem = options
+                .image_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("output");
+            options
+                .image_path
+                .with_file_name(format!("{}_clean.png", stem))
+        });
+        FileHandler::validate_output_path(&output_path)?;
+
+        let random = RandomSource::default();
+        SteganographyEngine::strip_data(
+            &options.image_path,
+            &output_path,
+            options.planes,
+            options.randomize.then_some(&random),
+        )?;
+
+        Ok(StripResult {
+            output_path,
+            width,
+            height,
+            planes: options.planes,
+            randomize: options.randomize,
+            had_header,
+        })
+    }
+
+    /// Reads the dimensions, format and capacity of each image, in the order
+    /// given. A directory stands for the images directly inside it. Files that
+    /// cannot be read are kept in the table with their error rather than
+    /// stopping the run.
+    pub fn capacity_table(image_paths: &[PathBuf]) -> Result<CapacityTable> {
+        let mut paths = Vec::with_capacity(image_paths.len());
+        for path in image_paths {
+            if path.is_dir() {
+                paths.extend(
+                    FileHandler::list_files(path, false)?
+                        .into_iter()
+                        .filter(|p| image::ImageFormat::from_path(p).is_ok()),
+                );
+            } else {
+                paths.push(path.clone());
+            }
+        }
+
+        let entries = paths
+            .into_iter()
+            .map(|image_path| {
+                let result = Self::carrier_capacity(&image_path);
+                CapacityTableEntry { image_path, result }
+            })
+            .collect();
+
+        Ok(CapacityTable { entries })
+    }
+
+    /// The cipher, key derivation functions, compression backend and
+    /// signature algorithm this build encodes with, read from the same
+    /// definitions encode and decode dispatch on.
+    pub fn algorithms() -> AlgorithmList {
+        let mut entries = vec![AlgorithmInfo {
+            category: "cipher",
+            name: CryptoEngine::CIPHER,
+            parameters: CryptoEngine::cipher_parameters(),
+            default: true,
+            min_version: 1,
+        }];
+
+        entries.extend(KdfAlgorithm::ALL.into_iter().map(|kdf| AlgorithmInfo {
+            category: "kdf",
+            name: kdf.name(),
+            parameters: kdf.parameters(),
+            default: kdf == KdfAlgorithm::default(),
+            // Argon2 was the only function before the identifier byte.
+            min_version: match kdf {
+                KdfAlgorithm::Argon2 => 1,
+                KdfAlgorithm::Scrypt => KDF_ID_VERSION,
+            },
+        }));
+
+        entries.push(AlgorithmInfo {
+            category: "compression",
+            name: CompressionEngine::ALGORITHM,
+            parameters: CompressionEngine::parameters(),
+            default: true,
+            min_version: 1,
+        });
+
+        entries.push(AlgorithmInfo {
+            category: "signature",
+            name: SigningEngine::ALGORITHM,
+            parameters: "over a BLAKE3 hash of the file, with --sign-key".to_string(),
+            default: false,
+            min_version: SIGNATURE_VERSION,
+        });
+
+        AlgorithmList { entries }
+    }
+
+    fn carrier_capacity(image_path: &Path) -> Result<CarrierCapacity> {
+        let (width, height) = SteganographyEngine::validate_image(image_path)?;
+        let format = if DngImage::is_dng_path(image_path) {
+            "DNG".to_string()
+        } else {
+            image::io::Reader::open(image_path)?
+                .with_guessed_format()?
+                .format()
+                .and_then(|f| f.extensions_str().first())
+                .map_or_else(|| "unknown".to_string(), |ext| ext.to_uppercase())
+        };
+
+        Ok(CarrierCapacity {
+            width,
+            height,
+            format,
+            lossless: SteganographyEngine::is_lossless_format(image_path),
+            capacity: SteganographyEngine::payload_capacity(width, height, &EmbedParams::default()),
+        })
+    }
+
+    /// Extracts from the image at `image_path`, which for decode and list may
+    /// also be `-` for standard input or an `http(s)://` URL.
+    fn extract(image_path: &Path, raw_dimensions: Option<(u32, u32)>) -> Result<ExtractedData> {
+        FileHandler::note_symlink(image_path);
+        if FileHandler::is_streamed_input(image_path) {
+            let bytes = FileHandler::read_streamed_input(image_path)?;
+            return SteganographyEngine::extract_data_from_memory(
+                bytes,
+                raw_dimensions,
+                image_path,
+            );
+        }
+
+        match raw_dimensions {
+            Some((width, height)) => {
+                SteganographyEngine::extract_data_raw(image_path, width, height)
+            }
+            None => SteganographyEngine::extract_data(image_path),
+        }
+    }
+
+    /// Like `load_carrier`, but also accepts standard input or a URL.
+    fn load_input(image_path: &Path, raw_dimensions: Option<(u32, u32)>) -> Result<RgbaImage> {
+        if !FileHandler::is_streamed_input(image_path) {
+            return Self::load_carrier(image_path, raw_dimensions);
+        }
+
+        let bytes = FileHandler::read_streamed_input(image_path)?;
+        match raw_dimensions {
+            Some((width, height)) => {
+                SteganographyEngine::load_raw_from_memory(bytes, width, height, image_path)
+            }
+            None => SteganographyEngine::load_image_from_memory(&bytes, image_path),
+        }
+    }
+
+    /// Fails when the encoded image is over the `max_output_size` budget. The
+    /// caller discards the image.
+    fn check_output_size(size: u64, max_output_size: Option<u64>) -> Result<()> {
+        match max_output_size {
+            Some(max) if size > max => Err(DeepSceneError::Validation(format!(
+                "The encoded image is {} ({} bytes), over the --max-output-size budget of {} bytes, and was not kept. Use a smaller carrier or a format that compresses better",
+                format_bytes(size as usize),
+                size,
+                max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses to convert a lossy carrier whose PNG may exceed
+    /// `CONFIRM_CONVERT_SIZE`. The estimate is the uncompressed RGB size,
+    /// since PNG compresses photographs little.
+    fn check_conversion_size(image_path: &Path) -> Result<()> {
+        let (width, height) = SteganographyEngine::validate_image(image_path)?;
+        let estimate = width as usize * height as usize * 3;
+        if estimate <= CONFIRM_CONVERT_SIZE {
+            return Ok(());
+        }
+
+        Err(DeepSceneError::Validation(format!(
+            "'{}' ({}x{}) is not a lossless format, and its PNG conversion would take about {}. Pass --confirm-convert to convert it anyway",
+            image_path.display(),
+            width,
+            height,
+            format_bytes(estimate)
+        )))
+    }
+
+    fn load_carrier(image_path: &Path, raw_dimensions: Option<(u32, u32)>) -> Result<RgbaImage> {
+        FileHandler::note_symlink(image_path);
+        match raw_dimensions {
+            Some((width, height)) => SteganographyEngine::load_raw(image_path, width, height),
+            None => SteganographyEngine::load_image(image_path),
+        }
+    }
+
+    /// Fails before reading, compressing or encrypting the file if even its
+    /// best-case payload cannot fit: the file size is known up front and
+    /// deflate shrinks data by a bounded factor, so the payload has a lower
+    /// bound. A missing file is left for `read_file` to report.
+    fn check_minimum_fits(
+        options: &EncodeOptions,
+        params: &EmbedParams,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        if let Some(region) = options.preserve_region {
+            region.validate(width, height)?;
+        }
+        SteganographyEngine::check_header_fits(width, height, params)?;
+
+        if options.from_clipboard {
+            return Ok(());
+        }
+        let Ok(file_size) = fs::metadata(&options.file_path).map(|m| m.len() as usize) else {
+            return Ok(());
+        };
+
+        let minimum_payload = match options.compat {
+            Some(_) => file_size,
+            None => {
+                let file_name = options
+                    .file_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or_default();
+                let metadata = Self::payload_metadata(file_name, options)
+                    .to_bytes(Self::target_version(options))
+                    .len();
+                if Self::target_version(options) < COMPRESS_THEN_ENCRYPT_VERSION {
+                    1 + CompressionEngine::min_compressed_len(
+                        metadata + file_size + Self::encryption_overhead(options),
+                    )
+                } else {
+                    Self::clear_prefix_len(options)
+                        + metadata
+                        + CompressionEngine::min_compressed_len(file_size)
+                        + Self::encryption_overhead(options)
+                }
+            }
+        };
+
+        let capacity = SteganographyEngine::payload_capacity(width, height, params);
+        if minimum_payload <= capacity {
+            return Ok(());
+        }
+
+        if let Some(strip) = params.tiff_strip {
+            return Err(DeepSceneError::CapacityExceeded(format!(
+                "'{}' ({}) cannot fit in TIFF strip {} (rows {}-{}) even at maximum compression: at least {} bytes needed, but the strip holds {}. Choose a carrier with more rows per strip",
+                options.file_path.display(),
+                format_bytes(file_size),
+                strip.index,
+                strip.first_row,
+                strip.first_row + strip.rows - 1,
+                minimum_payload,
+                capacity
+            )));
+        }
+
+        let required_bytes = SteganographyEngine::required_bytes(minimum_payload, params);
+        Err(DeepSceneError::CapacityExceeded(format!(
+            "'{}' ({}) cannot fit in a {}x{} image even at maximum compression: at least {} bytes needed. {}",
+            options.file_path.display(),
+            format_bytes(file_size),
+            width,
+            height,
+            minimum_payload,
+            SteganographyEngine::size_suggestion(width, height, required_bytes)
+        )))
+    }
+
+    /// The parts of the embedding layout that change how much a carrier
+    /// holds, for capacity checks made before the full parameters exist. A
+    /// TIFF strip is looked up in the carrier, which fails for a carrier that
+    /// is not a TIFF or has no such strip.
+    fn layout_params(options: &EncodeOptions) -> Result<EmbedParams> {
+        let tiff_strip = options
+            .tiff_strip
+            .map(|index| TiffStrip::read(&options.image_path, index))
+            .transpose()?;
+
+        Ok(EmbedParams {
+            compat: options.compat,
+            stride: options.stride,
+            channel_copies: options.channel_copies,
+            preserve_region: options.preserve_region,
+            format_version: options.format_version,
+            tiff_strip,
+            channel_bits: options.perceptual.then_some(PERCEPTUAL_CHANNEL_BITS),
+            ..EmbedParams::default()
+        })
+    }
+
+    /// Format version the payload is written in.
+    fn target_version(options: &EncodeOptions) -> u8 {
+        options.format_version.unwrap_or(FORMAT_VERSION)
+    }
+
+    /// Fails when an option needs a header or payload field the target
+    /// format version lacks, before anything is read or encrypted.
+    fn check_version_supports(options: &EncodeOptions) -> Result<()> {
+        SteganographyEngine::check_version_supports(&EmbedParams {
+            bit_plane: options.bit_plane,
+            ..Self::layout_params(options)?
+        })?;
+
+        let version = Self::target_version(options);
+        let unsupported = [
+            (
+                !options.metadata.is_empty(),
+                METADATA_TABLE_VERSION,
+                "--meta",
+            ),
+            (options.no_name, EXTENSION_VERSION, "--no-name"),
+            (
+                options.kdf != KdfAlgorithm::Argon2,
+                KDF_ID_VERSION,
+                "--kdf scrypt",
+            ),
+            (options.expires.is_some(), EXPIRY_VERSION, "--expires"),
+            (
+                options.encrypt_metadata,
+                PAYLOAD_FLAGS_VERSION,
+                "--encrypt-metadata",
+            ),
+            (options.hint.is_some(), HINT_VERSION, "--hint"),
+            (options.sign_key.is_some(), SIGNATURE_VERSION, "--sign-key"),
+            (
+                !options.recipient_passwords.is_empty(),
+                RECIPIENTS_VERSION,
+                "--recipient-password",
+            ),
+        ];
+
+        match unsupported
+            .into_iter()
+            .find(|&(used, minimum, _)| used && version < minimum)
+        {
+            Some((_, minimum, label)) => Err(DeepSceneError::Validation(
+                messages::version_lacks_feature(version, label, minimum),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn default_output_path(options: &EncodeOptions) -> PathBuf {
+        let stem = options
+            .image_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        // A strip only exists in a TIFF, and a DNG's sub-images and camera
+        // metadata only survive in a DNG, so those outputs keep the carrier's
+        // extension.
+        let ext = if options.raw_dimensions.is_some() {
+            "data"
+        } else if options.tiff_strip.is_some() || DngImage::is_dng_path(&options.image_path) {
+            options
+                .image_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("tiff")
+        } else {
+            "png"
+        };
+        options
+            .image_path
+            .with_file_name(format!("{}{}.{}", stem, options.output_suffix, ext))
+    }
+
+    /// Hidden sibling of the carrier, with the same extension so the output
+    /// format is unchanged, that an in-place encode writes first.
+    fn in_place_temp_path(carrier_path: &Path) -> PathBuf {
+        let name = carrier_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("carrier");
+        let ext = carrier_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        carrier_path.with_file_name(format!(".{}.deepscene-tmp.{}", name, ext))
+    }
+
+    fn print_planned_writes(converted_path: Option<&Path>, output_path: &Path) {
+        let action = |path: &Path| if path.exists() { "replace" } else { "create " };
+
+        message!("Planned file operations:");
+        if let Some(path) = converted_path {
+            message!(
+                "  {} '{}' (converted carrier)",
+                action(path),
+                path.display()
+            );
+        }
+        if FileHandler::is_stdout(output_path) {
+            message!("  write   standard output (output image)");
+        } else {
+            message!(
+                "  {} '{}' (output image)",
+                action(output_path),
+                output_path.display()
+            );
+        }
+        message!("");
+    }
+
+    /// Encode-side password policy, applied before any key derivation. Empty
+    /// passwords are still rejected by the crypto engine when the check is off.
+    pub(crate) fn check_password_length(password: Option<&str>, minimum: usize) -> Result<()> {
+        match password {
+            Some(pwd) if !pwd.is_empty() && pwd.chars().count() < minimum => {
+                Err(DeepSceneError::Validation(format!(
+                    "Password is {} characters long but at least {} are required. Choose a longer password, or lower the limit with --min-password-length (0 disables the check)",
+                    pwd.chars().count(),
+                    minimum
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn note_ignored_threads(threads: Option<usize>) {
+        if threads.is_some() && !parallel::is_enabled() {
+            message!("Note: --threads is ignored because this build lacks the rayon feature\n");
+        }
+    }
+
+    fn payload_metadata(file_name: &str, options: &EncodeOptions) -> PayloadMetadata {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        PayloadMetadata {
+            file_name: if options.no_name {
+                String::new()
+            } else {
+                file_name.to_string()
+            },
+            extension: extension.to_string(),
+            encrypted: Self::encrypts(options),
+            entries: options.metadata.clone(),
+            expires: options.expires,
+            fingerprint: None,
+            signature: None,
+        }
+    }
+
+    /// Bytes in front of the metadata: the payload flags and the hint.
+    fn clear_prefix_len(options: &EncodeOptions) -> usize {
+        1 + Self::hint_bytes(options).len()
+    }
+
+    /// The stored hint, or nothing for versions before `HINT_VERSION`.
+    fn hint_bytes(options: &EncodeOptions) -> Vec<u8> {
+        if Self::target_version(options) >= HINT_VERSION {
+            PayloadMetadata::hint_to_bytes(options.hint.as_deref())
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Frames processed file data as `[flags][hint][metadata][data]`,
+    /// encrypting the data when a password is set, or
+    /// `[flags][hint][encrypted metadata and data]` with `--encrypt-metadata`.
+    /// Also returns the salt and nonce used, if any.
+    fn build_payload(
+        processed_data: Vec<u8>,
+        compression_applied: bool,
+        file_name: &str,
+        fingerprint: Option<Fingerprint>,
+        signature: Option<PayloadSignature>,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<u8>, Option<[u8; CryptoEngine::SALT_NONCE_LEN]>)> {
+        let mut flags = if compression_applied {
+            COMPRESSED_FLAG
+        } else {
+            0
+        };
+
+        if !options.recipient_passwords.is_empty() {
+            flags |= RECIPIENTS_FLAG;
+        }
+
+        // In clear metadata, an encrypted file's fingerprint would let anyone
+        // holding a copy of the file confirm it is embedded, and its signature
+        // would do the same given the signer's public key.
+        let sealed = Self::seals_fingerprint(options);
+        let version = Self::target_version(options);
+        let metadata = PayloadMetadata {
+            fingerprint: fingerprint.filter(|_| !sealed),
+            signature: signature.filter(|_| !sealed),
+            ..Self::payload_metadata(file_name, options)
+        }
+        .to_bytes(version);
+        let processed_data = if sealed {
+            let prefix = PayloadMetadata::sealed_to_bytes(fingerprint, signature.as_ref(), version);
+            [prefix, processed_data].concat()
+        } else {
+            processed_data
+        };
+        let (clear_metadata, processed_data) = if options.encrypt_metadata {
+            flags |= ENCRYPTED_METADATA_FLAG;
+            (Vec::new(), [metadata, processed_data].concat())
+        } else {
+            (metadata, processed_data)
+        };
+
+        let mut final_payload = vec![flags];
+        final_payload.extend_from_slice(&Self::hint_bytes(options));
+        final_payload.extend_from_slice(&clear_metadata);
+
+        let (data_to_store, salt_nonce) = Self::encrypt_payload(processed_data, options)?;
+        final_payload.extend_from_slice(&data_to_store);
+
+        Ok((final_payload, salt_nonce))
+    }
+
+    /// Frames file data the way versions before
+    /// `COMPRESS_THEN_ENCRYPT_VERSION` did: the data is encrypted first, then
+    /// compressed together with the metadata in front of it, giving
+    /// `[compression flag][metadata and data]`, with no compression attempt
+    /// when the data is encrypted. Also returns the salt and nonce used, if
+    /// any, and whether compression was applied.
+    fn build_legacy_payload(
+        file_data: &[u8],
+        file_name: &str,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<u8>, Option<[u8; CryptoEngine::SALT_NONCE_LEN]>, bool)> {
+        let metadata =
+            Self::payload_metadata(file_name, options).to_bytes(Self::target_version(options));
+        let (data_to_store, salt_nonce) = Self::encrypt_payload(file_data.to_vec(), options)?;
+        let body = [metadata, data_to_store].concat();
+
+        // These versions compress after encrypting, and ciphertext never
+        // shrinks, so an encrypted payload is stored without a deflate pass.
+        let encrypted = Self::encrypts(options);
+        let (body, compressed) = if encrypted {
+            (body, false)
+        } else {
+            CompressionEngine::compress(&body)?
+        };
+
+        if encrypted {
+            progress!("      > Compression skipped (encrypted payload is incompressible).");
+        } else if compressed {
+            progress!(
+                "      > Compression applied: {} -> {}",
+                format_bytes(file_data.len()),
+                format_bytes(body.len())
+            );
+        } else {
+            progress!("      > Compression skipped: would not reduce size");
+        }
+
+        let mut final_payload = vec![if compressed { COMPRESSED_FLAG } else { 0 }];
+        final_payload.extend_from_slice(&body);
+
+        Ok((final_payload, salt_nonce, compressed))
+    }
+
+    /// Encrypts `data` as the options ask, returning it unchanged without a
+    /// password. Versions before `KDF_ID_VERSION` store no KDF identifier.
+    fn encrypt_payload(
+        data: Vec<u8>,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<u8>, Option<[u8; CryptoEngine::SALT_NONCE_LEN]>)> {
+        let mut rng = options.random.rng("encryption", 0);
+        let encrypted = if !options.recipient_passwords.is_empty() {
+            Self::timed_key_derivation("Encrypted", || {
+                CryptoEngine::encrypt_for_recipients(
+                    &data,
+                    &options.recipient_passwords,
+                    options.kdf,
+                    &mut rng,
+                )
+            })?
+        } else if let Some(ref pwd) = options.password {
+            match &options.salt_file {
+                Some(salt_path) => {
+                    let salt = Self::read_salt_file(salt_path)?;
+                    Self::timed_key_derivation("Encrypted", || {
+                        CryptoEngine::encrypt_with_salt(&data, pwd, options.kdf, &salt, &mut rng)
+                    })?
+                }
+                None => Self::timed_key_derivation("Encrypted", || {
+                    CryptoEngine::encrypt_with_rng(&data, pwd, options.kdf, &mut rng)
+                })?,
+            }
+        } else {
+            return Ok((data, None));
+        };
+
+        if options.password.is_none() {
+            return Ok((encrypted, None));
+        }
+
+        let salt_nonce = encrypted[CryptoEngine::KDF_ID_LEN..][..CryptoEngine::SALT_NONCE_LEN]
+            .try_into()
+            .ok();
+
+        if Self::target_version(options) < KDF_ID_VERSION {
+            Ok((encrypted[CryptoEngine::KDF_ID_LEN..].to_vec(), salt_nonce))
+        } else {
+            Ok((encrypted, salt_nonce))
+        }
+    }
+
+    /// The file to embed: `file_path`, or the clipboard text.
+    fn read_payload_file(options: &EncodeOptions) -> Result<FileData> {
+        if options.from_clipboard {
+            clipboard::read_text()
+        } else {
+            FileHandler::read_file(&options.file_path)
+        }
+    }
+
+    /// Embedded payload size for file data stored in `stored_len` bytes.
+    fn projected_payload_size(
+        file_name: &str,
+        stored_len: usize,
+        options: &EncodeOptions,
+    ) -> usize {
+        let version = Self::target_version(options);
+        let metadata = Self::payload_metadata(file_name, options);
+        let sealed = if Self::seals_fingerprint(options) {
+            PayloadMetadata::sealed_to_bytes(None, None, version).len()
+        } else {
+            0
+        };
+        let signature = if options.sign_key.is_some() {
+            PayloadSignature::LEN
+        } else {
+            0
+        };
+        Self::clear_prefix_len(options)
+            + metadata.to_bytes(version).len()
+            + sealed
+            + signature
+            + stored_len
+            + Self::encryption_overhead(options)
+    }
+
+    /// False for encrypted payloads of versions that compress after
+    /// encrypting, which cannot shrink.
+    fn compresses_before_encrypting(options: &EncodeOptions) -> bool {
+        Self::target_version(options) >= COMPRESS_THEN_ENCRYPT_VERSION || !Self::encrypts(options)
+    }
+
+    /// Whether encode encrypts, with one password or for several recipients.
+    fn encrypts(options: &EncodeOptions) -> bool {
+        options.password.is_some() || !options.recipient_passwords.is_empty()
+    }
+
+    /// Whether the fingerprint is stored in front of the encrypted file data
+    /// because the metadata it would otherwise go in is in the clear.
+    fn seals_fingerprint(options: &EncodeOptions) -> bool {
+        Self::encrypts(options)
+            && !options.encrypt_metadata
+            && Self::target_version(options) >= SEALED_FINGERPRINT_VERSION
+    }
+
+    /// Bytes encryption adds to the payload.
+    fn encryption_overhead(options: &EncodeOptions) -> usize {
+        match options.recipient_passwords.len() {
+            0 if options.password.is_some() && Self::target_version(options) < KDF_ID_VERSION => {
+                CryptoEngine::OVERHEAD - CryptoEngine::KDF_ID_LEN
+            }
+            0 if options.password.is_some() => CryptoEngine::OVERHEAD,
+            0 => 0,
+            recipients => CryptoEngine::envelope_overhead(recipients),
+        }
+    }
+
+    /// Reads a salt managed outside DeepScene, which must be exactly
+    /// `CryptoEngine::SALT_LEN` raw bytes.
+    pub(crate) fn read_salt_file(path: &Path) -> Result<[u8; CryptoEngine::SALT_LEN]> {
+        let salt = FileHandler::read_file(path)?;
+
+        salt.data.as_slice().try_into().map_err(|_| {
+            DeepSceneError::Validation(format!(
+                "Salt file '{}' must contain exactly {} bytes, but has {}",
+                path.display(),
+                CryptoEngine::SALT_LEN,
+                salt.data.len()
+            ))
+        })
+    }
+
+    /// Key derivation (Argon2 or scrypt) dominates encryption and decryption time and can
+    /// take seconds with high cost parameters, so announce it and report how
+    /// long it took rather than appearing hung.
+    fn timed_key_derivation<T>(action: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        progress!("      > Deriving key (this may take a while)...");
+        let started = Instant::now();
+        let result = f()?;
+        progress!(
+            "      > {} in {}",
+            action,
+            format_duration(started.elapsed())
+        );
+        Ok(result)
+    }
+
+    /// Expiry is advisory: it is stored unencrypted and anyone can strip it,
+    /// so by default decode only warns.
+    fn check_expiry(expires: Option<u64>, enforce: bool) -> Result<()> {
+        Self::check_expiry_at(expires, enforce, timestamp::now())
+    }
+
+    /// `check_expiry` at time `now`. A payload expires at the start of the
+    /// second its expiry names.
+    fn check_expiry_at(expires: Option<u64>, enforce: bool, now: u64) -> Result<()> {
+        let Some(expires) = expires else {
+            return Ok(());
+        };
+
+        if now < expires {
+            return Ok(());
+        }
+
+        let expiry = timestamp::format_timestamp(expires);
+        if enforce {
+            return Err(DeepSceneError::Expired(format!(
+                "Payload expired on {}. Remove --enforce-expiry to extract it anyway",
+                expiry
+            )));
+        }
+
+        warning!("payload expired on {}\n", expiry)
+    }
+
+    /// Splits the leading payload byte into the compression flag (`1` when
+    /// compressed), whether the metadata is encrypted and whether encryption
+    /// is for several recipients. Before `PAYLOAD_FLAGS_VERSION` the byte is
+    /// the compression flag itself.
+    fn payload_flags(byte: u8, version: u8) -> (u8, bool, bool) {
+        if version >= PAYLOAD_FLAGS_VERSION {
+            (
+                byte & COMPRESSED_FLAG,
+                byte & ENCRYPTED_METADATA_FLAG != 0,
+                version >= RECIPIENTS_VERSION && byte & RECIPIENTS_FLAG != 0,
+            )
+        } else {
+            (byte, false, false)
+        }
+    }
+
+    fn decompress_data(data: &[u8], compression_flag: u8) -> Result<Vec<u8>> {
+        let decompressed_data = if compression_flag == 1 {
+            let decompressed = CompressionEngine::decompress(data)?;
+            progress!(
+                "      > Decompressed: {} -> {}",
+                format_bytes(data.len()),
+                format_bytes(decompressed.len())
+            );
+            decompressed
+        } else {
+            progress!("      > No compression detected");
+            data.to_vec()
+        };
+
+        if decompressed_data.is_empty() {
+            return Err(DeepSceneError::Data("Processed data is empty".to_string()));
+        }
+
+        Ok(decompressed_data)
+    }
+
+    /// Tries each candidate with `decrypt` until one authenticates, taking
+    /// at least `PASSWORD_ATTEMPT_INTERVAL` per attempt. Reports the matching
+    /// candidate by position, not by value, so it stays out of logs.
+    fn try_password_list(
+        candidates: &[String],
+        decrypt: impl Fn(&str) -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        let total = candidates.len();
+        let started = Instant::now();
+
+        for (index, candidate) in candidates.iter().enumerate() {
+            let attempt_started = Instant::now();
+            progress!("      > Trying password {}/{}...", index + 1, total);
+
+            match decrypt(candidate) {
+                Ok(decrypted) => {
+                    message!(
+                        "Password found: candidate {} of {} in the list, after {}",
+                        index + 1,
+                        total,
+                        format_duration(started.elapsed())
+                    );
+                    return Ok(decrypted);
+                }
+                Err(DeepSceneError::WrongPassword(_)) => {}
+                Err(e) => return Err(e),
+            }
+
+            if index + 1 < total
+                && let Some(rest) = PASSWORD_ATTEMPT_INTERVAL.checked_sub(attempt_started.elapsed())
+            {
+                thread::sleep(rest);
+            }
+        }
+
+        Err(DeepSceneError::WrongPassword(format!(
+            "None of the {} passwords in the list decrypts this payload",
+            total
+        )))
+    }
+
+    fn decrypt_data(
+        data: &[u8],
+        encrypted: bool,
+        recipients: bool,
+        password: &Option<String>,
+        password_list: &[String],
+        hint: Option<&str>,
+        version: u8,
+    ) -> Result<Vec<u8>> {
+        let with_hint = |message: String| match hint {
+            Some(hint) => format!("{}\nHint: {}", message, hint),
+            None => message,
+        };
+        let decrypt = |pwd: &str| {
+            if recipients {
+                CryptoEngine::decrypt_envelope(data, pwd)
+            } else if version >= KDF_ID_VERSION {
+                CryptoEngine::decrypt(data, pwd)
+            } else {
+                CryptoEngine::decrypt_untagged(data, pwd)
+            }
+        };
+        let hinted = |e| match e {
+            DeepSceneError::WrongPassword(message) => {
+                DeepSceneError::WrongPassword(with_hint(message))
+            }
+            e => e,
+        };
+
+        if encrypted {
+            match password {
+                Some(pwd) => Self::timed_key_derivation("Decrypted", || decrypt(pwd))
+                    .map_err(hinted),
+                None if !password_list.is_empty() => {
+                    Self::try_password_list(password_list, decrypt).map_err(hinted)
+                }
+                None => Err(DeepSceneError::PasswordRequired(with_hint(
+                    "File is password-protected. Please provide the decryption password using -p or --password flag".to_string()
+                ))),
+            }
+        } else {
+            Ok(data.to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEADLINE: u64 = 1_767_225_600;
+
+    #[test]
+    fn expiry_boundary() {
+        let expired = |now| {
+            matches!(
+                DataProcessor::check_expiry_at(Some(DEADLINE), true, now),
+                Err(DeepSceneError::Expired(_))
+            )
+        };
+
+        assert!(!expired(DEADLINE - 1));
+        assert!(expired(DEADLINE));
+        assert!(expired(DEADLINE + 1));
+    }
+
+    #[test]
+    fn expiry_is_advisory_unless_enforced() {
+        assert!(DataProcessor::check_expiry_at(Some(DEADLINE), false, DEADLINE + 1).is_ok());
+        assert!(DataProcessor::check_expiry_at(None, true, u64::MAX).is_ok());
     }
 }
@@ -0,0 +1,163 @@
+use crate::core::{DeepSceneError, Result};
+
+/// Naming used by batch encode when no template is given.
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "{stem}-edited-{index}.png";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// Carrier file name without its extension.
+    Stem,
+    /// Carrier extension, without the dot.
+    Ext,
+    /// 1-based position of the file in the batch.
+    Index,
+    /// File name of the embedded payload.
+    Name,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Field),
+}
+
+/// Values substituted into an `OutputTemplate` for one batch entry.
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateValues<'a> {
+    pub stem: &'a str,
+    pub ext: &'a str,
+    pub index: usize,
+    pub name: &'a str,
+}
+
+/// Output file name pattern such as `{stem}_hidden_{index}.png`. Parsed once,
+/// so unknown placeholders are reported before any file is processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    segments: Vec<Segment>,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            DeepSceneError::Validation(format!(
+                "Invalid output template '{}': {}. Supported placeholders are {{stem}}, {{ext}}, {{index}} and {{name}}",
+                template, reason
+            ))
+        };
+
+        if template.contains(['/', '\\']) {
+            return Err(invalid("it must be a file name, not a path".to_string()));
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while !rest.is_empty() {
+            match rest.find(['{', '}']) {
+                Some(position) if rest[position..].starts_with('}') => {
+                    return Err(invalid("unmatched '}'".to_string()));
+                }
+                Some(position) => {
+                    if position > 0 {
+                        segments.push(Segment::Literal(rest[..position].to_string()));
+                    }
+
+                    let after = &rest[position + 1..];
+                    let end = after
+                        .find('}')
+                        .ok_or_else(|| invalid("unclosed '{'".to_string()))?;
+                    let field = match &after[..end] {
+                        "stem" => Field::Stem,
+                        "ext" => Field::Ext,
+                        "index" => Field::Index,
+                        "name" => Field::Name,
+                        other => {
+                            return Err(invalid(format!("unknown placeholder '{{{}}}'", other)));
+                        }
+                    };
+                    segments.push(Segment::Placeholder(field));
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    segments.push(Segment::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(invalid("it is empty".to_string()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn expand(&self, values: TemplateValues) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Placeholder(Field::Stem) => values.stem.to_string(),
+                Segment::Placeholder(Field::Ext) => values.ext.to_string(),
+                Segment::Placeholder(Field::Index) => values.index.to_string(),
+                Segment::Placeholder(Field::Name) => values.name.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: TemplateValues = TemplateValues {
+        stem: "beach",
+        ext: "jpg",
+        index: 7,
+        name: "notes.txt",
+    };
+
+    fn expand(template: &str) -> String {
+        OutputTemplate::parse(template).unwrap().expand(VALUES)
+    }
+
+    fn rejected(template: &str) -> bool {
+        matches!(
+            OutputTemplate::parse(template),
+            Err(DeepSceneError::Validation(_))
+        )
+    }
+
+    #[test]
+    fn expands_each_placeholder() {
+        assert_eq!(expand("{stem}"), "beach");
+        assert_eq!(expand("{ext}"), "jpg");
+        assert_eq!(expand("{index}"), "7");
+        assert_eq!(expand("{name}"), "notes.txt");
+    }
+
+    #[test]
+    fn expands_placeholders_among_literals() {
+        assert_eq!(expand("{stem}_hidden_{index}.png"), "beach_hidden_7.png");
+        assert_eq!(expand("{name}.{ext}.{stem}{index}"), "notes.txt.jpg.beach7");
+        assert_eq!(expand("fixed.png"), "fixed.png");
+        assert_eq!(expand(DEFAULT_OUTPUT_TEMPLATE), "beach-edited-7.png");
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(rejected("{stem}_{date}.png"));
+        assert!(rejected("{STEM}.png"));
+        assert!(rejected("{}.png"));
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(rejected(""));
+        assert!(rejected("{stem.png"));
+        assert!(rejected("stem}.png"));
+        assert!(rejected("out/{stem}.png"));
+        assert!(rejected("out\\{stem}.png"));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::core::{DeepSceneError, Result};
+use crate::io::FileHandler;
+use crate::processor::{DataProcessor, DecodeOptions};
+use crate::progress;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct VerifyOptions {
+    pub input_dir: PathBuf,
+    pub password: Option<String>,
+    pub recursive: bool,
+    pub threads: Option<usize>,
+}
+
+/// How much of a payload could be checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyLevel {
+    /// Header checksum, chunk CRCs, decryption and decompression all passed.
+    Full,
+    /// The payload is encrypted and no password was given, so only the
+    /// header checksum and chunk CRCs were checked.
+    ChecksumsOnly,
+}
+
+#[derive(Debug)]
+pub struct VerifyEntry {
+    pub image_path: PathBuf,
+    pub result: Result<VerifyLevel>,
+}
+
+#[derive(Debug)]
+pub struct VerifyReport {
+    pub entries: Vec<VerifyEntry>,
+}
+
+impl VerifyReport {
+    pub fn passed(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries.len() - self.passed()
+    }
+
+    pub fn checksums_only(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.result, Ok(VerifyLevel::ChecksumsOnly)))
+            .count()
+    }
+}
+
+pub struct VerifyProcessor;
+
+impl VerifyProcessor {
+    /// Extracts every image in the directory without writing anything,
+    /// carrying on past failures so one damaged image does not hide the rest.
+    pub fn verify_all(options: VerifyOptions) -> Result<VerifyReport> {
+        let images: Vec<PathBuf> = FileHandler::list_files(&options.input_dir, options.recursive)?
+            .into_iter()
+            .filter(|p| image::ImageFormat::from_path(p).is_ok())
+            .collect();
+        if images.is_empty() {
+            return Err(DeepSceneError::Validation(format!(
+                "No images found in '{}'",
+                options.input_dir.display()
+            )));
+        }
+
+        let total = images.len();
+        let mut entries = Vec::with_capacity(total);
+
+        for (index, image_path) in images.into_iter().enumerate() {
+            progress!(
+                "=== [{}/{}] '{}' ===\n",
+                index + 1,
+                total,
+                image_path.display()
+            );
+
+            let decode_options = DecodeOptions {
+                image_path: image_path.clone(),
+                output_path: None,
+                password: options.password.clone(),
+                password_list: Vec::new(),
+                threads: options.threads,
+                raw_dimensions: None,
+                compat: None,
+                enforce_expiry: false,
+                consume: false,
+                recover: false,
+                check: true,
+                to_clipboard: false,
+                verify_after_write: false,
+                verify_key: None,
+            };
+
+            let result = match DataProcessor::extract_payload(&decode_options) {
+                Ok(_) => Ok(VerifyLevel::Full),
+                // Raised only once the header and chunk CRCs have passed.
+                Err(DeepSceneError::PasswordRequired(_)) => Ok(VerifyLevel::ChecksumsOnly),
+                Err(e) => Err(e),
+            };
+
+            if let Err(ref e) = result {
+                progress!("      > Failed: {}\n", e);
+            }
+
+            entries.push(VerifyEntry { image_path, result });
+        }
+
+        Ok(VerifyReport { entries })
+    }
+}
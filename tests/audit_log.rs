@@ -0,0 +1,139 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct-horse-battery-staple";
+const WRONG_PASSWORD: &str = "tr0ub4dor-and-3-more";
+const HINT: &str = "the stable in the garden";
+const SECRET: &[u8] = b"launch codes: 0000-1111-2222";
+
+const KEYS: [&str; 12] = [
+    "timestamp",
+    "command",
+    "input",
+    "file",
+    "output",
+    "file_size",
+    "payload_size",
+    "encrypted",
+    "compressed",
+    "success",
+    "error_kind",
+    "error_message",
+];
+
+fn deepscene(log: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_deepscene"))
+        .arg("--audit-log")
+        .arg(log)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// The value of `key` in a log line, as its raw JSON text.
+fn field<'a>(line: &'a str, key: &str) -> &'a str {
+    let start = line
+        .find(&format!("\"{}\":", key))
+        .unwrap_or_else(|| panic!("no {} in {}", key, line))
+        + key.len()
+        + 3;
+    let rest = &line[start..];
+    let end = if let Some(string) = rest.strip_prefix('"') {
+        string.find('"').unwrap() + 2
+    } else {
+        rest.find([',', '}']).unwrap()
+    };
+    &rest[..end]
+}
+
+#[test]
+fn log_records_each_operation_without_secrets() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    let carrier = common::save_carrier(root, "carrier.png", 64, 48);
+    let secret = root.join("secret.txt");
+    fs::write(&secret, SECRET).unwrap();
+    let stego = root.join("stego.png");
+    let extracted = root.join("extracted");
+    let log = root.join("audit.jsonl");
+
+    let encode = deepscene(
+        &log,
+        &[
+            "encode",
+            carrier.to_str().unwrap(),
+            secret.to_str().unwrap(),
+            "-o",
+            stego.to_str().unwrap(),
+            "--password",
+            PASSWORD,
+            "--hint",
+            HINT,
+        ],
+    );
+    assert!(encode.status.success(), "{:?}", encode);
+
+    let wrong = deepscene(
+        &log,
+        &[
+            "decode",
+            stego.to_str().unwrap(),
+            "-o",
+            extracted.to_str().unwrap(),
+            "--password",
+            WRONG_PASSWORD,
+        ],
+    );
+    assert!(!wrong.status.success());
+
+    let decode = deepscene(
+        &log,
+        &[
+            "decode",
+            stego.to_str().unwrap(),
+            "-o",
+            extracted.to_str().unwrap(),
+            "--password",
+            PASSWORD,
+        ],
+    );
+    assert!(decode.status.success(), "{:?}", decode);
+
+    let contents = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", contents);
+
+    for line in &lines {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+        let positions: Vec<usize> = KEYS
+            .iter()
+            .map(|key| line.find(&format!("\"{}\":", key)).unwrap())
+            .collect();
+        assert!(positions.is_sorted(), "keys out of order in {}", line);
+    }
+
+    assert_eq!(field(lines[0], "command"), "\"encode\"");
+    assert_eq!(field(lines[0], "encrypted"), "true");
+    assert_eq!(field(lines[0], "success"), "true");
+    assert_eq!(field(lines[0], "error_kind"), "null");
+    assert_eq!(
+        field(lines[0], "file_size"),
+        SECRET.len().to_string().as_str()
+    );
+
+    assert_eq!(field(lines[1], "command"), "\"decode\"");
+    assert_eq!(field(lines[1], "success"), "false");
+    assert_ne!(field(lines[1], "error_kind"), "null");
+    assert_eq!(field(lines[1], "error_message"), "null");
+
+    assert_eq!(field(lines[2], "command"), "\"decode\"");
+    assert_eq!(field(lines[2], "success"), "true");
+
+    for secret in [PASSWORD, WRONG_PASSWORD, HINT, "launch codes"] {
+        assert!(!contents.contains(secret), "{:?} in {}", secret, contents);
+    }
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use deepscene::core::KdfAlgorithm;
+use deepscene::processor::DEFAULT_MIN_PASSWORD_LENGTH;
+use deepscene::processor::batch::{BatchOptions, BatchProcessor, MANIFEST_NAME, PairingStrategy};
+use deepscene::processor::output_template::DEFAULT_OUTPUT_TEMPLATE;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn batch_options(root: &Path, resume: bool) -> BatchOptions {
+    BatchOptions {
+        input_dir: root.join("files"),
+        carrier_dir: root.join("carriers"),
+        output_dir: root.join("out"),
+        password: None,
+        kdf: KdfAlgorithm::default(),
+        min_password_length: DEFAULT_MIN_PASSWORD_LENGTH,
+        encrypt_metadata: false,
+        bit_plane: 0,
+        salt_file: None,
+        lsb_matching: false,
+        carrier_noise: false,
+        match_size: false,
+        confirm_convert: false,
+        max_output_size: None,
+        size_bucket: None,
+        preserve_exif: false,
+        preserve_dpi: false,
+        metadata: Vec::new(),
+        expires: None,
+        hint: None,
+        threads: None,
+        pairing: PairingStrategy::RoundRobin,
+        output_template: DEFAULT_OUTPUT_TEMPLATE.to_string(),
+        resume,
+    }
+}
+
+/// Creates the input, carrier and output directories with one small carrier.
+fn set_up(root: &Path) {
+    for name in ["files", "carriers", "out"] {
+        fs::create_dir(root.join(name)).unwrap();
+    }
+    common::save_carrier(&root.join("carriers"), "carrier.png", 48, 48);
+}
+
+/// Bytes that do not compress, too many of them for the carrier.
+fn incompressible() -> Vec<u8> {
+    (0..4000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect()
+}
+
+#[test]
+fn resume_skips_entries_finished_before_a_failure() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    set_up(root);
+
+    // The middle file does not fit, so the run fails partway, after the
+    // first entry is done.
+    let random = incompressible();
+    fs::write(root.join("files/a.txt"), common::payload(200)).unwrap();
+    fs::write(root.join("files/b.bin"), &random).unwrap();
+    fs::write(root.join("files/c.txt"), common::payload(300)).unwrap();
+
+    let first = BatchProcessor::encode(batch_options(root, false)).unwrap();
+    assert_eq!(first.succeeded(), 2);
+    assert_eq!(first.failed(), 1);
+    assert!(first.entries[1].result.is_err());
+    assert!(root.join("out").join(MANIFEST_NAME).is_file());
+
+    let finished = first.entries[0].output_path.clone();
+    let finished_bytes = fs::read(&finished).unwrap();
+
+    fs::write(root.join("files/b.bin"), &random[..200]).unwrap();
+    let second = BatchProcessor::encode(batch_options(root, true)).unwrap();
+
+    assert_eq!(second.failed(), 0);
+    assert_eq!(second.skipped(), 2);
+    assert!(matches!(second.entries[0].result, Ok(None)));
+    assert!(matches!(second.entries[1].result, Ok(Some(_))));
+    assert!(matches!(second.entries[2].result, Ok(None)));
+    assert_eq!(fs::read(&finished).unwrap(), finished_bytes);
+    assert!(!root.join("out").join(MANIFEST_NAME).exists());
+}
+
+#[test]
+fn changed_file_is_not_skipped() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    set_up(root);
+    fs::write(root.join("files/a.txt"), common::payload(200)).unwrap();
+    fs::write(root.join("files/b.bin"), incompressible()).unwrap();
+
+    let first = BatchProcessor::encode(batch_options(root, false)).unwrap();
+    assert_eq!(first.failed(), 1);
+
+    // The manifest is keyed on the file hash, so editing a finished file
+    // makes resume encode it again.
+    fs::write(root.join("files/a.txt"), common::payload(201)).unwrap();
+    let second = BatchProcessor::encode(batch_options(root, true)).unwrap();
+    assert!(matches!(second.entries[0].result, Ok(Some(_))));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use deepscene::core::RandomSource;
+use deepscene::processor::{DataProcessor, DecodeOptions, EncodeOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct-horse-battery-staple";
+
+struct Fixture {
+    dir: TempDir,
+    carrier: PathBuf,
+    file: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+        let file = dir.path().join("secret.txt");
+        fs::write(&file, common::payload(700)).unwrap();
+        Self { dir, carrier, file }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Encodes with every randomized option on, drawing from `seed`, and
+    /// returns the bytes written.
+    fn encode(&self, output: &str, seed: &[u8], threads: Option<usize>) -> Vec<u8> {
+        let output = self.path(output);
+        DataProcessor::encode(EncodeOptions {
+            password: Some(PASSWORD.to_string()),
+            random: RandomSource::seeded(seed),
+            lsb_matching: true,
+            carrier_noise: true,
+            canonical_output: true,
+            threads,
+            ..common::encode_options(&self.file, &self.carrier, &output)
+        })
+        .unwrap();
+        fs::read(output).unwrap()
+    }
+}
+
+#[test]
+fn seeded_canonical_encodes_are_byte_identical() {
+    let fixture = Fixture::new();
+
+    let first = fixture.encode("first.png", b"fixture seed", None);
+    let second = fixture.encode("second.png", b"fixture seed", None);
+    let single_threaded = fixture.encode("third.png", b"fixture seed", Some(1));
+
+    assert_eq!(first, second);
+    assert_eq!(first, single_threaded);
+
+    let decoded = fixture.path("decoded.txt");
+    DataProcessor::decode(DecodeOptions {
+        password: Some(PASSWORD.to_string()),
+        ..common::decode_options(&fixture.path("first.png"), &decoded)
+    })
+    .unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), fs::read(&fixture.file).unwrap());
+}
+
+#[test]
+fn another_seed_gives_different_bytes() {
+    let fixture = Fixture::new();
+
+    let first = fixture.encode("first.png", b"fixture seed", None);
+    let other = fixture.encode("other.png", b"another seed", None);
+
+    assert_ne!(first, other);
+}
+
+#[test]
+fn seed_file_and_canonical_output_are_reproducible_from_the_cli() {
+    let fixture = Fixture::new();
+    let seed = fixture.path("seed");
+    fs::write(&seed, b"cli seed").unwrap();
+
+    let encode = |output: &Path| {
+        let result = Command::new(env!("CARGO_BIN_EXE_deepscene"))
+            .arg("encode")
+            .arg(&fixture.carrier)
+            .arg(&fixture.file)
+            .arg("-o")
+            .arg(output)
+            .args([
+                "--password",
+                PASSWORD,
+                "--lsb-matching",
+                "--canonical-output",
+            ])
+            .arg("--seed-file")
+            .arg(&seed)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{:?}", result);
+        fs::read(output).unwrap()
+    };
+
+    assert_eq!(
+        encode(&fixture.path("first.png")),
+        encode(&fixture.path("second.png"))
+    );
+}
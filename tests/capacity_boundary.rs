@@ -0,0 +1,176 @@
+//! A payload of exactly `payload_capacity` bytes must embed and extract in
+//! every layout, and one byte more must be rejected by encode rather than
+//! by decode.
+
+mod common;
+
+use deepscene::core::steganography::PERCEPTUAL_CHANNEL_BITS;
+use deepscene::core::{
+    CompatSpec, DeepSceneError, EmbedParams, ExtractedData, Region, SteganographyEngine,
+};
+use std::path::Path;
+use tempfile::TempDir;
+
+const WIDTH: u32 = 61;
+const HEIGHT: u32 = 47;
+
+fn embed(carrier: &Path, len: usize, params: &EmbedParams) -> deepscene::core::Result<Vec<u8>> {
+    SteganographyEngine::hide_data_to_bytes(carrier, &common::payload(len), "png", params)
+}
+
+fn extract(encoded: Vec<u8>) -> ExtractedData {
+    SteganographyEngine::extract_data_from_memory(encoded, None, Path::new("encoded")).unwrap()
+}
+
+fn check_boundary(name: &str, params: EmbedParams) {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", WIDTH, HEIGHT);
+    let capacity = SteganographyEngine::payload_capacity(WIDTH, HEIGHT, &params);
+
+    let encoded = embed(&carrier, capacity, &params)
+        .unwrap_or_else(|e| panic!("{}: {} bytes at capacity rejected: {}", name, capacity, e));
+    assert_eq!(
+        extract(encoded).data,
+        common::payload(capacity),
+        "{}: payload at capacity",
+        name
+    );
+
+    assert!(
+        matches!(
+            embed(&carrier, capacity + 1, &params),
+            Err(DeepSceneError::CapacityExceeded(_))
+        ),
+        "{}: {} bytes past capacity accepted",
+        name,
+        capacity + 1
+    );
+}
+
+#[test]
+fn contiguous() {
+    check_boundary("contiguous", EmbedParams::default());
+}
+
+#[test]
+fn bit_planes() {
+    for bit_plane in [1, 7] {
+        check_boundary(
+            &format!("bit plane {}", bit_plane),
+            EmbedParams {
+                bit_plane,
+                ..EmbedParams::default()
+            },
+        );
+    }
+}
+
+#[test]
+fn strides() {
+    for stride in [2, 3, 5] {
+        check_boundary(
+            &format!("stride {}", stride),
+            EmbedParams {
+                stride,
+                ..EmbedParams::default()
+            },
+        );
+    }
+}
+
+#[test]
+fn channel_copies() {
+    check_boundary(
+        "channel copies",
+        EmbedParams {
+            channel_copies: true,
+            ..EmbedParams::default()
+        },
+    );
+}
+
+#[test]
+fn perceptual_channel_bits() {
+    check_boundary(
+        "perceptual",
+        EmbedParams {
+            channel_bits: Some(PERCEPTUAL_CHANNEL_BITS),
+            ..EmbedParams::default()
+        },
+    );
+}
+
+#[test]
+fn carrier_noise_and_lsb_matching() {
+    check_boundary(
+        "carrier noise",
+        EmbedParams {
+            carrier_noise: true,
+            ..EmbedParams::default()
+        },
+    );
+    check_boundary(
+        "lsb matching",
+        EmbedParams {
+            lsb_matching: true,
+            ..EmbedParams::default()
+        },
+    );
+}
+
+#[test]
+fn older_format_versions() {
+    for version in [1, 2, 5, 6, 14] {
+        check_boundary(
+            &format!("format version {}", version),
+            EmbedParams {
+                format_version: Some(version),
+                ..EmbedParams::default()
+            },
+        );
+    }
+}
+
+#[test]
+fn preserved_region_fits_at_capacity() {
+    // Capacity around a region is a lower bound, since the header's share of
+    // the free slots depends on where the region falls, so only the fitting
+    // side is exact.
+    let params = EmbedParams {
+        preserve_region: Some(Region {
+            x: 10,
+            y: 5,
+            width: 20,
+            height: 15,
+        }),
+        ..EmbedParams::default()
+    };
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", WIDTH, HEIGHT);
+    let capacity = SteganographyEngine::payload_capacity(WIDTH, HEIGHT, &params);
+
+    let encoded = embed(&carrier, capacity, &params).unwrap();
+    assert_eq!(extract(encoded).data, common::payload(capacity));
+}
+
+#[test]
+fn compat_plain_lsb() {
+    let params = EmbedParams {
+        compat: Some(CompatSpec::PlainLsb),
+        ..EmbedParams::default()
+    };
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", WIDTH, HEIGHT);
+    let capacity = SteganographyEngine::payload_capacity(WIDTH, HEIGHT, &params);
+
+    let encoded = embed(&carrier, capacity, &params).unwrap();
+    let image = image::load_from_memory(&encoded).unwrap().to_rgba8();
+    assert_eq!(
+        SteganographyEngine::extract_compat(&image, CompatSpec::PlainLsb).unwrap(),
+        common::payload(capacity)
+    );
+    assert!(matches!(
+        embed(&carrier, capacity + 1, &params),
+        Err(DeepSceneError::CapacityExceeded(_))
+    ));
+}
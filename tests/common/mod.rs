@@ -0,0 +1,105 @@
+//! Carriers and payloads shared by the integration tests.
+
+#![allow(dead_code)]
+
+use deepscene::core::{KdfAlgorithm, RandomSource};
+use deepscene::io::OverwritePolicy;
+use deepscene::processor::{
+    DEFAULT_MIN_PASSWORD_LENGTH, DEFAULT_OUTPUT_SUFFIX, DecodeOptions, EncodeOptions,
+};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// A carrier whose low bits vary from pixel to pixel, so a payload that was
+/// not written or read back correctly cannot match by accident.
+pub fn carrier(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([
+            (x * 7 + y * 3) as u8,
+            (x * 3 + y * 5 + 17) as u8,
+            (x ^ y).wrapping_mul(11) as u8,
+            255,
+        ])
+    })
+}
+
+/// Saves `carrier(width, height)` without its alpha channel as `name` in
+/// `dir`, in the format its extension names.
+pub fn save_carrier(dir: &Path, name: &str, width: u32, height: u32) -> PathBuf {
+    let path = dir.join(name);
+    DynamicImage::ImageRgba8(carrier(width, height))
+        .to_rgb8()
+        .save(&path)
+        .unwrap();
+    path
+}
+
+/// Payload bytes that are neither constant nor a simple counter.
+pub fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 7 + 3) as u8).collect()
+}
+
+/// Encode options for embedding `file_path` into `image_path`, with every
+/// optional feature off. Tests change the fields they exercise.
+pub fn encode_options(file_path: &Path, image_path: &Path, output_path: &Path) -> EncodeOptions {
+    EncodeOptions {
+        file_path: file_path.to_path_buf(),
+        from_clipboard: false,
+        image_path: image_path.to_path_buf(),
+        output_path: Some(output_path.to_path_buf()),
+        password: None,
+        recipient_passwords: Vec::new(),
+        kdf: KdfAlgorithm::default(),
+        min_password_length: DEFAULT_MIN_PASSWORD_LENGTH,
+        encrypt_metadata: false,
+        bit_plane: 0,
+        random: RandomSource::default(),
+        salt_file: None,
+        lsb_matching: false,
+        carrier_noise: false,
+        stride: 1,
+        channel_copies: false,
+        perceptual: false,
+        preserve_region: None,
+        match_size: false,
+        confirm_convert: false,
+        max_output_size: None,
+        size_bucket: None,
+        canonical_output: false,
+        preserve_exif: false,
+        preserve_dpi: false,
+        no_name: false,
+        metadata: Vec::new(),
+        threads: None,
+        raw_dimensions: None,
+        compat: None,
+        overwrite_policy: OverwritePolicy::Overwrite,
+        output_suffix: DEFAULT_OUTPUT_SUFFIX.to_string(),
+        in_place: false,
+        expires: None,
+        hint: None,
+        format_version: None,
+        tiff_strip: None,
+        sign_key: None,
+    }
+}
+
+/// Decode options for extracting from `image_path` to `output_path`.
+pub fn decode_options(image_path: &Path, output_path: &Path) -> DecodeOptions {
+    DecodeOptions {
+        image_path: image_path.to_path_buf(),
+        output_path: Some(output_path.to_path_buf()),
+        password: None,
+        password_list: Vec::new(),
+        threads: None,
+        raw_dimensions: None,
+        compat: None,
+        enforce_expiry: false,
+        consume: false,
+        recover: false,
+        check: false,
+        to_clipboard: false,
+        verify_after_write: false,
+        verify_key: None,
+    }
+}
@@ -0,0 +1,93 @@
+//! `decode --all` writes each payload into the current directory, whatever
+//! name the image stores for it.
+
+mod common;
+
+use deepscene::core::{DeepSceneError, EmbedParams, SteganographyEngine};
+use deepscene::processor::{DataProcessor, DecodeAllOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+const CONTENTS: &[u8] = b"recovered contents";
+
+/// Encodes a file whose name is as long as `name`, then rewrites the stored
+/// name to `name` and embeds the payload again with fresh checksums, as a
+/// crafted image would store it.
+fn image_storing_name(dir: &Path, name: &str) -> PathBuf {
+    let carrier = common::save_carrier(dir, "carrier.png", 96, 64);
+    let placeholder = "Q".repeat(name.len());
+    let file = dir.join(&placeholder);
+    fs::write(&file, CONTENTS).unwrap();
+    let stego = dir.join("stego.png");
+    DataProcessor::encode(common::encode_options(&file, &carrier, &stego)).unwrap();
+    fs::remove_file(&file).unwrap();
+
+    let mut payload = SteganographyEngine::extract_data(&stego).unwrap().data;
+    let stored = [&[name.len() as u8], placeholder.as_bytes()].concat();
+    let start = payload
+        .windows(stored.len())
+        .position(|window| window == stored)
+        .unwrap()
+        + 1;
+    payload[start..start + name.len()].copy_from_slice(name.as_bytes());
+
+    let crafted = dir.join("crafted.png");
+    SteganographyEngine::hide_data(&stego, &payload, &crafted, &EmbedParams::default()).unwrap();
+    crafted
+}
+
+#[test]
+fn stored_paths_are_reduced_to_their_file_name() {
+    let dir = TempDir::new().unwrap();
+    let absolute = dir.path().join("absolute.txt");
+    let names = [
+        ("../escaped.txt", "escaped.txt"),
+        (absolute.to_str().unwrap(), "absolute.txt"),
+    ];
+
+    for (name, written) in names {
+        let image = image_storing_name(dir.path(), name);
+        let work = dir.path().join("work");
+        fs::create_dir(&work).unwrap();
+
+        let result = Command::new(env!("CARGO_BIN_EXE_deepscene"))
+            .current_dir(&work)
+            .arg("decode")
+            .arg(&image)
+            .arg("--all")
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{}: {:?}", name, result);
+
+        assert_eq!(fs::read(work.join(written)).unwrap(), CONTENTS, "{}", name);
+        assert!(!dir.path().join(written).exists(), "{}", name);
+        fs::remove_dir_all(&work).unwrap();
+    }
+}
+
+#[test]
+fn stored_names_without_a_file_name_are_rejected() {
+    for name in [".", ".."] {
+        let dir = TempDir::new().unwrap();
+        let image = image_storing_name(dir.path(), name);
+
+        let report = DataProcessor::decode_all(DecodeAllOptions {
+            image_path: image,
+            passwords: Vec::new(),
+            threads: None,
+            raw_dimensions: None,
+            enforce_expiry: false,
+        })
+        .unwrap();
+
+        assert_eq!(report.entries.len(), 1);
+        match &report.entries[0].result {
+            Err(DeepSceneError::Data(message)) => {
+                assert!(message.contains("does not name a file"), "{}", message)
+            }
+            other => panic!("{}: expected a data error, got {:?}", name, other),
+        }
+    }
+}
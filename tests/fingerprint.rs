@@ -0,0 +1,150 @@
+mod common;
+
+use deepscene::core::{DeepSceneError, SteganographyEngine};
+use deepscene::processor::metadata::Fingerprint;
+use deepscene::processor::{DataProcessor, DecodeOptions, EncodeOptions, FingerprintOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct-horse-battery-staple";
+
+struct Fixture {
+    _dir: TempDir,
+    file: PathBuf,
+    stego: PathBuf,
+    fingerprint: Fingerprint,
+}
+
+/// Encodes a file into a fresh carrier with the options `configure` sets.
+fn encode(configure: impl FnOnce(&mut EncodeOptions)) -> Fixture {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+    let file = dir.path().join("secret.txt");
+    let data = common::payload(600);
+    fs::write(&file, &data).unwrap();
+    let stego = dir.path().join("stego.png");
+
+    let mut options = common::encode_options(&file, &carrier, &stego);
+    configure(&mut options);
+    let result = DataProcessor::encode(options).unwrap();
+    assert_eq!(result.fingerprint, Some(Fingerprint::of(&data)));
+
+    Fixture {
+        _dir: dir,
+        file,
+        stego,
+        fingerprint: Fingerprint::of(&data),
+    }
+}
+
+fn fingerprint_options(image: &Path, password: Option<&str>) -> FingerprintOptions {
+    FingerprintOptions {
+        image_path: image.to_path_buf(),
+        password: password.map(str::to_string),
+        threads: None,
+        raw_dimensions: None,
+        compare: None,
+    }
+}
+
+/// Whether the fingerprint appears anywhere in the bytes embedded in `image`.
+fn stored_in_clear(image: &Path, fingerprint: Fingerprint) -> bool {
+    let embedded = SteganographyEngine::extract_data(image).unwrap().data;
+    embedded
+        .windows(Fingerprint::LEN)
+        .any(|window| window == fingerprint.0)
+}
+
+#[test]
+fn unencrypted_fingerprint_is_readable_without_a_password() {
+    let fixture = encode(|_| {});
+
+    assert!(stored_in_clear(&fixture.stego, fixture.fingerprint));
+    let result = DataProcessor::fingerprint(&FingerprintOptions {
+        compare: Some(fixture.file.clone()),
+        ..fingerprint_options(&fixture.stego, None)
+    })
+    .unwrap();
+    assert_eq!(result.fingerprint, fixture.fingerprint);
+    assert_eq!(result.matches, Some(true));
+}
+
+#[test]
+fn encrypted_file_keeps_its_fingerprint_out_of_the_clear() {
+    let fixture = encode(|options| options.password = Some(PASSWORD.to_string()));
+
+    assert!(!stored_in_clear(&fixture.stego, fixture.fingerprint));
+    assert!(matches!(
+        DataProcessor::fingerprint(&fingerprint_options(&fixture.stego, None)),
+        Err(DeepSceneError::PasswordRequired(_))
+    ));
+    assert!(matches!(
+        DataProcessor::fingerprint(&fingerprint_options(&fixture.stego, Some("wrong-password"))),
+        Err(DeepSceneError::WrongPassword(_))
+    ));
+
+    let result = DataProcessor::fingerprint(&FingerprintOptions {
+        compare: Some(fixture.file.clone()),
+        ..fingerprint_options(&fixture.stego, Some(PASSWORD))
+    })
+    .unwrap();
+    assert_eq!(result.fingerprint, fixture.fingerprint);
+    assert_eq!(result.matches, Some(true));
+}
+
+#[test]
+fn recipients_keep_the_fingerprint_out_of_the_clear() {
+    let fixture = encode(|options| {
+        options.recipient_passwords = vec![PASSWORD.to_string(), "second-recipient".to_string()]
+    });
+
+    assert!(!stored_in_clear(&fixture.stego, fixture.fingerprint));
+    let result = DataProcessor::fingerprint(&fingerprint_options(
+        &fixture.stego,
+        Some("second-recipient"),
+    ))
+    .unwrap();
+    assert_eq!(result.fingerprint, fixture.fingerprint);
+}
+
+#[test]
+fn sealed_fingerprint_is_checked_after_decoding() {
+    let fixture = encode(|options| options.password = Some(PASSWORD.to_string()));
+    let output = fixture.stego.with_file_name("decoded.txt");
+
+    let result = DataProcessor::decode(DecodeOptions {
+        password: Some(PASSWORD.to_string()),
+        verify_after_write: true,
+        ..common::decode_options(&fixture.stego, &output)
+    })
+    .unwrap();
+
+    assert_eq!(result.file_name, "secret.txt");
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&fixture.file).unwrap());
+}
+
+#[test]
+fn encrypted_metadata_still_holds_the_fingerprint() {
+    let fixture = encode(|options| {
+        options.password = Some(PASSWORD.to_string());
+        options.encrypt_metadata = true;
+    });
+
+    assert!(!stored_in_clear(&fixture.stego, fixture.fingerprint));
+    let result =
+        DataProcessor::fingerprint(&fingerprint_options(&fixture.stego, Some(PASSWORD))).unwrap();
+    assert_eq!(result.fingerprint, fixture.fingerprint);
+}
+
+#[test]
+fn older_versions_still_store_it_in_the_clear() {
+    let fixture = encode(|options| {
+        options.password = Some(PASSWORD.to_string());
+        options.format_version = Some(18);
+    });
+
+    assert!(stored_in_clear(&fixture.stego, fixture.fingerprint));
+    let result = DataProcessor::fingerprint(&fingerprint_options(&fixture.stego, None)).unwrap();
+    assert_eq!(result.fingerprint, fixture.fingerprint);
+}
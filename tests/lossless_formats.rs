@@ -0,0 +1,166 @@
+mod common;
+
+use deepscene::core::{DeepSceneError, EmbedParams, SteganographyEngine};
+use deepscene::processor::DataProcessor;
+use image::{DynamicImage, GrayImage, Luma};
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use tiff::encoder::{TiffEncoder, colortype};
+
+/// Embeds `data` into a PNG, re-saves the stego image through `image` as
+/// `resaved` and returns what `extract_data` reads from the copy.
+fn extract_after_resave(dir: &Path, data: &[u8], resaved: &str) -> Vec<u8> {
+    let carrier = common::save_carrier(dir, "carrier.png", 64, 48);
+    let stego = dir.join("stego.png");
+    SteganographyEngine::hide_data(&carrier, data, &stego, &EmbedParams::default()).unwrap();
+
+    let copy = dir.join(resaved);
+    image::open(&stego).unwrap().save(&copy).unwrap();
+
+    SteganographyEngine::extract_data(&copy).unwrap().data
+}
+
+#[test]
+fn ppm_carrier_round_trips() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.ppm", 64, 48);
+    let output = dir.path().join("output.ppm");
+    let data = common::payload(500);
+
+    SteganographyEngine::hide_data(&carrier, &data, &output, &EmbedParams::default()).unwrap();
+
+    assert_eq!(
+        SteganographyEngine::extract_data(&output).unwrap().data,
+        data
+    );
+}
+
+#[test]
+fn pgm_carrier_is_widened_to_rgb() {
+    let dir = TempDir::new().unwrap();
+    let carrier = dir.path().join("carrier.pgm");
+    GrayImage::from_fn(40, 30, |x, y| Luma([(x * 5 + y * 3) as u8]))
+        .save(&carrier)
+        .unwrap();
+    let output = dir.path().join("output.ppm");
+
+    // A grayscale carrier still has three channels once loaded as RGBA, so
+    // its capacity is that of an RGB image of the same size.
+    let (width, height) = SteganographyEngine::validate_image(&carrier).unwrap();
+    let capacity = SteganographyEngine::payload_capacity(width, height, &EmbedParams::default());
+    assert_eq!(
+        capacity,
+        SteganographyEngine::payload_capacity(40, 30, &EmbedParams::default())
+    );
+
+    let data = common::payload(capacity);
+    SteganographyEngine::hide_data(&carrier, &data, &output, &EmbedParams::default()).unwrap();
+
+    assert!(matches!(
+        image::open(&output).unwrap(),
+        DynamicImage::ImageRgb8(_)
+    ));
+    assert_eq!(
+        SteganographyEngine::extract_data(&output).unwrap().data,
+        data
+    );
+}
+
+#[test]
+fn png_resaved_as_bmp_keeps_payload() {
+    let dir = TempDir::new().unwrap();
+    let data = common::payload(800);
+
+    assert_eq!(extract_after_resave(dir.path(), &data, "copy.bmp"), data);
+}
+
+#[test]
+fn png_resaved_as_tiff_keeps_payload() {
+    let dir = TempDir::new().unwrap();
+    let data = common::payload(800);
+
+    assert_eq!(extract_after_resave(dir.path(), &data, "copy.tiff"), data);
+}
+
+#[test]
+fn cmyk_tiff_is_rejected_with_an_explicit_error() {
+    let dir = TempDir::new().unwrap();
+    let carrier = dir.path().join("carrier.tiff");
+    let samples: Vec<u8> = (0..32 * 32 * 4).map(|i| (i * 13) as u8).collect();
+    TiffEncoder::new(File::create(&carrier).unwrap())
+        .unwrap()
+        .write_image::<colortype::CMYK8>(32, 32, &samples)
+        .unwrap();
+
+    let is_cmyk_error =
+        |result| matches!(result, Err(DeepSceneError::Image(message)) if message.contains("CMYK"));
+
+    assert!(is_cmyk_error(
+        SteganographyEngine::validate_image(&carrier).map(|_| ())
+    ));
+    assert!(is_cmyk_error(
+        SteganographyEngine::load_image_from_memory(&fs::read(&carrier).unwrap(), &carrier)
+            .map(|_| ())
+    ));
+    assert!(is_cmyk_error(SteganographyEngine::hide_data(
+        &carrier,
+        &common::payload(10),
+        &dir.path().join("output.tiff"),
+        &EmbedParams::default(),
+    )));
+    assert!(!dir.path().join("output.tiff").exists());
+}
+
+#[test]
+fn eight_bit_bmp_carrier_warns_that_its_depth_is_lost() {
+    let dir = TempDir::new().unwrap();
+    let carrier = dir.path().join("carrier.bmp");
+    GrayImage::from_fn(64, 48, |x, y| Luma([(x * 5 + y * 3) as u8]))
+        .save(&carrier)
+        .unwrap();
+    let rgb = common::save_carrier(dir.path(), "rgb.bmp", 64, 48);
+    let file = dir.path().join("secret.txt");
+    fs::write(&file, common::payload(300)).unwrap();
+
+    assert_eq!(SteganographyEngine::bmp_bit_depth(&carrier), Some(8));
+    assert_eq!(SteganographyEngine::bmp_bit_depth(&rgb), Some(24));
+    assert_eq!(SteganographyEngine::bmp_bit_depth(&file), None);
+
+    let encode = |output: &Path, strict: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_deepscene"));
+        command
+            .arg("encode")
+            .arg(&carrier)
+            .arg(&file)
+            .arg("-o")
+            .arg(output);
+        if strict {
+            command.arg("--strict");
+        }
+        command.output().unwrap()
+    };
+    let warning = "is a BMP with 8 bits per pixel";
+
+    let output = dir.path().join("output.bmp");
+    let result = encode(&output, false);
+    assert!(result.status.success(), "{:?}", result);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(stdout.contains(warning), "{}", stdout);
+    assert_eq!(SteganographyEngine::bmp_bit_depth(&output), Some(32));
+    let decoded = dir.path().join("decoded.txt");
+    DataProcessor::decode(common::decode_options(&output, &decoded)).unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), common::payload(300));
+
+    let strict_output = dir.path().join("strict.bmp");
+    let result = encode(&strict_output, true);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains(warning) && stderr.contains("--strict"),
+        "{}",
+        stderr
+    );
+    assert!(!strict_output.exists());
+}
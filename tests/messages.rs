@@ -0,0 +1,140 @@
+//! Errors compared with the `core::messages` entry that produced them, so a
+//! rewording changes one place and these tests keep passing.
+
+mod common;
+
+use deepscene::core::{
+    CryptoEngine, DeepSceneError, KdfAlgorithm, Result, SteganographyEngine, messages,
+};
+use deepscene::io::{FileHandler, OverwritePolicy};
+use deepscene::processor::DataProcessor;
+use std::fs;
+use tempfile::TempDir;
+
+/// The message an error carries, without the category prefix `Display`
+/// adds.
+fn message<T>(result: Result<T>) -> String {
+    let Err(error) = result else {
+        panic!("expected an error");
+    };
+    match error {
+        DeepSceneError::Encryption(context) | DeepSceneError::Compression(context) => {
+            context.message().to_string()
+        }
+        DeepSceneError::Io(e) => panic!("unexpected I/O error: {}", e),
+        DeepSceneError::Image(message)
+        | DeepSceneError::Validation(message)
+        | DeepSceneError::Data(message)
+        | DeepSceneError::WrongPassword(message)
+        | DeepSceneError::PasswordRequired(message)
+        | DeepSceneError::NoData(message)
+        | DeepSceneError::CapacityExceeded(message)
+        | DeepSceneError::UnsupportedVersion(message)
+        | DeepSceneError::Expired(message) => message,
+    }
+}
+
+#[test]
+fn crypto_errors_use_their_keyed_messages() {
+    assert_eq!(
+        message(CryptoEngine::encrypt(b"data", "", KdfAlgorithm::Argon2)),
+        messages::EMPTY_PASSWORD
+    );
+    assert_eq!(
+        message(CryptoEngine::decrypt(&[], "password")),
+        messages::CORRUPTED_ENCRYPTED_DATA
+    );
+    assert_eq!(
+        message(CryptoEngine::decrypt(&[0, 1, 2], "password")),
+        messages::CORRUPTED_ENCRYPTED_DATA
+    );
+    assert_eq!(message(KdfAlgorithm::from_id(9)), messages::unknown_kdf(9));
+    assert_eq!(
+        message(CryptoEngine::decrypt(&[9; 64], "password")),
+        messages::unknown_kdf(9)
+    );
+
+    let encrypted = CryptoEngine::encrypt(b"data", "right-password", KdfAlgorithm::Argon2).unwrap();
+    assert_eq!(
+        message(CryptoEngine::decrypt(&encrypted, "wrong-password")),
+        messages::AUTHENTICATION_FAILED
+    );
+}
+
+#[test]
+fn file_errors_use_their_keyed_messages() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("missing.txt");
+    let empty = dir.path().join("empty.txt");
+    fs::write(&empty, b"").unwrap();
+    let existing = dir.path().join("existing.txt");
+    fs::write(&existing, b"data").unwrap();
+
+    assert_eq!(
+        message(FileHandler::read_file(&missing)),
+        messages::file_not_found(&missing)
+    );
+    assert_eq!(
+        message(FileHandler::read_file(dir.path())),
+        messages::not_a_file(dir.path())
+    );
+    assert_eq!(
+        message(FileHandler::read_file(&empty)),
+        messages::empty_file(&empty)
+    );
+    assert_eq!(
+        message(OverwritePolicy::Never.resolve(&existing)),
+        messages::output_exists(&existing)
+    );
+    assert_eq!(
+        message(FileHandler::validate_output_path(dir.path())),
+        messages::output_not_a_file(dir.path())
+    );
+
+    let no_parent = dir.path().join("missing").join("out.png");
+    assert_eq!(
+        message(FileHandler::validate_output_path(&no_parent)),
+        messages::output_dir_missing(&dir.path().join("missing"))
+    );
+    assert_eq!(
+        message(FileHandler::list_files(&existing, false)),
+        messages::not_a_directory(&existing)
+    );
+}
+
+#[test]
+fn image_errors_use_their_keyed_messages() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 32, 32);
+    assert_eq!(
+        message(SteganographyEngine::extract_data(&carrier)),
+        messages::NO_EMBEDDED_DATA
+    );
+
+    let not_an_image = dir.path().join("not-an-image.png");
+    fs::write(&not_an_image, b"plain text").unwrap();
+    let error = message(SteganographyEngine::extract_data(&not_an_image));
+    assert!(
+        error.starts_with(&messages::open_image_failed(&not_an_image, "")),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn version_errors_use_their_keyed_message() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 64, 48);
+    let file = dir.path().join("secret.txt");
+    fs::write(&file, common::payload(100)).unwrap();
+
+    let mut options = common::encode_options(&file, &carrier, &dir.path().join("stego.png"));
+    options.hint = Some("the usual".to_string());
+    options.password = Some("correct-horse-battery-staple".to_string());
+    options.format_version = Some(11);
+
+    assert_eq!(
+        message(DataProcessor::encode(options)),
+        messages::version_lacks_feature(11, "--hint", 12)
+    );
+}
@@ -0,0 +1,66 @@
+//! TIFF carriers decoded from a memory map, as builds with the `mmap`
+//! feature do.
+
+mod common;
+
+use deepscene::core::{EmbedParams, SteganographyEngine};
+use deepscene::processor::{DataProcessor, EncodeOptions};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn mapped_tiff_decodes_to_the_same_pixels() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.tiff", 97, 61);
+
+    let mapped = SteganographyEngine::load_image(&carrier).unwrap();
+    assert_eq!(mapped, image::open(&carrier).unwrap().to_rgba8());
+
+    let table = DataProcessor::capacity_table(std::slice::from_ref(&carrier)).unwrap();
+    let entry = table.entries[0].result.as_ref().unwrap();
+    assert_eq!((entry.width, entry.height), (97, 61));
+    assert_eq!(entry.format, "TIFF");
+    assert_eq!(
+        entry.capacity,
+        SteganographyEngine::payload_capacity(97, 61, &EmbedParams::default())
+    );
+}
+
+#[test]
+fn tiff_stego_image_decodes_from_a_map() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.tiff", 96, 64);
+    let file = dir.path().join("secret.txt");
+    fs::write(&file, common::payload(900)).unwrap();
+    let stego = dir.path().join("stego.tiff");
+
+    DataProcessor::encode(common::encode_options(&file, &carrier, &stego)).unwrap();
+    assert_eq!(
+        image::ImageFormat::from_path(&stego).unwrap(),
+        image::ImageFormat::Tiff
+    );
+
+    let decoded = dir.path().join("decoded.txt");
+    DataProcessor::decode(common::decode_options(&stego, &decoded)).unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), common::payload(900));
+}
+
+#[test]
+fn tiff_strip_payload_decodes_from_a_map() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.tiff", 96, 256);
+    let file = dir.path().join("secret.txt");
+    fs::write(&file, common::payload(300)).unwrap();
+    let stego = dir.path().join("stego.tiff");
+
+    let result = DataProcessor::encode(EncodeOptions {
+        tiff_strip: Some(0),
+        ..common::encode_options(&file, &carrier, &stego)
+    })
+    .unwrap();
+    assert!(result.tiff_strip.is_some());
+
+    let decoded = dir.path().join("decoded.txt");
+    DataProcessor::decode(common::decode_options(&stego, &decoded)).unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), common::payload(300));
+}
@@ -0,0 +1,77 @@
+//! The default build links no networking crates: networking is opt-in
+//! through the `net` feature.
+
+use std::collections::BTreeSet;
+use std::process::Command;
+
+/// Crates that make HTTP requests, speak TLS or open sockets.
+const NETWORKING_CRATES: &[&str] = &[
+    "ureq",
+    "ureq-proto",
+    "reqwest",
+    "hyper",
+    "http",
+    "httparse",
+    "h2",
+    "rustls",
+    "native-tls",
+    "openssl",
+    "webpki-roots",
+    "socket2",
+    "mio",
+    "tokio",
+    "curl",
+];
+
+/// The names of the crates a build with `features` links, from the resolved
+/// dependency graph of normal (not build or dev) dependencies.
+fn linked_crates(features: &[&str]) -> BTreeSet<String> {
+    let mut command = Command::new(env!("CARGO"));
+    command
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["tree", "--edges", "normal", "--prefix", "none"]);
+    if !features.is_empty() {
+        command.arg("--features").arg(features.join(","));
+    }
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "cargo tree failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+fn networking_crates(linked: &BTreeSet<String>) -> Vec<&'static str> {
+    NETWORKING_CRATES
+        .iter()
+        .copied()
+        .filter(|name| linked.contains(*name))
+        .collect()
+}
+
+#[test]
+fn default_build_links_no_networking_crates() {
+    let linked = linked_crates(&[]);
+    assert!(linked.contains("deepscene"), "{:?}", linked);
+
+    let leaked = networking_crates(&linked);
+    assert!(
+        leaked.is_empty(),
+        "networking crates in the default build: {:?}",
+        leaked
+    );
+}
+
+#[test]
+fn net_feature_brings_in_networking_crates() {
+    // Keeps the check above from passing because the crate list went stale.
+    let leaked = networking_crates(&linked_crates(&["net"]));
+    assert!(leaked.contains(&"ureq"), "{:?}", leaked);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use deepscene::core::DeepSceneError;
+use deepscene::io::FileHandler;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Runs the binary in `dir`, so relative paths resolve against it.
+fn deepscene(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_deepscene"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn set_up() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    common::save_carrier(dir.path(), "carrier.png", 64, 48);
+    fs::write(dir.path().join("secret.txt"), common::payload(300)).unwrap();
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    dir
+}
+
+fn validation_message(path: &Path) -> String {
+    match FileHandler::validate_output_path(path) {
+        Err(DeepSceneError::Validation(message)) => message,
+        other => panic!(
+            "expected a validation error for {:?}, got {:?}",
+            path, other
+        ),
+    }
+}
+
+#[test]
+fn every_path_form_writes_where_it_names() {
+    let dir = set_up();
+    let root = dir.path();
+    let absolute = root.join("sub").join("absolute.png");
+
+    for (output, written) in [
+        ("bare.png", root.join("bare.png")),
+        ("./dot.png", root.join("dot.png")),
+        ("sub/relative.png", root.join("sub/relative.png")),
+        ("sub/../parent.png", root.join("parent.png")),
+        (absolute.to_str().unwrap(), absolute.clone()),
+    ] {
+        let result = deepscene(root, &["encode", "carrier.png", "secret.txt", "-o", output]);
+        assert!(result.status.success(), "{}: {:?}", output, result);
+        assert!(written.is_file(), "{} was not written", output);
+    }
+}
+
+#[test]
+fn decode_writes_a_bare_name_to_the_current_directory() {
+    let dir = set_up();
+    let root = dir.path();
+    let encode = deepscene(
+        root,
+        &["encode", "carrier.png", "secret.txt", "-o", "stego.png"],
+    );
+    assert!(encode.status.success(), "{:?}", encode);
+
+    let sub = root.join("sub");
+    let decode = deepscene(&sub, &["decode", "../stego.png", "-o", "decoded.txt"]);
+    assert!(decode.status.success(), "{:?}", decode);
+    assert_eq!(
+        fs::read(sub.join("decoded.txt")).unwrap(),
+        common::payload(300)
+    );
+
+    // Without -o the stored name is used, again in the current directory.
+    let default = deepscene(&sub, &["decode", "../stego.png"]);
+    assert!(default.status.success(), "{:?}", default);
+    assert!(sub.join("secret.txt").is_file());
+}
+
+#[test]
+fn bare_and_dotted_names_are_checked_against_the_current_directory() {
+    assert!(FileHandler::validate_output_path(Path::new("out.png")).is_ok());
+    assert!(FileHandler::validate_output_path(Path::new("./out.png")).is_ok());
+}
+
+#[test]
+fn missing_parent_is_reported_in_every_form() {
+    let dir = set_up();
+    let root = dir.path();
+
+    let absolute = root.join("missing").join("out.png");
+    assert!(validation_message(&absolute).contains("does not exist"));
+
+    let relative = deepscene(
+        root,
+        &[
+            "encode",
+            "carrier.png",
+            "secret.txt",
+            "-o",
+            "missing/out.png",
+        ],
+    );
+    assert!(!relative.status.success());
+    assert!(String::from_utf8_lossy(&relative.stderr).contains("does not exist"));
+    assert!(!root.join("missing").exists());
+}
+
+#[test]
+fn parent_that_is_a_file_is_not_a_directory() {
+    let dir = set_up();
+    let path = dir.path().join("secret.txt").join("out.png");
+
+    assert!(validation_message(&path).contains("is not a directory"));
+}
+
+#[test]
+fn paths_without_a_file_name_are_rejected() {
+    let dir = set_up();
+
+    for path in [Path::new("/"), &dir.path().join("sub/..")] {
+        assert!(
+            validation_message(path).contains("does not name a file"),
+            "{:?}",
+            path
+        );
+    }
+}
+
+#[test]
+fn existing_directory_is_not_a_file() {
+    let dir = set_up();
+
+    assert!(validation_message(&dir.path().join("sub")).contains("is not a file"));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use deepscene::core::SteganographyEngine;
+use deepscene::processor::{DataProcessor, DecodeOptions, EncodeOptions};
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct horse battery";
+
+/// Encodes `data` with a password in the given format version and returns
+/// the embedded payload size.
+fn encrypted_payload_size(dir: &TempDir, data: &[u8], format_version: Option<u8>) -> usize {
+    let file = dir.path().join("notes.txt");
+    fs::write(&file, data).unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 256, 256);
+
+    let result = DataProcessor::encode(EncodeOptions {
+        password: Some(PASSWORD.to_string()),
+        format_version,
+        ..common::encode_options(&file, &carrier, &dir.path().join("output.png"))
+    })
+    .unwrap();
+
+    assert!(result.encrypted);
+    result.final_size
+}
+
+#[test]
+fn compressible_encrypted_payload_shrinks() {
+    let dir = TempDir::new().unwrap();
+    let data = b"the quick brown fox jumps over the lazy dog\n".repeat(400);
+
+    let size = encrypted_payload_size(&dir, &data, None);
+
+    assert!(
+        size < data.len() / 4,
+        "{} bytes of text encrypted to a {}-byte payload",
+        data.len(),
+        size
+    );
+}
+
+#[test]
+fn encrypted_payload_is_no_larger_than_before_compress_then_encrypt() {
+    let dir = TempDir::new().unwrap();
+    let data = b"the quick brown fox jumps over the lazy dog\n".repeat(400);
+
+    // Version 2 encrypted first and then compressed the ciphertext, which
+    // saved nothing.
+    let legacy = encrypted_payload_size(&dir, &data, Some(2));
+    let current = encrypted_payload_size(&dir, &data, None);
+
+    assert!(legacy > data.len());
+    assert!(current <= legacy);
+}
+
+#[test]
+fn legacy_encrypted_payload_is_not_deflated() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("notes.txt");
+    let data = b"the quick brown fox jumps over the lazy dog\n".repeat(400);
+    fs::write(&file, &data).unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 256, 256);
+    let output = dir.path().join("output.png");
+    let encode = |password: Option<&str>| {
+        DataProcessor::encode(EncodeOptions {
+            password: password.map(str::to_string),
+            format_version: Some(2),
+            ..common::encode_options(&file, &carrier, &output)
+        })
+        .unwrap()
+    };
+    // Version 2 payloads start with a compression flag, 1 when deflated.
+    let compression_flag = || SteganographyEngine::extract_data(&output).unwrap().data[0];
+
+    let plain = encode(None);
+    assert!(plain.compressed);
+    assert_eq!(compression_flag(), 1);
+
+    let encrypted = encode(Some(PASSWORD));
+    assert!(!encrypted.compressed);
+    assert_eq!(compression_flag(), 0);
+    assert!(encrypted.final_size > data.len());
+
+    let decoded = dir.path().join("decoded.txt");
+    DataProcessor::decode(DecodeOptions {
+        password: Some(PASSWORD.to_string()),
+        ..common::decode_options(&output, &decoded)
+    })
+    .unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), data);
+
+    let result = Command::new(env!("CARGO_BIN_EXE_deepscene"))
+        .arg("encode")
+        .arg(&carrier)
+        .arg(&file)
+        .arg("-o")
+        .arg(dir.path().join("cli.png"))
+        .args(["--format-version", "2", "--password", PASSWORD])
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{:?}", result);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(
+        stdout.contains("Compression skipped (encrypted payload is incompressible)."),
+        "{}",
+        stdout
+    );
+}
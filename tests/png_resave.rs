@@ -0,0 +1,108 @@
+mod common;
+
+use deepscene::core::{EmbedParams, SteganographyEngine};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ColorType, ImageEncoder};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const COMPRESSION: [CompressionType; 3] = [
+    CompressionType::Fast,
+    CompressionType::Default,
+    CompressionType::Best,
+];
+
+const FILTERS: [FilterType; 6] = [
+    FilterType::NoFilter,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Avg,
+    FilterType::Paeth,
+    FilterType::Adaptive,
+];
+
+/// Embeds `data` into a fresh PNG carrier with `params` and returns the
+/// stego image's path.
+fn stego_png(dir: &Path, data: &[u8], params: &EmbedParams) -> PathBuf {
+    let carrier = common::save_carrier(dir, "carrier.png", 64, 48);
+    let stego = dir.join("stego.png");
+    SteganographyEngine::hide_data(&carrier, data, &stego, params).unwrap();
+    stego
+}
+
+/// Re-encodes `source` as an RGBA PNG with the given encoder settings.
+fn resave(source: &Path, target: &Path, compression: CompressionType, filter: FilterType) {
+    let pixels = image::open(source).unwrap().to_rgba8();
+    PngEncoder::new_with_quality(File::create(target).unwrap(), compression, filter)
+        .write_image(&pixels, pixels.width(), pixels.height(), ColorType::Rgba8)
+        .unwrap();
+}
+
+#[test]
+fn every_compression_level_and_filter_keeps_the_payload() {
+    let dir = TempDir::new().unwrap();
+    let data = common::payload(900);
+    let stego = stego_png(dir.path(), &data, &EmbedParams::default());
+    let copy = dir.path().join("copy.png");
+
+    for compression in COMPRESSION {
+        for filter in FILTERS {
+            resave(&stego, &copy, compression, filter);
+            assert_eq!(
+                SteganographyEngine::extract_data(&copy).unwrap().data,
+                data,
+                "{:?} with {:?}",
+                compression,
+                filter
+            );
+        }
+    }
+}
+
+#[test]
+fn repeated_resaves_through_image_keep_the_payload() {
+    let dir = TempDir::new().unwrap();
+    let data = common::payload(400);
+    let params = EmbedParams {
+        stride: 2,
+        ..EmbedParams::default()
+    };
+    let stego = stego_png(dir.path(), &data, &params);
+
+    let mut current = stego;
+    for generation in 0..3 {
+        let next = dir.path().join(format!("generation-{}.png", generation));
+        image::open(&current).unwrap().save(&next).unwrap();
+        assert_eq!(
+            SteganographyEngine::extract_data(&next).unwrap().data,
+            data,
+            "generation {}",
+            generation
+        );
+        current = next;
+    }
+}
+
+#[test]
+fn resave_with_different_settings_is_pixel_identical() {
+    let dir = TempDir::new().unwrap();
+    let stego = stego_png(dir.path(), &common::payload(300), &EmbedParams::default());
+    let fast = dir.path().join("fast.png");
+    let best = dir.path().join("best.png");
+
+    resave(&stego, &fast, CompressionType::Fast, FilterType::NoFilter);
+    resave(&stego, &best, CompressionType::Best, FilterType::Paeth);
+
+    // The encoded files differ, but filtering is undone on decode, so the
+    // pixels and with them every low bit come back unchanged.
+    assert_ne!(std::fs::read(&fast).unwrap(), std::fs::read(&best).unwrap());
+    assert_eq!(
+        image::open(&fast).unwrap().to_rgba8(),
+        image::open(&stego).unwrap().to_rgba8()
+    );
+    assert_eq!(
+        image::open(&best).unwrap().to_rgba8(),
+        image::open(&stego).unwrap().to_rgba8()
+    );
+}
@@ -0,0 +1,142 @@
+//! `--preserve-dpi` carries the carrier's pixel density over to PNG, TIFF
+//! and BMP output.
+
+mod common;
+
+use deepscene::core::Resolution;
+use deepscene::processor::{DataProcessor, EncodeOptions};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tiff::encoder::{Rational, TiffEncoder, colortype};
+use tiff::tags::ResolutionUnit;
+
+const OUTPUTS: [&str; 3] = ["png", "tiff", "bmp"];
+
+fn png_carrier(dir: &Path, pixels_per_metre: (u32, u32)) -> PathBuf {
+    let path = dir.join("carrier.png");
+    let image = common::carrier(96, 64);
+    let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 96, 64);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_metre.0,
+        yppu: pixels_per_metre.1,
+        unit: png::Unit::Meter,
+    }));
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(image.as_raw()).unwrap();
+    writer.finish().unwrap();
+    path
+}
+
+/// A TIFF stating its density in pixels per centimetre.
+fn tiff_carrier(dir: &Path, pixels_per_centimetre: u32) -> PathBuf {
+    let path = dir.join("carrier.tiff");
+    let image = common::carrier(96, 64);
+    let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+    let mut tiff_image = encoder.new_image::<colortype::RGBA8>(96, 64).unwrap();
+    tiff_image.resolution_unit(ResolutionUnit::Centimeter);
+    tiff_image.x_resolution(Rational {
+        n: pixels_per_centimetre,
+        d: 1,
+    });
+    tiff_image.y_resolution(Rational {
+        n: pixels_per_centimetre,
+        d: 1,
+    });
+    tiff_image.write_data(image.as_raw()).unwrap();
+    path
+}
+
+fn bmp_carrier(dir: &Path, resolution: Resolution) -> PathBuf {
+    let path = common::save_carrier(dir, "carrier.bmp", 96, 64);
+    let mut bmp = fs::read(&path).unwrap();
+    resolution.apply_to_bmp(&mut bmp);
+    fs::write(&path, bmp).unwrap();
+    path
+}
+
+/// Encodes a payload into `carrier`, writing `output`.
+fn encode(dir: &Path, carrier: &Path, output: &str, preserve_dpi: bool) -> PathBuf {
+    let file = dir.join("secret.txt");
+    fs::write(&file, common::payload(200)).unwrap();
+    let output = dir.join(output);
+    DataProcessor::encode(EncodeOptions {
+        preserve_dpi,
+        ..common::encode_options(&file, carrier, &output)
+    })
+    .unwrap();
+    output
+}
+
+/// Densities are stored as whole pixels per metre or thousandths of a dot
+/// per inch, so a round-trip may move them by a fraction of a dot.
+fn assert_close(actual: Option<Resolution>, expected: Resolution, context: &str) {
+    let actual = actual.unwrap_or_else(|| panic!("{}: no resolution", context));
+    assert!(
+        (actual.x_dpi - expected.x_dpi).abs() < 0.01
+            && (actual.y_dpi - expected.y_dpi).abs() < 0.01,
+        "{}: {:?} instead of {:?}",
+        context,
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn density_survives_encode_into_every_supported_format() {
+    let dir = TempDir::new().unwrap();
+    let carriers = [
+        png_carrier(dir.path(), (11811, 5906)),
+        tiff_carrier(dir.path(), 118),
+        bmp_carrier(
+            dir.path(),
+            Resolution {
+                x_dpi: 72.0,
+                y_dpi: 72.0,
+            },
+        ),
+    ];
+
+    for carrier in &carriers {
+        let expected = Resolution::read(carrier).unwrap().unwrap();
+        for format in OUTPUTS {
+            let context = format!("{} -> {}", carrier.display(), format);
+            let output = encode(dir.path(), carrier, &format!("output.{}", format), true);
+            assert_close(Resolution::read(&output).unwrap(), expected, &context);
+        }
+    }
+
+    // 118 pixels per centimetre, converted to inches.
+    assert_close(
+        Resolution::read(&carriers[1]).unwrap(),
+        Resolution {
+            x_dpi: 299.72,
+            y_dpi: 299.72,
+        },
+        "centimetre TIFF",
+    );
+}
+
+#[test]
+fn density_is_only_copied_with_preserve_dpi() {
+    let dir = TempDir::new().unwrap();
+    let carrier = png_carrier(dir.path(), (11811, 11811));
+
+    for format in OUTPUTS {
+        let output = encode(dir.path(), &carrier, &format!("output.{}", format), false);
+        assert_eq!(Resolution::read(&output).unwrap(), None, "{}", format);
+    }
+}
+
+#[test]
+fn carrier_without_a_resolution_gives_output_without_one() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+    assert_eq!(Resolution::read(&carrier).unwrap(), None);
+
+    for format in OUTPUTS {
+        let output = encode(dir.path(), &carrier, &format!("output.{}", format), true);
+        assert_eq!(Resolution::read(&output).unwrap(), None, "{}", format);
+    }
+}
@@ -0,0 +1,161 @@
+//! `--preserve-exif` copies the carrier's EXIF attributes and ICC profile
+//! onto TIFF output as well as PNG.
+
+mod common;
+
+use deepscene::core::{CarrierMetadata, Resolution};
+use deepscene::processor::{DataProcessor, EncodeOptions};
+use exif::experimental::Writer;
+use exif::{Field, In, Tag, Value};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const ICC_PROFILE: &[u8] = b"not a real profile, but the bytes must come back unchanged";
+
+/// EXIF data with attributes in the image directory and the EXIF
+/// sub-directory, as a camera writes them.
+fn exif_data() -> Vec<u8> {
+    let ascii = |tag, value: &str| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+    };
+    let fields = [
+        ascii(Tag::Make, "Example Camera Co."),
+        ascii(Tag::Model, "Model 7"),
+        ascii(Tag::DateTimeOriginal, "2024:05:06 07:08:09"),
+        Field {
+            tag: Tag::XResolution,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![(72, 1).into()]),
+        },
+        Field {
+            tag: Tag::YResolution,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![(72, 1).into()]),
+        },
+    ];
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buffer = Cursor::new(Vec::new());
+    writer.write(&mut buffer, false).unwrap();
+    buffer.into_inner()
+}
+
+/// A PNG carrier holding `exif_data()` and `ICC_PROFILE`, and a density of
+/// 300 dpi.
+fn png_carrier(dir: &Path) -> PathBuf {
+    let path = common::save_carrier(dir, "carrier.png", 96, 64);
+    let metadata = CarrierMetadata {
+        exif: Some(exif_data()),
+        icc_profile: Some(ICC_PROFILE.to_vec()),
+    };
+    let mut chunks = metadata.png_chunks().unwrap();
+    chunks.push(
+        Resolution {
+            x_dpi: 300.0,
+            y_dpi: 300.0,
+        }
+        .png_chunk(),
+    );
+
+    // Ancillary chunks go right after the 8-byte signature and the IHDR
+    // chunk, before any image data.
+    let png = fs::read(&path).unwrap();
+    let mut output = png[..33].to_vec();
+    for (name, data) in chunks {
+        output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        output.extend_from_slice(&name);
+        output.extend_from_slice(&data);
+        output.extend_from_slice(&crc32fast::hash(&[&name[..], &data].concat()).to_be_bytes());
+    }
+    output.extend_from_slice(&png[33..]);
+    fs::write(&path, output).unwrap();
+    path
+}
+
+fn encode(carrier: &Path, output: &Path, preserve_dpi: bool) {
+    let file = output.with_file_name("secret.txt");
+    fs::write(&file, common::payload(400)).unwrap();
+    DataProcessor::encode(EncodeOptions {
+        preserve_exif: true,
+        preserve_dpi,
+        ..common::encode_options(&file, carrier, output)
+    })
+    .unwrap();
+
+    let decoded = output.with_file_name("decoded.txt");
+    DataProcessor::decode(common::decode_options(output, &decoded)).unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), common::payload(400));
+}
+
+fn ascii_field(exif: &exif::Exif, tag: Tag) -> String {
+    exif.get_field(tag, In::PRIMARY)
+        .unwrap_or_else(|| panic!("{} missing", tag))
+        .display_value()
+        .to_string()
+}
+
+/// Fails unless `path` holds the carrier's EXIF attributes and ICC profile.
+fn assert_metadata_copied(path: &Path) {
+    let metadata = CarrierMetadata::read(path).unwrap();
+    assert_eq!(metadata.icc_profile.as_deref(), Some(ICC_PROFILE));
+
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(fs::File::open(path).unwrap()))
+        .unwrap();
+    assert_eq!(ascii_field(&exif, Tag::Make), "\"Example Camera Co.\"");
+    assert_eq!(ascii_field(&exif, Tag::Model), "\"Model 7\"");
+    assert_eq!(
+        ascii_field(&exif, Tag::DateTimeOriginal),
+        "2024-05-06 07:08:09"
+    );
+}
+
+#[test]
+fn exif_and_icc_profile_survive_encode_into_tiff() {
+    let dir = TempDir::new().unwrap();
+    let carrier = png_carrier(dir.path());
+    let output = dir.path().join("output.tiff");
+
+    encode(&carrier, &output, false);
+
+    assert_metadata_copied(&output);
+    let image = image::open(&output).unwrap();
+    assert_eq!((image.width(), image.height()), (96, 64));
+}
+
+#[test]
+fn tiff_carrier_metadata_survives_a_second_encode() {
+    let dir = TempDir::new().unwrap();
+    let carrier = png_carrier(dir.path());
+    let first = dir.path().join("first.tiff");
+    encode(&carrier, &first, false);
+
+    for output in ["second.tiff", "second.png"] {
+        let output = dir.path().join(output);
+        encode(&first, &output, false);
+        assert_metadata_copied(&output);
+    }
+}
+
+#[test]
+fn tiff_resolution_tags_take_precedence_over_copied_ones() {
+    let dir = TempDir::new().unwrap();
+    let carrier = png_carrier(dir.path());
+    let output = dir.path().join("output.tiff");
+
+    // The EXIF data says 72 dpi, the carrier's density 300 dpi.
+    encode(&carrier, &output, true);
+
+    assert_metadata_copied(&output);
+    // 300 dpi went through the carrier's whole pixels per metre.
+    let resolution = Resolution::read(&output).unwrap().unwrap();
+    assert!((resolution.x_dpi - 300.0).abs() < 0.01, "{:?}", resolution);
+    assert!((resolution.y_dpi - 300.0).abs() < 0.01, "{:?}", resolution);
+}
@@ -0,0 +1,111 @@
+//! `encode --salt-file` encrypts with the given salt and warns that the salt
+//! is reused, even for a single encode.
+
+mod common;
+
+use deepscene::core::SteganographyEngine;
+use deepscene::processor::{DataProcessor, DecodeOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct-horse-battery-staple";
+const SALT: [u8; 16] = *b"externally-kept!";
+const WARNING: &str = "--salt-file reuses its salt on every encrypt";
+
+struct Fixture {
+    dir: TempDir,
+    carrier: PathBuf,
+    file: PathBuf,
+    salt: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+        let file = dir.path().join("secret.txt");
+        fs::write(&file, common::payload(300)).unwrap();
+        let salt = dir.path().join("salt.bin");
+        fs::write(&salt, SALT).unwrap();
+        Self {
+            dir,
+            carrier,
+            file,
+            salt,
+        }
+    }
+
+    fn encode(&self, output: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_deepscene"))
+            .arg("encode")
+            .arg(&self.carrier)
+            .arg(&self.file)
+            .arg("-o")
+            .arg(output)
+            .arg("--salt-file")
+            .arg(&self.salt)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+}
+
+#[test]
+fn single_encode_with_a_salt_file_warns_about_reuse() {
+    let fixture = Fixture::new();
+    let output = fixture.dir.path().join("output.png");
+
+    let result = fixture.encode(&output, &["--password", PASSWORD]);
+    assert!(result.status.success(), "{:?}", result);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(stdout.contains(WARNING), "{}", stdout);
+
+    let payload = SteganographyEngine::extract_data(&output).unwrap().data;
+    assert!(payload.windows(SALT.len()).any(|window| window == SALT));
+
+    let decoded = fixture.dir.path().join("decoded.txt");
+    DataProcessor::decode(DecodeOptions {
+        password: Some(PASSWORD.to_string()),
+        ..common::decode_options(&output, &decoded)
+    })
+    .unwrap();
+    assert_eq!(fs::read(decoded).unwrap(), common::payload(300));
+}
+
+#[test]
+fn salt_reuse_warning_is_an_error_with_strict() {
+    let fixture = Fixture::new();
+    let output = fixture.dir.path().join("output.png");
+
+    let result = fixture.encode(&output, &["--password", PASSWORD, "--strict"]);
+    assert!(!result.status.success(), "{:?}", result);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains(WARNING), "{}", stderr);
+    assert!(
+        stderr.contains("warnings are errors with --strict"),
+        "{}",
+        stderr
+    );
+    assert!(!output.exists());
+}
+
+#[test]
+fn salt_file_is_validated_before_warning() {
+    let fixture = Fixture::new();
+    fs::write(&fixture.salt, &SALT[..15]).unwrap();
+    let output = fixture.dir.path().join("output.png");
+
+    let result = fixture.encode(&output, &["--password", PASSWORD]);
+    assert!(!result.status.success(), "{:?}", result);
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("must contain exactly 16 bytes"),
+        "{}",
+        stderr
+    );
+    assert!(!stdout.contains(WARNING), "{}", stdout);
+    assert!(!output.exists());
+}
@@ -0,0 +1,283 @@
+mod common;
+
+use deepscene::core::{DeepSceneError, EmbedParams, SteganographyEngine};
+use deepscene::processor::metadata::SignerKey;
+use deepscene::processor::{DataProcessor, DecodeOptions, DecodeResult, EncodeOptions};
+use ed25519_dalek::SigningKey;
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct-horse-battery-staple";
+
+struct Fixture {
+    dir: TempDir,
+    carrier: PathBuf,
+    file: PathBuf,
+}
+
+impl Fixture {
+    /// A carrier and a file that does not compress, so its bytes are stored
+    /// as-is at the end of an unencrypted payload.
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+        let mut data = vec![0u8; 600];
+        blake3::Hasher::new()
+            .update(b"signed payload")
+            .finalize_xof()
+            .fill(&mut data);
+        let file = dir.path().join("secret.bin");
+        fs::write(&file, data).unwrap();
+        Self { dir, carrier, file }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Writes `key` as a PKCS#8 private key and a SubjectPublicKeyInfo
+    /// public key, as `openssl genpkey` and `openssl pkey -pubout` do.
+    fn key_files(&self, name: &str, key: &SigningKey) -> (PathBuf, PathBuf) {
+        let private = self.path(&format!("{}.pem", name));
+        let public = self.path(&format!("{}.pub.pem", name));
+        fs::write(
+            &private,
+            key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes(),
+        )
+        .unwrap();
+        fs::write(
+            &public,
+            key.verifying_key()
+                .to_public_key_pem(LineEnding::LF)
+                .unwrap(),
+        )
+        .unwrap();
+        (private, public)
+    }
+
+    fn encode(&self, stego: &str, configure: impl FnOnce(&mut EncodeOptions)) -> PathBuf {
+        let stego = self.path(stego);
+        let mut options = common::encode_options(&self.file, &self.carrier, &stego);
+        configure(&mut options);
+        DataProcessor::encode(options).unwrap();
+        stego
+    }
+
+    fn decode(
+        &self,
+        stego: &Path,
+        configure: impl FnOnce(&mut DecodeOptions),
+    ) -> Result<DecodeResult, DeepSceneError> {
+        let mut options = common::decode_options(stego, &self.path("decoded.bin"));
+        configure(&mut options);
+        DataProcessor::decode(options)
+    }
+}
+
+fn signer(key: &SigningKey) -> SignerKey {
+    SignerKey(key.verifying_key().to_bytes())
+}
+
+fn data_error(result: Result<DecodeResult, DeepSceneError>) -> String {
+    match result {
+        Err(DeepSceneError::Data(message)) => message,
+        other => panic!("expected a data error, got {:?}", other),
+    }
+}
+
+/// Flips the last stored byte of the payload embedded in `stego` and embeds
+/// the result again with fresh checksums, as someone who can read and write
+/// the carrier but not sign could.
+fn tamper(stego: &Path, output: &Path) {
+    let mut payload = SteganographyEngine::extract_data(stego).unwrap().data;
+    *payload.last_mut().unwrap() ^= 0x01;
+    SteganographyEngine::hide_data(stego, &payload, output, &EmbedParams::default()).unwrap();
+}
+
+#[test]
+fn signed_payload_reports_its_signer() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (private, public) = fixture.key_files("signing", &key);
+    let stego = fixture.encode("stego.png", |options| options.sign_key = Some(private));
+
+    let result = fixture.decode(&stego, |_| {}).unwrap();
+    assert_eq!(result.signer, Some(signer(&key)));
+    assert!(!result.signer_trusted);
+
+    let result = fixture
+        .decode(&stego, |options| options.verify_key = Some(public))
+        .unwrap();
+    assert_eq!(result.signer, Some(signer(&key)));
+    assert!(result.signer_trusted);
+    assert_eq!(
+        fs::read(fixture.path("decoded.bin")).unwrap(),
+        fs::read(&fixture.file).unwrap()
+    );
+}
+
+#[test]
+fn raw_key_files_are_accepted() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[9u8; 32]);
+    let private = fixture.path("raw.key");
+    let public = fixture.path("raw.pub");
+    fs::write(&private, key.to_bytes()).unwrap();
+    fs::write(&public, key.verifying_key().to_bytes()).unwrap();
+    let stego = fixture.encode("stego.png", |options| options.sign_key = Some(private));
+
+    let result = fixture
+        .decode(&stego, |options| options.verify_key = Some(public))
+        .unwrap();
+    assert_eq!(result.signer, Some(signer(&key)));
+}
+
+#[test]
+fn tampered_file_fails_its_signature() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (private, public) = fixture.key_files("signing", &key);
+    let signed = fixture.encode("signed.png", |options| options.sign_key = Some(private));
+    let unsigned = fixture.encode("unsigned.png", |_| {});
+
+    // Without a signature the change goes unnoticed: the chunk CRCs were
+    // recomputed and the file is neither compressed nor encrypted.
+    let tampered_unsigned = fixture.path("tampered-unsigned.png");
+    tamper(&unsigned, &tampered_unsigned);
+    let result = fixture.decode(&tampered_unsigned, |_| {}).unwrap();
+    assert_eq!(result.signer, None);
+    assert_ne!(
+        fs::read(fixture.path("decoded.bin")).unwrap(),
+        fs::read(&fixture.file).unwrap()
+    );
+    fs::remove_file(fixture.path("decoded.bin")).unwrap();
+
+    let tampered = fixture.path("tampered.png");
+    tamper(&signed, &tampered);
+    let message = data_error(fixture.decode(&tampered, |_| {}));
+    assert!(message.contains("signature does not match"), "{}", message);
+    let message =
+        data_error(fixture.decode(&tampered, |options| options.verify_key = Some(public)));
+    assert!(message.contains("signature does not match"), "{}", message);
+    assert!(!fixture.path("decoded.bin").exists());
+}
+
+#[test]
+fn payload_signed_by_another_key_is_rejected() {
+    let fixture = Fixture::new();
+    let trusted = SigningKey::from_bytes(&[7u8; 32]);
+    let attacker = SigningKey::from_bytes(&[13u8; 32]);
+    let (_, trusted_public) = fixture.key_files("trusted", &trusted);
+    let (attacker_private, _) = fixture.key_files("attacker", &attacker);
+    let stego = fixture.encode("stego.png", |options| {
+        options.sign_key = Some(attacker_private)
+    });
+
+    // The signature itself is valid, only by the wrong key.
+    assert_eq!(
+        fixture.decode(&stego, |_| {}).unwrap().signer,
+        Some(signer(&attacker))
+    );
+    fs::remove_file(fixture.path("decoded.bin")).unwrap();
+
+    let message =
+        data_error(fixture.decode(&stego, |options| options.verify_key = Some(trusted_public)));
+    assert!(
+        message.contains(&signer(&attacker).to_string()),
+        "{}",
+        message
+    );
+    assert!(!fixture.path("decoded.bin").exists());
+}
+
+#[test]
+fn unsigned_payload_fails_a_required_signature() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (_, public) = fixture.key_files("signing", &key);
+    let stego = fixture.encode("stego.png", |_| {});
+
+    let message = data_error(fixture.decode(&stego, |options| options.verify_key = Some(public)));
+    assert!(message.contains("not signed"), "{}", message);
+}
+
+#[test]
+fn encrypted_payload_keeps_its_signature_out_of_the_clear() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (private, public) = fixture.key_files("signing", &key);
+    let stego = fixture.encode("stego.png", |options| {
+        options.password = Some(PASSWORD.to_string());
+        options.sign_key = Some(private);
+    });
+
+    let embedded = SteganographyEngine::extract_data(&stego).unwrap().data;
+    let public_key = key.verifying_key().to_bytes();
+    assert!(!embedded.windows(public_key.len()).any(|w| w == public_key));
+
+    let result = fixture
+        .decode(&stego, |options| {
+            options.password = Some(PASSWORD.to_string());
+            options.verify_key = Some(public);
+        })
+        .unwrap();
+    assert_eq!(result.signer, Some(signer(&key)));
+    assert!(result.signer_trusted);
+}
+
+#[test]
+fn signing_needs_format_version_20() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (private, _) = fixture.key_files("signing", &key);
+    let mut options =
+        common::encode_options(&fixture.file, &fixture.carrier, &fixture.path("stego.png"));
+    options.sign_key = Some(private);
+    options.format_version = Some(19);
+
+    match DataProcessor::encode(options) {
+        Err(DeepSceneError::Validation(message)) => {
+            assert!(message.contains("--sign-key"), "{}", message)
+        }
+        other => panic!("expected a validation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unreadable_key_is_a_validation_error() {
+    let fixture = Fixture::new();
+    let garbage = fixture.path("garbage.key");
+    fs::write(&garbage, b"not a key").unwrap();
+    let mut options =
+        common::encode_options(&fixture.file, &fixture.carrier, &fixture.path("stego.png"));
+    options.sign_key = Some(garbage);
+
+    assert!(matches!(
+        DataProcessor::encode(options),
+        Err(DeepSceneError::Validation(_))
+    ));
+    assert!(!fixture.path("stego.png").exists());
+}
+
+#[test]
+fn estimate_counts_the_signature() {
+    let fixture = Fixture::new();
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let (private, _) = fixture.key_files("signing", &key);
+
+    for password in [None, Some(PASSWORD.to_string())] {
+        let stego = fixture.path("stego.png");
+        let options = || EncodeOptions {
+            password: password.clone(),
+            sign_key: Some(private.clone()),
+            ..common::encode_options(&fixture.file, &fixture.carrier, &stego)
+        };
+
+        let estimate = DataProcessor::estimate(&options()).unwrap();
+        let result = DataProcessor::encode(options()).unwrap();
+        assert_eq!(estimate.payload_size, result.final_size, "{:?}", password);
+    }
+}
@@ -0,0 +1,123 @@
+//! Stripped images leave nothing for extraction to read.
+
+mod common;
+
+use deepscene::core::{DeepSceneError, SteganographyEngine};
+use deepscene::processor::{DataProcessor, EncodeOptions, StripOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+struct Fixture {
+    dir: TempDir,
+    carrier: PathBuf,
+    file: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let carrier = common::save_carrier(dir.path(), "carrier.png", 96, 64);
+        let file = dir.path().join("secret.txt");
+        fs::write(&file, common::payload(800)).unwrap();
+        Self { dir, carrier, file }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn encode(&self, stego: &str, bit_plane: u8) -> PathBuf {
+        let stego = self.path(stego);
+        DataProcessor::encode(EncodeOptions {
+            bit_plane,
+            ..common::encode_options(&self.file, &self.carrier, &stego)
+        })
+        .unwrap();
+        stego
+    }
+
+    fn strip(&self, stego: &Path, clean: &str, planes: u8, randomize: bool) -> PathBuf {
+        let clean = self.path(clean);
+        let result = DataProcessor::strip(StripOptions {
+            image_path: stego.to_path_buf(),
+            output_path: Some(clean.clone()),
+            planes,
+            randomize,
+        })
+        .unwrap();
+        assert!(result.had_header);
+        clean
+    }
+}
+
+/// Fails unless `image` holds no payload, whether read directly or through a
+/// full decode.
+fn assert_no_payload(fixture: &Fixture, image: &Path) {
+    assert!(matches!(
+        SteganographyEngine::extract_data(image),
+        Err(DeepSceneError::NoData(_))
+    ));
+
+    let decoded = fixture.path("decoded.txt");
+    assert!(matches!(
+        DataProcessor::decode(common::decode_options(image, &decoded)),
+        Err(DeepSceneError::NoData(_))
+    ));
+    assert!(!decoded.exists());
+}
+
+/// The largest change to any R, G or B value between the two images.
+fn max_difference(a: &Path, b: &Path) -> u8 {
+    let a = image::open(a).unwrap().to_rgb8();
+    let b = image::open(b).unwrap().to_rgb8();
+    a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(x, y)| x.abs_diff(*y))
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn stripped_image_has_no_payload() {
+    let fixture = Fixture::new();
+    let stego = fixture.encode("stego.png", 0);
+    assert!(SteganographyEngine::extract_data(&stego).is_ok());
+
+    for randomize in [false, true] {
+        let clean = fixture.strip(&stego, "clean.png", 1, randomize);
+        assert_no_payload(&fixture, &clean);
+        assert!(max_difference(&stego, &clean) <= 1, "{}", randomize);
+    }
+}
+
+#[test]
+fn stripping_enough_planes_removes_a_higher_plane_payload() {
+    let fixture = Fixture::new();
+    let stego = fixture.encode("stego.png", 1);
+
+    let clean = fixture.strip(&stego, "clean.png", 2, false);
+    assert_no_payload(&fixture, &clean);
+    assert!(max_difference(&stego, &clean) <= 3);
+}
+
+#[test]
+fn strip_command_leaves_no_payload() {
+    let fixture = Fixture::new();
+    let stego = fixture.encode("stego.png", 0);
+    let clean = fixture.path("clean.png");
+
+    let result = Command::new(env!("CARGO_BIN_EXE_deepscene"))
+        .arg("strip")
+        .arg(&stego)
+        .arg("-o")
+        .arg(&clean)
+        .arg("--randomize")
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{:?}", result);
+
+    assert_no_payload(&fixture, &clean);
+}
@@ -0,0 +1,161 @@
+#![cfg(unix)]
+
+mod common;
+
+use deepscene::core::{DeepSceneError, Result};
+use deepscene::processor::DataProcessor;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+const VICTIM: &[u8] = b"must not be overwritten";
+
+struct Fixture {
+    dir: TempDir,
+    carrier: PathBuf,
+    file: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let carrier = common::save_carrier(dir.path(), "carrier.png", 64, 48);
+        let file = dir.path().join("secret.txt");
+        fs::write(&file, common::payload(300)).unwrap();
+        Self { dir, carrier, file }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// A file holding `VICTIM` and a link named `link` pointing at it.
+    fn linked_victim(&self, link: &str) -> (PathBuf, PathBuf) {
+        let victim = self.path("victim");
+        fs::write(&victim, VICTIM).unwrap();
+        let link = self.path(link);
+        symlink(&victim, &link).unwrap();
+        (victim, link)
+    }
+
+    fn encode_to(&self, output: &Path) -> Result<()> {
+        DataProcessor::encode(common::encode_options(&self.file, &self.carrier, output)).map(|_| ())
+    }
+}
+
+fn assert_refused<T: std::fmt::Debug>(result: Result<T>) {
+    match result {
+        Err(DeepSceneError::Validation(message)) => {
+            assert!(message.contains("is a symbolic link"), "{}", message);
+            assert!(message.contains("--follow-symlinks"), "{}", message);
+        }
+        other => panic!("expected a symlink refusal, got {:?}", other),
+    }
+}
+
+#[test]
+fn encode_refuses_a_symlinked_output() {
+    let fixture = Fixture::new();
+    let (victim, link) = fixture.linked_victim("output.png");
+
+    assert_refused(fixture.encode_to(&link));
+    assert_eq!(fs::read(&victim).unwrap(), VICTIM);
+}
+
+#[test]
+fn encode_refuses_a_dangling_symlinked_output() {
+    let fixture = Fixture::new();
+    let target = fixture.path("created-through-link.png");
+    let link = fixture.path("output.png");
+    symlink(&target, &link).unwrap();
+
+    assert_refused(fixture.encode_to(&link));
+    assert!(!target.exists());
+}
+
+#[test]
+fn decode_refuses_a_symlinked_output() {
+    let fixture = Fixture::new();
+    let stego = fixture.path("stego.png");
+    fixture.encode_to(&stego).unwrap();
+    let (victim, link) = fixture.linked_victim("decoded.txt");
+
+    assert_refused(DataProcessor::decode(common::decode_options(&stego, &link)));
+    assert_eq!(fs::read(&victim).unwrap(), VICTIM);
+}
+
+#[test]
+fn in_place_refuses_a_symlinked_carrier() {
+    let fixture = Fixture::new();
+    let link = fixture.path("linked-carrier.png");
+    symlink(&fixture.carrier, &link).unwrap();
+    let original = fs::read(&fixture.carrier).unwrap();
+
+    let mut options = common::encode_options(&fixture.file, &link, &link);
+    options.output_path = None;
+    options.in_place = true;
+
+    assert_refused(DataProcessor::encode(options));
+    assert_eq!(fs::read(&fixture.carrier).unwrap(), original);
+    assert!(
+        fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+}
+
+#[test]
+fn symlinked_inputs_are_followed() {
+    let fixture = Fixture::new();
+    let carrier_link = fixture.path("carrier-link.png");
+    let file_link = fixture.path("file-link.txt");
+    symlink(&fixture.carrier, &carrier_link).unwrap();
+    symlink(&fixture.file, &file_link).unwrap();
+    let stego = fixture.path("stego.png");
+
+    let result =
+        DataProcessor::encode(common::encode_options(&file_link, &carrier_link, &stego)).unwrap();
+
+    // The stored name is the link's, as given on the command line.
+    assert_eq!(result.file_name, "file-link.txt");
+    let stego_link = fixture.path("stego-link.png");
+    symlink(&stego, &stego_link).unwrap();
+    let output = fixture.path("decoded.txt");
+    DataProcessor::decode(common::decode_options(&stego_link, &output)).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&fixture.file).unwrap());
+}
+
+#[test]
+fn follow_symlinks_writes_through_the_link() {
+    let fixture = Fixture::new();
+    let (victim, link) = fixture.linked_victim("output.png");
+
+    // The opt-in is process-wide, so it is exercised through the binary
+    // rather than switched on for the other tests in this process.
+    let output = Command::new(env!("CARGO_BIN_EXE_deepscene"))
+        .arg("--follow-symlinks")
+        .arg("encode")
+        .arg(&fixture.carrier)
+        .arg(&fixture.file)
+        .arg("-o")
+        .arg(&link)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    let decoded = fixture.path("decoded.txt");
+    DataProcessor::decode(common::decode_options(&victim, &decoded)).unwrap();
+    assert_eq!(
+        fs::read(&decoded).unwrap(),
+        fs::read(&fixture.file).unwrap()
+    );
+}
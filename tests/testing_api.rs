@@ -0,0 +1,74 @@
+//! Round trips through the bit-level routines `core::steganography::testing`
+//! exposes, on in-memory images.
+
+mod common;
+
+use deepscene::core::EmbedParams;
+use deepscene::core::steganography::testing;
+
+fn round_trip(params: &EmbedParams) {
+    let mut image = common::carrier(96, 96);
+    let data = common::payload(700);
+
+    testing::embed_data(&mut image, &data, params).unwrap();
+
+    let extracted = testing::validate_and_extract(&image).unwrap();
+    assert_eq!(extracted.data, data);
+    assert_eq!(extracted.format_version, params.version());
+    assert_eq!(extracted.bit_plane, params.bit_plane);
+    assert_eq!(
+        testing::extract_bytes(&image, data.len(), params).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn contiguous_payload_round_trips() {
+    round_trip(&EmbedParams::default());
+}
+
+#[test]
+fn higher_bit_plane_round_trips() {
+    round_trip(&EmbedParams {
+        bit_plane: 3,
+        ..EmbedParams::default()
+    });
+}
+
+#[test]
+fn strided_payload_round_trips() {
+    let params = EmbedParams {
+        stride: 3,
+        ..EmbedParams::default()
+    };
+    round_trip(&params);
+
+    let mut image = common::carrier(96, 96);
+    testing::embed_data(&mut image, &common::payload(700), &params).unwrap();
+    assert_eq!(testing::validate_and_extract(&image).unwrap().stride, 3);
+}
+
+#[test]
+fn older_format_versions_round_trip() {
+    for version in [1, 5, 6, 14] {
+        round_trip(&EmbedParams {
+            format_version: Some(version),
+            ..EmbedParams::default()
+        });
+    }
+}
+
+#[test]
+fn extract_bytes_checks_the_stride() {
+    let mut image = common::carrier(96, 96);
+    let data = common::payload(700);
+    let params = EmbedParams {
+        stride: 2,
+        ..EmbedParams::default()
+    };
+    testing::embed_data(&mut image, &data, &params).unwrap();
+
+    // Reading contiguously picks up carrier bits between the groups, which
+    // the chunk CRCs catch.
+    assert!(testing::extract_bytes(&image, data.len(), &EmbedParams::default()).is_err());
+}
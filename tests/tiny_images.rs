@@ -0,0 +1,99 @@
+mod common;
+
+use deepscene::core::{DeepSceneError, EmbedParams, Header, SteganographyEngine};
+use tempfile::TempDir;
+
+/// Pixels the default header needs at three bits per pixel.
+fn min_pixels() -> usize {
+    (Header::for_params(&EmbedParams::default(), 0).encoded_len() * 8).div_ceil(3)
+}
+
+#[test]
+fn one_pixel_carrier_names_the_minimum_on_encode() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 1, 1);
+    let output = dir.path().join("output.png");
+
+    let result = SteganographyEngine::hide_data(&carrier, b"x", &output, &EmbedParams::default());
+
+    match result {
+        Err(DeepSceneError::CapacityExceeded(message)) => {
+            assert!(message.contains("A 1x1 image has 1 pixel,"), "{}", message);
+            assert!(
+                message.contains(&format!("needs at least {} pixels", min_pixels())),
+                "{}",
+                message
+            );
+        }
+        other => panic!("expected a capacity error, got {:?}", other),
+    }
+    assert!(!output.exists());
+}
+
+#[test]
+fn one_pixel_carrier_names_the_minimum_on_decode() {
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", 1, 1);
+
+    match SteganographyEngine::extract_data(&carrier) {
+        Err(DeepSceneError::NoData(message)) => {
+            assert!(message.contains("A 1x1 image has 1 pixel,"), "{}", message);
+            assert!(
+                message.contains("cannot contain embedded data"),
+                "{}",
+                message
+            );
+        }
+        other => panic!("expected a no-data error, got {:?}", other),
+    }
+}
+
+#[test]
+fn header_fit_changes_at_the_minimum_pixel_count() {
+    let min = min_pixels() as u32;
+    let params = EmbedParams::default();
+
+    let error = SteganographyEngine::check_header_fits(min - 1, 1, &params).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains(&format!("has {} pixels, but the header", min - 1)),
+        "{}",
+        error
+    );
+    assert!(SteganographyEngine::check_header_fits(min, 1, &params).is_ok());
+    assert_eq!(SteganographyEngine::payload_capacity(min, 1, &params), 0);
+}
+
+#[test]
+fn small_carriers_reject_the_header_in_every_shape() {
+    let params = EmbedParams::default();
+    for (width, height) in [(1, 1), (2, 2), (3, 3), (5, 5), (1, 10), (10, 1)] {
+        let fits = SteganographyEngine::check_header_fits(width, height, &params).is_ok();
+        assert_eq!(
+            fits,
+            (width * height) as usize >= min_pixels(),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+}
+
+#[test]
+fn smallest_carrier_that_holds_a_byte_round_trips() {
+    let params = EmbedParams::default();
+    let width = (1..)
+        .find(|&width| SteganographyEngine::payload_capacity(width, 1, &params) > 0)
+        .unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let carrier = common::save_carrier(dir.path(), "carrier.png", width, 1);
+    let output = dir.path().join("output.png");
+    SteganographyEngine::hide_data(&carrier, b"x", &output, &params).unwrap();
+
+    assert_eq!(
+        SteganographyEngine::extract_data(&output).unwrap().data,
+        b"x"
+    );
+}